@@ -254,6 +254,10 @@ if [[ "$(uname -s)" == "Darwin" || "$(uname -s)" == "Linux" ]]; then
         cargo +stable run --bin mullvad "${CARGO_ARGS[@]}" --release -- shell-completions "$sh" \
             "dist-assets/shell-completions/"
     done
+
+    mkdir -p "dist-assets/man"
+    echo "Generating man page..."
+    cargo +stable run --bin mullvad "${CARGO_ARGS[@]}" --release -- man-page "dist-assets/man/"
 fi
 
 ./update-relays.sh
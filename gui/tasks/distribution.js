@@ -156,6 +156,7 @@ const config = {
       distAssets('shell-completions/_mullvad') + '=/usr/local/share/zsh/site-functions/_mullvad',
       distAssets('shell-completions/mullvad.fish') +
         '=/usr/share/fish/vendor_completions.d/mullvad.fish',
+      distAssets('man/mullvad.1') + '=/usr/share/man/man1/mullvad.1',
     ],
     afterInstall: distAssets('linux/after-install.sh'),
     afterRemove: distAssets('linux/after-remove.sh'),
@@ -182,6 +183,7 @@ const config = {
       distAssets('shell-completions/_mullvad') + '=/usr/share/zsh/site-functions/_mullvad',
       distAssets('shell-completions/mullvad.fish') +
         '=/usr/share/fish/vendor_completions.d/mullvad.fish',
+      distAssets('man/mullvad.1') + '=/usr/share/man/man1/mullvad.1',
     ],
     afterInstall: distAssets('linux/after-install.sh'),
     afterRemove: distAssets('linux/after-remove.sh'),
@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use clap::value_t_or_exit;
 use itertools::Itertools;
 use mullvad_management_interface::{types::Timestamp, Code};
@@ -72,7 +72,11 @@ impl Command for Account {
             token = token.split_whitespace().join("").to_string();
             self.set(Some(token)).await
         } else if let Some(_matches) = matches.subcommand_matches("get") {
-            self.get().await
+            if matches.is_present("json") {
+                self.get_json().await
+            } else {
+                self.get().await
+            }
         } else if let Some(_matches) = matches.subcommand_matches("unset") {
             self.set(None).await
         } else if let Some(_matches) = matches.subcommand_matches("clear-history") {
@@ -120,6 +124,22 @@ impl Account {
         Ok(())
     }
 
+    async fn get_json(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if settings.account_token.is_empty() {
+            return format::print_json(&serde_json::json!({ "account_token": null }));
+        }
+        let account_data = rpc
+            .get_account_data(settings.account_token.clone())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to fetch account data", error))?
+            .into_inner();
+        let mut account = serde_json::to_value(account_data).map_err(Error::SerializeJson)?;
+        account["account_token"] = settings.account_token.into();
+        format::print_json(&account)
+    }
+
     async fn create(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.create_new_account(()).await?;
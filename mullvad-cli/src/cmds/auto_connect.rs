@@ -1,5 +1,12 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{format_network_action, format_network_rule},
+    new_rpc_client, Command, Error, Result,
+};
 use clap::value_t_or_exit;
+use mullvad_management_interface::types::{
+    network_rule, DefaultNetworkAction, NetworkAction, NetworkRule, NetworkRules,
+};
+use talpid_types::net::MacAddress;
 
 pub struct AutoConnect;
 
@@ -26,6 +33,7 @@ impl Command for AutoConnect {
                 clap::SubCommand::with_name("get")
                     .about("Display the current auto-connect setting"),
             )
+            .subcommand(create_rules_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -34,6 +42,8 @@ impl Command for AutoConnect {
             self.set(auto_connect == "on").await
         } else if let Some(_matches) = matches.subcommand_matches("get") {
             self.get().await
+        } else if let Some(rules_matches) = matches.subcommand_matches("rules") {
+            self.handle_rules_cmd(rules_matches).await
         } else {
             unreachable!("No auto-connect command given");
         }
@@ -54,4 +64,169 @@ impl AutoConnect {
         println!("Autoconnect: {}", if auto_connect { "on" } else { "off" });
         Ok(())
     }
+    async fn handle_rules_cmd(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("list", Some(_)) => self.list_rules().await,
+            ("add", Some(matches)) => {
+                let network = if let Some(ssid) = matches.value_of("ssid") {
+                    network_rule::Network::Ssid(ssid.to_string())
+                } else {
+                    let mac = value_t_or_exit!(matches.value_of("gateway mac"), MacAddress);
+                    network_rule::Network::GatewayMac(mac.to_string())
+                };
+                let action = parse_network_action(matches.value_of("action").unwrap());
+                self.update_rules(|rules| {
+                    rules.rules.push(NetworkRule {
+                        network: Some(network),
+                        action: i32::from(action),
+                    });
+                    Ok(())
+                })
+                .await?;
+                println!("Added network rule");
+                Ok(())
+            }
+            ("remove", Some(matches)) => {
+                let index = value_t_or_exit!(matches.value_of("index"), usize);
+                self.update_rules(|rules| {
+                    if index == 0 || index > rules.rules.len() {
+                        return Err(Error::InvalidCommand("No network rule with that index"));
+                    }
+                    rules.rules.remove(index - 1);
+                    Ok(())
+                })
+                .await?;
+                println!("Removed network rule");
+                Ok(())
+            }
+            ("default", Some(matches)) => {
+                let default_action = match matches.value_of("action").unwrap() {
+                    "none" => None,
+                    action => Some(DefaultNetworkAction {
+                        action: i32::from(parse_network_action(action)),
+                    }),
+                };
+                self.update_rules(|rules| {
+                    rules.default_action = default_action;
+                    Ok(())
+                })
+                .await?;
+                println!("Updated the action for networks that match no rule");
+                Ok(())
+            }
+            ("clear", Some(_)) => {
+                self.update_rules(|rules| {
+                    *rules = NetworkRules::default();
+                    Ok(())
+                })
+                .await?;
+                println!("Removed all network rules");
+                Ok(())
+            }
+            _ => unreachable!("No network rules command given"),
+        }
+    }
+
+    async fn list_rules(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let rules = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .network_rules
+            .unwrap_or_default();
+
+        if rules.rules.is_empty() {
+            println!("No network rules");
+        }
+        for (index, rule) in rules.rules.iter().enumerate() {
+            println!("{}: {}", index + 1, format_network_rule(rule));
+        }
+        match rules.default_action {
+            Some(default) => println!("Other networks: {}", format_network_action(default.action)),
+            None => println!("Other networks: no action"),
+        }
+        Ok(())
+    }
+
+    async fn update_rules(
+        &self,
+        update_fn: impl FnOnce(&mut NetworkRules) -> Result<()>,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut rules = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .network_rules
+            .unwrap_or_default();
+        update_fn(&mut rules)?;
+        rpc.set_network_rules(rules)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to update network rules", error))?;
+        Ok(())
+    }
+}
+
+fn create_rules_subcommand() -> clap::App<'static, 'static> {
+    let action_arg = clap::Arg::with_name("action")
+        .required(true)
+        .possible_values(&["connect", "disconnect"]);
+
+    clap::SubCommand::with_name("rules")
+        .about(
+            "Manage rules that connect or disconnect the tunnel automatically when joining a \
+             network. Rules are checked in order, and the first one matching the network is used",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("list").about("Display the network rules"))
+        .subcommand(
+            clap::SubCommand::with_name("add")
+                .about("Add a rule for a wireless network or a gateway")
+                .arg(
+                    clap::Arg::with_name("ssid")
+                        .long("ssid")
+                        .takes_value(true)
+                        .help("Match the wireless network with this SSID"),
+                )
+                .arg(
+                    clap::Arg::with_name("gateway mac")
+                        .long("gateway-mac")
+                        .takes_value(true)
+                        .help("Match any network where the default gateway has this MAC address"),
+                )
+                .group(
+                    clap::ArgGroup::with_name("network")
+                        .args(&["ssid", "gateway mac"])
+                        .required(true),
+                )
+                .arg(action_arg.clone()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("remove")
+                .about("Remove a rule")
+                .arg(
+                    clap::Arg::with_name("index")
+                        .required(true)
+                        .help("The number of the rule, as shown by 'list'"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("default")
+                .about("Set the action to take on networks that match no rule")
+                .arg(
+                    clap::Arg::with_name("action")
+                        .required(true)
+                        .possible_values(&["connect", "disconnect", "none"]),
+                ),
+        )
+        .subcommand(clap::SubCommand::with_name("clear").about("Remove all network rules"))
+}
+
+fn parse_network_action(action: &str) -> NetworkAction {
+    match action {
+        "connect" => NetworkAction::Connect,
+        "disconnect" => NetworkAction::Disconnect,
+        _ => unreachable!("Invalid network action"),
+    }
 }
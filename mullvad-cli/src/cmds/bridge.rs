@@ -1,10 +1,10 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, Command, Error, Result};
 use clap::{value_t, values_t};
 
 use mullvad_management_interface::types::{
     bridge_settings::{Type as BridgeSettingsType, *},
     bridge_state::State as BridgeStateType,
-    BridgeSettings, BridgeState, RelayLocation,
+    BridgeSettings, BridgeState,
 };
 use talpid_types::net::openvpn::SHADOWSOCKS_CIPHERS;
 
@@ -32,8 +32,9 @@ impl Command for Bridge {
     async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
             ("set", Some(set_matches)) => Self::handle_set(set_matches).await,
+            ("get", _) if matches.is_present("json") => Self::handle_get_json().await,
             ("get", _) => Self::handle_get().await,
-            ("list", _) => Self::list_bridge_relays().await,
+            ("list", _) => Self::list_bridge_relays(matches.is_present("json")).await,
             _ => unreachable!("unhandled command"),
         }
     }
@@ -58,9 +59,14 @@ fn create_bridge_set_subcommand() -> clap::App<'static, 'static> {
                         .required(true),
                 ),
         )
+        .subcommand(location::get_ownership_subcommand().about(
+            "Set whether to select bridge relays that are owned by Mullvad or rented. The 'list' \
+             command shows the ownership of each relay.",
+        ))
         .subcommand(location::get_subcommand().about(
             "Set country or city to select bridge relays from. Use the 'list' \
-             command to show available alternatives.",
+             command to show available alternatives. Use --include, --exclude and \
+             --exclude-provider to combine several locations.",
         ))
 }
 
@@ -188,6 +194,9 @@ impl Bridge {
             ("provider", Some(provider_matches)) => {
                 Self::handle_set_bridge_provider(provider_matches).await
             }
+            ("ownership", Some(ownership_matches)) => {
+                Self::handle_set_bridge_ownership(ownership_matches).await
+            }
             ("custom", Some(custom_matches)) => {
                 Self::handle_bridge_set_custom_settings(custom_matches).await
             }
@@ -208,17 +217,32 @@ impl Bridge {
             }
             BridgeSettingsType::Normal(constraints) => {
                 println!(
-                    "Bridge constraints - {}, {}",
-                    location::format_location(constraints.location.as_ref()),
-                    location::format_providers(&constraints.providers)
+                    "Bridge constraints - {}, {}{}",
+                    location::format_location_constraint(constraints.location_constraint.as_ref()),
+                    location::format_providers(&constraints.providers),
+                    location::format_ownership(constraints.ownership.as_ref())
                 );
             }
         };
         Ok(())
     }
 
+    async fn handle_get_json() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        format::print_json(&serde_json::json!({
+            "bridge_state": settings.bridge_state,
+            "bridge_settings": settings.bridge_settings,
+        }))
+    }
+
     async fn handle_set_bridge_location(matches: &clap::ArgMatches<'_>) -> Result<()> {
-        Self::update_bridge_settings(Some(location::get_constraint_from_args(matches)), None).await
+        let location = location::get_location_constraint_from_args(matches).await?;
+        Self::update_bridge_settings(|constraints| {
+            constraints.location = location::single_location(&location);
+            constraints.location_constraint = Some(location);
+        })
+        .await
     }
 
     async fn handle_set_bridge_provider(matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -230,37 +254,27 @@ impl Bridge {
             providers
         };
 
-        Self::update_bridge_settings(None, Some(providers)).await
+        Self::update_bridge_settings(|constraints| constraints.providers = providers).await
+    }
+
+    async fn handle_set_bridge_ownership(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let ownership =
+            location::parse_ownership_constraint(matches.value_of("ownership").unwrap());
+        Self::update_bridge_settings(|constraints| constraints.ownership = ownership).await
     }
 
-    async fn update_bridge_settings(
-        location: Option<RelayLocation>,
-        providers: Option<Vec<String>>,
-    ) -> Result<()> {
+    /// Applies `update` to the current bridge constraints. If bridge relays are not currently
+    /// selected by constraints, `update` is applied to the default constraints instead.
+    async fn update_bridge_settings(update: impl FnOnce(&mut BridgeConstraints)) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
 
         let bridge_settings = settings.bridge_settings.unwrap();
-        let constraints = match bridge_settings.r#type.unwrap() {
-            BridgeSettingsType::Normal(mut constraints) => {
-                if let Some(new_location) = location {
-                    constraints.location = Some(new_location);
-                }
-                if let Some(new_providers) = providers {
-                    constraints.providers = new_providers;
-                }
-                constraints
-            }
-            _ => {
-                let location = location.unwrap_or_default();
-                let providers = providers.unwrap_or_default();
-
-                BridgeConstraints {
-                    location: Some(location),
-                    providers,
-                }
-            }
+        let mut constraints = match bridge_settings.r#type.unwrap() {
+            BridgeSettingsType::Normal(constraints) => constraints,
+            _ => BridgeConstraints::default(),
         };
+        update(&mut constraints);
 
         rpc.set_bridge_settings(BridgeSettings {
             r#type: Some(BridgeSettingsType::Normal(constraints)),
@@ -420,7 +434,7 @@ impl Bridge {
         println!("  cipher: {}", proxy.cipher);
     }
 
-    async fn list_bridge_relays() -> Result<()> {
+    async fn list_bridge_relays(json: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let mut locations = rpc
             .get_relay_locations(())
@@ -452,23 +466,24 @@ impl Bridge {
             }
         }
 
-        countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-        for mut country in countries {
-            country
-                .cities
-                .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+        location::sort_countries(&mut countries);
+        if json {
+            return format::print_json(&countries);
+        }
+        for country in countries {
             println!("{} ({})", country.name, country.code);
-            for mut city in country.cities {
-                city.relays
-                    .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+            for city in country.cities {
                 println!(
                     "\t{} ({}) @ {:.5}°N, {:.5}°W",
                     city.name, city.code, city.latitude, city.longitude
                 );
                 for relay in &city.relays {
                     println!(
-                        "\t\t{} ({}) - hosted by {}",
-                        relay.hostname, relay.ipv4_addr_in, relay.provider
+                        "\t\t{} ({}) - hosted by {} ({})",
+                        relay.hostname,
+                        relay.ipv4_addr_in,
+                        relay.provider,
+                        location::format_relay_ownership(relay.owned)
                     );
                 }
             }
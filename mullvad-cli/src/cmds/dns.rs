@@ -1,7 +1,13 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::types;
-use mullvad_types::settings::{DnsOptions, DnsState};
-use std::convert::TryInto;
+use mullvad_types::{
+    dns_blocklist::{DnsBlocklist, DnsBlocklistSource},
+    settings::{DnsOptions, DnsState, EncryptedDnsProtocol, EncryptedDnsUpstream},
+};
+use std::{
+    convert::{TryFrom, TryInto},
+    net::{IpAddr, SocketAddr},
+};
 
 pub struct Dns;
 
@@ -47,6 +53,111 @@ impl Command for Dns {
                                     .help("One or more IP addresses pointing to DNS resolvers.")
                                     .required(true),
                             ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("encrypted")
+                            .about(
+                                "Resolve domain names using DNS-over-HTTPS or DNS-over-TLS \
+                                 servers, which are tried in the given order",
+                            )
+                            .arg(
+                                clap::Arg::with_name("servers")
+                                    .multiple(true)
+                                    .help(
+                                        "One or more servers on the form \
+                                         <https|tls>://<hostname>@<ip>[:port], \
+                                         e.g. https://dns.example.com@192.0.2.1",
+                                    )
+                                    .required(true),
+                            ),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("split")
+                    .about(
+                        "Resolve some domains using other DNS servers, such as the ones on the \
+                         local network. Only supported on Linux with systemd-resolved",
+                    )
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::SubCommand::with_name("add")
+                            .about("Resolve a domain and its subdomains using the given servers")
+                            .arg(
+                                clap::Arg::with_name("domain")
+                                    .help("The domain, e.g. corp.example.com")
+                                    .required(true),
+                            )
+                            .arg(
+                                clap::Arg::with_name("servers")
+                                    .multiple(true)
+                                    .help("One or more IP addresses of DNS resolvers")
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("remove")
+                            .about("Stop resolving a domain using other servers")
+                            .arg(
+                                clap::Arg::with_name("domain")
+                                    .help("The domain to remove")
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("clear").about("Remove all split DNS rules"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("blocklist")
+                    .about(
+                        "Block domains on lists of your choice. Queries are filtered by a local \
+                         resolver in front of the DNS servers set above",
+                    )
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::SubCommand::with_name("add")
+                            .about(
+                                "Add and enable a list. The list is either a hosts file or a \
+                                 list with one domain per line",
+                            )
+                            .arg(
+                                clap::Arg::with_name("name")
+                                    .help("Name to refer to the list by")
+                                    .required(true),
+                            )
+                            .arg(
+                                clap::Arg::with_name("source")
+                                    .help(
+                                        "HTTPS URL to download the list from, or path to a file \
+                                         in the dns-blocklists directory of the daemon's \
+                                         settings directory",
+                                    )
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("remove")
+                            .about("Remove a list")
+                            .arg(clap::Arg::with_name("name").required(true)),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("enable")
+                            .about("Enable a list")
+                            .arg(clap::Arg::with_name("name").required(true)),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("disable")
+                            .about("Disable a list without removing it")
+                            .arg(clap::Arg::with_name("name").required(true)),
+                    )
+                    .subcommand(clap::SubCommand::with_name("list").about("Display all lists"))
+                    .subcommand(
+                        clap::SubCommand::with_name("stats")
+                            .about("Display the number of queries blocked by each list"),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("update")
+                            .about("Download the enabled lists again and reload them"),
                     ),
             )
     }
@@ -64,9 +175,57 @@ impl Command for Dns {
                 ("custom", Some(matches)) => {
                     self.set_custom(matches.values_of_lossy("servers")).await
                 }
+                ("encrypted", Some(matches)) => {
+                    let upstreams = matches
+                        .values_of("servers")
+                        .unwrap()
+                        .map(parse_encrypted_dns_upstream)
+                        .collect::<Result<Vec<_>>>()?;
+                    self.set_encrypted(upstreams).await
+                }
                 _ => unreachable!("No custom-dns server command given"),
             },
-            ("get", _) => self.get().await,
+            ("split", Some(matches)) => match matches.subcommand() {
+                ("add", Some(matches)) => {
+                    self.add_split_rule(
+                        matches.value_of("domain").unwrap(),
+                        matches.values_of_lossy("servers").unwrap(),
+                    )
+                    .await
+                }
+                ("remove", Some(matches)) => {
+                    self.remove_split_rule(matches.value_of("domain").unwrap())
+                        .await
+                }
+                ("clear", Some(_)) => self.clear_split_rules().await,
+                _ => unreachable!("No split DNS command given"),
+            },
+            ("blocklist", Some(matches)) => match matches.subcommand() {
+                ("add", Some(matches)) => {
+                    self.add_blocklist(
+                        matches.value_of("name").unwrap(),
+                        matches.value_of("source").unwrap(),
+                    )
+                    .await
+                }
+                ("remove", Some(matches)) => {
+                    self.remove_blocklist(matches.value_of("name").unwrap())
+                        .await
+                }
+                ("enable", Some(matches)) => {
+                    self.set_blocklist_enabled(matches.value_of("name").unwrap(), true)
+                        .await
+                }
+                ("disable", Some(matches)) => {
+                    self.set_blocklist_enabled(matches.value_of("name").unwrap(), false)
+                        .await
+                }
+                ("list", Some(_)) => self.list_blocklists().await,
+                ("stats", Some(_)) => self.get_blocklist_stats().await,
+                ("update", Some(_)) => self.update_blocklist_contents().await,
+                _ => unreachable!("No DNS blocklist command given"),
+            },
+            ("get", _) => self.get(matches.is_present("json")).await,
             _ => unreachable!("No custom-dns command given"),
         }
     }
@@ -104,9 +263,160 @@ impl Dns {
         Ok(())
     }
 
-    async fn get(&self) -> Result<()> {
+    async fn set_encrypted(&self, upstreams: Vec<EncryptedDnsUpstream>) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
-        let options: DnsOptions = rpc
+        let settings = rpc.get_settings(()).await?.into_inner();
+        rpc.set_dns_options(types::DnsOptions {
+            state: types::dns_options::DnsState::Encrypted as i32,
+            encrypted_options: Some(types::EncryptedDnsOptions {
+                upstreams: upstreams
+                    .iter()
+                    .map(types::EncryptedDnsUpstream::from)
+                    .collect(),
+            }),
+            ..settings.tunnel_options.unwrap().dns_options.unwrap()
+        })
+        .await?;
+        println!("Updated DNS settings");
+        Ok(())
+    }
+
+    async fn add_split_rule(&self, domain: &str, servers: Vec<String>) -> Result<()> {
+        let domain = domain.trim_matches('.').to_lowercase();
+        self.update_split_rules(|rules| {
+            rules.retain(|rule| rule.domain != domain);
+            rules.push(types::SplitDnsRule {
+                domain: domain.clone(),
+                resolvers: servers,
+            });
+            true
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_split_rule(&self, domain: &str) -> Result<()> {
+        let domain = domain.trim_matches('.').to_lowercase();
+        let removed = self
+            .update_split_rules(|rules| {
+                let num_rules = rules.len();
+                rules.retain(|rule| rule.domain != domain);
+                rules.len() != num_rules
+            })
+            .await?;
+        if !removed {
+            return Err(Error::CommandFailed("No split DNS rule for the domain"));
+        }
+        Ok(())
+    }
+
+    async fn clear_split_rules(&self) -> Result<()> {
+        self.update_split_rules(|rules| {
+            let changed = !rules.is_empty();
+            rules.clear();
+            changed
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Applies `update_fn` to the split DNS rules, and saves them if it returns `true`.
+    async fn update_split_rules(
+        &self,
+        update_fn: impl FnOnce(&mut Vec<types::SplitDnsRule>) -> bool,
+    ) -> Result<bool> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let mut dns_options = settings.tunnel_options.unwrap().dns_options.unwrap();
+        if !update_fn(&mut dns_options.split_rules) {
+            return Ok(false);
+        }
+        rpc.set_dns_options(dns_options).await?;
+        println!("Updated DNS settings");
+        Ok(true)
+    }
+
+    async fn add_blocklist(&self, name: &str, raw_source: &str) -> Result<()> {
+        let source = if raw_source.starts_with("https://") {
+            DnsBlocklistSource::Url(raw_source.to_owned())
+        } else if raw_source.starts_with("http://") {
+            return Err(Error::InvalidCommand("Lists must be downloaded over HTTPS"));
+        } else {
+            // The daemon does not share the working directory of the CLI.
+            let path = std::fs::canonicalize(raw_source)
+                .map_err(|error| Error::ReadFile(raw_source.to_owned(), error))?;
+            DnsBlocklistSource::File(path)
+        };
+        let blocklist = types::DnsBlocklist::from(&DnsBlocklist {
+            name: name.trim().to_owned(),
+            source,
+            enabled: true,
+        });
+        self.update_blocklists(|blocklists| {
+            blocklists.retain(|other| other.name != blocklist.name);
+            blocklists.push(blocklist);
+            true
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_blocklist(&self, name: &str) -> Result<()> {
+        let removed = self
+            .update_blocklists(|blocklists| {
+                let num_blocklists = blocklists.len();
+                blocklists.retain(|blocklist| blocklist.name != name);
+                blocklists.len() != num_blocklists
+            })
+            .await?;
+        if !removed {
+            return Err(Error::CommandFailed("No blocklist with the name"));
+        }
+        Ok(())
+    }
+
+    async fn set_blocklist_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        let mut found = false;
+        self.update_blocklists(|blocklists| {
+            match blocklists
+                .iter_mut()
+                .find(|blocklist| blocklist.name == name)
+            {
+                Some(blocklist) => {
+                    found = true;
+                    let changed = blocklist.enabled != enabled;
+                    blocklist.enabled = enabled;
+                    changed
+                }
+                None => false,
+            }
+        })
+        .await?;
+        if !found {
+            return Err(Error::CommandFailed("No blocklist with the name"));
+        }
+        Ok(())
+    }
+
+    /// Applies `update_fn` to the DNS blocklists, and saves them if it returns `true`.
+    async fn update_blocklists(
+        &self,
+        update_fn: impl FnOnce(&mut Vec<types::DnsBlocklist>) -> bool,
+    ) -> Result<bool> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let mut dns_options = settings.tunnel_options.unwrap().dns_options.unwrap();
+        if !update_fn(&mut dns_options.blocklists) {
+            return Ok(false);
+        }
+        rpc.set_dns_options(dns_options).await?;
+        println!("Updated DNS settings");
+        Ok(true)
+    }
+
+    async fn list_blocklists(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let blocklists = rpc
             .get_settings(())
             .await?
             .into_inner()
@@ -114,8 +424,61 @@ impl Dns {
             .unwrap()
             .dns_options
             .unwrap()
-            .try_into()
+            .blocklists;
+        if blocklists.is_empty() {
+            println!("No blocklists");
+        }
+        for blocklist in blocklists {
+            let blocklist = DnsBlocklist::try_from(blocklist).unwrap();
+            println!(
+                "{} ({}): {}",
+                blocklist.name,
+                if blocklist.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                blocklist.source
+            );
+        }
+        Ok(())
+    }
+
+    async fn get_blocklist_stats(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let stats = rpc.get_dns_blocklist_stats(()).await?.into_inner();
+        println!("Queries: {}", stats.queries);
+        println!("Blocked: {}", stats.blocked);
+        for list in &stats.lists {
+            println!(
+                "{}: {} domains, {} blocked",
+                list.name, list.domains, list.blocked
+            );
+        }
+        Ok(())
+    }
+
+    async fn update_blocklist_contents(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.update_dns_blocklists(()).await?;
+        println!("Reloaded DNS blocklists");
+        Ok(())
+    }
+
+    async fn get(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let options = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .tunnel_options
+            .unwrap()
+            .dns_options
             .unwrap();
+        if json {
+            return format::print_json(&options);
+        }
+        let options: DnsOptions = options.try_into().unwrap();
 
         match options.state {
             DnsState::Default => {
@@ -129,8 +492,114 @@ impl Dns {
                     println!("{}", server);
                 }
             }
+            DnsState::Encrypted => {
+                println!("Custom DNS: encrypted\nServers:");
+                for upstream in &options.encrypted_options.upstreams {
+                    println!("{}", upstream);
+                }
+            }
+        }
+        if !options.split_rules.is_empty() {
+            println!("Split DNS rules:");
+            for rule in &options.split_rules {
+                println!("{}", rule);
+            }
+        }
+        let enabled_blocklists: Vec<_> = options
+            .blocklists
+            .iter()
+            .filter(|blocklist| blocklist.enabled)
+            .map(|blocklist| blocklist.name.as_str())
+            .collect();
+        if !enabled_blocklists.is_empty() {
+            println!("Blocklists: {}", enabled_blocklists.join(", "));
         }
 
         Ok(())
     }
 }
+
+/// Parses a server on the form `<https|tls>://<hostname>@<ip>[:port]`.
+fn parse_encrypted_dns_upstream(raw_upstream: &str) -> Result<EncryptedDnsUpstream> {
+    const INVALID_SERVER: &str =
+        "Invalid server. Must be on the form <https|tls>://<hostname>@<ip>[:port].";
+
+    let mut parts = raw_upstream.splitn(2, "://");
+    let protocol = match parts.next().unwrap().to_lowercase().as_str() {
+        "https" => EncryptedDnsProtocol::Https,
+        "tls" => EncryptedDnsProtocol::Tls,
+        _ => return Err(Error::InvalidCommand(INVALID_SERVER)),
+    };
+    let mut parts = parts
+        .next()
+        .ok_or(Error::InvalidCommand(INVALID_SERVER))?
+        .rsplitn(2, '@');
+    let raw_address = parts.next().unwrap();
+    let hostname = parts
+        .next()
+        .filter(|hostname| !hostname.is_empty())
+        .ok_or(Error::InvalidCommand(INVALID_SERVER))?;
+    let address = match raw_address.parse::<SocketAddr>() {
+        Ok(address) => address,
+        Err(_) => raw_address
+            .parse::<IpAddr>()
+            .map(|ip| SocketAddr::new(ip, protocol.default_port()))
+            .map_err(|_| Error::InvalidCommand(INVALID_SERVER))?,
+    };
+
+    Ok(EncryptedDnsUpstream {
+        protocol,
+        address,
+        hostname: hostname.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_encrypted_dns_upstream() {
+        assert_eq!(
+            parse_encrypted_dns_upstream("https://dns.example.com@192.0.2.1").unwrap(),
+            EncryptedDnsUpstream {
+                protocol: EncryptedDnsProtocol::Https,
+                address: "192.0.2.1:443".parse().unwrap(),
+                hostname: "dns.example.com".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_encrypted_dns_upstream("TLS://dns.example.com@[2001:db8::1]:8853").unwrap(),
+            EncryptedDnsUpstream {
+                protocol: EncryptedDnsProtocol::Tls,
+                address: "[2001:db8::1]:8853".parse().unwrap(),
+                hostname: "dns.example.com".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_encrypted_dns_upstream("tls://dns.example.com@2001:db8::1")
+                .unwrap()
+                .address,
+            "[2001:db8::1]:853".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_encrypted_dns_upstream() {
+        for invalid in &[
+            "dns.example.com@192.0.2.1",
+            "http://dns.example.com@192.0.2.1",
+            "https://192.0.2.1",
+            "https://@192.0.2.1",
+            "https://dns.example.com@",
+            "https://dns.example.com@dns.example.com",
+            "https://dns.example.com@192.0.2.1:port",
+        ] {
+            assert!(
+                parse_encrypted_dns_upstream(invalid).is_err(),
+                "{} was accepted",
+                invalid
+            );
+        }
+    }
+}
@@ -28,6 +28,9 @@ pub use self::dns::Dns;
 mod lan;
 pub use self::lan::Lan;
 
+mod profile;
+pub use self::profile::Profile;
+
 mod reconnect;
 pub use self::reconnect::Reconnect;
 
@@ -37,6 +40,9 @@ pub use self::relay::Relay;
 mod reset;
 pub use self::reset::Reset;
 
+mod settings;
+pub use self::settings::Settings;
+
 #[cfg(target_os = "linux")]
 mod split_tunnel;
 #[cfg(target_os = "linux")]
@@ -45,6 +51,9 @@ pub use self::split_tunnel::SplitTunnel;
 mod status;
 pub use self::status::Status;
 
+mod tui;
+pub use self::tui::Tui;
+
 mod tunnel;
 pub use self::tunnel::Tunnel;
 
@@ -62,13 +71,16 @@ pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
         Box::new(Connect),
         Box::new(Disconnect),
         Box::new(Dns),
+        Box::new(Profile),
         Box::new(Reconnect),
         Box::new(Lan),
         Box::new(Relay),
         Box::new(Reset),
+        Box::new(Settings),
         #[cfg(target_os = "linux")]
         Box::new(SplitTunnel),
         Box::new(Status),
+        Box::new(Tui),
         Box::new(Tunnel),
         Box::new(Version),
     ];
@@ -0,0 +1,102 @@
+use crate::{new_rpc_client, Command, Error, Result};
+
+pub struct Profile;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Profile {
+    fn name(&self) -> &'static str {
+        "profile"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage named profiles of relay, bridge, tunnel and LAN settings that can be \
+                 activated all at once",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("create")
+                    .about("Save the current settings as a new profile")
+                    .arg(
+                        clap::Arg::with_name("name")
+                            .help("The name of the profile")
+                            .required(true),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("list").about("List all profiles"))
+            .subcommand(
+                clap::SubCommand::with_name("activate")
+                    .about("Replace the current settings with the ones in a profile")
+                    .arg(
+                        clap::Arg::with_name("name")
+                            .help("The name of the profile")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("delete")
+                    .about("Remove a profile")
+                    .arg(
+                        clap::Arg::with_name("name")
+                            .help("The name of the profile")
+                            .required(true),
+                    ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("create", Some(matches)) => Self::create(matches.value_of("name").unwrap()).await,
+            ("list", Some(_)) => Self::list().await,
+            ("activate", Some(matches)) => Self::activate(matches.value_of("name").unwrap()).await,
+            ("delete", Some(matches)) => Self::delete(matches.value_of("name").unwrap()).await,
+            _ => unreachable!("No profile command given"),
+        }
+    }
+}
+
+impl Profile {
+    async fn create(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.create_profile(name.to_owned())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to create profile", error))?;
+        println!("Saved the current settings as profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if settings.profiles.is_empty() {
+            println!("No profiles");
+        }
+        for profile in settings.profiles {
+            if profile.active {
+                println!("{} (active)", profile.name);
+            } else {
+                println!("{}", profile.name);
+            }
+        }
+        Ok(())
+    }
+
+    async fn activate(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.activate_profile(name.to_owned())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to activate profile", error))?;
+        println!("Activated profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn delete(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.delete_profile(name.to_owned())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to delete profile", error))?;
+        println!("Deleted profile \"{}\"", name);
+        Ok(())
+    }
+}
@@ -1,22 +1,29 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, Command, Error, Result};
 use clap::{value_t, values_t};
 use itertools::Itertools;
 use std::{
+    convert::TryFrom,
     fmt::Write,
+    fs,
     io::{self, BufRead},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
 };
 
 use mullvad_management_interface::types::{
+    self,
     connection_config::{self, OpenvpnConfig, WireguardConfig},
-    relay_settings, relay_settings_update, ConnectionConfig, CustomRelaySettings, IpVersion,
-    IpVersionConstraint, NormalRelaySettingsUpdate, OpenvpnConstraints, ProviderUpdate,
-    RelayListCountry, RelayLocation, RelaySettingsUpdate, TransportProtocol,
+    relay_selection_mode::Mode as RelaySelectionModeType,
+    relay_settings, relay_settings_update, ConnectionConfig, CustomRelaySettings, Duration,
+    IpVersion, IpVersionConstraint, Location, LocationConstraint, NormalRelaySettingsUpdate,
+    ObfuscationConstraint, ObfuscationType, OpenvpnConstraints, OwnershipUpdate, ProviderUpdate,
+    RelayListCountry, RelayLocation, RelaySelectionMode, RelaySettingsUpdate, TransportProtocol,
     TransportProtocolConstraint, TunnelType, TunnelTypeConstraint, TunnelTypeUpdate,
     WireguardConstraints,
 };
-use mullvad_types::relay_constraints::Constraint;
+use mullvad_types::{
+    location::haversine_dist_deg, relay_constraints::Constraint, wg_quick::WgQuickConfig,
+};
 use talpid_types::net::all_of_the_internet;
 
 pub struct Relay;
@@ -45,29 +52,49 @@ impl Command for Relay {
                                 .arg(
                                     clap::Arg::with_name("host")
                                         .help("Hostname or IP")
-                                        .required(true),
+                                        .required_unless("config"),
                                 )
                                 .arg(
                                     clap::Arg::with_name("port")
                                         .help("Remote network port")
-                                        .required(true),
+                                        .required_unless("config"),
                                 )
                                 .arg(
                                     clap::Arg::with_name("peer-pubkey")
                                         .help("Base64 encoded peer public key")
-                                        .required(true),
+                                        .required_unless("config"),
                                 )
                                 .arg(
                                     clap::Arg::with_name("v4-gateway")
                                         .help("IPv4 gateway address")
-                                        .required(true),
+                                        .required_unless("config"),
                                 )
                                 .arg(
                                     clap::Arg::with_name("addr")
                                         .help("Local address of wireguard tunnel")
-                                        .required(true)
+                                        .required_unless("config")
                                         .multiple(true),
                                 )
+                                .arg(
+                                    clap::Arg::with_name("config")
+                                        .help("Read the relay, keys and addresses from a wg-quick \
+                                               configuration file instead. The first DNS server \
+                                               in the file is used as the gateway unless \
+                                               --gateway is given, and the MTU in the file \
+                                               replaces the WireGuard MTU setting")
+                                        .long("config")
+                                        .takes_value(true)
+                                        .conflicts_with_all(
+                                            &["host", "port", "peer-pubkey", "v4-gateway", "addr"],
+                                        ),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("gateway")
+                                        .help("IPv4 gateway address to use with --config")
+                                        .long("gateway")
+                                        .takes_value(true)
+                                        .requires("config"),
+                                )
                                 .arg(
                                     clap::Arg::with_name("protocol")
                                         .help("Transport protocol. If TCP is selected, traffic is \
@@ -116,7 +143,9 @@ impl Command for Relay {
                     .subcommand(
                         location::get_subcommand()
                             .about("Set country or city to select relays from. Use the 'list' \
-                                   command to show available alternatives.")
+                                   command to show available alternatives. Use --include, \
+                                   --exclude and --exclude-provider to combine several \
+                                   locations.")
                     )
                     .subcommand(
                         clap::SubCommand::with_name("hostname")
@@ -139,6 +168,11 @@ impl Command for Relay {
                                 .required(true)
                             )
                     )
+                    .subcommand(
+                        location::get_ownership_subcommand()
+                            .about("Set whether to select relays that are owned by Mullvad or \
+                                   rented. The 'list' command shows the ownership of each relay.")
+                    )
                     .subcommand(
                         clap::SubCommand::with_name("tunnel")
                             .about("Set tunnel protocol-specific constraints.")
@@ -157,6 +191,18 @@ impl Command for Relay {
                                             .default_value("any")
                                             .possible_values(&["any", "udp", "tcp"]),
                                     )
+                                    .arg(
+                                        clap::Arg::with_name("entry location")
+                                            .help("Relay to enter through, using one of its \
+                                                   bridges. This can be 'any', 'none', or any \
+                                                   location that is valid with 'set location', \
+                                                   such as 'se got'.")
+                                            .default_value("none")
+                                            .long("entry-location")
+                                            .multiple(true)
+                                            .min_values(1)
+                                            .max_values(3),
+                                    )
                             )
                             .subcommand(
                                 clap::SubCommand::with_name("wireguard")
@@ -183,6 +229,26 @@ impl Command for Relay {
                                             .min_values(1)
                                             .max_values(3),
                                     )
+                                    .arg(
+                                        clap::Arg::with_name("intermediate location")
+                                            .help("Additional relay to pass through after the \
+                                                   entry relay, such as 'de fra'. Can be given \
+                                                   several times, in the order that the traffic \
+                                                   should pass through the relays. Ignored \
+                                                   without an entry location.")
+                                            .long("intermediate-location")
+                                            .multiple(true)
+                                            .number_of_values(1),
+                                    )
+                                    .arg(
+                                        clap::Arg::with_name("obfuscation")
+                                            .help("Wrap the traffic to the entry relay in an \
+                                                   obfuscation protocol, for networks that \
+                                                   block WireGuard.")
+                                            .long("obfuscation")
+                                            .default_value("off")
+                                            .possible_values(&["off", "udp2tcp", "shadowsocks"]),
+                                    )
                             )
                     )
                     .subcommand(clap::SubCommand::with_name("tunnel-protocol")
@@ -193,11 +259,99 @@ impl Command for Relay {
                                     .index(1)
                                     .possible_values(&["any", "wireguard", "openvpn", ]),
                                     )
+                                )
+                    .subcommand(clap::SubCommand::with_name("selection-mode")
+                                .about("Set how to pick among the relays matching the constraints")
+                                .arg(
+                                    clap::Arg::with_name("mode")
+                                    .help("'weighted' picks a random relay. 'fastest' prefers \
+                                           the relays with the lowest measured latency. \
+                                           'nearest' prefers the relays closest to this device")
+                                    .required(true)
+                                    .index(1)
+                                    .possible_values(&["weighted", "fastest", "nearest"]),
+                                    )
+                                .arg(
+                                    clap::Arg::with_name("max distance")
+                                    .help("Only prefer the nearest relays if they are within \
+                                           this many kilometers")
+                                    .long("max-distance")
+                                    .takes_value(true),
+                                    )
+                                )
+                    .subcommand(clap::SubCommand::with_name("reconnect-interval")
+                                .about("Reconnect to a different relay matching the constraints \
+                                        after being connected for a while")
+                                .arg(
+                                    clap::Arg::with_name("interval")
+                                    .help("Number of hours between reconnects, from 24 to \
+                                           168, or 'off' to disable scheduled reconnects")
+                                    .required(true)
+                                    .index(1),
+                                    )
                                 ),
             )
             .subcommand(clap::SubCommand::with_name("get"))
             .subcommand(
-                clap::SubCommand::with_name("list").about("List available countries and cities"),
+                clap::SubCommand::with_name("list")
+                    .about("List available countries and cities")
+                    .arg(
+                        clap::Arg::with_name("tunnel protocol")
+                            .help("Only list relays that support this tunnel protocol")
+                            .long("tunnel-protocol")
+                            .takes_value(true)
+                            .possible_values(&["openvpn", "wireguard"]),
+                    )
+                    .arg(
+                        clap::Arg::with_name("provider")
+                            .help("Only list relays hosted by this provider")
+                            .long("provider")
+                            .multiple(true)
+                            .number_of_values(1),
+                    )
+                    .arg(
+                        clap::Arg::with_name("ownership")
+                            .help("Only list Mullvad-owned or rented relays")
+                            .long("ownership")
+                            .takes_value(true)
+                            .possible_values(&["owned", "rented"]),
+                    )
+                    .arg(
+                        clap::Arg::with_name("include inactive")
+                            .help("Also list relays that are not active")
+                            .long("include-inactive"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("ipv6")
+                            .help("Only list relays that have an IPv6 address")
+                            .long("ipv6"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("bridge")
+                            .help("Only list relays that can be used as bridges")
+                            .long("bridge"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("sort")
+                            .help(
+                                "How to sort the relays. 'distance' lists the relays nearest to \
+                                 the current location first, and 'weight' lists the relays with \
+                                 the highest weight first. Both list relays instead of countries \
+                                 and cities.",
+                            )
+                            .long("sort")
+                            .takes_value(true)
+                            .possible_values(&["name", "distance", "weight"])
+                            .default_value("name"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("endpoints")
+                            .help(
+                                "Print the endpoints of each relay, such as ports and WireGuard \
+                                 public keys",
+                            )
+                            .long("endpoints"),
+                    ),
             )
             .subcommand(
                 clap::SubCommand::with_name("update")
@@ -209,9 +363,13 @@ impl Command for Relay {
         if let Some(set_matches) = matches.subcommand_matches("set") {
             self.set(set_matches).await
         } else if matches.subcommand_matches("get").is_some() {
-            self.get().await
-        } else if matches.subcommand_matches("list").is_some() {
-            self.list().await
+            if matches.is_present("json") {
+                self.get_json().await
+            } else {
+                self.get().await
+            }
+        } else if let Some(list_matches) = matches.subcommand_matches("list") {
+            self.list(list_matches).await
         } else if matches.subcommand_matches("update").is_some() {
             self.update().await
         } else {
@@ -239,6 +397,8 @@ impl Relay {
             self.set_hostname(relay_matches).await
         } else if let Some(providers_matches) = matches.subcommand_matches("provider") {
             self.set_providers(providers_matches).await
+        } else if let Some(ownership_matches) = matches.subcommand_matches("ownership") {
+            self.set_ownership(ownership_matches).await
         } else if let Some(matches) = matches.subcommand_matches("tunnel") {
             if let Some(tunnel_matches) = matches.subcommand_matches("openvpn") {
                 self.set_openvpn_constraints(tunnel_matches).await
@@ -249,6 +409,10 @@ impl Relay {
             }
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel-protocol") {
             self.set_tunnel_protocol(tunnel_matches).await
+        } else if let Some(mode_matches) = matches.subcommand_matches("selection-mode") {
+            self.set_selection_mode(mode_matches).await
+        } else if let Some(interval_matches) = matches.subcommand_matches("reconnect-interval") {
+            self.set_reconnect_interval(interval_matches).await
         } else {
             unreachable!("No set relay command given");
         }
@@ -257,6 +421,9 @@ impl Relay {
     async fn set_custom(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let custom_endpoint = match matches.subcommand() {
             ("openvpn", Some(openvpn_matches)) => Self::read_custom_openvpn_relay(openvpn_matches),
+            ("wireguard", Some(wg_matches)) if wg_matches.is_present("config") => {
+                return self.set_custom_wireguard_config(wg_matches).await;
+            }
             ("wireguard", Some(wg_matches)) => Self::read_custom_wireguard_relay(wg_matches),
             (_unknown_tunnel, _) => unreachable!("No set relay command given"),
         };
@@ -289,6 +456,72 @@ impl Relay {
         }
     }
 
+    /// Sets a custom WireGuard relay read from a wg-quick configuration file, along with the MTU
+    /// in the file.
+    async fn set_custom_wireguard_config(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let path = matches.value_of("config").unwrap();
+        let config: WgQuickConfig = fs::read_to_string(path)
+            .map_err(|error| Error::ReadFile(path.to_owned(), error))?
+            .parse()
+            .map_err(Error::ParseWireguardConfig)?;
+        for key in &config.ignored_keys {
+            eprintln!("Ignoring {}, which is not supported", key);
+        }
+
+        let ipv4_gateway = match value_t!(matches.value_of("gateway"), Ipv4Addr) {
+            Ok(gateway) => gateway,
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => config
+                    .dns
+                    .iter()
+                    .find_map(|address| match address {
+                        IpAddr::V4(address) => Some(*address),
+                        IpAddr::V6(_) => None,
+                    })
+                    .ok_or(Error::CommandFailed(
+                        "The configuration has no IPv4 DNS server to use as the gateway. Set the \
+                         gateway with --gateway",
+                    ))?,
+                _ => e.exit(),
+            },
+        };
+        let ipv6_gateway = match value_t!(matches.value_of("v6-gateway"), Ipv6Addr) {
+            Ok(gateway) => Some(gateway),
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => {
+                    config.dns.iter().find_map(|address| match address {
+                        IpAddr::V4(_) => None,
+                        IpAddr::V6(address) => Some(*address),
+                    })
+                }
+                _ => e.exit(),
+            },
+        };
+        let protocol = value_t!(
+            matches.value_of("protocol"),
+            talpid_types::net::TransportProtocol
+        )
+        .unwrap_or_else(|e| e.exit());
+        let endpoint = config.to_custom_tunnel_endpoint(protocol, ipv4_gateway, ipv6_gateway);
+
+        self.update_constraints(RelaySettingsUpdate {
+            r#type: Some(relay_settings_update::Type::Custom(CustomRelaySettings {
+                host: endpoint.host,
+                config: Some(ConnectionConfig::from(endpoint.config)),
+            })),
+        })
+        .await?;
+        // Only change the MTU once the relay has been accepted
+        if let Some(mtu) = config.mtu {
+            let mut rpc = new_rpc_client().await?;
+            rpc.set_wireguard_mtu(u32::from(mtu))
+                .await
+                .map_err(|error| Error::RpcFailedExt("Failed to set WireGuard MTU", error))?;
+            println!("Wireguard MTU has been updated");
+        }
+        Ok(())
+    }
+
     fn read_custom_wireguard_relay(matches: &clap::ArgMatches<'_>) -> CustomRelaySettings {
         use connection_config::wireguard_config;
 
@@ -337,6 +570,7 @@ impl Relay {
                         endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)
                             .to_string(),
                         protocol: protocol as i32,
+                        psk: vec![],
                     }),
                     ipv4_gateway: ipv4_gateway.to_string(),
                     ipv6_gateway: ipv6_gateway
@@ -428,6 +662,7 @@ impl Relay {
 
     async fn set_location(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let location_constraint = location::get_constraint_from_args(matches);
+        let full_constraint = location::get_location_constraint_from_args(matches).await?;
         let mut found = false;
 
         if !location_constraint.country.is_empty() {
@@ -476,6 +711,7 @@ impl Relay {
             r#type: Some(relay_settings_update::Type::Normal(
                 NormalRelaySettingsUpdate {
                     location: Some(location_constraint),
+                    location_constraint: Some(full_constraint),
                     ..Default::default()
                 },
             )),
@@ -507,6 +743,8 @@ impl Relay {
     async fn set_openvpn_constraints(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let port = parse_port_constraint(matches.value_of("port").unwrap())?;
         let protocol = parse_protocol_constraint(matches.value_of("transport protocol").unwrap());
+        let entry_location =
+            parse_entry_location_constraint(matches.values_of("entry location").unwrap());
 
         self.update_constraints(RelaySettingsUpdate {
             r#type: Some(relay_settings_update::Type::Normal(
@@ -518,6 +756,8 @@ impl Relay {
                             .map(|protocol| TransportProtocolConstraint {
                                 protocol: protocol as i32,
                             }),
+                        entry_location,
+                        entry_location_constraint: None,
                     }),
                     ..Default::default()
                 },
@@ -526,11 +766,31 @@ impl Relay {
         .await
     }
 
+    async fn set_ownership(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let ownership =
+            location::parse_ownership_constraint(matches.value_of("ownership").unwrap());
+
+        self.update_constraints(RelaySettingsUpdate {
+            r#type: Some(relay_settings_update::Type::Normal(
+                NormalRelaySettingsUpdate {
+                    ownership: Some(OwnershipUpdate { ownership }),
+                    ..Default::default()
+                },
+            )),
+        })
+        .await
+    }
+
     async fn set_wireguard_constraints(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let port = parse_port_constraint(matches.value_of("port").unwrap())?;
         let ip_version = parse_ip_version_constraint(matches.value_of("ip version").unwrap());
         let entry_location =
             parse_entry_location_constraint(matches.values_of("entry location").unwrap());
+        let intermediate_locations = matches
+            .values_of("intermediate location")
+            .map(|locations| locations.map(parse_intermediate_location).collect())
+            .unwrap_or_default();
+        let obfuscation = parse_obfuscation_constraint(matches.value_of("obfuscation").unwrap());
 
         self.update_constraints(RelaySettingsUpdate {
             r#type: Some(relay_settings_update::Type::Normal(
@@ -541,6 +801,11 @@ impl Relay {
                             protocol: protocol as i32,
                         }),
                         entry_location,
+                        entry_location_constraint: None,
+                        obfuscation: obfuscation.map(|obfuscation_type| ObfuscationConstraint {
+                            obfuscation_type: obfuscation_type as i32,
+                        }),
+                        intermediate_locations,
                     }),
                     ..Default::default()
                 },
@@ -571,14 +836,51 @@ impl Relay {
         .await
     }
 
+    async fn set_selection_mode(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let mode = match matches.value_of("mode").unwrap() {
+            "weighted" => RelaySelectionModeType::Weighted as i32,
+            "fastest" => RelaySelectionModeType::Fastest as i32,
+            "nearest" => RelaySelectionModeType::Nearest as i32,
+            _ => unreachable!(),
+        };
+        let max_distance = match value_t!(matches.value_of("max distance"), u32) {
+            Ok(max_distance) => Some(max_distance),
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => None,
+                _ => e.exit(),
+            },
+        };
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_relay_selection_mode(RelaySelectionMode { mode, max_distance })
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to set relay selection mode", error))?;
+        println!("Relay selection mode updated");
+        Ok(())
+    }
+
+    async fn set_reconnect_interval(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        if matches.value_of("interval").unwrap() == "off" {
+            rpc.reset_reconnect_interval(())
+                .await
+                .map_err(|error| Error::RpcFailedExt("Failed to disable reconnects", error))?;
+            println!("Scheduled reconnects disabled");
+        } else {
+            let hours = value_t!(matches.value_of("interval"), u64).unwrap_or_else(|e| e.exit());
+            rpc.set_reconnect_interval(Duration::from(std::time::Duration::from_secs(
+                60 * 60 * hours,
+            )))
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to set reconnect interval", error))?;
+            println!("Reconnect interval updated");
+        }
+        Ok(())
+    }
+
     async fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
-        let constraints = rpc
-            .get_settings(())
-            .await?
-            .into_inner()
-            .relay_settings
-            .unwrap();
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let constraints = settings.relay_settings.unwrap();
 
         print!("Current constraints: ");
 
@@ -586,30 +888,37 @@ impl Relay {
             relay_settings::Endpoint::Normal(settings) => match settings.tunnel_type {
                 None => {
                     println!(
-                        "Any tunnel protocol with OpenVPN over {} and WireGuard over {} in {} using {}",
+                        "Any tunnel protocol with OpenVPN over {} and WireGuard over {} in {} using {}{}",
                         Self::format_openvpn_constraints(settings.openvpn_constraints.as_ref()),
                         Self::format_wireguard_constraints(settings.wireguard_constraints.as_ref()),
-                        location::format_location(settings.location.as_ref()),
-                        location::format_providers(&settings.providers)
+                        location::format_location_constraint(settings.location_constraint.as_ref()),
+                        location::format_providers(&settings.providers),
+                        location::format_ownership(settings.ownership.as_ref())
                     );
                 }
                 Some(constraint) => match TunnelType::from_i32(constraint.tunnel_type).unwrap() {
                     TunnelType::Wireguard => {
                         println!(
-                            "WireGuard over {} in {} using {}",
+                            "WireGuard over {} in {} using {}{}",
                             Self::format_wireguard_constraints(
                                 settings.wireguard_constraints.as_ref()
                             ),
-                            location::format_location(settings.location.as_ref()),
-                            location::format_providers(&settings.providers)
+                            location::format_location_constraint(
+                                settings.location_constraint.as_ref()
+                            ),
+                            location::format_providers(&settings.providers),
+                            location::format_ownership(settings.ownership.as_ref())
                         );
                     }
                     TunnelType::Openvpn => {
                         println!(
-                            "OpenVPN over {} in {} using {}",
+                            "OpenVPN over {} in {} using {}{}",
                             Self::format_openvpn_constraints(settings.openvpn_constraints.as_ref()),
-                            location::format_location(settings.location.as_ref()),
-                            location::format_providers(&settings.providers)
+                            location::format_location_constraint(
+                                settings.location_constraint.as_ref()
+                            ),
+                            location::format_providers(&settings.providers),
+                            location::format_ownership(settings.ownership.as_ref())
                         );
                     }
                 },
@@ -639,52 +948,202 @@ impl Relay {
             }
         }
 
+        if let Some(mode) = settings.relay_selection_mode {
+            Self::print_selection_mode(mode);
+        }
+        if let Some(interval) = settings.reconnect_interval {
+            let interval = std::time::Duration::try_from(interval).unwrap();
+            println!(
+                "Reconnect to a different relay every {} hour(s)",
+                interval.as_secs() / 60 / 60
+            );
+        }
+
         Ok(())
     }
 
-    async fn list(&self) -> Result<()> {
-        let mut countries = Self::get_filtered_relays().await?;
-        countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-        for mut country in countries {
-            country
-                .cities
-                .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-            println!("{} ({})", country.name, country.code);
-            for mut city in country.cities {
-                city.relays
-                    .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
-                println!(
-                    "\t{} ({}) @ {:.5}°N, {:.5}°W",
-                    city.name, city.code, city.latitude, city.longitude
-                );
-                for relay in &city.relays {
-                    let tunnels = relay.tunnels.as_ref().unwrap();
-                    let supports_openvpn = !tunnels.openvpn.is_empty();
-                    let supports_wireguard = !tunnels.wireguard.is_empty();
-                    let support_msg = match (supports_openvpn, supports_wireguard) {
-                        (true, true) => "OpenVPN and WireGuard",
-                        (true, false) => "OpenVPN",
-                        (false, true) => "WireGuard",
-                        _ => unreachable!("Bug in relay filtering earlier on"),
-                    };
-                    let mut addresses = vec![&relay.ipv4_addr_in];
-                    if !relay.ipv6_addr_in.is_empty() {
-                        addresses.push(&relay.ipv6_addr_in);
-                    }
+    async fn get_json(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        format::print_json(&serde_json::json!({
+            "relay_settings": settings.relay_settings,
+            "relay_selection_mode": settings.relay_selection_mode,
+            "reconnect_interval": settings.reconnect_interval.map(|interval| interval.seconds),
+        }))
+    }
+
+    fn print_selection_mode(mode: RelaySelectionMode) {
+        let mode_type =
+            RelaySelectionModeType::from_i32(mode.mode).expect("unknown relay selection mode");
+        match (mode_type, mode.max_distance) {
+            (RelaySelectionModeType::Weighted, _) => println!("Relay selection mode: weighted"),
+            (RelaySelectionModeType::Fastest, _) => println!("Relay selection mode: fastest"),
+            (RelaySelectionModeType::Nearest, Some(max_distance)) => {
+                println!("Relay selection mode: nearest within {} km", max_distance)
+            }
+            (RelaySelectionModeType::Nearest, None) => println!("Relay selection mode: nearest"),
+        }
+    }
+
+    async fn list(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let filter = RelayListFilter::from_args(matches);
+        let json = matches.is_present("json");
+        let endpoints = matches.is_present("endpoints");
+
+        let mut countries = Self::get_relay_list().await?;
+        retain_relays(&mut countries, |relay| filter.matches(relay));
+        location::sort_countries(&mut countries);
+
+        let sort = matches.value_of("sort").unwrap();
+        if sort == "name" {
+            if json {
+                return format::print_json(&countries);
+            }
+            for country in countries {
+                println!("{} ({})", country.name, country.code);
+                for city in country.cities {
                     println!(
-                        "\t\t{} ({}) - {}, hosted by {}",
-                        relay.hostname,
-                        addresses.iter().join(", "),
-                        support_msg,
-                        relay.provider
+                        "\t{} ({}) @ {:.5}°N, {:.5}°W",
+                        city.name, city.code, city.latitude, city.longitude
                     );
+                    for relay in &city.relays {
+                        println!("\t\t{}", Self::format_relay(relay));
+                        if endpoints {
+                            Self::print_relay_endpoints(relay, "\t\t\t");
+                        }
+                    }
                 }
+                println!();
+            }
+            return Ok(());
+        }
+
+        let relays = flatten_relays(countries);
+        let relays: Vec<(types::Relay, String)> = if sort == "distance" {
+            let mut rpc = new_rpc_client().await?;
+            let current = location::get_current_location(&mut rpc)
+                .await?
+                .ok_or(Error::CommandFailed("The current location is unknown"))?;
+            sort_relays_by_distance(relays, current.latitude, current.longitude)
+                .into_iter()
+                .map(|(relay, distance)| (relay, format!("{:.0} km", distance)))
+                .collect()
+        } else {
+            sort_relays_by_weight(relays)
+                .into_iter()
+                .map(|relay| {
+                    let sort_key = format!("weight {}", relay.weight);
+                    (relay, sort_key)
+                })
+                .collect()
+        };
+
+        if json {
+            let relays: Vec<&types::Relay> = relays.iter().map(|(relay, _)| relay).collect();
+            return format::print_json(&relays);
+        }
+        for (relay, sort_key) in &relays {
+            let location = match &relay.location {
+                Some(location) => location,
+                None => continue,
+            };
+            println!(
+                "{} - {}, {} - {}",
+                Self::format_relay(relay),
+                location.city,
+                location.country,
+                sort_key
+            );
+            if endpoints {
+                Self::print_relay_endpoints(relay, "\t");
             }
-            println!();
         }
         Ok(())
     }
 
+    fn format_relay(relay: &types::Relay) -> String {
+        let mut addresses = vec![&relay.ipv4_addr_in];
+        if !relay.ipv6_addr_in.is_empty() {
+            addresses.push(&relay.ipv6_addr_in);
+        }
+        let mut line = format!(
+            "{} ({}) - {}, hosted by {} ({})",
+            relay.hostname,
+            addresses.iter().join(", "),
+            Self::format_relay_support(relay),
+            relay.provider,
+            location::format_relay_ownership(relay.owned)
+        );
+        if !relay.active {
+            line.push_str(" - inactive");
+        }
+        line
+    }
+
+    /// Returns a description of what the relay can be used for, such as "OpenVPN and WireGuard".
+    fn format_relay_support(relay: &types::Relay) -> String {
+        let mut supported = Vec::new();
+        if let Some(tunnels) = &relay.tunnels {
+            if !tunnels.openvpn.is_empty() {
+                supported.push("OpenVPN");
+            }
+            if !tunnels.wireguard.is_empty() {
+                supported.push("WireGuard");
+            }
+        }
+        if has_bridges(relay) {
+            supported.push("bridge");
+        }
+        match supported.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+            None => "no tunnels".to_string(),
+        }
+    }
+
+    fn print_relay_endpoints(relay: &types::Relay, indent: &str) {
+        if let Some(tunnels) = &relay.tunnels {
+            if !tunnels.openvpn.is_empty() {
+                let endpoints = tunnels.openvpn.iter().map(|endpoint| {
+                    format!(
+                        "{}/{}",
+                        endpoint.port,
+                        Self::format_transport_protocol(TransportProtocol::from_i32(
+                            endpoint.protocol
+                        ))
+                    )
+                });
+                println!("{}OpenVPN: {}", indent, endpoints.format(", "));
+            }
+            for endpoint in &tunnels.wireguard {
+                let port_ranges = endpoint.port_ranges.iter().map(|range| {
+                    if range.first == range.last {
+                        range.first.to_string()
+                    } else {
+                        format!("{}-{}", range.first, range.last)
+                    }
+                });
+                println!(
+                    "{}WireGuard: public key {}, ports {}",
+                    indent,
+                    base64::encode(&endpoint.public_key),
+                    port_ranges.format(", ")
+                );
+            }
+        }
+        if let Some(bridges) = &relay.bridges {
+            for endpoint in &bridges.shadowsocks {
+                println!(
+                    "{}Shadowsocks: {}/{} with cipher {}",
+                    indent,
+                    endpoint.port,
+                    Self::format_transport_protocol(TransportProtocol::from_i32(endpoint.protocol)),
+                    endpoint.cipher
+                );
+            }
+        }
+    }
+
     async fn update(&self) -> Result<()> {
         new_rpc_client().await?.update_relay_locations(()).await?;
         println!("Updating relay list in the background...");
@@ -717,7 +1176,7 @@ impl Relay {
 
     fn format_openvpn_constraints(constraints: Option<&OpenvpnConstraints>) -> String {
         if let Some(constraints) = constraints {
-            format!(
+            let mut out = format!(
                 "{} over {}",
                 Self::format_port(constraints.port),
                 Self::format_transport_protocol(
@@ -726,7 +1185,18 @@ impl Relay {
                         .clone()
                         .map(|protocol| TransportProtocol::from_i32(protocol.protocol).unwrap())
                 )
-            )
+            );
+
+            if let Some(ref entry) = constraints.entry_location_constraint {
+                write!(
+                    &mut out,
+                    " (via {})",
+                    location::format_location_constraint(Some(entry))
+                )
+                .unwrap();
+            }
+
+            out
         } else {
             "any port over any transport protocol".to_string()
         }
@@ -745,11 +1215,30 @@ impl Relay {
                 )
             );
 
-            if let Some(ref entry) = constraints.entry_location {
+            if let Some(ref entry) = constraints.entry_location_constraint {
                 write!(
                     &mut out,
-                    " (via {})",
-                    location::format_location(Some(entry))
+                    " (via {}",
+                    location::format_location_constraint(Some(entry))
+                )
+                .unwrap();
+                for intermediate in &constraints.intermediate_locations {
+                    write!(
+                        &mut out,
+                        " and {}",
+                        location::format_location_constraint(Some(intermediate))
+                    )
+                    .unwrap();
+                }
+                out.push(')');
+            }
+
+            if let Some(ref obfuscation) = constraints.obfuscation {
+                let obfuscation = ObfuscationType::from_i32(obfuscation.obfuscation_type).unwrap();
+                write!(
+                    &mut out,
+                    " obfuscated by {}",
+                    talpid_types::net::obfuscation::ObfuscationType::from(obfuscation)
                 )
                 .unwrap();
             }
@@ -760,7 +1249,19 @@ impl Relay {
         }
     }
 
+    /// Returns the relays that can be used as tunnel endpoints.
     async fn get_filtered_relays() -> Result<Vec<RelayListCountry>> {
+        let mut countries = Self::get_relay_list().await?;
+        retain_relays(&mut countries, |relay| {
+            relay.active
+                && relay.tunnels.is_some()
+                && !(relay.tunnels.as_ref().unwrap().openvpn.is_empty()
+                    && relay.tunnels.as_ref().unwrap().wireguard.is_empty())
+        });
+        Ok(countries)
+    }
+
+    async fn get_relay_list() -> Result<Vec<RelayListCountry>> {
         let mut rpc = new_rpc_client().await?;
         let mut locations = rpc
             .get_relay_locations(())
@@ -769,32 +1270,134 @@ impl Relay {
             .into_inner();
 
         let mut countries = Vec::new();
+        while let Some(country) = locations.message().await? {
+            countries.push(country);
+        }
+        Ok(countries)
+    }
+}
 
-        while let Some(mut country) = locations.message().await? {
-            country.cities = country
-                .cities
-                .into_iter()
-                .filter_map(|mut city| {
-                    city.relays.retain(|relay| {
-                        relay.active
-                            && relay.tunnels.is_some()
-                            && !(relay.tunnels.as_ref().unwrap().openvpn.is_empty()
-                                && relay.tunnels.as_ref().unwrap().wireguard.is_empty())
+/// Filters applied by `relay list`.
+struct RelayListFilter {
+    tunnel_type: Option<TunnelType>,
+    providers: Vec<String>,
+    owned: Option<bool>,
+    include_inactive: bool,
+    ipv6: bool,
+    bridge: bool,
+}
+
+impl RelayListFilter {
+    fn from_args(matches: &clap::ArgMatches<'_>) -> Self {
+        RelayListFilter {
+            tunnel_type: matches
+                .value_of("tunnel protocol")
+                .map(|protocol| match protocol {
+                    "openvpn" => TunnelType::Openvpn,
+                    "wireguard" => TunnelType::Wireguard,
+                    _ => unreachable!("invalid tunnel protocol"),
+                }),
+            providers: matches
+                .values_of("provider")
+                .map(|providers| providers.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            owned: matches
+                .value_of("ownership")
+                .map(|ownership| ownership == "owned"),
+            include_inactive: matches.is_present("include inactive"),
+            ipv6: matches.is_present("ipv6"),
+            bridge: matches.is_present("bridge"),
+        }
+    }
+
+    /// Returns whether the relay should be listed. Relays that cannot be used as tunnel endpoints
+    /// are only listed along with bridges.
+    fn matches(&self, relay: &types::Relay) -> bool {
+        let (openvpn, wireguard) = match &relay.tunnels {
+            Some(tunnels) => (!tunnels.openvpn.is_empty(), !tunnels.wireguard.is_empty()),
+            None => (false, false),
+        };
+        let supports_tunnel = match self.tunnel_type {
+            None => openvpn || wireguard || self.bridge,
+            Some(TunnelType::Openvpn) => openvpn,
+            Some(TunnelType::Wireguard) => wireguard,
+        };
+        supports_tunnel
+            && (relay.active || self.include_inactive)
+            && (!self.bridge || has_bridges(relay))
+            && (!self.ipv6 || !relay.ipv6_addr_in.is_empty())
+            && self.owned.map(|owned| owned == relay.owned).unwrap_or(true)
+            && (self.providers.is_empty() || self.providers.contains(&relay.provider))
+    }
+}
+
+fn has_bridges(relay: &types::Relay) -> bool {
+    relay
+        .bridges
+        .as_ref()
+        .map(|bridges| !bridges.shadowsocks.is_empty())
+        .unwrap_or(false)
+}
+
+/// Removes the relays that `keep` returns `false` for, along with cities and countries that
+/// become empty.
+fn retain_relays(countries: &mut Vec<RelayListCountry>, keep: impl Fn(&types::Relay) -> bool) {
+    for country in countries.iter_mut() {
+        for city in &mut country.cities {
+            city.relays.retain(|relay| keep(relay));
+        }
+        country.cities.retain(|city| !city.relays.is_empty());
+    }
+    countries.retain(|country| !country.cities.is_empty());
+}
+
+/// Returns all relays, with their locations set.
+fn flatten_relays(countries: Vec<RelayListCountry>) -> Vec<types::Relay> {
+    let mut relays = Vec::new();
+    for country in countries {
+        for city in country.cities {
+            for mut relay in city.relays {
+                if relay.location.is_none() {
+                    relay.location = Some(Location {
+                        country: country.name.clone(),
+                        country_code: country.code.clone(),
+                        city: city.name.clone(),
+                        city_code: city.code.clone(),
+                        latitude: city.latitude,
+                        longitude: city.longitude,
                     });
-                    if !city.relays.is_empty() {
-                        Some(city)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            if !country.cities.is_empty() {
-                countries.push(country);
+                }
+                relays.push(relay);
             }
         }
-
-        Ok(countries)
     }
+    relays
+}
+
+/// Returns the relays with a known location, nearest first, along with their distance in
+/// kilometers from the given coordinates.
+fn sort_relays_by_distance(
+    relays: Vec<types::Relay>,
+    latitude: f64,
+    longitude: f64,
+) -> Vec<(types::Relay, f64)> {
+    let mut relays: Vec<(types::Relay, f64)> = relays
+        .into_iter()
+        .filter_map(|relay| {
+            let location = relay.location.as_ref()?;
+            let distance =
+                haversine_dist_deg(latitude, longitude, location.latitude, location.longitude);
+            Some((relay, distance))
+        })
+        .collect();
+    relays.sort_by_key(|(_, distance)| (distance * 1000.0) as i64);
+    relays
+}
+
+/// Returns the relays ordered by descending weight.
+fn sort_relays_by_weight(mut relays: Vec<types::Relay>) -> Vec<types::Relay> {
+    relays.sort_by_key(|relay| std::cmp::Reverse(relay.weight));
+    relays
 }
 
 
@@ -827,6 +1430,15 @@ fn parse_ip_version_constraint(raw_protocol: &str) -> Constraint<IpVersion> {
     }
 }
 
+fn parse_obfuscation_constraint(raw_obfuscation: &str) -> Option<ObfuscationType> {
+    match raw_obfuscation {
+        "off" => None,
+        "udp2tcp" => Some(ObfuscationType::Udp2tcp),
+        "shadowsocks" => Some(ObfuscationType::Shadowsocks),
+        _ => unreachable!(),
+    }
+}
+
 fn parse_entry_location_constraint<'a, T: Iterator<Item = &'a str>>(
     mut location: T,
 ) -> Option<RelayLocation> {
@@ -842,3 +1454,199 @@ fn parse_entry_location_constraint<'a, T: Iterator<Item = &'a str>>(
         location.next(),
     ))
 }
+
+/// Parses a location given as a single `<country> [city] [hostname]` value.
+fn parse_intermediate_location(location: &str) -> LocationConstraint {
+    let mut parts = location.split_whitespace();
+    let country = parts.next().unwrap_or("any");
+    let location = location::get_constraint(country, parts.next(), parts.next());
+    if parts.next().is_some() {
+        clap::Error::with_description(
+            "Invalid country, city and hostname combination given",
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit();
+    }
+    LocationConstraint {
+        include: if location == RelayLocation::default() {
+            vec![]
+        } else {
+            vec![location]
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_management_interface::types::{
+        OpenVpnEndpointData, RelayBridges, RelayTunnels, ShadowsocksEndpointData,
+        WireguardEndpointData,
+    };
+
+    fn relay(hostname: &str, openvpn: bool, wireguard: bool, bridge: bool) -> types::Relay {
+        types::Relay {
+            hostname: hostname.to_owned(),
+            active: true,
+            owned: true,
+            provider: "Provider".to_owned(),
+            tunnels: Some(RelayTunnels {
+                openvpn: if openvpn {
+                    vec![OpenVpnEndpointData::default()]
+                } else {
+                    vec![]
+                },
+                wireguard: if wireguard {
+                    vec![WireguardEndpointData::default()]
+                } else {
+                    vec![]
+                },
+            }),
+            bridges: Some(RelayBridges {
+                shadowsocks: if bridge {
+                    vec![ShadowsocksEndpointData::default()]
+                } else {
+                    vec![]
+                },
+            }),
+            ..types::Relay::default()
+        }
+    }
+
+    fn located(relay: types::Relay, latitude: f64, longitude: f64) -> types::Relay {
+        types::Relay {
+            location: Some(Location {
+                latitude,
+                longitude,
+                ..Location::default()
+            }),
+            ..relay
+        }
+    }
+
+    fn filter() -> RelayListFilter {
+        RelayListFilter {
+            tunnel_type: None,
+            providers: vec![],
+            owned: None,
+            include_inactive: false,
+            ipv6: false,
+            bridge: false,
+        }
+    }
+
+    fn hostnames(relays: &[types::Relay]) -> Vec<&str> {
+        relays.iter().map(|relay| relay.hostname.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter_tunnel_types() {
+        let openvpn = relay("openvpn", true, false, false);
+        let wireguard = relay("wireguard", false, true, false);
+        let bridge = relay("bridge", false, false, true);
+
+        let mut filter = filter();
+        assert!(filter.matches(&openvpn) && filter.matches(&wireguard));
+        assert!(!filter.matches(&bridge));
+
+        filter.tunnel_type = Some(TunnelType::Wireguard);
+        assert!(!filter.matches(&openvpn) && filter.matches(&wireguard));
+
+        filter.tunnel_type = None;
+        filter.bridge = true;
+        assert!(!filter.matches(&openvpn) && !filter.matches(&wireguard));
+        assert!(filter.matches(&bridge));
+    }
+
+    #[test]
+    fn test_filter_relay_properties() {
+        let relay = relay("relay", true, true, false);
+        let mut filter = filter();
+
+        let inactive = types::Relay {
+            active: false,
+            ..relay.clone()
+        };
+        assert!(!filter.matches(&inactive));
+        filter.include_inactive = true;
+        assert!(filter.matches(&inactive));
+
+        filter.ipv6 = true;
+        assert!(!filter.matches(&relay));
+        let ipv6 = types::Relay {
+            ipv6_addr_in: "2001:db8::1".to_owned(),
+            ..relay.clone()
+        };
+        assert!(filter.matches(&ipv6));
+        filter.ipv6 = false;
+
+        filter.owned = Some(false);
+        assert!(!filter.matches(&relay));
+        filter.owned = Some(true);
+        assert!(filter.matches(&relay));
+
+        filter.providers = vec!["Other".to_owned()];
+        assert!(!filter.matches(&relay));
+        filter.providers.push("Provider".to_owned());
+        assert!(filter.matches(&relay));
+    }
+
+    #[test]
+    fn test_sort_relays_by_distance() {
+        let relays = vec![
+            located(relay("far", true, false, false), 10.0, 10.0),
+            relay("unknown", true, false, false),
+            located(relay("near", true, false, false), 1.0, 1.0),
+            located(relay("here", true, false, false), 0.0, 0.0),
+        ];
+        let sorted = sort_relays_by_distance(relays, 0.0, 0.0);
+        let relays: Vec<types::Relay> = sorted.iter().map(|(relay, _)| relay.clone()).collect();
+        assert_eq!(hostnames(&relays), vec!["here", "near", "far"]);
+        assert_eq!(sorted[0].1, 0.0);
+        assert!(sorted[1].1 > 150.0 && sorted[1].1 < 160.0);
+    }
+
+    #[test]
+    fn test_sort_relays_by_weight() {
+        let relays = vec![
+            types::Relay {
+                weight: 100,
+                ..relay("light", true, false, false)
+            },
+            types::Relay {
+                weight: 500,
+                ..relay("heavy", true, false, false)
+            },
+        ];
+        assert_eq!(
+            hostnames(&sort_relays_by_weight(relays)),
+            vec!["heavy", "light"]
+        );
+    }
+
+    #[test]
+    fn test_format_relay_support() {
+        assert_eq!(
+            Relay::format_relay_support(&relay("relay", true, false, false)),
+            "OpenVPN"
+        );
+        assert_eq!(
+            Relay::format_relay_support(&relay("relay", true, true, false)),
+            "OpenVPN and WireGuard"
+        );
+        assert_eq!(
+            Relay::format_relay_support(&relay("relay", true, true, true)),
+            "OpenVPN, WireGuard and bridge"
+        );
+        assert_eq!(
+            Relay::format_relay_support(&relay("relay", false, false, false)),
+            "no tunnels"
+        );
+        let without_tunnels = types::Relay {
+            tunnels: None,
+            ..relay("relay", false, false, true)
+        };
+        assert_eq!(Relay::format_relay_support(&without_tunnels), "bridge");
+    }
+}
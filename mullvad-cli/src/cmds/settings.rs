@@ -0,0 +1,109 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types::SettingsImport;
+use std::fs;
+
+pub struct Settings;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Settings {
+    fn name(&self) -> &'static str {
+        "settings"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Export or import all settings of the daemon")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("export")
+                    .about("Write the current settings to a file")
+                    .arg(
+                        clap::Arg::with_name("file")
+                            .help("The file to write the settings to")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("include-account-token")
+                            .long("include-account-token")
+                            .help("Include the account token in the exported settings"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("import")
+                    .about(
+                        "Replace the current settings with the ones in a file written by \
+                         \"export\", possibly by an older version",
+                    )
+                    .arg(
+                        clap::Arg::with_name("file")
+                            .help("The file to read the settings from")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("include-account-token")
+                            .long("include-account-token")
+                            .help(
+                                "Also replace the account token with the one in the file. \
+                                 The current account token is kept otherwise",
+                            ),
+                    ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("export", Some(matches)) => {
+                Self::export(
+                    matches.value_of("file").unwrap(),
+                    matches.is_present("include-account-token"),
+                )
+                .await
+            }
+            ("import", Some(matches)) => {
+                Self::import(
+                    matches.value_of("file").unwrap(),
+                    matches.is_present("include-account-token"),
+                )
+                .await
+            }
+            _ => unreachable!("No settings command given"),
+        }
+    }
+}
+
+impl Settings {
+    async fn export(path: &str, include_account_token: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc
+            .export_settings(include_account_token)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to export settings", error))?
+            .into_inner();
+        fs::write(path, settings).map_err(|error| Error::WriteFile(path.to_owned(), error))?;
+        println!("Exported settings to {}", path);
+        Ok(())
+    }
+
+    async fn import(path: &str, include_account_token: bool) -> Result<()> {
+        let settings =
+            fs::read_to_string(path).map_err(|error| Error::ReadFile(path.to_owned(), error))?;
+        let mut rpc = new_rpc_client().await?;
+        let result = rpc
+            .import_settings(SettingsImport {
+                settings,
+                include_account_token,
+            })
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to import settings", error))?
+            .into_inner();
+        if result.changed_fields.is_empty() {
+            println!("Imported settings from {}. Nothing changed", path);
+        } else {
+            println!("Imported settings from {}. Changed settings:", path);
+            for field in result.changed_fields {
+                println!("    {}", field);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
-use crate::{format, format::print_keygen_event, new_rpc_client, Command, Error, Result};
+use crate::{
+    format,
+    format::{print_keygen_event, print_network_rule_event},
+    location, new_rpc_client, Command, Error, Result,
+};
 use mullvad_management_interface::{
     types::daemon_event::Event as EventType, ManagementServiceClient,
 };
+use std::io::{self, Write};
 
 pub struct Status;
 
@@ -20,6 +25,11 @@ impl Command for Status {
                     .short("l")
                     .help("Prints the current location and IP. Based on GeoIP lookups"),
             )
+            .arg(
+                clap::Arg::with_name("stats")
+                    .long("stats")
+                    .help("Continuously prints the traffic statistics of the tunnel"),
+            )
             .subcommand(
                 clap::SubCommand::with_name("listen")
                     .about("Listen for VPN tunnel state changes")
@@ -29,15 +39,62 @@ impl Command for Status {
                             .help("Enables verbose output"),
                     ),
             )
+            .subcommand(
+                clap::SubCommand::with_name("history")
+                    .about("Show the tunnel states that have been entered, oldest first"),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
+        let json = matches.is_present("json");
+
+        if matches.subcommand_matches("history").is_some() {
+            let history = rpc.get_connection_history(()).await?.into_inner();
+            if json {
+                return format::print_json(&history);
+            }
+            for entry in history.entries {
+                println!(
+                    "{}  {}",
+                    format::format_timestamp(entry.timestamp.as_ref().unwrap()),
+                    format::format_state(entry.state.as_ref().unwrap())
+                );
+            }
+            return Ok(());
+        }
+
         let state = rpc.get_tunnel_state(()).await?.into_inner();
 
-        format::print_state(&state);
-        if matches.is_present("location") {
-            print_location(&mut rpc).await?;
+        if json {
+            let mut status = serde_json::json!({ "tunnel_state": state });
+            if matches.is_present("location") {
+                status["location"] =
+                    serde_json::to_value(location::get_current_location(&mut rpc).await?)
+                        .map_err(Error::SerializeJson)?;
+            }
+            format::print_json(&status)?;
+        } else {
+            format::print_state(&state);
+            if matches.is_present("location") {
+                print_location(&mut rpc).await?;
+            }
+        }
+
+        if matches.is_present("stats") {
+            let mut stats = rpc.traffic_stats_listen(()).await?.into_inner();
+            while let Some(stats) = stats.message().await? {
+                if json {
+                    format::print_json(&stats)?;
+                    continue;
+                }
+                print!("\r\x1b[K{}", format::format_traffic_stats(&stats));
+                let _ = io::stdout().flush();
+            }
+            if !json {
+                println!();
+            }
+            return Ok(());
         }
 
         if let Some(listen_matches) = matches.subcommand_matches("listen") {
@@ -46,6 +103,10 @@ impl Command for Status {
             let mut events = rpc.events_listen(()).await?.into_inner();
 
             while let Some(event) = events.message().await? {
+                if json {
+                    format::print_json(&event)?;
+                    continue;
+                }
                 match event.event.unwrap() {
                     EventType::TunnelState(new_state) => {
                         format::print_state(&new_state);
@@ -80,6 +141,9 @@ impl Command for Status {
                             print_keygen_event(&key_event);
                         }
                     }
+                    EventType::NetworkRule(event) => {
+                        print_network_rule_event(&event);
+                    }
                 }
             }
         }
@@ -89,21 +153,28 @@ impl Command for Status {
 }
 
 async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
-    let location = rpc.get_current_location(()).await;
-    let location = match location {
-        Ok(response) => response.into_inner(),
-        Err(status) => {
-            if status.code() == mullvad_management_interface::Code::NotFound {
-                println!("Location data unavailable");
-                return Ok(());
-            } else {
-                return Err(Error::RpcFailed(status));
-            }
+    let location = match location::get_current_location(rpc).await? {
+        Some(location) => location,
+        None => {
+            println!("Location data unavailable");
+            return Ok(());
         }
     };
     if !location.hostname.is_empty() {
         println!("Relay: {}", location.hostname);
     }
+    if !location.entry_hostname.is_empty() {
+        println!("Entry relay: {}", location.entry_hostname);
+    }
+    if !location.intermediate_hostnames.is_empty() {
+        println!(
+            "Intermediate relays: {}",
+            location.intermediate_hostnames.join(", ")
+        );
+    }
+    if !location.bridge_hostname.is_empty() {
+        println!("Bridge: {}", location.bridge_hostname);
+    }
     if !location.ipv4.is_empty() {
         println!("IPv4: {}", location.ipv4);
     }
@@ -0,0 +1,508 @@
+use crate::{format, location, new_rpc_client, Command, Error, Result};
+use crossterm::{
+    cursor,
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use futures::{pin_mut, stream, StreamExt};
+use mullvad_management_interface::{
+    types::{
+        daemon_event::Event as EventType, relay_settings_update, tunnel_state::State, DaemonEvent,
+        GeoIpLocation, LocationConstraint, NormalRelaySettingsUpdate, RelayLocation,
+        RelaySettingsUpdate, Timestamp, TrafficStats, TunnelState, TunnelType,
+        TunnelTypeConstraint, TunnelTypeUpdate,
+    },
+    ManagementServiceClient, Status,
+};
+use std::io::{self, Write};
+
+mod relays;
+use relays::{relay_entries, RelayEntry, RelayFilter};
+
+/// Number of relays to move the selection by for page up and page down.
+const PAGE_SIZE: usize = 10;
+
+pub struct Tui;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Tui {
+    fn name(&self) -> &'static str {
+        "tui"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Show a live dashboard of the tunnel state, along with a relay browser")
+    }
+
+    async fn run(&self, _: &clap::ArgMatches<'_>) -> Result<()> {
+        let rpc = new_rpc_client().await?;
+        let mut dashboard = Dashboard::new(rpc).await?;
+        let mut terminal = Terminal::enter()?;
+        dashboard.run(&mut terminal).await
+    }
+}
+
+enum Input {
+    Daemon(std::result::Result<DaemonEvent, Status>),
+    Stats(std::result::Result<TrafficStats, Status>),
+    Terminal(crossterm::Result<Event>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Dashboard,
+    Relays,
+    /// The relay browser, while the filter is being edited.
+    Filter,
+}
+
+struct Dashboard {
+    rpc: ManagementServiceClient,
+    tunnel_state: TunnelState,
+    location: Option<GeoIpLocation>,
+    stats: Option<TrafficStats>,
+    account_token: String,
+    account_expiry: Option<Timestamp>,
+    relays: Vec<RelayEntry>,
+    filter: RelayFilter,
+    selected: usize,
+    view: View,
+    /// Result of the latest action, shown until the next key is pressed.
+    message: Option<String>,
+}
+
+impl Dashboard {
+    async fn new(mut rpc: ManagementServiceClient) -> Result<Self> {
+        let tunnel_state = rpc.get_tunnel_state(()).await?.into_inner();
+        let settings = rpc.get_settings(()).await?.into_inner();
+
+        let mut locations = rpc
+            .get_relay_locations(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to obtain relay locations", error))?
+            .into_inner();
+        let mut countries = Vec::new();
+        while let Some(country) = locations.message().await? {
+            countries.push(country);
+        }
+        location::sort_countries(&mut countries);
+
+        let mut dashboard = Dashboard {
+            rpc,
+            tunnel_state,
+            location: None,
+            stats: None,
+            account_token: settings.account_token,
+            account_expiry: None,
+            relays: relay_entries(&countries),
+            filter: RelayFilter::default(),
+            selected: 0,
+            view: View::Dashboard,
+            message: None,
+        };
+        dashboard.update_location().await;
+        dashboard.update_account_expiry().await;
+        Ok(dashboard)
+    }
+
+    async fn run(&mut self, terminal: &mut Terminal) -> Result<()> {
+        let events = self.rpc.events_listen(()).await?.into_inner();
+        let stats = self.rpc.traffic_stats_listen(()).await?.into_inner();
+        let inputs = stream::select(
+            stream::select(events.map(Input::Daemon), stats.map(Input::Stats)),
+            EventStream::new().map(Input::Terminal),
+        );
+        pin_mut!(inputs);
+
+        self.draw(terminal)?;
+        while let Some(input) = inputs.next().await {
+            match input {
+                Input::Daemon(event) => self.handle_daemon_event(event?).await,
+                Input::Stats(stats) => self.stats = Some(stats?),
+                Input::Terminal(event) => {
+                    if let Event::Key(key) = event? {
+                        if !self.handle_key(key).await {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            self.draw(terminal)?;
+        }
+        Ok(())
+    }
+
+    async fn handle_daemon_event(&mut self, event: DaemonEvent) {
+        match event.event {
+            Some(EventType::TunnelState(new_state)) => {
+                let update_location = matches!(
+                    new_state.state,
+                    Some(State::Connected(_)) | Some(State::Disconnected(_))
+                );
+                self.tunnel_state = new_state;
+                if update_location {
+                    self.update_location().await;
+                }
+            }
+            Some(EventType::Settings(settings)) => {
+                if settings.account_token != self.account_token {
+                    self.account_token = settings.account_token;
+                    self.update_account_expiry().await;
+                }
+            }
+            Some(EventType::RelayList(relay_list)) => {
+                let mut countries = relay_list.countries;
+                location::sort_countries(&mut countries);
+                self.relays = relay_entries(&countries);
+                self.selected = 0;
+            }
+            _ => (),
+        }
+    }
+
+    async fn update_location(&mut self) {
+        self.location = location::get_current_location(&mut self.rpc)
+            .await
+            .ok()
+            .flatten();
+    }
+
+    async fn update_account_expiry(&mut self) {
+        self.account_expiry = None;
+        if self.account_token.is_empty() {
+            return;
+        }
+        if let Ok(account_data) = self.rpc.get_account_data(self.account_token.clone()).await {
+            self.account_expiry = account_data.into_inner().expiry;
+        }
+    }
+
+    /// Handles a key press. Returns `false` if the dashboard should be closed.
+    async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        self.message = None;
+
+        if self.view == View::Filter {
+            match key.code {
+                KeyCode::Char(c) => self.filter.query.push(c),
+                KeyCode::Backspace => {
+                    self.filter.query.pop();
+                }
+                KeyCode::Enter | KeyCode::Esc => self.view = View::Relays,
+                _ => (),
+            }
+            self.selected = 0;
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') => {
+                if let Err(status) = self.rpc.connect_tunnel(()).await {
+                    self.show_error("Failed to connect", &status);
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Err(status) = self.rpc.disconnect_tunnel(()).await {
+                    self.show_error("Failed to disconnect", &status);
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Err(status) = self.rpc.reconnect_tunnel(()).await {
+                    self.show_error("Failed to reconnect", &status);
+                }
+            }
+            KeyCode::Char('b') | KeyCode::Tab => {
+                self.view = match self.view {
+                    View::Dashboard => View::Relays,
+                    _ => View::Dashboard,
+                };
+            }
+            _ if self.view == View::Relays => self.handle_relay_key(key.code).await,
+            _ => (),
+        }
+        true
+    }
+
+    async fn handle_relay_key(&mut self, code: KeyCode) {
+        let last = self.filtered_relays().len().saturating_sub(1);
+        match code {
+            KeyCode::Esc => self.view = View::Dashboard,
+            KeyCode::Char('/') => self.view = View::Filter,
+            KeyCode::Char('t') => {
+                self.filter.cycle_tunnel_type();
+                self.selected = 0;
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(PAGE_SIZE),
+            KeyCode::PageDown => self.selected = (self.selected + PAGE_SIZE).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Enter => self.connect_to_selected_relay().await,
+            _ => (),
+        }
+    }
+
+    /// Constrains the location to the selected relay, and connects to it. The tunnel type is
+    /// constrained as well if the relays are filtered by tunnel type.
+    async fn connect_to_selected_relay(&mut self) {
+        let (location, hostname) = match self.filtered_relays().get(self.selected) {
+            Some(relay) => (
+                RelayLocation {
+                    country: relay.country_code.clone(),
+                    city: relay.city_code.clone(),
+                    hostname: relay.hostname.clone(),
+                },
+                relay.hostname.clone(),
+            ),
+            None => return,
+        };
+        let update = RelaySettingsUpdate {
+            r#type: Some(relay_settings_update::Type::Normal(
+                NormalRelaySettingsUpdate {
+                    location: Some(location.clone()),
+                    location_constraint: Some(LocationConstraint {
+                        include: vec![location],
+                        ..Default::default()
+                    }),
+                    tunnel_type: self.filter.tunnel_type.map(|tunnel_type| TunnelTypeUpdate {
+                        tunnel_type: Some(TunnelTypeConstraint {
+                            tunnel_type: tunnel_type as i32,
+                        }),
+                    }),
+                    ..Default::default()
+                },
+            )),
+        };
+
+        if let Err(status) = self.rpc.update_relay_settings(update).await {
+            self.show_error("Failed to update relay settings", &status);
+            return;
+        }
+        match self.rpc.connect_tunnel(()).await {
+            Ok(_) => {
+                self.message = Some(format!("Connecting to {}", hostname));
+                self.view = View::Dashboard;
+            }
+            Err(status) => self.show_error("Failed to connect", &status),
+        }
+    }
+
+    fn show_error(&mut self, message: &str, status: &Status) {
+        self.message = Some(format!("{}: {}", message, status.message()));
+    }
+
+    fn filtered_relays(&self) -> Vec<&RelayEntry> {
+        self.relays
+            .iter()
+            .filter(|relay| self.filter.matches(relay))
+            .collect()
+    }
+
+    fn draw(&self, terminal: &mut Terminal) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let lines = match self.view {
+            View::Dashboard => self.dashboard_lines(),
+            View::Relays | View::Filter => self.relay_lines(height as usize),
+        };
+        terminal.draw(&lines, width as usize, height as usize)
+    }
+
+    fn dashboard_lines(&self) -> Vec<Line> {
+        let mut lines = vec![
+            Line::highlighted("Mullvad VPN".to_string()),
+            Line::default(),
+            Line::new(format!(
+                "State:     {}",
+                format::format_state(&self.tunnel_state)
+            )),
+        ];
+
+        match &self.location {
+            Some(location) => {
+                if !location.hostname.is_empty() {
+                    lines.push(Line::new(format!("Relay:     {}", location.hostname)));
+                }
+                let hops: Vec<&str> = std::iter::once(location.entry_hostname.as_str())
+                    .chain(location.intermediate_hostnames.iter().map(String::as_str))
+                    .filter(|hostname| !hostname.is_empty())
+                    .collect();
+                if !hops.is_empty() {
+                    lines.push(Line::new(format!("Via:       {}", hops.join(", "))));
+                }
+                let mut place = location.country.clone();
+                if !location.city.is_empty() {
+                    place = format!("{}, {}", location.city, place);
+                }
+                lines.push(Line::new(format!("Location:  {}", place)));
+                let addresses: Vec<&str> = [location.ipv4.as_str(), location.ipv6.as_str()]
+                    .iter()
+                    .filter(|address| !address.is_empty())
+                    .copied()
+                    .collect();
+                lines.push(Line::new(format!("IP:        {}", addresses.join(", "))));
+            }
+            None => lines.push(Line::new("Location:  unavailable".to_string())),
+        }
+
+        let traffic = match &self.stats {
+            Some(stats) => format::format_traffic_stats(stats),
+            None => "-".to_string(),
+        };
+        lines.push(Line::new(format!("Traffic:   {}", traffic)));
+
+        let account = match &self.account_expiry {
+            _ if self.account_token.is_empty() => "not set".to_string(),
+            Some(expiry) => format!("expires {}", format::format_timestamp(expiry)),
+            None => "expiry unknown".to_string(),
+        };
+        lines.push(Line::new(format!("Account:   {}", account)));
+
+        lines.push(Line::default());
+        lines.push(Line::new(
+            "[c] connect  [d] disconnect  [r] reconnect  [b] relays  [q] quit".to_string(),
+        ));
+        if let Some(message) = &self.message {
+            lines.push(Line::new(message.clone()));
+        }
+        lines
+    }
+
+    fn relay_lines(&self, height: usize) -> Vec<Line> {
+        let relays = self.filtered_relays();
+        let cursor = if self.view == View::Filter { "_" } else { "" };
+        let tunnel_type = match self.filter.tunnel_type {
+            None => "any",
+            Some(TunnelType::Openvpn) => "OpenVPN",
+            Some(TunnelType::Wireguard) => "WireGuard",
+        };
+        let mut lines = vec![
+            Line::highlighted(format!(
+                "Relays - {} of {}",
+                relays.len(),
+                self.relays.len()
+            )),
+            Line::new(format!(
+                "Filter: {}{}    Tunnel type: {}",
+                self.filter.query, cursor, tunnel_type
+            )),
+            Line::default(),
+        ];
+
+        // Rows left for relays after the header, the key bindings and the message.
+        let rows = height.saturating_sub(lines.len() + 3).max(1);
+        let offset = (self.selected + 1).saturating_sub(rows);
+        for (index, relay) in relays.iter().enumerate().skip(offset).take(rows) {
+            let text = format_relay(relay);
+            if index == self.selected {
+                lines.push(Line::highlighted(text));
+            } else {
+                lines.push(Line::new(text));
+            }
+        }
+
+        lines.push(Line::default());
+        lines.push(Line::new(
+            "[/] filter  [t] tunnel type  [enter] connect  [esc] back  [q] quit".to_string(),
+        ));
+        if let Some(message) = &self.message {
+            lines.push(Line::new(message.clone()));
+        }
+        lines
+    }
+}
+
+fn format_relay(relay: &RelayEntry) -> String {
+    let tunnels = match (relay.openvpn, relay.wireguard) {
+        (true, true) => "OpenVPN, WireGuard",
+        (true, false) => "OpenVPN",
+        _ => "WireGuard",
+    };
+    format!(
+        "{:<20} {:<30} {:<20} {} ({})",
+        relay.hostname,
+        format!("{}, {}", relay.city, relay.country),
+        tunnels,
+        relay.provider,
+        location::format_relay_ownership(relay.owned)
+    )
+}
+
+#[derive(Default)]
+struct Line {
+    text: String,
+    highlighted: bool,
+}
+
+impl Line {
+    fn new(text: String) -> Self {
+        Line {
+            text,
+            highlighted: false,
+        }
+    }
+
+    fn highlighted(text: String) -> Self {
+        Line {
+            text,
+            highlighted: true,
+        }
+    }
+}
+
+/// Switches the terminal to raw mode and an alternate screen, and restores it when dropped.
+struct Terminal {
+    stdout: io::Stdout,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut terminal = Terminal {
+            stdout: io::stdout(),
+        };
+        execute!(
+            terminal.stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        Ok(terminal)
+    }
+
+    fn draw(&mut self, lines: &[Line], width: usize, height: usize) -> Result<()> {
+        for (row, line) in lines.iter().take(height).enumerate() {
+            let text: String = line.text.chars().take(width).collect();
+            queue!(self.stdout, cursor::MoveTo(0, row as u16))?;
+            if line.highlighted {
+                queue!(self.stdout, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                self.stdout,
+                Print(text),
+                SetAttribute(Attribute::Reset),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, lines.len().min(height) as u16),
+            terminal::Clear(ClearType::FromCursorDown)
+        )?;
+        self.stdout
+            .flush()
+            .map_err(|error| Error::Terminal(error.into()))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,260 @@
+use mullvad_management_interface::types::{RelayListCountry, TunnelType};
+
+/// A relay in the relay browser, along with the location it is in.
+pub struct RelayEntry {
+    pub country: String,
+    pub country_code: String,
+    pub city: String,
+    pub city_code: String,
+    pub hostname: String,
+    pub provider: String,
+    pub owned: bool,
+    pub openvpn: bool,
+    pub wireguard: bool,
+}
+
+impl RelayEntry {
+    pub fn supports(&self, tunnel_type: TunnelType) -> bool {
+        match tunnel_type {
+            TunnelType::Openvpn => self.openvpn,
+            TunnelType::Wireguard => self.wireguard,
+        }
+    }
+}
+
+/// Flattens the relay list into the active relays that support at least one tunnel type.
+pub fn relay_entries(countries: &[RelayListCountry]) -> Vec<RelayEntry> {
+    let mut entries = Vec::new();
+    for country in countries {
+        for city in &country.cities {
+            for relay in &city.relays {
+                let tunnels = match &relay.tunnels {
+                    Some(tunnels) if relay.active => tunnels,
+                    _ => continue,
+                };
+                if tunnels.openvpn.is_empty() && tunnels.wireguard.is_empty() {
+                    continue;
+                }
+                entries.push(RelayEntry {
+                    country: country.name.clone(),
+                    country_code: country.code.clone(),
+                    city: city.name.clone(),
+                    city_code: city.code.clone(),
+                    hostname: relay.hostname.clone(),
+                    provider: relay.provider.clone(),
+                    owned: relay.owned,
+                    openvpn: !tunnels.openvpn.is_empty(),
+                    wireguard: !tunnels.wireguard.is_empty(),
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Filter of the relay browser. The query consists of whitespace separated terms that must all
+/// match. A term can be prefixed with `country:`, `city:` or `provider:` to only match that field.
+/// Other terms match the country, city, hostname or provider. Terms are matched case-insensitively
+/// against both names and codes.
+#[derive(Default)]
+pub struct RelayFilter {
+    pub query: String,
+    pub tunnel_type: Option<TunnelType>,
+}
+
+impl RelayFilter {
+    pub fn matches(&self, relay: &RelayEntry) -> bool {
+        if let Some(tunnel_type) = self.tunnel_type {
+            if !relay.supports(tunnel_type) {
+                return false;
+            }
+        }
+        self.query
+            .to_lowercase()
+            .split_whitespace()
+            .all(|term| Self::term_matches(term, relay))
+    }
+
+    fn term_matches(term: &str, relay: &RelayEntry) -> bool {
+        let (term, fields) = if let Some(term) = term.strip_prefix("country:") {
+            (term, vec![&relay.country, &relay.country_code])
+        } else if let Some(term) = term.strip_prefix("city:") {
+            (term, vec![&relay.city, &relay.city_code])
+        } else if let Some(term) = term.strip_prefix("provider:") {
+            (term, vec![&relay.provider])
+        } else {
+            (
+                term,
+                vec![
+                    &relay.country,
+                    &relay.country_code,
+                    &relay.city,
+                    &relay.city_code,
+                    &relay.hostname,
+                    &relay.provider,
+                ],
+            )
+        };
+        fields
+            .iter()
+            .any(|field| field.to_lowercase().contains(term))
+    }
+
+    /// Switches to the next tunnel type: any, OpenVPN, WireGuard and back to any.
+    pub fn cycle_tunnel_type(&mut self) {
+        self.tunnel_type = match self.tunnel_type {
+            None => Some(TunnelType::Openvpn),
+            Some(TunnelType::Openvpn) => Some(TunnelType::Wireguard),
+            Some(TunnelType::Wireguard) => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_management_interface::types::{
+        OpenVpnEndpointData, Relay, RelayListCity, RelayTunnels, WireguardEndpointData,
+    };
+
+    fn relay(hostname: &str, active: bool, openvpn: bool, wireguard: bool) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            active,
+            provider: "Provider".to_owned(),
+            tunnels: Some(RelayTunnels {
+                openvpn: if openvpn {
+                    vec![OpenVpnEndpointData::default()]
+                } else {
+                    vec![]
+                },
+                wireguard: if wireguard {
+                    vec![WireguardEndpointData::default()]
+                } else {
+                    vec![]
+                },
+            }),
+            ..Relay::default()
+        }
+    }
+
+    fn countries() -> Vec<RelayListCountry> {
+        vec![
+            RelayListCountry {
+                name: "Sweden".to_owned(),
+                code: "se".to_owned(),
+                cities: vec![RelayListCity {
+                    name: "Gothenburg".to_owned(),
+                    code: "got".to_owned(),
+                    relays: vec![
+                        relay("se-got-001", true, true, false),
+                        relay("se-got-wg-001", true, false, true),
+                        relay("se-got-002", false, true, false),
+                        relay("se-got-003", true, false, false),
+                        Relay {
+                            tunnels: None,
+                            ..relay("se-got-004", true, true, true)
+                        },
+                    ],
+                    ..RelayListCity::default()
+                }],
+            },
+            RelayListCountry {
+                name: "Germany".to_owned(),
+                code: "de".to_owned(),
+                cities: vec![RelayListCity {
+                    name: "Frankfurt".to_owned(),
+                    code: "fra".to_owned(),
+                    relays: vec![Relay {
+                        provider: "Other".to_owned(),
+                        ..relay("de-fra-001", true, true, true)
+                    }],
+                    ..RelayListCity::default()
+                }],
+            },
+        ]
+    }
+
+    fn matching_hostnames(filter: &RelayFilter) -> Vec<String> {
+        relay_entries(&countries())
+            .into_iter()
+            .filter(|relay| filter.matches(relay))
+            .map(|relay| relay.hostname)
+            .collect()
+    }
+
+    fn query(query: &str) -> RelayFilter {
+        RelayFilter {
+            query: query.to_owned(),
+            tunnel_type: None,
+        }
+    }
+
+    #[test]
+    fn test_relay_entries() {
+        let entries = relay_entries(&countries());
+        let hostnames: Vec<&str> = entries
+            .iter()
+            .map(|relay| relay.hostname.as_str())
+            .collect();
+        assert_eq!(hostnames, vec!["se-got-001", "se-got-wg-001", "de-fra-001"]);
+
+        let relay = &entries[2];
+        assert_eq!(
+            (relay.country.as_str(), relay.city_code.as_str()),
+            ("Germany", "fra")
+        );
+        assert!(relay.supports(TunnelType::Openvpn) && relay.supports(TunnelType::Wireguard));
+        assert!(!entries[0].supports(TunnelType::Wireguard));
+    }
+
+    #[test]
+    fn test_filter_terms() {
+        assert_eq!(matching_hostnames(&query("")).len(), 3);
+        assert_eq!(matching_hostnames(&query("  GOTHEN  ")).len(), 2);
+        assert_eq!(matching_hostnames(&query("germany")), vec!["de-fra-001"]);
+        assert_eq!(matching_hostnames(&query("got wg")), vec!["se-got-wg-001"]);
+        assert_eq!(matching_hostnames(&query("other")), vec!["de-fra-001"]);
+        assert!(matching_hostnames(&query("se fra")).is_empty());
+    }
+
+    #[test]
+    fn test_filter_prefixed_terms() {
+        assert_eq!(
+            matching_hostnames(&query("country:Germany")),
+            vec!["de-fra-001"]
+        );
+        assert_eq!(matching_hostnames(&query("city:FRA")), vec!["de-fra-001"]);
+        assert_eq!(
+            matching_hostnames(&query("provider:other")),
+            vec!["de-fra-001"]
+        );
+        // Prefixed terms only match their own field.
+        assert!(matching_hostnames(&query("city:se")).is_empty());
+        assert!(matching_hostnames(&query("provider:got")).is_empty());
+    }
+
+    #[test]
+    fn test_filter_tunnel_type() {
+        let mut filter = RelayFilter::default();
+        filter.cycle_tunnel_type();
+        assert_eq!(filter.tunnel_type, Some(TunnelType::Openvpn));
+        assert_eq!(
+            matching_hostnames(&filter),
+            vec!["se-got-001", "de-fra-001"]
+        );
+
+        filter.cycle_tunnel_type();
+        assert_eq!(filter.tunnel_type, Some(TunnelType::Wireguard));
+        assert_eq!(
+            matching_hostnames(&filter),
+            vec!["se-got-wg-001", "de-fra-001"]
+        );
+
+        filter.query = "se".to_owned();
+        assert_eq!(matching_hostnames(&filter), vec!["se-got-wg-001"]);
+
+        filter.cycle_tunnel_type();
+        assert_eq!(filter.tunnel_type, None);
+    }
+}
@@ -1,8 +1,11 @@
-use crate::{format::print_keygen_event, new_rpc_client, Command, Error, Result};
+use crate::{
+    format::{self, print_keygen_event},
+    new_rpc_client, Command, Error, Result,
+};
 use clap::value_t;
 use mullvad_management_interface::types::{self, Timestamp, TunnelOptions};
 use mullvad_types::wireguard::DEFAULT_ROTATION_INTERVAL;
-use std::{convert::TryFrom, time::Duration};
+use std::{convert::TryFrom, fs, io::Write, time::Duration};
 
 pub struct Tunnel;
 
@@ -39,6 +42,17 @@ fn create_wireguard_subcommand() -> clap::App<'static, 'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
         .subcommand(create_wireguard_keys_subcommand())
+        .subcommand(create_wireguard_quantum_resistant_subcommand())
+        .subcommand(
+            clap::SubCommand::with_name("export")
+                .about(
+                    "Print the configuration of the current WireGuard tunnel in the wg-quick \
+                     format, including the private key",
+                )
+                .arg(clap::Arg::with_name("file").help(
+                    "Write the configuration to this file instead. Only the owner may read it",
+                )),
+        )
 }
 
 fn create_wireguard_mtu_subcommand() -> clap::App<'static, 'static> {
@@ -52,6 +66,24 @@ fn create_wireguard_mtu_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_wireguard_quantum_resistant_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("quantum-resistant-tunnel")
+        .about(
+            "Negotiate a preshared key with the relay using a post-quantum key exchange once \
+             the tunnel is up",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set").arg(
+                clap::Arg::with_name("policy")
+                    .required(true)
+                    .takes_value(true)
+                    .possible_values(&["on", "off"]),
+            ),
+        )
+}
+
 fn create_wireguard_keys_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("key")
         .about("Manage your wireguard key")
@@ -72,7 +104,6 @@ fn create_wireguard_keys_rotation_interval_subcommand() -> clap::App<'static, 's
         )
 }
 
-
 fn create_openvpn_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("openvpn")
         .about("Manage options for OpenVPN tunnels")
@@ -117,7 +148,7 @@ impl Tunnel {
 
     async fn handle_openvpn_mssfix_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
-            ("get", Some(_)) => Self::process_openvpn_mssfix_get().await,
+            ("get", Some(_)) => Self::process_openvpn_mssfix_get(matches.is_present("json")).await,
             ("unset", Some(_)) => Self::process_openvpn_mssfix_unset().await,
             ("set", Some(set_matches)) => Self::process_openvpn_mssfix_set(set_matches).await,
             _ => unreachable!("unhandled command"),
@@ -127,7 +158,7 @@ impl Tunnel {
     async fn handle_wireguard_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
             ("mtu", Some(matches)) => match matches.subcommand() {
-                ("get", _) => Self::process_wireguard_mtu_get().await,
+                ("get", _) => Self::process_wireguard_mtu_get(matches.is_present("json")).await,
                 ("set", Some(matches)) => Self::process_wireguard_mtu_set(matches).await,
                 ("unset", _) => Self::process_wireguard_mtu_unset().await,
                 _ => unreachable!("unhandled command"),
@@ -137,7 +168,10 @@ impl Tunnel {
                 ("check", _) => Self::process_wireguard_key_check().await,
                 ("regenerate", _) => Self::process_wireguard_key_generate().await,
                 ("rotation-interval", Some(matches)) => match matches.subcommand() {
-                    ("get", _) => Self::process_wireguard_rotation_interval_get().await,
+                    ("get", _) => {
+                        Self::process_wireguard_rotation_interval_get(matches.is_present("json"))
+                            .await
+                    }
                     ("set", Some(matches)) => {
                         Self::process_wireguard_rotation_interval_set(matches).await
                     }
@@ -147,13 +181,64 @@ impl Tunnel {
                 _ => unreachable!("unhandled command"),
             },
 
+            ("quantum-resistant-tunnel", Some(matches)) => match matches.subcommand() {
+                ("get", _) => {
+                    Self::process_wireguard_quantum_resistant_get(matches.is_present("json")).await
+                }
+                ("set", Some(matches)) => {
+                    Self::process_wireguard_quantum_resistant_set(matches).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
+
+            ("export", Some(matches)) => {
+                Self::process_wireguard_export(matches.value_of("file")).await
+            }
+
             _ => unreachable!("unhandled command"),
         }
     }
 
-    async fn process_wireguard_mtu_get() -> Result<()> {
+    async fn process_wireguard_export(path: Option<&str>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let config = rpc
+            .export_wireguard_config(())
+            .await
+            .map_err(|error| {
+                Error::RpcFailedExt("Failed to export WireGuard configuration", error)
+            })?
+            .into_inner();
+        let path = match path {
+            Some(path) => path,
+            None => {
+                print!("{}", config);
+                return Ok(());
+            }
+        };
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(config.as_bytes()))
+            .map_err(|error| Error::WriteFile(path.to_owned(), error))?;
+        println!("Exported WireGuard configuration to {}", path);
+        Ok(())
+    }
+
+    async fn process_wireguard_mtu_get(json: bool) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mtu = tunnel_options.wireguard.unwrap().mtu;
+        if json {
+            return format::print_json(&serde_json::json!({
+                "mtu": if mtu != 0 { Some(mtu) } else { None },
+            }));
+        }
         println!(
             "mtu: {}",
             if mtu != 0 {
@@ -180,6 +265,34 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_wireguard_quantum_resistant_get(json: bool) -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let quantum_resistant = tunnel_options.wireguard.unwrap().quantum_resistant;
+        if json {
+            return format::print_json(&serde_json::json!({
+                "quantum_resistant": quantum_resistant,
+            }));
+        }
+        println!(
+            "Quantum-resistant tunnel: {}",
+            if quantum_resistant { "on" } else { "off" }
+        );
+        Ok(())
+    }
+
+    async fn process_wireguard_quantum_resistant_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let enabled = matches.value_of("policy").unwrap() == "on";
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_quantum_resistant_tunnel(enabled).await?;
+        if enabled {
+            println!("Enabled quantum-resistant tunnels");
+        } else {
+            println!("Disabled quantum-resistant tunnels");
+        }
+        Ok(())
+    }
+
     async fn process_wireguard_key_check() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let key = rpc.get_wireguard_key(()).await;
@@ -220,9 +333,15 @@ impl Tunnel {
         Ok(())
     }
 
-    async fn process_wireguard_rotation_interval_get() -> Result<()> {
+    async fn process_wireguard_rotation_interval_get(json: bool) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        match tunnel_options.wireguard.unwrap().rotation_interval {
+        let rotation_interval = tunnel_options.wireguard.unwrap().rotation_interval;
+        if json {
+            return format::print_json(&serde_json::json!({
+                "rotation_interval": rotation_interval.map(|interval| interval.seconds),
+            }));
+        }
+        match rotation_interval {
             Some(interval) => {
                 let hours = duration_hours(&Duration::try_from(interval).unwrap());
                 println!("Rotation interval: {} hour(s)", hours);
@@ -259,7 +378,7 @@ impl Tunnel {
 
     async fn handle_ipv6_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         if matches.subcommand_matches("get").is_some() {
-            Self::process_ipv6_get().await
+            Self::process_ipv6_get(matches.is_present("json")).await
         } else if let Some(m) = matches.subcommand_matches("set") {
             Self::process_ipv6_set(m).await
         } else {
@@ -267,9 +386,14 @@ impl Tunnel {
         }
     }
 
-    async fn process_openvpn_mssfix_get() -> Result<()> {
+    async fn process_openvpn_mssfix_get(json: bool) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mssfix = tunnel_options.openvpn.unwrap().mssfix;
+        if json {
+            return format::print_json(&serde_json::json!({
+                "mssfix": if mssfix != 0 { Some(mssfix) } else { None },
+            }));
+        }
         println!(
             "mssfix: {}",
             if mssfix != 0 {
@@ -306,16 +430,13 @@ impl Tunnel {
         Ok(())
     }
 
-    async fn process_ipv6_get() -> Result<()> {
+    async fn process_ipv6_get(json: bool) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        println!(
-            "IPv6: {}",
-            if tunnel_options.generic.unwrap().enable_ipv6 {
-                "on"
-            } else {
-                "off"
-            }
-        );
+        let enable_ipv6 = tunnel_options.generic.unwrap().enable_ipv6;
+        if json {
+            return format::print_json(&serde_json::json!({ "enable_ipv6": enable_ipv6 }));
+        }
+        println!("IPv6: {}", if enable_ipv6 { "on" } else { "off" });
         Ok(())
     }
 
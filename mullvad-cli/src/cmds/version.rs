@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 
 pub struct Version;
 
@@ -13,19 +13,27 @@ impl Command for Version {
             .about("Shows current version, and the currently supported versions")
     }
 
-    async fn run(&self, _: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let current_version = rpc
             .get_current_version(())
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain current version", error))?
             .into_inner();
-        println!("Current version: {}", current_version);
         let version_info = rpc
             .get_version_info(())
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain version info", error))?
             .into_inner();
+
+        if matches.is_present("json") {
+            return format::print_json(&serde_json::json!({
+                "current_version": current_version,
+                "version_info": version_info,
+            }));
+        }
+
+        println!("Current version: {}", current_version);
         println!("\tIs supported: {}", version_info.supported);
 
         if !version_info.suggested_upgrade.is_empty() {
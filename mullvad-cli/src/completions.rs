@@ -0,0 +1,486 @@
+//! Shell completion scripts. On top of what clap generates, the bash, zsh and fish scripts call
+//! the hidden `complete` subcommand to complete locations, hostnames and providers from the relay
+//! list of the daemon.
+
+use crate::{location, Error, Result, BIN_NAME};
+use mullvad_management_interface::types::RelayListCountry;
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Options whose value is the next word on the command line.
+const VALUE_OPTIONS: &[&str] = &["--include", "--exclude", "--exclude-provider", "--provider"];
+
+const BASH_DYNAMIC: &str = r#"
+_mullvad_dynamic() {
+    local values
+    values="$(mullvad complete -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)"
+    if [[ -n "$values" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=( $(compgen -W "$values" -- "${COMP_WORDS[COMP_CWORD]}") )
+    else
+        _mullvad "$@"
+    fi
+}
+
+complete -F _mullvad_dynamic -o bashdefault -o default mullvad
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_mullvad_dynamic() {
+    local -a values
+    values=(${(f)"$(mullvad complete -- "${(@)words[2,CURRENT-1]}" 2>/dev/null)"})
+    if (( ${#values} )); then
+        compadd -a values
+    else
+        _mullvad "$@"
+    fi
+}
+
+compdef _mullvad_dynamic mullvad
+_mullvad_dynamic "$@"
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __fish_mullvad_relay_values
+    set -l values (mullvad complete -- (commandline -opc)[2..-1] 2>/dev/null)
+    test (count $values) -gt 0; or return 1
+    printf '%s\n' $values
+end
+
+complete -c mullvad -n '__fish_mullvad_relay_values >/dev/null' -f -a '(__fish_mullvad_relay_values)'
+"#;
+
+pub fn get_shell_completions_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("shell-completions")
+        .about("Generates completion scripts for your shell")
+        .arg(
+            clap::Arg::with_name("SHELL")
+                .required(true)
+                .possible_values(&clap::Shell::variants()[..])
+                .help("The shell to generate the script for"),
+        )
+        .arg(clap::Arg::with_name("DIR").help(
+            "Output directory where the shell completions are written. The script is printed \
+             if no directory is given",
+        ))
+}
+
+pub fn get_complete_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("complete")
+        .about(
+            "Prints the relay list values that can follow the given words, one per line. Used \
+             by the completion scripts",
+        )
+        .setting(clap::AppSettings::Hidden)
+        .setting(clap::AppSettings::TrailingVarArg)
+        .setting(clap::AppSettings::AllowLeadingHyphen)
+        .arg(
+            clap::Arg::with_name("WORDS")
+                .multiple(true)
+                .help("The words after 'mullvad' and before the word being completed"),
+        )
+}
+
+/// Writes the completion script for `shell` to `out_dir`, or to stdout if no directory is given.
+pub fn generate(
+    mut app: clap::App<'static, 'static>,
+    shell: clap::Shell,
+    out_dir: Option<&OsStr>,
+) -> Result<()> {
+    let mut script = Vec::new();
+    app.gen_completions_to(BIN_NAME, shell, &mut script);
+    let mut script = String::from_utf8(script).expect("Completion script is not UTF-8");
+
+    match shell {
+        clap::Shell::Bash => script.push_str(BASH_DYNAMIC),
+        clap::Shell::Zsh => {
+            // The generated function is replaced by the wrapper as the entry point.
+            let entry_point = format!("_{} \"$@\"", BIN_NAME);
+            if script.trim_end().ends_with(&entry_point) {
+                script.truncate(script.trim_end().len() - entry_point.len());
+            }
+            script.push_str(ZSH_DYNAMIC);
+        }
+        clap::Shell::Fish => script.push_str(FISH_DYNAMIC),
+        clap::Shell::PowerShell | clap::Shell::Elvish => (),
+    }
+
+    match out_dir {
+        Some(out_dir) => {
+            let path = Path::new(out_dir).join(script_file_name(shell));
+            fs::write(&path, script)
+                .map_err(|error| Error::WriteFile(path.display().to_string(), error))
+        }
+        None => io::stdout()
+            .write_all(script.as_bytes())
+            .map_err(|error| Error::WriteFile("stdout".to_owned(), error)),
+    }
+}
+
+/// Returns the name clap gives the script when writing it to a directory.
+fn script_file_name(shell: clap::Shell) -> String {
+    match shell {
+        clap::Shell::Bash => format!("{}.bash", BIN_NAME),
+        clap::Shell::Fish => format!("{}.fish", BIN_NAME),
+        clap::Shell::Zsh => format!("_{}", BIN_NAME),
+        clap::Shell::PowerShell => format!("_{}.ps1", BIN_NAME),
+        clap::Shell::Elvish => format!("{}.elv", BIN_NAME),
+    }
+}
+
+/// Prints the values that can follow the words given to the `complete` subcommand. Nothing is
+/// printed if the words are not followed by a value from the relay list, or if the relay list
+/// cannot be obtained, in which case the scripts fall back on the static completions.
+pub async fn complete(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    let words: Vec<&str> = matches
+        .values_of("WORDS")
+        .map(Iterator::collect)
+        .unwrap_or_default();
+    let values = match values_to_complete(&words) {
+        Some(values) => values,
+        None => return Ok(()),
+    };
+    let countries = match location::get_relay_locations().await {
+        Ok(countries) => countries,
+        Err(_) => return Ok(()),
+    };
+    let bridges = words.iter().find(|word| !word.starts_with('-')) == Some(&"bridge");
+    for candidate in candidates(&values, &countries, bridges) {
+        println!("{}", candidate);
+    }
+    Ok(())
+}
+
+/// Relay list values that can be completed.
+#[derive(Debug, PartialEq)]
+enum Values {
+    /// Country codes, country and city codes such as `se-got`, and hostnames, as accepted by
+    /// `--include` and `--exclude`.
+    Locations,
+    Countries,
+    Cities {
+        country: String,
+    },
+    Hostnames {
+        country: String,
+        city: String,
+    },
+    AllHostnames,
+    Providers,
+}
+
+/// Returns the values that can follow `words`, or `None` if the next word is not a value from
+/// the relay list.
+fn values_to_complete(words: &[&str]) -> Option<Values> {
+    match words.last() {
+        Some(&"--include") | Some(&"--exclude") => return Some(Values::Locations),
+        Some(&"--exclude-provider") | Some(&"--provider") => return Some(Values::Providers),
+        _ => (),
+    }
+
+    if let Some(position) = words.iter().rposition(|word| *word == "--entry-location") {
+        let location = &words[position + 1..];
+        if !location.iter().any(|word| word.starts_with('-')) {
+            return location_values(location);
+        }
+    }
+
+    let mut args = Vec::new();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        if VALUE_OPTIONS.contains(word) {
+            words.next();
+        } else if !word.starts_with('-') {
+            args.push(*word);
+        }
+    }
+
+    match args.as_slice() {
+        ["relay", "set", "location", location @ ..]
+        | ["bridge", "set", "location", location @ ..] => location_values(location),
+        ["relay", "set", "hostname"] => Some(Values::AllHostnames),
+        ["relay", "set", "provider", ..] | ["bridge", "set", "provider", ..] => {
+            Some(Values::Providers)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the values that can follow the given parts of a `<country> [city] [hostname]`
+/// location.
+fn location_values(location: &[&str]) -> Option<Values> {
+    match location {
+        [] => Some(Values::Countries),
+        [country] => Some(Values::Cities {
+            country: country.to_string(),
+        }),
+        [country, city] => Some(Values::Hostnames {
+            country: country.to_string(),
+            city: city.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Returns the sorted candidates for `values`, taken from the active relays, or the active
+/// bridges if `bridges` is set.
+fn candidates(values: &Values, countries: &[RelayListCountry], bridges: bool) -> BTreeSet<String> {
+    let mut candidates = BTreeSet::new();
+    for country in countries {
+        for city in &country.cities {
+            for relay in &city.relays {
+                let usable = if bridges {
+                    relay
+                        .bridges
+                        .as_ref()
+                        .map(|bridges| !bridges.shadowsocks.is_empty())
+                        .unwrap_or(false)
+                } else {
+                    relay
+                        .tunnels
+                        .as_ref()
+                        .map(|tunnels| !tunnels.openvpn.is_empty() || !tunnels.wireguard.is_empty())
+                        .unwrap_or(false)
+                };
+                if !relay.active || !usable {
+                    continue;
+                }
+
+                match values {
+                    Values::Locations => {
+                        candidates.insert(country.code.clone());
+                        candidates.insert(format!("{}-{}", country.code, city.code));
+                        candidates.insert(relay.hostname.clone());
+                    }
+                    Values::Countries => {
+                        candidates.insert(country.code.clone());
+                    }
+                    Values::Cities { country: code } if *code == country.code => {
+                        candidates.insert(city.code.clone());
+                    }
+                    Values::Hostnames {
+                        country: country_code,
+                        city: city_code,
+                    } if *country_code == country.code && *city_code == city.code => {
+                        candidates.insert(relay.hostname.clone());
+                    }
+                    Values::AllHostnames => {
+                        candidates.insert(relay.hostname.clone());
+                    }
+                    Values::Providers => {
+                        candidates.insert(relay.provider.clone());
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_management_interface::types::{
+        Relay, RelayBridges, RelayListCity, RelayTunnels, ShadowsocksEndpointData,
+        WireguardEndpointData,
+    };
+
+    fn relay(hostname: &str, provider: &str, tunnel: bool, bridge: bool) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            active: true,
+            provider: provider.to_owned(),
+            tunnels: Some(RelayTunnels {
+                openvpn: vec![],
+                wireguard: if tunnel {
+                    vec![WireguardEndpointData::default()]
+                } else {
+                    vec![]
+                },
+            }),
+            bridges: Some(RelayBridges {
+                shadowsocks: if bridge {
+                    vec![ShadowsocksEndpointData::default()]
+                } else {
+                    vec![]
+                },
+            }),
+            ..Relay::default()
+        }
+    }
+
+    fn countries() -> Vec<RelayListCountry> {
+        vec![
+            RelayListCountry {
+                code: "se".to_owned(),
+                cities: vec![RelayListCity {
+                    code: "got".to_owned(),
+                    relays: vec![
+                        relay("se1-wireguard", "Provider", true, false),
+                        relay("se-got-br-001", "Provider", false, true),
+                        Relay {
+                            active: false,
+                            ..relay("se2-wireguard", "Inactive", true, false)
+                        },
+                    ],
+                    ..RelayListCity::default()
+                }],
+                ..RelayListCountry::default()
+            },
+            RelayListCountry {
+                code: "de".to_owned(),
+                cities: vec![RelayListCity {
+                    code: "fra".to_owned(),
+                    relays: vec![relay("de1-wireguard", "Other", true, true)],
+                    ..RelayListCity::default()
+                }],
+                ..RelayListCountry::default()
+            },
+        ]
+    }
+
+    fn complete(values: Values, bridges: bool) -> Vec<String> {
+        candidates(&values, &countries(), bridges)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_location_values() {
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location"]),
+            Some(Values::Countries)
+        );
+        assert_eq!(
+            values_to_complete(&["bridge", "set", "location", "se"]),
+            Some(Values::Cities {
+                country: "se".to_owned()
+            })
+        );
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location", "se", "got"]),
+            Some(Values::Hostnames {
+                country: "se".to_owned(),
+                city: "got".to_owned()
+            })
+        );
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location", "se", "got", "se1-wireguard"]),
+            None
+        );
+        assert_eq!(
+            values_to_complete(&["relay", "set", "hostname"]),
+            Some(Values::AllHostnames)
+        );
+    }
+
+    #[test]
+    fn test_option_values() {
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location", "--include"]),
+            Some(Values::Locations)
+        );
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location", "--exclude-provider"]),
+            Some(Values::Providers)
+        );
+        // Option values are not taken as parts of the location.
+        assert_eq!(
+            values_to_complete(&["relay", "set", "location", "--exclude", "de"]),
+            Some(Values::Countries)
+        );
+        assert_eq!(
+            values_to_complete(&[
+                "relay",
+                "set",
+                "tunnel",
+                "wireguard",
+                "--entry-location",
+                "se"
+            ]),
+            Some(Values::Cities {
+                country: "se".to_owned()
+            })
+        );
+        assert_eq!(
+            values_to_complete(&[
+                "relay",
+                "set",
+                "tunnel",
+                "wireguard",
+                "--entry-location",
+                "se",
+                "--port"
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_no_values() {
+        assert_eq!(values_to_complete(&[]), None);
+        assert_eq!(values_to_complete(&["relay"]), None);
+        assert_eq!(values_to_complete(&["relay", "set"]), None);
+        assert_eq!(values_to_complete(&["account", "set"]), None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(complete(Values::Countries, false), vec!["de", "se"]);
+        assert_eq!(
+            complete(
+                Values::Cities {
+                    country: "se".to_owned()
+                },
+                false
+            ),
+            vec!["got"]
+        );
+        assert_eq!(
+            complete(
+                Values::Hostnames {
+                    country: "se".to_owned(),
+                    city: "got".to_owned()
+                },
+                false
+            ),
+            vec!["se1-wireguard"]
+        );
+        assert_eq!(
+            complete(Values::Locations, false),
+            vec![
+                "de",
+                "de-fra",
+                "de1-wireguard",
+                "se",
+                "se-got",
+                "se1-wireguard"
+            ]
+        );
+        assert_eq!(
+            complete(Values::Providers, false),
+            vec!["Other", "Provider"]
+        );
+    }
+
+    #[test]
+    fn test_bridge_candidates() {
+        assert_eq!(
+            complete(Values::AllHostnames, true),
+            vec!["de1-wireguard", "se-got-br-001"]
+        );
+        assert!(complete(
+            Values::Cities {
+                country: "no".to_owned()
+            },
+            true
+        )
+        .is_empty());
+    }
+}
@@ -1,15 +1,54 @@
-use mullvad_management_interface::types::{
-    error_state::{
-        firewall_policy_error::ErrorType as FirewallPolicyErrorType, Cause as ErrorStateCause,
-        FirewallPolicyError, GenerationError,
+use crate::{Error, Result};
+use mullvad_management_interface::{
+    json,
+    types::{
+        error_state::{
+            firewall_policy_error::ErrorType as FirewallPolicyErrorType, Cause as ErrorStateCause,
+            FirewallPolicyError, GenerationError,
+        },
+        network_rule, tunnel_state,
+        tunnel_state::State::*,
+        AfterDisconnect, ErrorState, KeygenEvent, NetworkAction, NetworkIdentity, NetworkRule,
+        NetworkRuleEvent, ObfuscationType, ProxyType, Timestamp, TrafficStats, TransportProtocol,
+        TunnelEndpoint, TunnelState, TunnelStateRelayInfo, TunnelType,
     },
-    tunnel_state,
-    tunnel_state::State::*,
-    ErrorState, KeygenEvent, ProxyType, TransportProtocol, TunnelEndpoint, TunnelState, TunnelType,
 };
 use mullvad_types::auth_failed::AuthFailed;
+use serde::Serialize;
 use std::fmt::Write;
 
+/// Prints `value` as JSON on a single line.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string(value).map_err(Error::SerializeJson)?
+    );
+    Ok(())
+}
+
+/// Prints `error` as a JSON error object.
+pub fn print_json_error(error: &Error) {
+    let (message, code) = match error {
+        Error::RpcFailed(status) | Error::RpcFailedExt(_, status) => (
+            format!("{}: {}", error, status.message()),
+            Some(json::to_snake_case(&format!("{:?}", status.code()))),
+        ),
+        error => {
+            let mut message = error.to_string();
+            let mut source = std::error::Error::source(error);
+            while let Some(error) = source {
+                write!(&mut message, ": {}", error).unwrap();
+                source = error.source();
+            }
+            (message, None)
+        }
+    };
+    println!(
+        "{}",
+        serde_json::json!({ "error": { "message": message, "code": code } })
+    );
+}
+
 pub fn print_keygen_event(key_event: &KeygenEvent) {
     use mullvad_management_interface::types::keygen_event::KeygenEvent as EventType;
 
@@ -29,6 +68,93 @@ pub fn print_keygen_event(key_event: &KeygenEvent) {
     }
 }
 
+pub fn print_network_rule_event(event: &NetworkRuleEvent) {
+    let network = format_network_identity(event.network.as_ref().unwrap());
+    match &event.rule {
+        Some(rule) => println!(
+            "Joined {}, applied rule: {}",
+            network,
+            format_network_rule(rule)
+        ),
+        None => println!(
+            "Joined {}, which matches no rule, applied default action: {}",
+            network,
+            format_network_action(event.action)
+        ),
+    }
+}
+
+/// Formats a timestamp in the local time zone.
+pub fn format_timestamp(timestamp: &Timestamp) -> String {
+    let ndt = chrono::NaiveDateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32);
+    let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
+    utc.with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+pub fn format_traffic_stats(stats: &TrafficStats) -> String {
+    if !stats.connected {
+        return "Not connected".to_string();
+    }
+    let mut line = format!(
+        "Received: {} ({}/s), sent: {} ({}/s)",
+        format_bytes(stats.rx_bytes),
+        format_bytes(stats.rx_rate),
+        format_bytes(stats.tx_bytes),
+        format_bytes(stats.tx_rate),
+    );
+    if let Some(age) = &stats.handshake_age {
+        line.push_str(&format!(", latest handshake: {}s ago", age.seconds));
+    }
+    line
+}
+
+/// Formats a number of bytes using binary prefixes, e.g. "1.5 MiB".
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+pub fn format_network_rule(rule: &NetworkRule) -> String {
+    let network = match rule.network.as_ref().unwrap() {
+        network_rule::Network::Ssid(ssid) => format!("SSID \"{}\"", ssid),
+        network_rule::Network::GatewayMac(mac) => format!("gateway {}", mac),
+    };
+    format!("{} on {}", format_network_action(rule.action), network)
+}
+
+pub fn format_network_action(action: i32) -> &'static str {
+    match NetworkAction::from_i32(action).expect("unknown network action") {
+        NetworkAction::Connect => "connect",
+        NetworkAction::Disconnect => "disconnect",
+    }
+}
+
+fn format_network_identity(network: &NetworkIdentity) -> String {
+    match (network.ssid.is_empty(), network.gateway_mac.is_empty()) {
+        (false, false) => format!(
+            "SSID \"{}\" with gateway {}",
+            network.ssid, network.gateway_mac
+        ),
+        (false, true) => format!("SSID \"{}\"", network.ssid),
+        (true, false) => format!("gateway {}", network.gateway_mac),
+        (true, true) => "unknown network".to_string(),
+    }
+}
+
 pub fn print_state(state: &TunnelState) {
     print!("Tunnel status: ");
     match state.state.as_ref().unwrap() {
@@ -56,6 +182,40 @@ pub fn print_state(state: &TunnelState) {
     }
 }
 
+/// Formats a tunnel state on a single line.
+pub fn format_state(state: &TunnelState) -> String {
+    let format_relay = |relay_info: &Option<TunnelStateRelayInfo>| {
+        let relay_info = relay_info.as_ref().unwrap();
+        let endpoint = format_endpoint(relay_info.tunnel_endpoint.as_ref().unwrap());
+        match &relay_info.location {
+            Some(location) if !location.hostname.is_empty() => {
+                format!("{} ({})", location.hostname, endpoint)
+            }
+            _ => endpoint,
+        }
+    };
+    match state.state.as_ref().unwrap() {
+        Error(error) => format!(
+            "Blocked: {}",
+            error_state_to_string(error.error_state.as_ref().unwrap())
+        ),
+        Connected(tunnel_state::Connected { relay_info }) => {
+            format!("Connected to {}", format_relay(relay_info))
+        }
+        Connecting(tunnel_state::Connecting { relay_info }) => {
+            format!("Connecting to {}", format_relay(relay_info))
+        }
+        Disconnected(_) => "Disconnected".to_string(),
+        Disconnecting(tunnel_state::Disconnecting { after_disconnect }) => {
+            match AfterDisconnect::from_i32(*after_disconnect).expect("invalid after disconnect") {
+                AfterDisconnect::Nothing => "Disconnecting".to_string(),
+                AfterDisconnect::Block => "Disconnecting to block".to_string(),
+                AfterDisconnect::Reconnect => "Disconnecting to reconnect".to_string(),
+            }
+        }
+    }
+}
+
 fn format_endpoint(endpoint: &TunnelEndpoint) -> String {
     let tunnel_type = TunnelType::from_i32(endpoint.tunnel_type).expect("invalid tunnel protocol");
     let mut out = format!(
@@ -102,6 +262,36 @@ fn format_endpoint(endpoint: &TunnelEndpoint) -> String {
                 )
                 .unwrap();
             }
+            for intermediate_endpoint in &endpoint.intermediate_endpoints {
+                write!(
+                    &mut out,
+                    " and {} over {}",
+                    intermediate_endpoint.address,
+                    format_protocol(
+                        TransportProtocol::from_i32(intermediate_endpoint.protocol)
+                            .expect("invalid transport protocol")
+                    )
+                )
+                .unwrap();
+            }
+            if let Some(ref obfuscation) = endpoint.obfuscation {
+                write!(
+                    &mut out,
+                    " obfuscated by {} {} over {}",
+                    match ObfuscationType::from_i32(obfuscation.obfuscation_type)
+                        .expect("invalid obfuscation type")
+                    {
+                        ObfuscationType::Udp2tcp => "UDP-over-TCP",
+                        ObfuscationType::Shadowsocks => "Shadowsocks",
+                    },
+                    obfuscation.address,
+                    format_protocol(
+                        TransportProtocol::from_i32(obfuscation.protocol)
+                            .expect("invalid transport protocol")
+                    ),
+                )
+                .unwrap();
+            }
         }
     }
 
@@ -1,4 +1,11 @@
-use mullvad_management_interface::types::RelayLocation;
+use crate::{new_rpc_client, Error, Result};
+use mullvad_management_interface::{
+    types::{
+        GeoIpLocation, LocationConstraint, Ownership, OwnershipConstraint, RelayListCountry,
+        RelayLocation,
+    },
+    Code, ManagementServiceClient,
+};
 
 pub fn get_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("location")
@@ -20,6 +27,154 @@ pub fn get_subcommand() -> clap::App<'static, 'static> {
                 .help("The hostname")
                 .index(3),
         )
+        .arg(
+            clap::Arg::with_name("include")
+                .help(
+                    "An additional location to select relays from. Either a country code, \
+                     a country and city code such as 'se-got', or a hostname.",
+                )
+                .long("include")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("exclude")
+                .help(
+                    "A location to never select relays from. Either a country code, \
+                     a country and city code such as 'se-got', or a hostname.",
+                )
+                .long("exclude")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("exclude provider")
+                .help("A hosting provider to never select relays from")
+                .long("exclude-provider")
+                .multiple(true)
+                .number_of_values(1),
+        )
+}
+
+/// Returns the full location constraint given by the positional location and the `--include`,
+/// `--exclude` and `--exclude-provider` options.
+pub async fn get_location_constraint_from_args(
+    matches: &clap::ArgMatches<'_>,
+) -> Result<LocationConstraint> {
+    let location = get_constraint_from_args(matches);
+    let mut include = Vec::new();
+    if !location.country.is_empty() {
+        include.push(location);
+    }
+
+    let mut countries = None;
+    include.extend(parse_locations(matches.values_of("include"), &mut countries).await?);
+    let exclude = parse_locations(matches.values_of("exclude"), &mut countries).await?;
+    let exclude_providers = matches
+        .values_of("exclude provider")
+        .map(|providers| providers.map(str::to_owned).collect())
+        .unwrap_or_default();
+
+    Ok(LocationConstraint {
+        include,
+        exclude,
+        exclude_providers,
+    })
+}
+
+/// Parses locations written as `<country>`, `<country>-<city>` or `<hostname>`. The relay list
+/// is fetched the first time a location has to be checked against it.
+async fn parse_locations(
+    values: Option<clap::Values<'_>>,
+    countries: &mut Option<Vec<RelayListCountry>>,
+) -> Result<Vec<RelayLocation>> {
+    let mut locations = Vec::new();
+    for value in values.into_iter().flatten() {
+        if countries.is_none() {
+            *countries = Some(get_relay_locations().await?);
+        }
+        let location = parse_location(value, countries.as_ref().unwrap()).unwrap_or_else(|error| {
+            clap::Error::with_description(&error, clap::ErrorKind::InvalidValue).exit()
+        });
+        locations.push(location);
+    }
+    Ok(locations)
+}
+
+/// Parses a location written as `<country>`, `<country>-<city>` or `<hostname>`, and checks that
+/// it exists in the relay list.
+fn parse_location(
+    value: &str,
+    countries: &[RelayListCountry],
+) -> std::result::Result<RelayLocation, String> {
+    let value = value.to_lowercase();
+    let parts: Vec<&str> = value.split('-').collect();
+    match parts.as_slice() {
+        [country_code] if country_code.len() == 2 => {
+            find_country(countries, country_code)?;
+            Ok(RelayLocation {
+                country: country_code.to_string(),
+                ..Default::default()
+            })
+        }
+        [country_code, city_code] if country_code.len() == 2 && city_code.len() == 3 => {
+            let country = find_country(countries, country_code)?;
+            if !country.cities.iter().any(|city| city.code == *city_code) {
+                return Err(format!(
+                    "No city with the code '{}' was found in '{}'",
+                    city_code, country_code
+                ));
+            }
+            Ok(RelayLocation {
+                country: country_code.to_string(),
+                city: city_code.to_string(),
+                ..Default::default()
+            })
+        }
+        _ => find_hostname(countries, &value)
+            .ok_or_else(|| format!("No relay with the hostname '{}' was found", value)),
+    }
+}
+
+fn find_country<'a>(
+    countries: &'a [RelayListCountry],
+    code: &str,
+) -> std::result::Result<&'a RelayListCountry, String> {
+    countries
+        .iter()
+        .find(|country| country.code == code)
+        .ok_or_else(|| format!("No country with the code '{}' was found", code))
+}
+
+fn find_hostname(countries: &[RelayListCountry], hostname: &str) -> Option<RelayLocation> {
+    for country in countries {
+        for city in &country.cities {
+            if city.relays.iter().any(|relay| relay.hostname == hostname) {
+                return Some(RelayLocation {
+                    country: country.code.clone(),
+                    city: city.code.clone(),
+                    hostname: hostname.to_owned(),
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Returns the relay list of the daemon.
+pub async fn get_relay_locations() -> Result<Vec<RelayListCountry>> {
+    let mut rpc = new_rpc_client().await?;
+    let mut locations = rpc
+        .get_relay_locations(())
+        .await
+        .map_err(|error| Error::RpcFailedExt("Failed to obtain relay locations", error))?
+        .into_inner();
+
+    let mut countries = Vec::new();
+    while let Some(country) = locations.message().await? {
+        countries.push(country);
+    }
+    Ok(countries)
 }
 
 pub fn get_constraint_from_args(matches: &clap::ArgMatches<'_>) -> RelayLocation {
@@ -84,6 +239,52 @@ pub fn format_location(location: Option<&RelayLocation>) -> String {
     "any location".to_string()
 }
 
+/// Returns the location described by `constraint` if it consists of a single location.
+pub fn single_location(constraint: &LocationConstraint) -> Option<RelayLocation> {
+    if constraint.include.len() == 1
+        && constraint.exclude.is_empty()
+        && constraint.exclude_providers.is_empty()
+    {
+        Some(constraint.include[0].clone())
+    } else {
+        None
+    }
+}
+
+pub fn format_location_constraint(constraint: Option<&LocationConstraint>) -> String {
+    let constraint = match constraint {
+        Some(constraint) => constraint,
+        None => return format_location(None),
+    };
+
+    let mut out = if constraint.include.is_empty() {
+        format_location(None)
+    } else {
+        constraint
+            .include
+            .iter()
+            .map(|location| format_location(Some(location)))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    };
+    if !constraint.exclude.is_empty() {
+        out.push_str(" except ");
+        out.push_str(
+            &constraint
+                .exclude
+                .iter()
+                .map(|location| format_location(Some(location)))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+    if !constraint.exclude_providers.is_empty() {
+        out.push_str(" not hosted by ");
+        out.push_str(&constraint.exclude_providers.join(", "));
+    }
+    out
+}
+
 pub fn format_providers(providers: &Vec<String>) -> String {
     if !providers.is_empty() {
         format!("provider(s) {}", providers.join(", "))
@@ -92,6 +293,74 @@ pub fn format_providers(providers: &Vec<String>) -> String {
     }
 }
 
+pub fn get_ownership_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("ownership").arg(
+        clap::Arg::with_name("ownership")
+            .help(
+                "Whether to use Mullvad-owned servers, rented servers, or 'any' for no preference.",
+            )
+            .required(true)
+            .index(1)
+            .possible_values(&["any", "owned", "rented"]),
+    )
+}
+
+/// Parses an ownership constraint. Can be infallible because the possible values are limited
+/// with clap.
+pub fn parse_ownership_constraint(ownership: &str) -> Option<OwnershipConstraint> {
+    let ownership = match ownership {
+        "any" => return None,
+        "owned" => Ownership::MullvadOwned,
+        "rented" => Ownership::Rented,
+        _ => unreachable!(),
+    };
+    Some(OwnershipConstraint {
+        ownership: ownership as i32,
+    })
+}
+
+/// Returns a suffix describing the ownership constraint, or an empty string if there is none.
+pub fn format_ownership(constraint: Option<&OwnershipConstraint>) -> String {
+    match constraint.and_then(|constraint| Ownership::from_i32(constraint.ownership)) {
+        Some(Ownership::MullvadOwned) => " on Mullvad-owned servers".to_string(),
+        Some(Ownership::Rented) => " on rented servers".to_string(),
+        None => String::new(),
+    }
+}
+
+pub fn format_relay_ownership(owned: bool) -> &'static str {
+    if owned {
+        "Mullvad-owned"
+    } else {
+        "rented"
+    }
+}
+
+/// Returns the current location, or `None` if location data is unavailable.
+pub async fn get_current_location(
+    rpc: &mut ManagementServiceClient,
+) -> Result<Option<GeoIpLocation>> {
+    match rpc.get_current_location(()).await {
+        Ok(response) => Ok(Some(response.into_inner())),
+        Err(status) if status.code() == Code::NotFound => Ok(None),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
+/// Sorts countries, their cities and the relays in each city by name.
+pub fn sort_countries(countries: &mut Vec<RelayListCountry>) {
+    countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+    for country in countries {
+        country
+            .cities
+            .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+        for city in &mut country.cities {
+            city.relays
+                .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+        }
+    }
+}
+
 pub fn country_code_validator<T: AsRef<str>>(code: T) -> std::result::Result<(), String> {
     if code.as_ref().len() == 2 || code.as_ref() == "any" {
         Ok(())
@@ -107,3 +376,62 @@ pub fn city_code_validator<T: AsRef<str>>(code: T) -> std::result::Result<(), St
         Err(String::from("City codes must be three letters"))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_management_interface::types::{Relay, RelayListCity};
+
+    fn countries() -> Vec<RelayListCountry> {
+        vec![RelayListCountry {
+            name: "Sweden".to_owned(),
+            code: "se".to_owned(),
+            cities: vec![RelayListCity {
+                name: "Gothenburg".to_owned(),
+                code: "got".to_owned(),
+                relays: vec![Relay {
+                    hostname: "se-got-wg-001".to_owned(),
+                    ..Relay::default()
+                }],
+                ..RelayListCity::default()
+            }],
+        }]
+    }
+
+    #[test]
+    fn test_parse_location() {
+        let countries = countries();
+
+        let location = parse_location("SE", &countries).unwrap();
+        assert_eq!(location.country, "se");
+        assert!(location.city.is_empty());
+
+        let location = parse_location("se-got", &countries).unwrap();
+        assert_eq!(
+            (location.country.as_str(), location.city.as_str()),
+            ("se", "got")
+        );
+        assert!(location.hostname.is_empty());
+
+        let location = parse_location("se-got-wg-001", &countries).unwrap();
+        assert_eq!(
+            location,
+            RelayLocation {
+                country: "se".to_owned(),
+                city: "got".to_owned(),
+                hostname: "se-got-wg-001".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_location() {
+        let countries = countries();
+
+        assert!(parse_location("de", &countries).is_err());
+        assert!(parse_location("de-fra", &countries).is_err());
+        assert!(parse_location("se-sto", &countries).is_err());
+        assert!(parse_location("se-got-wg-002", &countries).is_err());
+        assert!(parse_location("sweden", &countries).is_err());
+    }
+}
@@ -8,8 +8,10 @@ use talpid_types::ErrorExt;
 pub use mullvad_management_interface::{self, new_rpc_client};
 
 mod cmds;
+mod completions;
 mod format;
 mod location;
+mod man_page;
 mod state;
 
 pub const BIN_NAME: &str = "mullvad";
@@ -43,12 +45,36 @@ pub enum Error {
 
     #[error(display = "Failed to listen for status updates")]
     StatusListenerFailed,
+
+    #[error(display = "Unable to read {}", _0)]
+    ReadFile(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to write {}", _0)]
+    WriteFile(String, #[error(source)] io::Error),
+
+    #[error(display = "Invalid WireGuard configuration")]
+    ParseWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
+
+    #[error(display = "Failed to serialize JSON output")]
+    SerializeJson(#[error(source)] serde_json::Error),
+
+    #[error(display = "Terminal error")]
+    Terminal(#[error(source)] crossterm::ErrorKind),
 }
 
 #[tokio::main]
 async fn main() {
-    let exit_code = match run().await {
+    env_logger::init();
+
+    let commands = cmds::get_commands();
+    let app_matches = build_cli(&commands).get_matches();
+
+    let exit_code = match run(&commands, &app_matches).await {
         Ok(_) => 0,
+        Err(error) if app_matches.is_present("json") => {
+            format::print_json_error(&error);
+            1
+        }
         Err(error) => {
             match &error {
                 Error::RpcFailed(status) => {
@@ -68,30 +94,10 @@ async fn main() {
     std::process::exit(exit_code);
 }
 
-async fn run() -> Result<()> {
-    env_logger::init();
-
-    let commands = cmds::get_commands();
-    let app = build_cli(&commands);
-
-    let app = app.subcommand(
-        clap::SubCommand::with_name("shell-completions")
-            .about("Generates completion scripts for your shell")
-            .arg(
-                clap::Arg::with_name("SHELL")
-                    .required(true)
-                    .possible_values(&clap::Shell::variants()[..])
-                    .help("The shell to generate the script for"),
-            )
-            .arg(
-                clap::Arg::with_name("DIR")
-                    .default_value("./")
-                    .help("Output directory where the shell completions are written"),
-            )
-            .setting(clap::AppSettings::Hidden),
-    );
-
-    let app_matches = app.get_matches();
+async fn run(
+    commands: &HashMap<&'static str, Box<dyn Command>>,
+    app_matches: &clap::ArgMatches<'_>,
+) -> Result<()> {
     match app_matches.subcommand() {
         ("shell-completions", Some(sub_matches)) => {
             let shell = sub_matches
@@ -99,9 +105,11 @@ async fn run() -> Result<()> {
                 .unwrap()
                 .parse()
                 .expect("Invalid shell");
-            let out_dir = sub_matches.value_of_os("DIR").unwrap();
-            build_cli(&commands).gen_completions(BIN_NAME, shell, out_dir);
-            Ok(())
+            completions::generate(build_cli(commands), shell, sub_matches.value_of_os("DIR"))
+        }
+        ("complete", Some(sub_matches)) => completions::complete(sub_matches).await,
+        ("man-page", Some(sub_matches)) => {
+            man_page::generate(|| build_cli(commands), sub_matches.value_of_os("DIR"))
         }
         (sub_name, Some(sub_matches)) => {
             if let Some(cmd) = commands.get(sub_name) {
@@ -126,7 +134,16 @@ fn build_cli(commands: &HashMap<&'static str, Box<dyn Command>>) -> clap::App<'s
             clap::AppSettings::DisableHelpSubcommand,
             clap::AppSettings::VersionlessSubcommands,
         ])
+        .arg(
+            clap::Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print the output of supported commands as JSON"),
+        )
         .subcommands(commands.values().map(|cmd| cmd.clap_subcommand()))
+        .subcommand(completions::get_shell_completions_subcommand())
+        .subcommand(completions::get_complete_subcommand())
+        .subcommand(man_page::get_subcommand())
 }
 
 #[async_trait]
@@ -0,0 +1,130 @@
+//! Generation of a man page from the same clap definitions as the command line interface.
+
+use crate::{Error, Result, BIN_NAME, PRODUCT_VERSION};
+use clap::crate_description;
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    iter,
+    path::Path,
+};
+
+/// Width that the help texts are wrapped to.
+const TEXT_WIDTH: usize = 80;
+
+pub fn get_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("man-page")
+        .about("Generates a man page that describes all commands")
+        .setting(clap::AppSettings::Hidden)
+        .arg(clap::Arg::with_name("DIR").help(
+            "Output directory where the man page is written. The man page is printed if no \
+             directory is given",
+        ))
+}
+
+/// Writes the man page for the app returned by `build_app` to `out_dir`, or to stdout if no
+/// directory is given. The app is built once for every subcommand, since clap only renders the
+/// help of a subcommand while parsing arguments.
+pub fn generate(
+    build_app: impl Fn() -> clap::App<'static, 'static>,
+    out_dir: Option<&OsStr>,
+) -> Result<()> {
+    let mut commands = Vec::new();
+    subcommands(&build_app(), &mut Vec::new(), &mut commands);
+    commands.sort();
+
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+        BIN_NAME.to_uppercase(),
+        BIN_NAME,
+        PRODUCT_VERSION.trim(),
+    );
+    page.push_str(&format!(
+        ".SH NAME\n{} \\- {}\n",
+        BIN_NAME,
+        escape(crate_description!())
+    ));
+    page.push_str(".SH DESCRIPTION\n");
+    page.push_str(&preformatted(&help(&build_app, &[])));
+    page.push_str(".SH COMMANDS\n");
+    for (path, about) in &commands {
+        page.push_str(&format!(
+            ".SS \"{}\"\n",
+            escape(&format!("{} {}", BIN_NAME, path.join(" ")))
+        ));
+        if let Some(about) = about {
+            page.push_str(&format!("{}\n.PP\n", escape(about)));
+        }
+        page.push_str(&preformatted(&help(&build_app, path)));
+    }
+
+    match out_dir {
+        Some(out_dir) => {
+            let path = Path::new(out_dir).join(format!("{}.1", BIN_NAME));
+            fs::write(&path, page)
+                .map_err(|error| Error::WriteFile(path.display().to_string(), error))
+        }
+        None => io::stdout()
+            .write_all(page.as_bytes())
+            .map_err(|error| Error::WriteFile("stdout".to_owned(), error)),
+    }
+}
+
+/// Collects the path and description of every visible subcommand of `app`, recursively. Clap 2
+/// has no public API for this, so the subcommands are read from its parser.
+fn subcommands(
+    app: &clap::App<'_, '_>,
+    path: &mut Vec<String>,
+    commands: &mut Vec<(Vec<String>, Option<String>)>,
+) {
+    for subcommand in &app.p.subcommands {
+        if subcommand.p.is_set(clap::AppSettings::Hidden) {
+            continue;
+        }
+        path.push(subcommand.p.meta.name.clone());
+        commands.push((path.clone(), subcommand.p.meta.about.map(str::to_owned)));
+        subcommands(subcommand, path, commands);
+        path.pop();
+    }
+}
+
+/// Returns the help text of the subcommand at `path`, starting with the usage.
+fn help(build_app: &impl Fn() -> clap::App<'static, 'static>, path: &[String]) -> String {
+    let args = iter::once(BIN_NAME)
+        .chain(path.iter().map(String::as_str))
+        .chain(iter::once("--help"));
+    match build_app()
+        .set_term_width(TEXT_WIDTH)
+        .get_matches_from_safe(args)
+    {
+        Err(error) if error.kind == clap::ErrorKind::HelpDisplayed => {
+            match error.message.find("USAGE:") {
+                Some(usage) => error.message[usage..].to_owned(),
+                None => error.message,
+            }
+        }
+        _ => unreachable!("--help did not display the help"),
+    }
+}
+
+/// Returns `text` as a block that is displayed as is.
+fn preformatted(text: &str) -> String {
+    let mut block = String::from(".nf\n");
+    for line in text.trim_end().lines() {
+        block.push_str(&escape(line));
+        block.push('\n');
+    }
+    block.push_str(".fi\n");
+    block
+}
+
+/// Escapes characters that roff would otherwise interpret.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,171 @@
+use chrono::Utc;
+use mullvad_types::{
+    connection_history::{ConnectionHistoryEntry, CONNECTION_HISTORY_FILE},
+    states::TunnelState,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    path::{Path, PathBuf},
+};
+use talpid_types::ErrorExt;
+use tokio::{fs, io::AsyncWriteExt};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Unable to read connection history file")]
+    Read(#[error(source)] io::Error),
+
+    #[error(display = "Failed to serialize connection history entry")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "Unable to write connection history file")]
+    Write(#[error(source)] io::Error),
+}
+
+/// Number of entries to keep.
+const CONNECTION_HISTORY_LIMIT: usize = 1000;
+
+/// A bounded journal of tunnel state transitions. Every entry is appended to a file with one
+/// JSON object per line. When the file holds twice as many entries as are kept, it is rewritten
+/// with only the newest ones.
+pub struct ConnectionHistory {
+    path: PathBuf,
+    entries: VecDeque<ConnectionHistoryEntry>,
+    entries_in_file: usize,
+}
+
+impl ConnectionHistory {
+    /// Loads the journal from the cache directory. Entries that cannot be read are skipped.
+    pub async fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(CONNECTION_HISTORY_FILE);
+        let mut history = ConnectionHistory {
+            path,
+            entries: VecDeque::new(),
+            entries_in_file: 0,
+        };
+        if let Err(error) = history.read_entries().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to load connection history")
+            );
+        }
+        history
+    }
+
+    async fn read_entries(&mut self) -> Result<()> {
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(Error::Read(error)),
+        };
+        for line in contents.lines() {
+            self.entries_in_file += 1;
+            match serde_json::from_str(line) {
+                Ok(entry) => self.push_entry(entry),
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Skipping invalid connection history entry")
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Records that `state` was entered now.
+    pub async fn add(&mut self, state: TunnelState) -> Result<()> {
+        let entry = ConnectionHistoryEntry {
+            timestamp: Utc::now(),
+            state,
+        };
+        self.push_entry(entry.clone());
+
+        if self.entries_in_file >= 2 * CONNECTION_HISTORY_LIMIT {
+            self.rewrite_file().await
+        } else {
+            self.append_to_file(&entry).await
+        }
+    }
+
+    /// Returns all entries, oldest first.
+    pub fn entries(&self) -> Vec<ConnectionHistoryEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    fn push_entry(&mut self, entry: ConnectionHistoryEntry) {
+        if self.entries.len() >= CONNECTION_HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    async fn append_to_file(&mut self, entry: &ConnectionHistoryEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry).map_err(Error::Serialize)?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(Error::Write)?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(Error::Write)?;
+        self.entries_in_file += 1;
+        Ok(())
+    }
+
+    /// Replaces the file with one that only holds the entries in memory.
+    async fn rewrite_file(&mut self) -> Result<()> {
+        let mut contents = String::new();
+        for entry in &self.entries {
+            contents.push_str(&serde_json::to_string(entry).map_err(Error::Serialize)?);
+            contents.push('\n');
+        }
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp_path, contents)
+            .await
+            .map_err(Error::Write)?;
+        fs::rename(&temp_path, &self.path)
+            .await
+            .map_err(Error::Write)?;
+        self.entries_in_file = self.entries.len();
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::tunnel::ActionAfterDisconnect;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_bounded_journal() {
+        Runtime::new().unwrap().block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut history = ConnectionHistory::load(dir.path()).await;
+            for _ in 0..2 * CONNECTION_HISTORY_LIMIT {
+                history.add(TunnelState::Disconnected).await.unwrap();
+            }
+            history
+                .add(TunnelState::Disconnecting(ActionAfterDisconnect::Reconnect))
+                .await
+                .unwrap();
+
+            assert_eq!(history.entries().len(), CONNECTION_HISTORY_LIMIT);
+            assert_eq!(history.entries_in_file, CONNECTION_HISTORY_LIMIT);
+
+            let loaded = ConnectionHistory::load(dir.path()).await;
+            assert_eq!(loaded.entries(), history.entries());
+            assert_eq!(
+                loaded.entries().last().unwrap().state,
+                TunnelState::Disconnecting(ActionAfterDisconnect::Reconnect)
+            );
+        });
+    }
+}
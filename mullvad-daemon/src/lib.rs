@@ -6,8 +6,10 @@ extern crate serde;
 
 
 pub mod account_history;
+mod connection_history;
 pub mod exception_logging;
 mod geoip;
+mod local_dns;
 pub mod logging;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
@@ -28,14 +30,17 @@ use log::{debug, error, info, warn};
 use mullvad_rpc::AccountsProxy;
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
+    connection_history::ConnectionHistoryEntry,
+    dns_blocklist::{DnsBlocklistSource, DnsBlocklistStats},
     endpoint::MullvadEndpoint,
     location::GeoIpLocation,
     relay_constraints::{
-        BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, RelaySettings,
-        RelaySettingsUpdate,
+        BridgeSettings, BridgeState, Constraint, GeographicLocationConstraint,
+        InternalBridgeConstraints, LocationConstraint, RelayConstraints, RelaySelectionMode,
+        RelaySettings, RelaySettingsUpdate,
     },
     relay_list::{Relay, RelayList},
-    settings::{DnsOptions, DnsState, Settings},
+    settings::{DnsOptions, DnsState, NetworkAction, NetworkRuleEvent, NetworkRules, Settings},
     states::{TargetState, TunnelState},
     version::{AppVersion, AppVersionInfo},
     wireguard::{KeygenEvent, RotationInterval},
@@ -46,7 +51,7 @@ use std::os::unix::io::RawFd;
 use std::{
     marker::PhantomData,
     mem,
-    net::IpAddr,
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     pin::Pin,
     sync::{mpsc as sync_mpsc, Arc, Weak},
@@ -56,12 +61,16 @@ use std::{
 use talpid_core::split_tunnel;
 use talpid_core::{
     mpsc::Sender,
+    tunnel::{TunnelMetadata, TunnelStats},
     tunnel_state_machine::{self, TunnelCommand, TunnelParametersGenerator},
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
 use talpid_types::{
-    net::{openvpn, Endpoint, TransportProtocol, TunnelEndpoint, TunnelParameters, TunnelType},
+    net::{
+        openvpn, Endpoint, NetworkIdentity, SplitDnsRule, TransportProtocol, TunnelEndpoint,
+        TunnelParameters, TunnelType,
+    },
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
     ErrorExt,
 };
@@ -80,6 +89,9 @@ const FIRST_KEY_PUSH_TIMEOUT: Duration = Duration::from_secs(5);
 /// Delay between generating a new WireGuard key and reconnecting
 const WG_RECONNECT_DELAY: Duration = Duration::from_secs(4 * 60);
 
+/// How often DNS blocklists that are downloaded are updated.
+const DNS_BLOCKLIST_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
 lazy_static::lazy_static! {
     static ref DNS_AD_BLOCKING_SERVERS: [IpAddr; 1] = ["100.64.0.1".parse().unwrap()];
     static ref DNS_TRACKER_BLOCKING_SERVERS: [IpAddr; 1] = ["100.64.0.2".parse().unwrap()];
@@ -119,6 +131,12 @@ pub enum Error {
     #[error(display = "No matching entry relay was found")]
     NoEntryRelayAvailable,
 
+    #[error(display = "An OpenVPN entry relay cannot be used when {}", _0)]
+    OpenVpnEntryBridgeConflict(&'static str),
+
+    #[error(display = "No server for the selected obfuscation was found")]
+    NoObfuscatorAvailable,
+
     #[error(display = "No account token is set")]
     NoAccountToken,
 
@@ -128,6 +146,18 @@ pub enum Error {
     #[error(display = "Settings error")]
     SettingsError(#[error(source)] settings::Error),
 
+    #[error(display = "Unable to parse imported settings")]
+    ParseImportedSettings(#[error(source)] mullvad_types::settings::Error),
+
+    #[error(display = "Imported settings refer to unknown relay locations: {}", _0)]
+    UnknownRelayLocations(String),
+
+    #[error(display = "Invalid DNS blocklist: {}", _0)]
+    InvalidDnsBlocklist(String),
+
+    #[error(display = "Split DNS rules cannot be applied: {}", _0)]
+    SplitDnsNotSupported(String),
+
     #[error(display = "Account history error")]
     AccountHistory(#[error(source)] account_history::Error),
 
@@ -172,6 +202,12 @@ pub enum Error {
 
     #[error(display = "Failed to open cached target tunnel state")]
     OpenCachedTargetState(#[error(source)] io::Error),
+
+    #[error(display = "No WireGuard tunnel is active")]
+    NoWireguardTunnel,
+
+    #[error(display = "Failed to export the WireGuard configuration")]
+    ExportWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
 }
 
 /// Enum representing commands that can be sent to the daemon.
@@ -196,6 +232,8 @@ pub enum DaemonCommand {
     SubmitVoucher(ResponseTx<VoucherSubmission, Error>, String),
     /// Request account history
     GetAccountHistory(oneshot::Sender<Vec<AccountToken>>),
+    /// Request the journal of tunnel state transitions, oldest first
+    GetConnectionHistory(oneshot::Sender<Vec<ConnectionHistoryEntry>>),
     /// Request account history
     RemoveAccountFromHistory(ResponseTx<(), Error>, AccountToken),
     /// Clear account history
@@ -217,28 +255,54 @@ pub enum DaemonCommand {
     SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
+    /// Set the rules for connecting or disconnecting when joining a network
+    SetNetworkRules(ResponseTx<(), settings::Error>, NetworkRules),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(ResponseTx<(), settings::Error>, Option<u16>),
     /// Set proxy details for OpenVPN
     SetBridgeSettings(ResponseTx<(), settings::Error>, BridgeSettings),
     /// Set proxy state
     SetBridgeState(ResponseTx<(), settings::Error>, BridgeState),
+    /// Set the strategy used to pick among matching relays
+    SetRelaySelectionMode(ResponseTx<(), settings::Error>, RelaySelectionMode),
+    /// Set how often to reconnect to a different relay. `None` disables scheduled reconnects
+    SetReconnectInterval(ResponseTx<(), settings::Error>, Option<RotationInterval>),
     /// Set if IPv6 should be enabled in the tunnel
     SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
     /// Set DNS options or servers to use
-    SetDnsOptions(ResponseTx<(), settings::Error>, DnsOptions),
+    SetDnsOptions(ResponseTx<(), Error>, DnsOptions),
+    /// Get statistics for the DNS blocklists in use
+    GetDnsBlocklistStats(oneshot::Sender<DnsBlocklistStats>),
+    /// Reload the enabled DNS blocklists, downloading them again. Responds once they are loaded
+    UpdateDnsBlocklists(oneshot::Sender<()>),
+    /// Get the traffic statistics of the current tunnel. Responds with `None` unless connected
+    GetTrafficStats(oneshot::Sender<Option<TunnelStats>>),
     /// Set MTU for wireguard tunnels
     SetWireguardMtu(ResponseTx<(), settings::Error>, Option<u16>),
+    /// Set whether to negotiate a post-quantum preshared key for wireguard tunnels
+    SetQuantumResistantTunnel(ResponseTx<(), settings::Error>, bool),
     /// Set automatic key rotation interval for wireguard tunnels
     SetWireguardRotationInterval(ResponseTx<(), settings::Error>, Option<RotationInterval>),
     /// Get the daemon settings
     GetSettings(oneshot::Sender<Settings>),
+    /// Store the current connection settings as a named profile
+    CreateProfile(ResponseTx<(), settings::Error>, String),
+    /// Replace the connection settings with the ones in a named profile
+    ActivateProfile(ResponseTx<(), settings::Error>, String),
+    /// Remove a named profile
+    DeleteProfile(ResponseTx<(), settings::Error>, String),
+    /// Replace all settings with serialized ones, possibly written by an older version. The
+    /// account token is only replaced if the boolean is set. Returns the names of the changed
+    /// settings
+    ImportSettings(ResponseTx<Vec<String>, Error>, String, bool),
     /// Generate new wireguard key
     GenerateWireguardKey(ResponseTx<wireguard::KeygenEvent, Error>),
     /// Return a public key of the currently set wireguard private key, if there is one
     GetWireguardKey(ResponseTx<Option<wireguard::PublicKey>, Error>),
     /// Verify if the currently set wireguard key is valid.
     VerifyWireguardKey(ResponseTx<bool, Error>),
+    /// Return the configuration of the current WireGuard tunnel in the `wg-quick` format
+    ExportWireguardConfig(ResponseTx<String, Error>),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<Option<AppVersionInfo>>),
     /// Get current version of the app
@@ -291,6 +355,16 @@ pub(crate) enum InternalDaemonEvent {
     NewAccountEvent(AccountToken, oneshot::Sender<Result<String, Error>>),
     /// The background job fetching new `AppVersionInfo`s got a new info object.
     NewAppVersionInfo(AppVersionInfo),
+    /// The GeoIP location of the device was fetched while disconnected.
+    DeviceLocation(GeoIpLocation),
+    /// The device joined a different network.
+    NetworkIdentity(NetworkIdentity),
+    /// A tunnel came up.
+    TunnelUp(TunnelMetadata),
+    /// The reconnect interval has passed since the tunnel was connected.
+    PeriodicReconnect,
+    /// The enabled DNS blocklists have been loaded.
+    DnsBlocklistLoaded(Arc<local_dns::Blocklist>, Option<oneshot::Sender<()>>),
 }
 
 impl From<TunnelStateTransition> for InternalDaemonEvent {
@@ -311,6 +385,18 @@ impl From<AppVersionInfo> for InternalDaemonEvent {
     }
 }
 
+impl From<NetworkIdentity> for InternalDaemonEvent {
+    fn from(network: NetworkIdentity) -> Self {
+        InternalDaemonEvent::NetworkIdentity(network)
+    }
+}
+
+impl From<TunnelMetadata> for InternalDaemonEvent {
+    fn from(metadata: TunnelMetadata) -> Self {
+        InternalDaemonEvent::TunnelUp(metadata)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum DaemonExecutionState {
     Running,
@@ -474,6 +560,9 @@ pub trait EventListener {
 
     /// Notify clients of a key generation event.
     fn notify_key_event(&self, key_event: KeygenEvent);
+
+    /// Notify that the target state was changed because of the network rules.
+    fn notify_network_rule_applied(&self, event: NetworkRuleEvent);
 }
 
 pub struct Daemon<L: EventListener> {
@@ -487,17 +576,40 @@ pub struct Daemon<L: EventListener> {
     rx: mpsc::UnboundedReceiver<InternalDaemonEvent>,
     tx: DaemonEventSender,
     reconnection_job: Option<AbortHandle>,
+    periodic_reconnect_job: Option<AbortHandle>,
+    /// Relay that should not be selected when generating tunnel parameters, until the tunnel
+    /// connects.
+    relay_to_avoid: Option<GeographicLocationConstraint>,
     event_listener: L,
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
+    connection_history: connection_history::ConnectionHistory,
     accounts_proxy: AccountsProxy,
     rpc_runtime: mullvad_rpc::MullvadRpcRuntime,
     rpc_handle: mullvad_rpc::rest::MullvadRestHandle,
     wireguard_key_manager: wireguard::KeyManager,
     version_updater_handle: version_check::VersionUpdaterHandle,
     relay_selector: relays::RelaySelector,
+    local_resolver: Option<local_dns::LocalResolver>,
+    dns_blocklist: Arc<local_dns::Blocklist>,
+    /// Job that loads the DNS blocklists, and reloads them periodically.
+    dns_blocklist_job: Option<AbortHandle>,
+    dns_blocklist_service: mullvad_rpc::rest::RequestServiceHandle,
+    /// Directory that DNS blocklist files must be stored in.
+    dns_blocklist_dir: PathBuf,
+    /// Gateway of the current tunnel, which the local resolver forwards queries to when the
+    /// default DNS servers are used. The gateway of an OpenVPN tunnel is only known once it is up.
+    tunnel_gateway: Option<IpAddr>,
+    /// The network that the host was last reported to be on, which the network rules are
+    /// evaluated against again when they change.
+    network_identity: Option<NetworkIdentity>,
+    /// Parameters of the last generated WireGuard tunnel. `None` if the last parameters were for
+    /// OpenVPN.
+    last_wireguard_parameters: Option<wireguard::TunnelParameters>,
     last_generated_relay: Option<Relay>,
     last_generated_bridge_relay: Option<Relay>,
+    /// Relays that the tunnel passes through before the exit relay, starting with the entry relay.
+    last_generated_entry_relays: Vec<Relay>,
     app_version_info: Option<AppVersionInfo>,
     shutdown_tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
     /// oneshot channel that completes once the tunnel state machine has been shut down
@@ -550,6 +662,8 @@ where
         .await
         .map_err(Error::InitRpcFactory)?;
         let rpc_handle = rpc_runtime.mullvad_rest_handle();
+        let dns_blocklist_service =
+            rpc_runtime.rest_handle_with_tls_config(Arc::new(local_dns::system_tls_config()));
 
         let relay_list_listener = event_listener.clone();
         let on_relay_list_update = move |relay_list: &RelayList| {
@@ -561,10 +675,12 @@ where
             on_relay_list_update,
             &resource_dir,
             &cache_dir,
+            Arc::new(relays::TcpLatencyProber),
         );
 
 
         let mut settings = SettingsPersister::load(&settings_dir).await;
+        relay_selector.set_selection_mode(settings.relay_selection_mode);
 
         if version::is_beta_version() {
             let _ = settings.set_show_beta_releases(true).await;
@@ -583,6 +699,7 @@ where
             account_history::AccountHistory::new(&cache_dir, &settings_dir, rpc_handle.clone())
                 .await
                 .map_err(Error::LoadAccountHistory)?;
+        let connection_history = connection_history::ConnectionHistory::load(&cache_dir).await;
 
         // Restore the tunnel to a previous state
         let target_cache = cache_dir.join(TARGET_START_STATE_FILE);
@@ -638,13 +755,20 @@ where
         let tunnel_command_tx = tunnel_state_machine::spawn(
             settings.allow_lan,
             settings.block_when_disconnected,
-            Self::get_dns_resolvers(&settings.tunnel_options.dns_options),
+            Self::get_dns_resolvers(&settings.tunnel_options.dns_options, None),
+            settings.tunnel_options.dns_options.split_rules.clone(),
+            Self::get_plain_upstream_addresses(&Self::get_local_resolver_upstreams(
+                &settings.tunnel_options.dns_options,
+                None,
+            )),
             initial_api_endpoint,
             tunnel_parameters_generator,
             log_dir,
             resource_dir,
             cache_dir.clone(),
             internal_event_tx.to_specialized_sender(),
+            internal_event_tx.to_specialized_sender(),
+            internal_event_tx.to_specialized_sender(),
             tunnel_state_machine_shutdown_tx,
             initial_target_state != TargetState::Secured,
             #[cfg(target_os = "android")]
@@ -681,17 +805,29 @@ where
             rx: internal_event_rx,
             tx: internal_event_tx,
             reconnection_job: None,
+            periodic_reconnect_job: None,
+            relay_to_avoid: None,
             event_listener,
             settings,
             account_history,
+            connection_history,
             rpc_runtime,
             accounts_proxy: AccountsProxy::new(rpc_handle.clone()),
             rpc_handle,
             wireguard_key_manager,
             version_updater_handle,
             relay_selector,
+            local_resolver: None,
+            dns_blocklist: Arc::new(local_dns::Blocklist::empty()),
+            dns_blocklist_job: None,
+            dns_blocklist_service,
+            dns_blocklist_dir: settings_dir.join(local_dns::blocklist::FILE_DIR_NAME),
+            tunnel_gateway: None,
+            network_identity: None,
+            last_wireguard_parameters: None,
             last_generated_relay: None,
             last_generated_bridge_relay: None,
+            last_generated_entry_relays: vec![],
             app_version_info,
             shutdown_tasks: vec![],
             tunnel_state_machine_shutdown_signal,
@@ -699,11 +835,48 @@ where
         };
 
         daemon.ensure_wireguard_keys_for_current_account().await;
+        daemon.update_local_resolver().await;
+        if daemon.local_resolver.is_some() {
+            daemon.send_dns_config();
+        }
+        daemon.reload_dns_blocklists(None);
 
         Ok(daemon)
     }
 
-    fn get_dns_resolvers(options: &DnsOptions) -> Option<Vec<IpAddr>> {
+    /// Returns whether the local resolver is used as the DNS server, either to forward queries
+    /// to encrypted DNS servers, or to apply blocklists.
+    fn uses_local_resolver(options: &DnsOptions) -> bool {
+        (options.state == DnsState::Encrypted && !options.encrypted_options.upstreams.is_empty())
+            || options.has_enabled_blocklists()
+    }
+
+    /// Returns the DNS servers to use, or `None` if the tunnel gateway should be used. The local
+    /// resolver is only used if it is running, so that DNS keeps working if it failed to start.
+    fn get_dns_resolvers(
+        options: &DnsOptions,
+        local_resolver: Option<&local_dns::LocalResolver>,
+    ) -> Option<Vec<IpAddr>> {
+        match local_resolver {
+            Some(resolver) if Self::uses_local_resolver(options) => {
+                Some(vec![resolver.local_addr().ip()])
+            }
+            _ => Self::get_plain_dns_resolvers(options),
+        }
+    }
+
+    /// Sends the DNS servers and split DNS rules to use to the tunnel state machine.
+    fn send_dns_config(&mut self) {
+        let dns_options = &self.settings.tunnel_options.dns_options;
+        let command = TunnelCommand::Dns(
+            Self::get_dns_resolvers(dns_options, self.local_resolver.as_ref()),
+            dns_options.split_rules.clone(),
+        );
+        self.send_tunnel_command(command);
+    }
+
+    /// Returns the plain DNS servers to use, or `None` if the tunnel gateway should be used.
+    fn get_plain_dns_resolvers(options: &DnsOptions) -> Option<Vec<IpAddr>> {
         match options.state {
             DnsState::Default => {
                 if options.default_options.block_ads {
@@ -725,6 +898,130 @@ where
                     Some(options.custom_options.addresses.clone())
                 }
             }
+            DnsState::Encrypted => None,
+        }
+    }
+
+    /// Returns the servers that the local resolver forwards queries to. Plain DNS servers are
+    /// used unless encrypted DNS servers are configured, and `tunnel_gateway` is used if no DNS
+    /// servers are configured at all.
+    fn get_local_resolver_upstreams(
+        options: &DnsOptions,
+        tunnel_gateway: Option<IpAddr>,
+    ) -> Vec<local_dns::Upstream> {
+        if !Self::uses_local_resolver(options) {
+            return vec![];
+        }
+        if options.state == DnsState::Encrypted && !options.encrypted_options.upstreams.is_empty() {
+            return options
+                .encrypted_options
+                .upstreams
+                .iter()
+                .cloned()
+                .map(local_dns::Upstream::Encrypted)
+                .collect();
+        }
+        Self::get_plain_dns_resolvers(options)
+            .or_else(|| tunnel_gateway.map(|gateway| vec![gateway]))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|address| {
+                local_dns::Upstream::Plain(SocketAddr::new(address, local_dns::PLAIN_DNS_PORT))
+            })
+            .collect()
+    }
+
+    /// Returns the addresses of the plain DNS servers among `upstreams`. These must be allowed
+    /// through the firewall.
+    fn get_plain_upstream_addresses(upstreams: &[local_dns::Upstream]) -> Vec<IpAddr> {
+        upstreams
+            .iter()
+            .filter_map(|upstream| match upstream {
+                local_dns::Upstream::Plain(address) => Some(address.ip()),
+                local_dns::Upstream::Encrypted(_) => None,
+            })
+            .collect()
+    }
+
+    /// Starts, reconfigures or stops the local resolver, depending on the DNS settings.
+    async fn update_local_resolver(&mut self) {
+        let upstreams = Self::get_local_resolver_upstreams(
+            &self.settings.tunnel_options.dns_options,
+            self.tunnel_gateway,
+        );
+        self.send_tunnel_command(TunnelCommand::DnsUpstreams(
+            Self::get_plain_upstream_addresses(&upstreams),
+        ));
+        if !Self::uses_local_resolver(&self.settings.tunnel_options.dns_options) {
+            self.local_resolver = None;
+            return;
+        }
+        if upstreams.is_empty() {
+            warn!("The local DNS resolver has no upstream servers to forward queries to");
+        }
+
+        match &self.local_resolver {
+            Some(resolver) => resolver.set_upstreams(upstreams),
+            None => {
+                match local_dns::LocalResolver::start(upstreams, self.dns_blocklist.clone()).await {
+                    Ok(resolver) => self.local_resolver = Some(resolver),
+                    Err(error) => error!(
+                        "{}",
+                        error.display_chain_with_msg(
+                            "Failed to start local DNS resolver, falling back on plain DNS"
+                        )
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Loads the enabled DNS blocklists in the background, replacing any ongoing load. `tx` is
+    /// notified once the lists are in use. Lists that are downloaded are reloaded every
+    /// [`DNS_BLOCKLIST_REFRESH_INTERVAL`].
+    fn reload_dns_blocklists(&mut self, tx: Option<oneshot::Sender<()>>) {
+        if let Some(job) = self.dns_blocklist_job.take() {
+            job.abort();
+        }
+        let blocklists = self.settings.tunnel_options.dns_options.blocklists.clone();
+        let has_downloaded_lists = blocklists.iter().any(|blocklist| {
+            blocklist.enabled && matches!(blocklist.source, DnsBlocklistSource::Url(_))
+        });
+        let cache_dir = self.cache_dir.clone();
+        let file_dir = self.dns_blocklist_dir.clone();
+        let service = self.dns_blocklist_service.clone();
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(Box::pin(async move {
+            let mut tx = tx;
+            loop {
+                let blocklist =
+                    local_dns::blocklist::load(&blocklists, &cache_dir, &file_dir, &service).await;
+                let _ = daemon_tx.send(InternalDaemonEvent::DnsBlocklistLoaded(
+                    Arc::new(blocklist),
+                    tx.take(),
+                ));
+                if !has_downloaded_lists {
+                    break;
+                }
+                tokio::time::delay_for(DNS_BLOCKLIST_REFRESH_INTERVAL).await;
+            }
+        }));
+
+        tokio::spawn(future);
+        self.dns_blocklist_job = Some(abort_handle);
+    }
+
+    fn handle_dns_blocklist_loaded(
+        &mut self,
+        blocklist: Arc<local_dns::Blocklist>,
+        tx: Option<oneshot::Sender<()>>,
+    ) {
+        if let Some(resolver) = &self.local_resolver {
+            resolver.set_blocklist(blocklist.clone());
+        }
+        self.dns_blocklist = blocklist;
+        if let Some(tx) = tx {
+            Self::oneshot_send(tx, (), "update_dns_blocklists response");
         }
     }
 
@@ -733,6 +1030,8 @@ where
     pub async fn run(mut self) -> Result<(), Error> {
         if self.target_state == TargetState::Secured {
             self.connect_tunnel();
+        } else {
+            self.update_relay_selection_data();
         }
 
         while let Some(event) = self.rx.next().await {
@@ -844,6 +1143,71 @@ where
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info)
             }
+            DeviceLocation(location) => self.handle_device_location(location),
+            NetworkIdentity(network) => self.handle_network_identity(network).await,
+            TunnelUp(metadata) => self.handle_tunnel_up(metadata).await,
+            PeriodicReconnect => self.handle_periodic_reconnect(),
+            DnsBlocklistLoaded(blocklist, tx) => self.handle_dns_blocklist_loaded(blocklist, tx),
+        }
+    }
+
+    fn handle_device_location(&mut self, location: GeoIpLocation) {
+        // The location is only relevant if the tunnel was not brought up while it was fetched.
+        if self.tunnel_state == TunnelState::Disconnected && !location.mullvad_exit_ip {
+            self.relay_selector.set_device_location(location);
+        }
+    }
+
+    async fn handle_network_identity(&mut self, network: NetworkIdentity) {
+        debug!("Joined {}", network);
+        self.network_identity = Some(network.clone());
+        self.apply_network_rules(network).await;
+    }
+
+    /// Connects or disconnects according to the first network rule that matches `network`.
+    async fn apply_network_rules(&mut self, network: NetworkIdentity) {
+        if !self.state.is_running() {
+            return;
+        }
+        let (action, rule) = match self.settings.network_rules.evaluate(&network) {
+            Some((action, rule)) => (action, rule.cloned()),
+            None => return,
+        };
+        let new_target_state = match action {
+            NetworkAction::Connect => TargetState::Secured,
+            NetworkAction::Disconnect => TargetState::Unsecured,
+        };
+        if new_target_state == TargetState::Secured && self.settings.get_account_token().is_none() {
+            debug!("Ignoring network rules since no account token is set");
+            return;
+        }
+
+        let event = NetworkRuleEvent {
+            network,
+            action,
+            rule,
+        };
+        if self.set_target_state(new_target_state).await {
+            info!("{}", event);
+            self.event_listener.notify_network_rule_applied(event);
+        }
+    }
+
+    async fn handle_tunnel_up(&mut self, metadata: TunnelMetadata) {
+        self.set_tunnel_gateway(Some(metadata.ipv4_gateway.into()))
+            .await;
+    }
+
+    /// Updates the gateway that the local resolver falls back on when no DNS servers are
+    /// configured.
+    async fn set_tunnel_gateway(&mut self, tunnel_gateway: Option<IpAddr>) {
+        if tunnel_gateway != self.tunnel_gateway {
+            self.tunnel_gateway = tunnel_gateway;
+            let was_running = self.local_resolver.is_some();
+            self.update_local_resolver().await;
+            if !was_running && self.local_resolver.is_some() {
+                self.send_dns_config();
+            }
         }
     }
 
@@ -871,10 +1235,28 @@ where
 
 
         self.unschedule_reconnect();
+        self.unschedule_periodic_reconnect();
 
         debug!("New tunnel state: {:?}", tunnel_state);
+        if let Err(error) = self.connection_history.add(tunnel_state.clone()).await {
+            error!(
+                "{}",
+                error.display_chain_with_msg("Failed to record tunnel state in connection history")
+            );
+        }
         match tunnel_state {
-            TunnelState::Disconnected => self.state.disconnected(),
+            TunnelState::Disconnected => {
+                self.relay_to_avoid = None;
+                self.state.disconnected();
+            }
+            TunnelState::Connected { .. } => {
+                self.relay_to_avoid = None;
+                self.schedule_periodic_reconnect();
+                // Downloads may have failed because they were blocked before the tunnel was up
+                if self.dns_blocklist.download_failed() {
+                    self.reload_dns_blocklists(None);
+                }
+            }
             TunnelState::Error(ref error_state) => {
                 if error_state.is_blocking() {
                     info!(
@@ -897,6 +1279,37 @@ where
 
         self.tunnel_state = tunnel_state.clone();
         self.event_listener.notify_new_state(tunnel_state);
+        self.update_relay_selection_data();
+    }
+
+    /// Collects the data needed by the current relay selection mode: relay latencies or the
+    /// location of the device. This is only done in the disconnected state, where the
+    /// measurements are not affected by the tunnel.
+    fn update_relay_selection_data(&mut self) {
+        if self.tunnel_state != TunnelState::Disconnected {
+            return;
+        }
+        match self.settings.relay_selection_mode {
+            RelaySelectionMode::Weighted => (),
+            RelaySelectionMode::Fastest => {
+                // Relays cannot be reached when the firewall blocks all traffic.
+                if self.settings.block_when_disconnected {
+                    return;
+                }
+                if let RelaySettings::Normal(constraints) = self.settings.get_relay_settings() {
+                    tokio::spawn(self.relay_selector.update_latencies(&constraints));
+                }
+            }
+            RelaySelectionMode::Nearest { .. } => {
+                let location_future = self.get_geo_location();
+                let daemon_tx = self.tx.clone();
+                tokio::spawn(async move {
+                    if let Ok(location) = location_future.await {
+                        let _ = daemon_tx.send(InternalDaemonEvent::DeviceLocation(location));
+                    }
+                });
+            }
+        }
     }
 
     async fn reset_rpc_sockets_on_tunnel_state_transition(
@@ -932,20 +1345,38 @@ where
                         })
                 }
                 RelaySettings::Normal(constraints) => {
-                    let endpoint = self
-                        .relay_selector
-                        .get_tunnel_endpoint(
-                            &constraints,
-                            self.settings.get_bridge_state(),
-                            retry_attempt,
-                            self.account_history
-                                .get(&account_token)
-                                .await
-                                .unwrap_or(None)
-                                .and_then(|entry| entry.wireguard)
-                                .is_some(),
-                        )
-                        .ok();
+                    let wg_key_exists = self
+                        .account_history
+                        .get(&account_token)
+                        .await
+                        .unwrap_or(None)
+                        .and_then(|entry| entry.wireguard)
+                        .is_some();
+                    let mut endpoint = None;
+                    if let Some(relay_to_avoid) = self.relay_to_avoid.clone() {
+                        endpoint = self
+                            .relay_selector
+                            .get_tunnel_endpoint(
+                                &exclude_from_constraints(&constraints, relay_to_avoid),
+                                self.settings.get_bridge_state(),
+                                retry_attempt,
+                                wg_key_exists,
+                            )
+                            .ok();
+                        if endpoint.is_none() {
+                            debug!("No other relay matches the constraints");
+                        }
+                    }
+                    let endpoint = endpoint.or_else(|| {
+                        self.relay_selector
+                            .get_tunnel_endpoint(
+                                &constraints,
+                                self.settings.get_bridge_state(),
+                                retry_attempt,
+                                wg_key_exists,
+                            )
+                            .ok()
+                    });
                     if let Some((relay, endpoint)) = endpoint {
                         let result = self
                             .create_tunnel_parameters(
@@ -964,6 +1395,10 @@ where
                             Err(Error::NoBridgeAvailable) => {
                                 Err(ParameterGenerationError::NoMatchingBridgeRelay)
                             }
+                            Err(err @ Error::OpenVpnEntryBridgeConflict(_)) => {
+                                log::error!("{}", err);
+                                Err(ParameterGenerationError::NoMatchingBridgeRelay)
+                            }
                             Err(err) => {
                                 log::error!(
                                     "{}",
@@ -979,6 +1414,21 @@ where
                     }
                 }
             };
+            if let Ok(parameters) = &result {
+                self.last_wireguard_parameters = match parameters {
+                    TunnelParameters::Wireguard(parameters) => Some(parameters.clone()),
+                    TunnelParameters::OpenVpn(_) => None,
+                };
+                // The gateway of an OpenVPN tunnel is pushed by the server, so it is not known
+                // until the tunnel is up.
+                let tunnel_gateway = match parameters {
+                    TunnelParameters::Wireguard(parameters) => {
+                        Some(parameters.connection.ipv4_gateway.into())
+                    }
+                    TunnelParameters::OpenVpn(_) => None,
+                };
+                self.set_tunnel_gateway(tunnel_gateway).await;
+            }
             if tunnel_parameters_tx.send(result).is_err() {
                 log::error!("Failed to send tunnel parameters");
             }
@@ -997,13 +1447,41 @@ where
         let tunnel_options = self.settings.tunnel_options.clone();
         let location = relay.location.as_ref().expect("Relay has no location set");
         self.last_generated_bridge_relay = None;
+        self.last_generated_entry_relays = vec![];
         match endpoint {
             MullvadEndpoint::OpenVpn(endpoint) => {
-                let proxy_settings = match &self.settings.bridge_settings {
-                    BridgeSettings::Normal(settings) => {
+                let relay_settings = self.settings.get_relay_settings();
+                let proxy_settings = match (&relay_settings, &self.settings.bridge_settings) {
+                    (RelaySettings::Normal(relay_constraints), bridge_settings)
+                        if relay_constraints
+                            .openvpn_constraints
+                            .entry_location
+                            .is_some() =>
+                    {
+                        // The entry relay is reached through one of its bridges, so it cannot be
+                        // combined with another bridge or with bridges being turned off.
+                        if let BridgeSettings::Custom(_) = bridge_settings {
+                            return Err(Error::OpenVpnEntryBridgeConflict(
+                                "a custom bridge is set",
+                            ));
+                        }
+                        if self.settings.get_bridge_state() == BridgeState::Off {
+                            return Err(Error::OpenVpnEntryBridgeConflict(
+                                "the bridge state is off",
+                            ));
+                        }
+                        let (bridge_settings, entry_relay) = self
+                            .relay_selector
+                            .get_openvpn_entry_proxy(relay, relay_constraints)
+                            .ok_or(Error::NoEntryRelayAvailable)?;
+                        self.last_generated_entry_relays = vec![entry_relay];
+                        Some(bridge_settings)
+                    }
+                    (_, BridgeSettings::Normal(settings)) => {
                         let bridge_constraints = InternalBridgeConstraints {
                             location: settings.location.clone(),
                             providers: settings.providers.clone(),
+                            ownership: settings.ownership,
                             // FIXME: This is temporary while talpid-core only supports TCP proxies
                             transport_protocol: Constraint::Only(TransportProtocol::Tcp),
                         };
@@ -1033,7 +1511,7 @@ where
                             BridgeState::Off => None,
                         }
                     }
-                    BridgeSettings::Custom(proxy_settings) => {
+                    (_, BridgeSettings::Custom(proxy_settings)) => {
                         match self.settings.get_bridge_state() {
                             BridgeState::On => Some(proxy_settings.clone()),
                             BridgeState::Auto => {
@@ -1065,27 +1543,46 @@ where
                 ipv4_gateway,
                 ipv6_gateway,
             } => {
-                let entry_peer = match self.settings.get_relay_settings() {
-                    RelaySettings::Normal(ref relay_constraints)
-                        if relay_constraints
-                            .wireguard_constraints
-                            .entry_location
-                            .is_some() =>
-                    {
-                        Some(
-                            self.relay_selector
-                                .get_tunnel_entry_endpoint(&peer, relay_constraints, retry_attempt)
-                                .and_then(|(_relay, mullvad_endpoint)| match mullvad_endpoint {
-                                    MullvadEndpoint::Wireguard { peer, .. } => Some(peer),
-                                    _ => None,
-                                })
-                                .ok_or(Error::NoEntryRelayAvailable)?,
-                        )
+                let relay_settings = self.settings.get_relay_settings();
+                let chain = match relay_settings {
+                    RelaySettings::Normal(ref relay_constraints) => self
+                        .relay_selector
+                        .get_tunnel_entry_chain(relay, &peer, relay_constraints, retry_attempt)
+                        .ok_or(Error::NoEntryRelayAvailable)?,
+                    RelaySettings::CustomTunnelEndpoint(_) => vec![],
+                };
+                let (entry_relay, entry_peer, next_peers) = if chain.is_empty() {
+                    (relay.clone(), peer, vec![])
+                } else {
+                    self.last_generated_entry_relays =
+                        chain.iter().map(|(relay, _)| relay.clone()).collect();
+                    let mut peers = chain.into_iter().map(|(_, peer)| peer);
+                    let entry_peer = peers.next().unwrap();
+                    (
+                        self.last_generated_entry_relays[0].clone(),
+                        entry_peer,
+                        peers.chain(std::iter::once(peer)).collect(),
+                    )
+                };
+
+                let obfuscation = match relay_settings {
+                    RelaySettings::Normal(ref relay_constraints) => {
+                        match relay_constraints.wireguard_constraints.obfuscation {
+                            Some(obfuscation) => Some(
+                                self.relay_selector
+                                    .get_obfuscator(
+                                        obfuscation,
+                                        relay_constraints,
+                                        &entry_relay,
+                                        entry_peer.endpoint,
+                                    )
+                                    .ok_or(Error::NoObfuscatorAvailable)?,
+                            ),
+                            None => None,
+                        }
                     }
-                    _ => None,
+                    RelaySettings::CustomTunnelEndpoint(_) => None,
                 };
-                let exit_peer = entry_peer.as_ref().map(|_| peer.clone());
-                let entry_peer = entry_peer.unwrap_or(peer);
 
                 let wg_data = self
                     .account_history
@@ -1105,12 +1602,13 @@ where
                     connection: wireguard::ConnectionConfig {
                         tunnel,
                         peer: entry_peer,
-                        exit_peer,
+                        next_peers,
                         ipv4_gateway,
                         ipv6_gateway: Some(ipv6_gateway),
                     },
                     options: tunnel_options.wireguard.options,
                     generic_options: tunnel_options.generic,
+                    obfuscation,
                 }
                 .into())
             }
@@ -1136,6 +1634,46 @@ where
         }
     }
 
+    /// Schedules a reconnect to a different relay once the reconnect interval has passed, if
+    /// one is set.
+    fn schedule_periodic_reconnect(&mut self) {
+        let interval = match self.settings.reconnect_interval {
+            Some(interval) => *interval.as_duration(),
+            None => return,
+        };
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(Box::pin(async move {
+            tokio::time::delay_for(interval).await;
+            let _ = daemon_tx.send(InternalDaemonEvent::PeriodicReconnect);
+        }));
+
+        tokio::spawn(future);
+        self.periodic_reconnect_job = Some(abort_handle);
+    }
+
+    fn unschedule_periodic_reconnect(&mut self) {
+        if let Some(job) = self.periodic_reconnect_job.take() {
+            job.abort();
+        }
+    }
+
+    fn handle_periodic_reconnect(&mut self) {
+        if self.target_state != TargetState::Secured {
+            return;
+        }
+        info!("Reconnect interval has passed, reconnecting to a different relay");
+        self.relay_to_avoid = self.last_generated_relay.as_ref().and_then(|relay| {
+            relay.location.as_ref().map(|location| {
+                GeographicLocationConstraint::Hostname(
+                    location.country_code.clone(),
+                    location.city_code.clone(),
+                    relay.hostname.clone(),
+                )
+            })
+        });
+        self.reconnect_tunnel();
+    }
+
 
     async fn handle_command(&mut self, command: DaemonCommand) {
         use self::DaemonCommand::*;
@@ -1156,6 +1694,7 @@ where
             UpdateRelayLocations => self.on_update_relay_locations().await,
             SetAccount(tx, account_token) => self.on_set_account(tx, account_token).await,
             GetAccountHistory(tx) => self.on_get_account_history(tx),
+            GetConnectionHistory(tx) => self.on_get_connection_history(tx),
             RemoveAccountFromHistory(tx, account_token) => {
                 self.on_remove_account_from_history(tx, account_token).await
             }
@@ -1168,21 +1707,43 @@ where
                     .await
             }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
+            SetNetworkRules(tx, network_rules) => {
+                self.on_set_network_rules(tx, network_rules).await
+            }
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings).await
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state).await,
+            SetRelaySelectionMode(tx, mode) => self.on_set_relay_selection_mode(tx, mode).await,
+            SetReconnectInterval(tx, interval) => {
+                self.on_set_reconnect_interval(tx, interval).await
+            }
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6).await,
             SetDnsOptions(tx, dns_servers) => self.on_set_dns_options(tx, dns_servers).await,
+            GetDnsBlocklistStats(tx) => self.on_get_dns_blocklist_stats(tx),
+            UpdateDnsBlocklists(tx) => self.reload_dns_blocklists(Some(tx)),
+            GetTrafficStats(tx) => self.on_get_traffic_stats(tx),
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu).await,
+            SetQuantumResistantTunnel(tx, quantum_resistant) => {
+                self.on_set_quantum_resistant_tunnel(tx, quantum_resistant)
+                    .await
+            }
             SetWireguardRotationInterval(tx, interval) => {
                 self.on_set_wireguard_rotation_interval(tx, interval).await
             }
             GetSettings(tx) => self.on_get_settings(tx),
+            CreateProfile(tx, name) => self.on_create_profile(tx, name).await,
+            ActivateProfile(tx, name) => self.on_activate_profile(tx, name).await,
+            DeleteProfile(tx, name) => self.on_delete_profile(tx, name).await,
+            ImportSettings(tx, settings, include_account_token) => {
+                self.on_import_settings(tx, settings, include_account_token)
+                    .await
+            }
             GenerateWireguardKey(tx) => self.on_generate_wireguard_key(tx).await,
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
             VerifyWireguardKey(tx) => self.on_verify_wireguard_key(tx).await,
+            ExportWireguardConfig(tx) => self.on_export_wireguard_config(tx),
             GetVersionInfo(tx) => self.on_get_version_info(tx).await,
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
             #[cfg(not(target_os = "android"))]
@@ -1398,6 +1959,12 @@ where
             .map(|bridge| bridge.hostname.clone());
         let location = relay.location.as_ref().cloned().unwrap();
         let hostname = relay.hostname.clone();
+        let mut entry_hostnames = self
+            .last_generated_entry_relays
+            .iter()
+            .map(|relay| relay.hostname.clone());
+        let entry_hostname = entry_hostnames.next();
+        let intermediate_hostnames = entry_hostnames.collect();
 
         Some(GeoIpLocation {
             ipv4: None,
@@ -1409,6 +1976,8 @@ where
             mullvad_exit_ip: true,
             hostname: Some(hostname),
             bridge_hostname,
+            entry_hostname,
+            intermediate_hostnames,
         })
     }
 
@@ -1541,6 +2110,14 @@ where
         Ok(account_changed)
     }
 
+    fn on_get_connection_history(&mut self, tx: oneshot::Sender<Vec<ConnectionHistoryEntry>>) {
+        Self::oneshot_send(
+            tx,
+            self.connection_history.entries(),
+            "get_connection_history response",
+        );
+    }
+
     fn on_get_account_history(&mut self, tx: oneshot::Sender<Vec<AccountToken>>) {
         Self::oneshot_send(
             tx,
@@ -1596,6 +2173,23 @@ where
         );
     }
 
+    fn on_get_dns_blocklist_stats(&mut self, tx: oneshot::Sender<DnsBlocklistStats>) {
+        Self::oneshot_send(
+            tx,
+            self.dns_blocklist.stats(),
+            "get_dns_blocklist_stats response",
+        );
+    }
+
+    fn on_get_traffic_stats(&mut self, tx: oneshot::Sender<Option<TunnelStats>>) {
+        match self.tunnel_state {
+            TunnelState::Connected { .. } => {
+                self.send_tunnel_command(TunnelCommand::GetTrafficStats(tx));
+            }
+            _ => Self::oneshot_send(tx, None, "get_traffic_stats response"),
+        }
+    }
+
     fn on_get_current_version(&mut self, tx: oneshot::Sender<AppVersion>) {
         Self::oneshot_send(
             tx,
@@ -1696,6 +2290,7 @@ where
                         .notify_settings(self.settings.to_settings());
                     info!("Initiating tunnel restart because the relay settings changed");
                     self.reconnect_tunnel();
+                    self.update_relay_selection_data();
                 }
             }
             Err(e) => {
@@ -1794,6 +2389,30 @@ where
         }
     }
 
+    async fn on_set_network_rules(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        network_rules: NetworkRules,
+    ) {
+        let save_result = self.settings.set_network_rules(network_rules).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set network rules response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(network) = self.network_identity.clone() {
+                        self.apply_network_rules(network).await;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set network rules response");
+            }
+        }
+    }
+
     async fn on_set_openvpn_mssfix(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1872,6 +2491,52 @@ where
         Self::oneshot_send(tx, result, "on_set_bridge_state response");
     }
 
+    async fn on_set_relay_selection_mode(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        mode: RelaySelectionMode,
+    ) {
+        match self.settings.set_relay_selection_mode(mode).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_relay_selection_mode response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.relay_selector.set_selection_mode(mode);
+                    self.update_relay_selection_data();
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_relay_selection_mode response");
+            }
+        }
+    }
+
+
+    async fn on_set_reconnect_interval(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        interval: Option<RotationInterval>,
+    ) {
+        match self.settings.set_reconnect_interval(interval).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_reconnect_interval response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let TunnelState::Connected { .. } = self.tunnel_state {
+                        self.unschedule_periodic_reconnect();
+                        self.schedule_periodic_reconnect();
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_reconnect_interval response");
+            }
+        }
+    }
 
     async fn on_set_enable_ipv6(&mut self, tx: ResponseTx<(), settings::Error>, enable_ipv6: bool) {
         let save_result = self.settings.set_enable_ipv6(enable_ipv6).await;
@@ -1892,25 +2557,44 @@ where
         }
     }
 
-    async fn on_set_dns_options(
-        &mut self,
-        tx: ResponseTx<(), settings::Error>,
-        dns_options: DnsOptions,
-    ) {
+    async fn on_set_dns_options(&mut self, tx: ResponseTx<(), Error>, dns_options: DnsOptions) {
+        if let Err(error) =
+            local_dns::blocklist::validate_sources(&dns_options.blocklists, &self.dns_blocklist_dir)
+                .await
+        {
+            Self::oneshot_send(
+                tx,
+                Err(Error::InvalidDnsBlocklist(error.display_chain())),
+                "set_dns_options response",
+            );
+            return;
+        }
+
+        if dns_options.split_rules != self.settings.tunnel_options.dns_options.split_rules {
+            if let Err(error) = Self::validate_split_dns_rules(&dns_options.split_rules) {
+                Self::oneshot_send(tx, Err(error), "set_dns_options response");
+                return;
+            }
+        }
+
+        let previous_blocklists = self.settings.tunnel_options.dns_options.blocklists.clone();
         let save_result = self.settings.set_dns_options(dns_options.clone()).await;
         match save_result {
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "set_dns_options response");
                 if settings_changed {
-                    let settings = self.settings.to_settings();
-                    let resolvers = Self::get_dns_resolvers(&settings.tunnel_options.dns_options);
-                    self.event_listener.notify_settings(settings);
-                    self.send_tunnel_command(TunnelCommand::Dns(resolvers));
+                    if dns_options.blocklists != previous_blocklists {
+                        self.reload_dns_blocklists(None);
+                    }
+                    self.update_local_resolver().await;
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_dns_config();
                 }
             }
             Err(e) => {
                 error!("{}", e.display_chain_with_msg("Unable to save settings"));
-                Self::oneshot_send(tx, Err(e), "set_dns_options response");
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), "set_dns_options response");
             }
         }
     }
@@ -1942,6 +2626,36 @@ where
         }
     }
 
+    async fn on_set_quantum_resistant_tunnel(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        quantum_resistant: bool,
+    ) {
+        let save_result = self
+            .settings
+            .set_quantum_resistant_tunnel(quantum_resistant)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_quantum_resistant_tunnel response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the quantum-resistant tunnel setting changed"
+                        );
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_quantum_resistant_tunnel response");
+            }
+        }
+    }
+
     async fn on_set_wireguard_rotation_interval(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1967,6 +2681,207 @@ where
         }
     }
 
+    async fn on_create_profile(&mut self, tx: ResponseTx<(), settings::Error>, name: String) {
+        let save_result = self.settings.create_profile(name).await;
+        match save_result {
+            Ok(()) => {
+                Self::oneshot_send(tx, Ok(()), "create_profile response");
+                self.event_listener
+                    .notify_settings(self.settings.to_settings());
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to create profile"));
+                Self::oneshot_send(tx, Err(e), "create_profile response");
+            }
+        }
+    }
+
+    async fn on_activate_profile(&mut self, tx: ResponseTx<(), settings::Error>, name: String) {
+        let previous_settings = self.settings.to_settings();
+        let save_result = self.settings.activate_profile(&name).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "activate_profile response");
+                if settings_changed {
+                    self.apply_settings_changes(&previous_settings).await;
+                    info!(
+                        "Initiating tunnel restart because profile {} was activated",
+                        name
+                    );
+                    self.reconnect_tunnel();
+                    self.update_relay_selection_data();
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to activate profile"));
+                Self::oneshot_send(tx, Err(e), "activate_profile response");
+            }
+        }
+    }
+
+    async fn on_import_settings(
+        &mut self,
+        tx: ResponseTx<Vec<String>, Error>,
+        settings: String,
+        include_account_token: bool,
+    ) {
+        match self.import_settings(settings, include_account_token).await {
+            Ok(changed_fields) => {
+                Self::oneshot_send(tx, Ok(changed_fields), "import_settings response");
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to import settings"));
+                Self::oneshot_send(tx, Err(e), "import_settings response");
+            }
+        }
+    }
+
+    async fn import_settings(
+        &mut self,
+        settings: String,
+        include_account_token: bool,
+    ) -> Result<Vec<String>, Error> {
+        let mut settings = Settings::migrate_from_bytes(settings.as_bytes())
+            .map_err(Error::ParseImportedSettings)?;
+        let previous_settings = self.settings.to_settings();
+        if !include_account_token {
+            settings.set_account_token(previous_settings.get_account_token());
+        }
+        self.validate_relay_locations(&settings)?;
+        let split_rules = &settings.tunnel_options.dns_options.split_rules;
+        if *split_rules != previous_settings.tunnel_options.dns_options.split_rules {
+            Self::validate_split_dns_rules(split_rules)?;
+        }
+
+        if !self
+            .settings
+            .replace(settings)
+            .await
+            .map_err(Error::SettingsError)?
+        {
+            return Ok(vec![]);
+        }
+        let changed_fields = previous_settings.changed_fields(&self.settings.to_settings());
+        self.apply_settings_changes(&previous_settings).await;
+
+        let account_token = self.settings.get_account_token();
+        if account_token != previous_settings.get_account_token() {
+            if let Some(token) = account_token {
+                if let Err(e) = self.account_history.bump_history(&token).await {
+                    log::error!("Failed to bump account history: {}", e);
+                }
+                self.ensure_wireguard_keys_for_current_account().await;
+            } else {
+                info!("Disconnecting because the imported settings have no account token");
+                self.set_target_state(TargetState::Unsecured).await;
+                return Ok(changed_fields);
+            }
+        }
+
+        info!("Initiating tunnel restart because settings were imported");
+        self.reconnect_tunnel();
+        self.update_relay_selection_data();
+        Ok(changed_fields)
+    }
+
+    /// Fails if the relay or bridge constraints in `settings` refer to locations that are not in
+    /// the current relay list. Nothing is checked if no relay list has been fetched yet.
+    fn validate_relay_locations(&mut self, settings: &Settings) -> Result<(), Error> {
+        let relay_list = self.relay_selector.get_locations();
+        if relay_list.countries.is_empty() {
+            warn!("Not validating relay locations since the relay list is empty");
+            return Ok(());
+        }
+
+        let unknown_locations = settings
+            .unknown_relay_locations(&relay_list)
+            .into_iter()
+            .map(|location| location.to_string())
+            .collect::<Vec<_>>();
+        if unknown_locations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnknownRelayLocations(unknown_locations.join(", ")))
+        }
+    }
+
+    /// Fails if `split_rules` cannot be applied with the way DNS is managed on this system.
+    fn validate_split_dns_rules(split_rules: &[SplitDnsRule]) -> Result<(), Error> {
+        if split_rules.is_empty() {
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            talpid_core::dns::check_split_dns_support()
+                .map_err(|error| Error::SplitDnsNotSupported(error.to_string()))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(Error::SplitDnsNotSupported(
+                "They are only supported on Linux".to_owned(),
+            ))
+        }
+    }
+
+    /// Notifies listeners of new settings and propagates the changes since `previous_settings`
+    /// that do not require a reconnect to take effect.
+    async fn apply_settings_changes(&mut self, previous_settings: &Settings) {
+        let settings = self.settings.to_settings();
+        self.event_listener.notify_settings(settings.clone());
+
+        if settings.allow_lan != previous_settings.allow_lan {
+            self.send_tunnel_command(TunnelCommand::AllowLan(settings.allow_lan));
+        }
+        if settings.block_when_disconnected != previous_settings.block_when_disconnected {
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                settings.block_when_disconnected,
+            ));
+        }
+        let dns_options = &settings.tunnel_options.dns_options;
+        if *dns_options != previous_settings.tunnel_options.dns_options {
+            if dns_options.blocklists != previous_settings.tunnel_options.dns_options.blocklists {
+                self.reload_dns_blocklists(None);
+            }
+            self.update_local_resolver().await;
+            self.send_dns_config();
+        }
+        if settings.tunnel_options.wireguard.rotation_interval
+            != previous_settings.tunnel_options.wireguard.rotation_interval
+        {
+            self.ensure_key_rotation().await;
+        }
+        if settings.relay_selection_mode != previous_settings.relay_selection_mode {
+            self.relay_selector
+                .set_selection_mode(settings.relay_selection_mode);
+        }
+        if settings.show_beta_releases != previous_settings.show_beta_releases {
+            let mut handle = self.version_updater_handle.clone();
+            handle
+                .set_show_beta_releases(settings.show_beta_releases)
+                .await;
+        }
+        if settings.network_rules != previous_settings.network_rules {
+            if let Some(network) = self.network_identity.clone() {
+                self.apply_network_rules(network).await;
+            }
+        }
+    }
+
+    async fn on_delete_profile(&mut self, tx: ResponseTx<(), settings::Error>, name: String) {
+        let save_result = self.settings.delete_profile(&name).await;
+        match save_result {
+            Ok(()) => {
+                Self::oneshot_send(tx, Ok(()), "delete_profile response");
+                self.event_listener
+                    .notify_settings(self.settings.to_settings());
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to delete profile"));
+                Self::oneshot_send(tx, Err(e), "delete_profile response");
+            }
+        }
+    }
+
     async fn ensure_wireguard_keys_for_current_account(&mut self) {
         if let Some(account) = self.settings.get_account_token() {
             if self
@@ -2130,6 +3045,26 @@ where
         });
     }
 
+    fn on_export_wireguard_config(&self, tx: ResponseTx<String, Error>) {
+        let is_wireguard = match &self.tunnel_state {
+            TunnelState::Connecting { endpoint, .. } | TunnelState::Connected { endpoint, .. } => {
+                endpoint.tunnel_type == TunnelType::Wireguard
+            }
+            _ => false,
+        };
+        let result = match &self.last_wireguard_parameters {
+            Some(parameters) if is_wireguard => {
+                let dns = Self::get_plain_dns_resolvers(&self.settings.tunnel_options.dns_options)
+                    .unwrap_or_else(|| vec![parameters.connection.ipv4_gateway.into()]);
+                mullvad_types::wg_quick::WgQuickConfig::from_tunnel_parameters(parameters, dns)
+                    .map(|config| config.to_string())
+                    .map_err(Error::ExportWireguardConfig)
+            }
+            _ => Err(Error::NoWireguardTunnel),
+        };
+        Self::oneshot_send(tx, result, "export_wireguard_config response");
+    }
+
     fn on_get_settings(&self, tx: oneshot::Sender<Settings>) {
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
@@ -2376,3 +3311,76 @@ impl TunnelParametersGenerator for MullvadTunnelParametersGenerator {
         }
     }
 }
+
+/// Returns `constraints` with `location` added to the excluded locations.
+fn exclude_from_constraints(
+    constraints: &RelayConstraints,
+    location: GeographicLocationConstraint,
+) -> RelayConstraints {
+    let mut location_constraint = match &constraints.location {
+        Constraint::Any => LocationConstraint::default(),
+        Constraint::Only(location) => location.clone(),
+    };
+    location_constraint.exclude.push(location);
+    RelayConstraints {
+        location: Constraint::Only(location_constraint),
+        ..constraints.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hostname(hostname: &str) -> GeographicLocationConstraint {
+        GeographicLocationConstraint::Hostname(
+            "se".to_owned(),
+            "got".to_owned(),
+            hostname.to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_exclude_from_any_location() {
+        let constraints = RelayConstraints::default();
+        let constraints = exclude_from_constraints(&constraints, hostname("se1-wireguard"));
+
+        assert_eq!(
+            constraints.location,
+            Constraint::Only(LocationConstraint {
+                exclude: vec![hostname("se1-wireguard")],
+                ..LocationConstraint::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_exclude_keeps_existing_constraints() {
+        let constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint {
+                include: vec![GeographicLocationConstraint::Country("se".to_owned())],
+                exclude: vec![hostname("se2-wireguard")],
+                exclude_providers: vec!["provider".to_owned()],
+            }),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        let excluded = exclude_from_constraints(&constraints, hostname("se1-wireguard"));
+
+        assert_eq!(
+            excluded.location,
+            Constraint::Only(LocationConstraint {
+                include: vec![GeographicLocationConstraint::Country("se".to_owned())],
+                exclude: vec![hostname("se2-wireguard"), hostname("se1-wireguard")],
+                exclude_providers: vec!["provider".to_owned()],
+            })
+        );
+        assert_eq!(
+            RelayConstraints {
+                location: constraints.location.clone(),
+                ..excluded
+            },
+            constraints
+        );
+    }
+}
@@ -0,0 +1,497 @@
+//! Loading and matching of the domain blocklists that the local resolver applies.
+
+use hyper::body::HttpBody;
+use mullvad_rpc::rest::{RequestServiceHandle, RestRequest};
+use mullvad_types::dns_blocklist::{
+    DnsBlocklist, DnsBlocklistListStats, DnsBlocklistSource, DnsBlocklistStats,
+};
+use std::{
+    collections::HashSet,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+use tokio::{fs, io::AsyncReadExt, time::timeout};
+
+const CACHE_DIR_NAME: &str = "dns-blocklists";
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Directory in the settings directory that blocklist files must be stored in. Only users that
+/// may change the daemon's settings files can add files to it.
+pub const FILE_DIR_NAME: &str = "dns-blocklists";
+
+/// Lists larger than this are rejected, so that a list cannot exhaust the memory of the daemon.
+const MAX_LIST_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Names that hosts files commonly map to the loopback or broadcast addresses. These are never
+/// blocked.
+const IGNORED_DOMAINS: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+];
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to read blocklist file")]
+    ReadFileError(#[error(source)] io::Error),
+
+    #[error(display = "Blocklist files must be stored in {}", _0)]
+    FileNotInBlocklistDir(String),
+
+    #[error(display = "Invalid blocklist URL: {}", _0)]
+    InvalidUrl(String),
+
+    #[error(display = "HTTP request for blocklist failed")]
+    RestError(#[error(source)] mullvad_rpc::rest::Error),
+
+    #[error(display = "Failed to receive blocklist")]
+    HttpError(#[error(source)] hyper::Error),
+
+    #[error(display = "Blocklist host responded with HTTP status {}", _0)]
+    HttpStatus(hyper::StatusCode),
+
+    #[error(display = "Blocklist is larger than {} bytes", MAX_LIST_SIZE)]
+    TooLarge,
+
+    #[error(display = "Timed out downloading blocklist")]
+    Timeout,
+}
+
+/// Domains that the local resolver refuses to resolve, along with counters of the queries that
+/// have been checked against them.
+pub struct Blocklist {
+    lists: Vec<List>,
+    queries: AtomicU64,
+    blocked: AtomicU64,
+    download_failed: bool,
+}
+
+struct List {
+    name: String,
+    domains: HashSet<String>,
+    blocked: AtomicU64,
+}
+
+impl Blocklist {
+    /// Returns a blocklist that blocks nothing.
+    pub fn empty() -> Self {
+        Self::new(vec![])
+    }
+
+    /// Creates a blocklist from named sets of domains, such as those returned by [`parse`].
+    pub fn new(lists: Vec<(String, HashSet<String>)>) -> Self {
+        Blocklist {
+            lists: lists
+                .into_iter()
+                .map(|(name, domains)| List {
+                    name,
+                    domains,
+                    blocked: AtomicU64::new(0),
+                })
+                .collect(),
+            queries: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+            download_failed: false,
+        }
+    }
+
+    /// Returns whether any list could not be downloaded when this blocklist was loaded, in which
+    /// case it is missing or a cached copy of it is used.
+    pub fn download_failed(&self) -> bool {
+        self.download_failed
+    }
+
+    /// Returns whether queries for `domain` should be blocked, and counts the query. A domain is
+    /// blocked if it, or any domain that it is a subdomain of, is on one of the lists.
+    pub fn check(&self, domain: &str) -> bool {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+
+        let domain = domain.trim_matches('.').to_lowercase();
+        for list in &self.lists {
+            if Self::parent_domains(&domain).any(|parent| list.domains.contains(parent)) {
+                list.blocked.fetch_add(1, Ordering::Relaxed);
+                self.blocked.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns `domain` followed by every domain that it is a subdomain of.
+    fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+        std::iter::successors(Some(domain), |domain| {
+            domain.find('.').map(|index| &domain[index + 1..])
+        })
+        .filter(|domain| !domain.is_empty())
+    }
+
+    pub fn stats(&self) -> DnsBlocklistStats {
+        DnsBlocklistStats {
+            queries: self.queries.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            lists: self
+                .lists
+                .iter()
+                .map(|list| DnsBlocklistListStats {
+                    name: list.name.clone(),
+                    domains: list.domains.len() as u64,
+                    blocked: list.blocked.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses a blocklist in either hosts file format, where every domain mapped to an address is
+/// blocked, or with one domain per line. Comments starting with `#` are ignored.
+pub fn parse(contents: &str) -> HashSet<String> {
+    let mut domains = HashSet::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let first = match tokens.next() {
+            Some(token) => token,
+            None => continue,
+        };
+        if first.parse::<IpAddr>().is_ok() {
+            domains.extend(tokens.filter_map(normalize_domain));
+        } else if tokens.next().is_none() {
+            domains.extend(normalize_domain(first));
+        }
+    }
+    domains
+}
+
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain
+        .trim_start_matches("*.")
+        .trim_matches('.')
+        .to_lowercase();
+    let is_valid = !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !IGNORED_DOMAINS.contains(&domain.as_str());
+    if is_valid {
+        Some(domain)
+    } else {
+        None
+    }
+}
+
+/// Returns an error if `blocklists` contains a file outside of `file_dir`. Such files are never
+/// loaded.
+pub async fn validate_sources(blocklists: &[DnsBlocklist], file_dir: &Path) -> Result<(), Error> {
+    for blocklist in blocklists {
+        if let DnsBlocklistSource::File(path) = &blocklist.source {
+            resolve_file_path(path, file_dir).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the enabled lists among `blocklists`. Lists that fail to load are left out. Lists are
+/// downloaded using `service`, and files are only read from `file_dir`.
+pub async fn load(
+    blocklists: &[DnsBlocklist],
+    cache_dir: &Path,
+    file_dir: &Path,
+    service: &RequestServiceHandle,
+) -> Blocklist {
+    let mut lists = vec![];
+    let mut download_failed = false;
+    for blocklist in blocklists.iter().filter(|blocklist| blocklist.enabled) {
+        match load_list(
+            blocklist,
+            cache_dir,
+            file_dir,
+            service,
+            &mut download_failed,
+        )
+        .await
+        {
+            Ok(domains) => {
+                log::info!(
+                    "Loaded {} domains from DNS blocklist \"{}\"",
+                    domains.len(),
+                    blocklist.name
+                );
+                lists.push((blocklist.name.clone(), domains));
+            }
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Failed to load DNS blocklist \"{}\"",
+                    blocklist.name
+                ))
+            ),
+        }
+    }
+    let mut blocklist = Blocklist::new(lists);
+    blocklist.download_failed = download_failed;
+    blocklist
+}
+
+/// Reads or downloads a list. The downloaded copy of a list is cached, and the cached copy is used
+/// if the list cannot be downloaded. `download_failed` is set if the list cannot be downloaded.
+async fn load_list(
+    blocklist: &DnsBlocklist,
+    cache_dir: &Path,
+    file_dir: &Path,
+    service: &RequestServiceHandle,
+    download_failed: &mut bool,
+) -> Result<HashSet<String>, Error> {
+    let url = match &blocklist.source {
+        DnsBlocklistSource::File(path) => {
+            let path = resolve_file_path(path, file_dir).await?;
+            let contents = read_file(&path).await?;
+            return Ok(parse(&String::from_utf8_lossy(&contents)));
+        }
+        DnsBlocklistSource::Url(url) => url,
+    };
+
+    let cache_path = cache_path(cache_dir, &blocklist.name);
+    let result = timeout(DOWNLOAD_TIMEOUT, download(url, service))
+        .await
+        .unwrap_or(Err(Error::Timeout));
+    *download_failed |= result.is_err();
+    match result {
+        Ok(contents) => {
+            if let Err(error) = write_cache(&cache_path, &contents).await {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to cache downloaded DNS blocklist")
+                );
+            }
+            Ok(parse(&String::from_utf8_lossy(&contents)))
+        }
+        Err(error) => match read_file(&cache_path).await {
+            Ok(contents) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to download DNS blocklist \"{}\". Using the cached copy",
+                        blocklist.name
+                    ))
+                );
+                Ok(parse(&String::from_utf8_lossy(&contents)))
+            }
+            Err(_) => Err(error),
+        },
+    }
+}
+
+/// Returns the canonical form of `path`, or an error if it is not in `file_dir`.
+async fn resolve_file_path(path: &Path, file_dir: &Path) -> Result<PathBuf, Error> {
+    let not_in_dir = || Error::FileNotInBlocklistDir(file_dir.display().to_string());
+    let file_dir = fs::canonicalize(file_dir).await.map_err(|_| not_in_dir())?;
+    let path = fs::canonicalize(path).await.map_err(Error::ReadFileError)?;
+    if path.starts_with(&file_dir) {
+        Ok(path)
+    } else {
+        Err(not_in_dir())
+    }
+}
+
+async fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    let file = fs::File::open(path).await.map_err(Error::ReadFileError)?;
+    let mut contents = vec![];
+    file.take(MAX_LIST_SIZE + 1)
+        .read_to_end(&mut contents)
+        .await
+        .map_err(Error::ReadFileError)?;
+    if contents.len() as u64 > MAX_LIST_SIZE {
+        return Err(Error::TooLarge);
+    }
+    Ok(contents)
+}
+
+/// Returns the path of the cached copy of the list named `name`. Since characters that are not
+/// allowed in file names are replaced, a hash of the name is added to keep the paths of lists
+/// with similar names apart.
+fn cache_path(cache_dir: &Path, name: &str) -> PathBuf {
+    let sanitized_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let file_name = format!("{}-{:016x}", sanitized_name, fnv1a(name.as_bytes()));
+    cache_dir.join(CACHE_DIR_NAME).join(file_name)
+}
+
+/// 64-bit FNV-1a hash. Unlike the hasher of the standard library, it is stable across Rust
+/// versions, so cached lists are found after an upgrade.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+async fn write_cache(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    fs::write(path, contents).await
+}
+
+async fn download(url: &str, service: &RequestServiceHandle) -> Result<Vec<u8>, Error> {
+    let mut request = RestRequest::get(url).map_err(|_| Error::InvalidUrl(url.to_owned()))?;
+    request.set_timeout(DOWNLOAD_TIMEOUT);
+    let response = service.request(request).await.map_err(Error::RestError)?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+
+    let mut body = response.into_body();
+    let mut contents = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Error::HttpError)?;
+        if (contents.len() + chunk.len()) as u64 > MAX_LIST_SIZE {
+            return Err(Error::TooLarge);
+        }
+        contents.extend_from_slice(&chunk);
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_parse() {
+        let contents = "# Comment\n\
+                        127.0.0.1 localhost\n\
+                        0.0.0.0 ads.example.com tracker.example.com # Inline comment\n\
+                        :: Ads.Example.Net.\n\
+                        example.org\n\
+                        *.example.io\n\
+                        not a domain\n";
+        let domains = parse(contents);
+        let expected: HashSet<String> = [
+            "ads.example.com",
+            "tracker.example.com",
+            "ads.example.net",
+            "example.org",
+            "example.io",
+        ]
+        .iter()
+        .map(|domain| domain.to_string())
+        .collect();
+        assert_eq!(domains, expected);
+    }
+
+    #[test]
+    fn test_check_subdomains() {
+        let blocklist = Blocklist::new(vec![
+            ("first".to_owned(), parse("example.com")),
+            ("second".to_owned(), parse("other.example.org")),
+        ]);
+
+        assert!(blocklist.check("example.com"));
+        assert!(blocklist.check("a.b.Example.com."));
+        assert!(blocklist.check("other.example.org"));
+        assert!(!blocklist.check("example.org"));
+        assert!(!blocklist.check("notexample.com"));
+
+        let stats = blocklist.stats();
+        assert_eq!(stats.queries, 5);
+        assert_eq!(stats.blocked, 3);
+        assert_eq!(stats.lists[0].blocked, 2);
+        assert_eq!(stats.lists[1].blocked, 1);
+        assert_eq!(stats.lists[1].domains, 1);
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let cache_dir = Path::new("/cache");
+        let path = cache_path(cache_dir, "My list");
+        assert_eq!(path, cache_path(cache_dir, "My list"));
+        assert!(path.starts_with(cache_dir.join(CACHE_DIR_NAME)));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("My_list-"));
+
+        assert_ne!(path, cache_path(cache_dir, "My_list"));
+        assert_ne!(path, cache_path(cache_dir, "My/list"));
+    }
+
+    fn blocklist_file(path: PathBuf) -> DnsBlocklist {
+        DnsBlocklist {
+            name: "file".to_owned(),
+            source: DnsBlocklistSource::File(path),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_files_outside_blocklist_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_dir = temp_dir.path().join(FILE_DIR_NAME);
+        std::fs::create_dir(&file_dir).unwrap();
+        let inside = file_dir.join("list.txt");
+        let outside = temp_dir.path().join("list.txt");
+        std::fs::write(&inside, "example.com").unwrap();
+        std::fs::write(&outside, "example.com").unwrap();
+
+        Runtime::new().unwrap().block_on(async {
+            assert!(validate_sources(&[blocklist_file(inside)], &file_dir)
+                .await
+                .is_ok());
+            assert!(matches!(
+                validate_sources(&[blocklist_file(outside.clone())], &file_dir).await,
+                Err(Error::FileNotInBlocklistDir(_))
+            ));
+            assert!(matches!(
+                validate_sources(&[blocklist_file(file_dir.join("../list.txt"))], &file_dir).await,
+                Err(Error::FileNotInBlocklistDir(_))
+            ));
+
+            #[cfg(unix)]
+            {
+                let link = file_dir.join("link.txt");
+                std::os::unix::fs::symlink(&outside, &link).unwrap();
+                assert!(matches!(
+                    validate_sources(&[blocklist_file(link)], &file_dir).await,
+                    Err(Error::FileNotInBlocklistDir(_))
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn test_read_file_size_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("list.txt");
+        let file = std::fs::File::create(&path).unwrap();
+
+        Runtime::new().unwrap().block_on(async {
+            file.set_len(MAX_LIST_SIZE).unwrap();
+            assert_eq!(read_file(&path).await.unwrap().len() as u64, MAX_LIST_SIZE);
+
+            file.set_len(MAX_LIST_SIZE + 1).unwrap();
+            assert!(matches!(read_file(&path).await, Err(Error::TooLarge)));
+        });
+    }
+}
@@ -0,0 +1,712 @@
+//! DNS resolver on the loopback interface. Queries for domains on the enabled blocklists are
+//! answered with `NXDOMAIN`, and all other queries are forwarded to upstream servers, either as
+//! plain DNS or over DNS-over-HTTPS or DNS-over-TLS.
+
+use futures::future::{abortable, AbortHandle};
+use mullvad_types::settings::{EncryptedDnsProtocol, EncryptedDnsUpstream};
+use std::{
+    convert::TryFrom,
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{udp::SendHalf, TcpListener, TcpStream, UdpSocket},
+    time::timeout,
+};
+use tokio_rustls::{
+    rustls::{ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use webpki::DNSNameRef;
+
+pub mod blocklist;
+
+pub use blocklist::Blocklist;
+
+/// Address that the resolver listens on, and which is used as the system's DNS server.
+pub const RESOLVER_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const RESOLVER_PORT: u16 = 53;
+/// Port that plain DNS upstream servers are queried on.
+pub const PLAIN_DNS_PORT: u16 = 53;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_MESSAGE_SIZE: usize = 65535;
+const DNS_HEADER_SIZE: usize = 12;
+
+const DOH_PATH: &str = "/dns-query";
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to bind resolver socket")]
+    BindError(#[error(source)] io::Error),
+
+    #[error(display = "Invalid upstream hostname: {}", _0)]
+    InvalidHostname(String),
+
+    #[error(display = "Failed to connect to upstream server")]
+    ConnectError(#[error(source)] io::Error),
+
+    #[error(display = "TLS handshake with upstream server failed")]
+    TlsError(#[error(source)] io::Error),
+
+    #[error(display = "HTTP request to upstream server failed")]
+    HttpError(#[error(source)] hyper::Error),
+
+    #[error(display = "Upstream server responded with HTTP status {}", _0)]
+    HttpStatus(hyper::StatusCode),
+
+    #[error(display = "Failed to exchange messages with upstream server")]
+    IoError(#[error(source)] io::Error),
+
+    #[error(display = "Timed out waiting for upstream server")]
+    Timeout,
+
+    #[error(display = "Upstream server sent a response that does not match the query")]
+    MismatchedResponse,
+
+    #[error(display = "No upstream server answered the query")]
+    NoUpstreamAnswer,
+}
+
+/// Server that queries are forwarded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    Plain(SocketAddr),
+    Encrypted(EncryptedDnsUpstream),
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Plain(address) => write!(f, "{}", address),
+            Upstream::Encrypted(upstream) => upstream.fmt(f),
+        }
+    }
+}
+
+/// Handle to a running resolver. The resolver is stopped when this is dropped.
+pub struct LocalResolver {
+    forwarder: Arc<Forwarder>,
+    abort_handle: AbortHandle,
+    local_addr: SocketAddr,
+}
+
+impl LocalResolver {
+    /// Starts a resolver on [`RESOLVER_ADDRESS`] that trusts the system's root certificates.
+    pub async fn start(upstreams: Vec<Upstream>, blocklist: Arc<Blocklist>) -> Result<Self, Error> {
+        Self::start_with_config(
+            SocketAddr::new(RESOLVER_ADDRESS, RESOLVER_PORT),
+            upstreams,
+            blocklist,
+            system_tls_config(),
+        )
+        .await
+    }
+
+    async fn start_with_config(
+        bind_addr: SocketAddr,
+        upstreams: Vec<Upstream>,
+        blocklist: Arc<Blocklist>,
+        tls_config: ClientConfig,
+    ) -> Result<Self, Error> {
+        let udp_socket = UdpSocket::bind(bind_addr).await.map_err(Error::BindError)?;
+        let local_addr = udp_socket.local_addr().map_err(Error::BindError)?;
+        let tcp_listener = TcpListener::bind(local_addr)
+            .await
+            .map_err(Error::BindError)?;
+
+        let forwarder = Arc::new(Forwarder {
+            upstreams: Mutex::new(Arc::new(upstreams)),
+            blocklist: Mutex::new(blocklist),
+            tls: TlsConnector::from(Arc::new(tls_config)),
+        });
+        let (server, abort_handle) = abortable(futures::future::join(
+            serve_udp(udp_socket, forwarder.clone()),
+            serve_tcp(tcp_listener, forwarder.clone()),
+        ));
+        tokio::spawn(server);
+
+        log::info!("Started local DNS resolver on {}", local_addr);
+        Ok(LocalResolver {
+            forwarder,
+            abort_handle,
+            local_addr,
+        })
+    }
+
+    /// Replaces the servers that queries are forwarded to.
+    pub fn set_upstreams(&self, upstreams: Vec<Upstream>) {
+        *self.forwarder.upstreams.lock().unwrap() = Arc::new(upstreams);
+    }
+
+    /// Replaces the domains that are blocked.
+    pub fn set_blocklist(&self, blocklist: Arc<Blocklist>) {
+        *self.forwarder.blocklist.lock().unwrap() = blocklist;
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for LocalResolver {
+    fn drop(&mut self) {
+        log::info!("Stopping local DNS resolver");
+        self.abort_handle.abort();
+    }
+}
+
+/// Returns a TLS configuration that trusts the system's root certificates.
+pub fn system_tls_config() -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) => store,
+        Err((Some(store), error)) => {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to load some root certificates")
+            );
+            store
+        }
+        Err((None, error)) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to load root certificates")
+            );
+            RootCertStore::empty()
+        }
+    };
+    config
+}
+
+async fn serve_udp(socket: UdpSocket, forwarder: Arc<Forwarder>) {
+    let (mut recv_half, send_half) = socket.split();
+    let send_half = Arc::new(tokio::sync::Mutex::new(send_half));
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+    loop {
+        let (length, client) = match recv_half.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(error) => {
+                log::error!("Failed to receive DNS query: {}", error);
+                continue;
+            }
+        };
+        let query = buffer[..length].to_vec();
+        let forwarder = forwarder.clone();
+        let send_half = send_half.clone();
+        tokio::spawn(async move {
+            if let Some(response) = forwarder.resolve_or_fail(&query).await {
+                send_udp_response(&send_half, &response, client).await;
+            }
+        });
+    }
+}
+
+async fn send_udp_response(
+    send_half: &tokio::sync::Mutex<SendHalf>,
+    response: &[u8],
+    client: SocketAddr,
+) {
+    if let Err(error) = send_half.lock().await.send_to(response, &client).await {
+        log::error!("Failed to send DNS response: {}", error);
+    }
+}
+
+async fn serve_tcp(mut listener: TcpListener, forwarder: Arc<Forwarder>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                log::error!("Failed to accept DNS connection: {}", error);
+                continue;
+            }
+        };
+        let forwarder = forwarder.clone();
+        tokio::spawn(async move {
+            if let Err(error) = serve_tcp_connection(stream, &forwarder).await {
+                log::debug!("DNS connection closed: {}", error);
+            }
+        });
+    }
+}
+
+async fn serve_tcp_connection(mut stream: TcpStream, forwarder: &Forwarder) -> io::Result<()> {
+    loop {
+        let query = read_tcp_message(&mut stream).await?;
+        if let Some(response) = forwarder.resolve_or_fail(&query).await {
+            write_tcp_message(&mut stream, &response).await?;
+        }
+    }
+}
+
+/// Reads a DNS message prefixed by its length, as sent over TCP and TLS.
+async fn read_tcp_message(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let mut length = [0u8; 2];
+    stream.read_exact(&mut length).await?;
+    let mut message = vec![0u8; usize::from(u16::from_be_bytes(length))];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// Writes a DNS message prefixed by its length, as sent over TCP and TLS.
+async fn write_tcp_message(
+    stream: &mut (impl AsyncWrite + Unpin),
+    message: &[u8],
+) -> io::Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS message is too large"))?;
+    let mut buffer = Vec::with_capacity(2 + message.len());
+    buffer.extend_from_slice(&length.to_be_bytes());
+    buffer.extend_from_slice(message);
+    stream.write_all(&buffer).await?;
+    stream.flush().await
+}
+
+struct Forwarder {
+    upstreams: Mutex<Arc<Vec<Upstream>>>,
+    blocklist: Mutex<Arc<Blocklist>>,
+    tls: TlsConnector,
+}
+
+impl Forwarder {
+    /// Returns the answer to `query`, a `NXDOMAIN` response if the queried domain is blocked, or
+    /// a `SERVFAIL` response if no upstream server answered. `None` is returned if the query is
+    /// too short to be answered at all.
+    async fn resolve_or_fail(&self, query: &[u8]) -> Option<Vec<u8>> {
+        if query.len() < DNS_HEADER_SIZE {
+            return None;
+        }
+        if let Some((domain, question_end)) = parse_question(query) {
+            let blocklist = self.blocklist.lock().unwrap().clone();
+            if blocklist.check(&domain) {
+                log::debug!("Blocked query for {}", domain);
+                return Some(name_error(query, question_end));
+            }
+        }
+        match self.resolve(query).await {
+            Ok(response) => Some(response),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to resolve query")
+                );
+                Some(server_failure(query))
+            }
+        }
+    }
+
+    async fn resolve(&self, query: &[u8]) -> Result<Vec<u8>, Error> {
+        let upstreams = self.upstreams.lock().unwrap().clone();
+        for upstream in upstreams.iter() {
+            let result = timeout(UPSTREAM_TIMEOUT, self.query_upstream(upstream, query))
+                .await
+                .unwrap_or(Err(Error::Timeout));
+            match result {
+                Ok(response) => return Ok(response),
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!("Query to {} failed", upstream))
+                ),
+            }
+        }
+        Err(Error::NoUpstreamAnswer)
+    }
+
+    async fn query_upstream(&self, upstream: &Upstream, query: &[u8]) -> Result<Vec<u8>, Error> {
+        match upstream {
+            Upstream::Plain(address) => query_plain(*address, query).await,
+            Upstream::Encrypted(upstream) => self.query_encrypted(upstream, query).await,
+        }
+    }
+
+    async fn query_encrypted(
+        &self,
+        upstream: &EncryptedDnsUpstream,
+        query: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let hostname = DNSNameRef::try_from_ascii_str(&upstream.hostname)
+            .map_err(|_| Error::InvalidHostname(upstream.hostname.clone()))?;
+        let stream = TcpStream::connect(upstream.address)
+            .await
+            .map_err(Error::ConnectError)?;
+        let stream = self
+            .tls
+            .connect(hostname, stream)
+            .await
+            .map_err(Error::TlsError)?;
+
+        match upstream.protocol {
+            EncryptedDnsProtocol::Https => query_https(stream, &upstream.hostname, query).await,
+            EncryptedDnsProtocol::Tls => query_tls(stream, query).await,
+        }
+    }
+}
+
+/// Sends `query` to a plain DNS server over UDP, and retries over TCP if the response is
+/// truncated.
+async fn query_plain(address: SocketAddr, query: &[u8]) -> Result<Vec<u8>, Error> {
+    const TRUNCATED_FLAG: u8 = 0x02;
+
+    let bind_addr = match address {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let mut socket = UdpSocket::bind(bind_addr)
+        .await
+        .map_err(Error::ConnectError)?;
+    socket.connect(address).await.map_err(Error::ConnectError)?;
+    socket.send(query).await.map_err(Error::IoError)?;
+
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+    let response = loop {
+        let length = socket.recv(&mut buffer).await.map_err(Error::IoError)?;
+        // Ignore stray datagrams that answer other queries.
+        if length >= DNS_HEADER_SIZE && buffer[..2] == query[..2] {
+            break &buffer[..length];
+        }
+    };
+    if response[2] & TRUNCATED_FLAG == 0 {
+        return Ok(response.to_vec());
+    }
+
+    let mut stream = TcpStream::connect(address)
+        .await
+        .map_err(Error::ConnectError)?;
+    write_tcp_message(&mut stream, query)
+        .await
+        .map_err(Error::IoError)?;
+    let response = read_tcp_message(&mut stream)
+        .await
+        .map_err(Error::IoError)?;
+    if response.len() < DNS_HEADER_SIZE || response[..2] != query[..2] {
+        return Err(Error::MismatchedResponse);
+    }
+    Ok(response)
+}
+
+async fn query_https<S>(stream: S, hostname: &str, query: &[u8]) -> Result<Vec<u8>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(Error::HttpError)?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            log::debug!("DNS-over-HTTPS connection failed: {}", error);
+        }
+    });
+
+    let request = hyper::Request::post(DOH_PATH)
+        .header(hyper::header::HOST, hostname)
+        .header(hyper::header::CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+        .header(hyper::header::ACCEPT, DNS_MESSAGE_CONTENT_TYPE)
+        .body(hyper::Body::from(query.to_vec()))
+        .expect("Failed to build DNS-over-HTTPS request");
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(Error::HttpError)?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(Error::HttpError)?;
+    Ok(body.to_vec())
+}
+
+async fn query_tls<S>(mut stream: S, query: &[u8]) -> Result<Vec<u8>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    write_tcp_message(&mut stream, query)
+        .await
+        .map_err(Error::IoError)?;
+    read_tcp_message(&mut stream).await.map_err(Error::IoError)
+}
+
+/// Returns a `SERVFAIL` response to `query`, which must be at least as long as a DNS header. The
+/// response consists of the header only.
+fn server_failure(query: &[u8]) -> Vec<u8> {
+    const RESPONSE_FLAG: u8 = 0x80;
+    const RECURSION_AVAILABLE_FLAG: u8 = 0x80;
+    const SERVFAIL_RCODE: u8 = 2;
+
+    let mut response = query[..DNS_HEADER_SIZE].to_vec();
+    response[2] |= RESPONSE_FLAG;
+    response[3] = RECURSION_AVAILABLE_FLAG | SERVFAIL_RCODE;
+    // Clear the section counts.
+    for byte in &mut response[4..DNS_HEADER_SIZE] {
+        *byte = 0;
+    }
+    response
+}
+
+/// Returns the name in the first question of `query`, along with the offset of the end of the
+/// question. `None` is returned if the query has no question or if the name is compressed or
+/// malformed.
+fn parse_question(query: &[u8]) -> Option<(String, usize)> {
+    const QUESTION_FOOTER_SIZE: usize = 4;
+
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+    if question_count == 0 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut offset = DNS_HEADER_SIZE;
+    loop {
+        let length = usize::from(*query.get(offset)?);
+        offset += 1;
+        if length == 0 {
+            break;
+        }
+        // Names in queries are never compressed, so longer labels are treated as malformed.
+        if length > 63 {
+            return None;
+        }
+        let label = query.get(offset..offset + length)?;
+        labels.push(String::from_utf8_lossy(label).to_lowercase());
+        offset += length;
+    }
+
+    let question_end = offset + QUESTION_FOOTER_SIZE;
+    if query.len() < question_end {
+        return None;
+    }
+    Some((labels.join("."), question_end))
+}
+
+/// Returns a `NXDOMAIN` response to `query`, consisting of the header and the first question,
+/// which ends at `question_end`.
+fn name_error(query: &[u8], question_end: usize) -> Vec<u8> {
+    const RESPONSE_FLAG: u8 = 0x80;
+    const RECURSION_AVAILABLE_FLAG: u8 = 0x80;
+    const NXDOMAIN_RCODE: u8 = 3;
+
+    let mut response = query[..question_end].to_vec();
+    // Keep the opcode and the recursion desired flag, and clear all other flags.
+    response[2] = RESPONSE_FLAG | (response[2] & 0x79);
+    response[3] = RECURSION_AVAILABLE_FLAG | NXDOMAIN_RCODE;
+    // Answer the first question only, and drop any additional records.
+    response[4..6].copy_from_slice(&1u16.to_be_bytes());
+    for byte in &mut response[6..DNS_HEADER_SIZE] {
+        *byte = 0;
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper::{server::conn::Http, service::service_fn, Body, Request, Response};
+    use std::io::BufReader;
+    use tokio::runtime::Runtime;
+    use tokio_rustls::{
+        rustls::{
+            internal::pemfile::{certs, pkcs8_private_keys},
+            NoClientAuth, ServerConfig,
+        },
+        TlsAcceptor,
+    };
+
+    const TEST_CA: &[u8] = include_bytes!("test_ca.pem");
+    const TEST_SERVER_CERT: &[u8] = include_bytes!("test_server_cert.pem");
+    const TEST_SERVER_KEY: &[u8] = include_bytes!("test_server_key.pem");
+    const TEST_SERVER_HOSTNAME: &str = "dns.test";
+
+    const QUERY: &[u8] = &[
+        0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, b'e', b'x',
+        b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00, 0x01,
+    ];
+
+    /// Answers queries with the query itself, with the response flag set.
+    fn answer(query: &[u8]) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x80;
+        response
+    }
+
+    fn tls_acceptor() -> TlsAcceptor {
+        let certs = certs(&mut BufReader::new(TEST_SERVER_CERT)).unwrap();
+        let mut keys = pkcs8_private_keys(&mut BufReader::new(TEST_SERVER_KEY)).unwrap();
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(certs, keys.remove(0)).unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    fn client_tls_config() -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut BufReader::new(TEST_CA))
+            .unwrap();
+        config
+    }
+
+    /// Starts a stand-in DNS-over-HTTPS server on the loopback interface.
+    async fn start_doh_server() -> SocketAddr {
+        let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls_acceptor();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let stream = acceptor.accept(stream).await.unwrap();
+                tokio::spawn(Http::new().serve_connection(
+                    stream,
+                    service_fn(|request: Request<Body>| async move {
+                        assert_eq!(request.uri().path(), DOH_PATH);
+                        let query = hyper::body::to_bytes(request.into_body()).await?;
+                        Ok::<_, hyper::Error>(Response::new(Body::from(answer(&query))))
+                    }),
+                ));
+            }
+        });
+        addr
+    }
+
+    /// Starts a stand-in DNS-over-TLS server on the loopback interface.
+    async fn start_dot_server() -> SocketAddr {
+        let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls_acceptor();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = acceptor.accept(stream).await.unwrap();
+                let query = read_tcp_message(&mut stream).await.unwrap();
+                write_tcp_message(&mut stream, &answer(&query))
+                    .await
+                    .unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Starts a stand-in plain DNS server on the loopback interface.
+    async fn start_plain_server() -> SocketAddr {
+        let mut socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+            loop {
+                let (length, client) = socket.recv_from(&mut buffer).await.unwrap();
+                let response = answer(&buffer[..length]);
+                socket.send_to(&response, &client).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    async fn start_resolver(upstreams: Vec<Upstream>) -> LocalResolver {
+        LocalResolver::start_with_config(
+            SocketAddr::new(RESOLVER_ADDRESS, 0),
+            upstreams,
+            Arc::new(Blocklist::empty()),
+            client_tls_config(),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn udp_query(resolver: &LocalResolver) -> Vec<u8> {
+        let mut socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        socket.send_to(QUERY, resolver.local_addr()).await.unwrap();
+        let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+        let (length, _) = timeout(Duration::from_secs(10), socket.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        buffer.truncate(length);
+        buffer
+    }
+
+    #[test]
+    fn test_doh_forwarding() {
+        Runtime::new().unwrap().block_on(async {
+            let upstream = Upstream::Encrypted(EncryptedDnsUpstream {
+                protocol: EncryptedDnsProtocol::Https,
+                address: start_doh_server().await,
+                hostname: TEST_SERVER_HOSTNAME.to_owned(),
+            });
+            let resolver = start_resolver(vec![upstream]).await;
+
+            assert_eq!(udp_query(&resolver).await, answer(QUERY));
+
+            let mut stream = TcpStream::connect(resolver.local_addr()).await.unwrap();
+            write_tcp_message(&mut stream, QUERY).await.unwrap();
+            assert_eq!(read_tcp_message(&mut stream).await.unwrap(), answer(QUERY));
+        });
+    }
+
+    #[test]
+    fn test_dot_forwarding() {
+        Runtime::new().unwrap().block_on(async {
+            let upstream = Upstream::Encrypted(EncryptedDnsUpstream {
+                protocol: EncryptedDnsProtocol::Tls,
+                address: start_dot_server().await,
+                hostname: TEST_SERVER_HOSTNAME.to_owned(),
+            });
+            let resolver = start_resolver(vec![upstream]).await;
+
+            assert_eq!(udp_query(&resolver).await, answer(QUERY));
+        });
+    }
+
+    #[test]
+    fn test_untrusted_upstream() {
+        Runtime::new().unwrap().block_on(async {
+            let upstream = Upstream::Encrypted(EncryptedDnsUpstream {
+                protocol: EncryptedDnsProtocol::Https,
+                address: start_doh_server().await,
+                hostname: "other.test".to_owned(),
+            });
+            let resolver = start_resolver(vec![upstream]).await;
+
+            let response = udp_query(&resolver).await;
+            assert_eq!(response, server_failure(QUERY));
+            assert_eq!(response[3] & 0x0f, 2);
+        });
+    }
+
+    #[test]
+    fn test_plain_forwarding() {
+        Runtime::new().unwrap().block_on(async {
+            let upstream = Upstream::Plain(start_plain_server().await);
+            let resolver = start_resolver(vec![upstream]).await;
+
+            assert_eq!(udp_query(&resolver).await, answer(QUERY));
+        });
+    }
+
+    #[test]
+    fn test_blocked_domain() {
+        Runtime::new().unwrap().block_on(async {
+            let upstream = Upstream::Plain(start_plain_server().await);
+            let resolver = start_resolver(vec![upstream]).await;
+            let blocklist = Arc::new(Blocklist::new(vec![(
+                "test".to_owned(),
+                blocklist::parse("0.0.0.0 example.com\n"),
+            )]));
+            resolver.set_blocklist(blocklist.clone());
+
+            let response = udp_query(&resolver).await;
+            assert_eq!(&response[..2], &QUERY[..2]);
+            assert_eq!(response[3] & 0x0f, 3);
+            assert_eq!(&response[DNS_HEADER_SIZE..], &QUERY[DNS_HEADER_SIZE..]);
+
+            let stats = blocklist.stats();
+            assert_eq!(stats.queries, 1);
+            assert_eq!(stats.blocked, 1);
+            assert_eq!(stats.lists[0].blocked, 1);
+        });
+    }
+}
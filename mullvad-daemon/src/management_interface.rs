@@ -10,9 +10,9 @@ use mullvad_rpc::{rest::Error as RestError, StatusCode};
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
     account::AccountToken,
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
+    relay_constraints::{BridgeSettings, BridgeState, RelaySelectionMode, RelaySettingsUpdate},
     relay_list::RelayList,
-    settings::Settings,
+    settings::{NetworkRuleEvent, NetworkRules, Settings},
     states::{TargetState, TunnelState},
     version,
     wireguard::{RotationInterval, RotationIntervalError},
@@ -22,8 +22,9 @@ use std::{
     cmp,
     convert::{TryFrom, TryInto},
     sync::{mpsc, Arc},
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
+use talpid_core::tunnel::TunnelStats;
 use talpid_types::ErrorExt;
 
 #[derive(err_derive::Error, Debug)]
@@ -36,13 +37,27 @@ pub enum Error {
 
 struct ManagementServiceImpl {
     daemon_tx: DaemonCommandSender,
-    subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    subscriptions: Arc<RwLock<Vec<EventsSubscriber>>>,
+    traffic_stats_subscriptions: Arc<RwLock<Vec<TrafficStatsSender>>>,
 }
 
 pub type ServiceResult<T> = std::result::Result<Response<T>, Status>;
 type EventsListenerReceiver =
     tokio::sync::mpsc::UnboundedReceiver<Result<types::DaemonEvent, Status>>;
 type EventsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<types::DaemonEvent, Status>>;
+type TrafficStatsReceiver =
+    tokio::sync::mpsc::UnboundedReceiver<Result<types::TrafficStats, Status>>;
+type TrafficStatsSender = tokio::sync::mpsc::UnboundedSender<Result<types::TrafficStats, Status>>;
+
+/// A client listening for daemon events.
+struct EventsSubscriber {
+    tx: EventsListenerSender,
+    /// Whether the client may receive the account token and secret keys in the settings.
+    include_secrets: bool,
+}
+
+/// How often traffic statistics are sent to `TrafficStatsListen` clients.
+const TRAFFIC_STATS_INTERVAL: Duration = Duration::from_secs(1);
 
 const INVALID_VOUCHER_MESSAGE: &str = "This voucher code is invalid";
 const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
@@ -53,6 +68,7 @@ impl ManagementService for ManagementServiceImpl {
         tokio::sync::mpsc::Receiver<Result<types::RelayListCountry, Status>>;
     type GetSplitTunnelProcessesStream = tokio::sync::mpsc::UnboundedReceiver<Result<i32, Status>>;
     type EventsListenStream = EventsListenerReceiver;
+    type TrafficStatsListenStream = TrafficStatsReceiver;
 
     // Control and get the tunnel state
     //
@@ -94,10 +110,34 @@ impl ManagementService for ManagementServiceImpl {
     // Control the daemon and receive events
     //
 
-    async fn events_listen(&self, _: Request<()>) -> ServiceResult<Self::EventsListenStream> {
+    async fn events_listen(&self, request: Request<()>) -> ServiceResult<Self::EventsListenStream> {
+        let include_secrets = mullvad_management_interface::is_admin_request(&request);
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
         let mut subscriptions = self.subscriptions.write();
+        subscriptions.push(EventsSubscriber {
+            tx,
+            include_secrets,
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn traffic_stats_listen(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<Self::TrafficStatsListenStream> {
+        log::debug!("traffic_stats_listen");
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut subscriptions = self.traffic_stats_subscriptions.write();
+        // The statistics are sampled by a single task, which stops when the last client is gone
+        if subscriptions.is_empty() {
+            tokio::spawn(broadcast_traffic_stats(
+                self.daemon_tx.clone(),
+                self.traffic_stats_subscriptions.clone(),
+            ));
+        }
         subscriptions.push(tx);
 
         Ok(Response::new(rx))
@@ -261,16 +301,98 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_relay_selection_mode(
+        &self,
+        request: Request<types::RelaySelectionMode>,
+    ) -> ServiceResult<()> {
+        let mode =
+            RelaySelectionMode::try_from(request.into_inner()).map_err(|error| match error {
+                types::FromProtobufTypeError::InvalidArgument(error) => {
+                    Status::invalid_argument(error)
+                }
+            })?;
+
+        log::debug!("set_relay_selection_mode({:?})", mode);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRelaySelectionMode(tx, mode))?;
+        let settings_result = self.wait_for_result(rx).await?;
+        settings_result
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn set_reconnect_interval(&self, request: Request<types::Duration>) -> ServiceResult<()> {
+        let interval: RotationInterval = Duration::try_from(request.into_inner())
+            .map_err(|_| Status::invalid_argument("unexpected negative reconnect interval"))?
+            .try_into()
+            .map_err(|error: RotationIntervalError| {
+                Status::invalid_argument(error.display_chain())
+            })?;
+
+        log::debug!("set_reconnect_interval({:?})", interval);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetReconnectInterval(tx, Some(interval)))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn reset_reconnect_interval(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("reset_reconnect_interval");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetReconnectInterval(tx, None))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     // Settings
     //
 
-    async fn get_settings(&self, _: Request<()>) -> ServiceResult<types::Settings> {
+    async fn get_settings(&self, request: Request<()>) -> ServiceResult<types::Settings> {
         log::debug!("get_settings");
+        let include_secrets = mullvad_management_interface::is_admin_request(&request);
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::GetSettings(tx))?;
+        let mut settings = types::Settings::from(&self.wait_for_result(rx).await?);
+        if !include_secrets {
+            settings.clear_secrets();
+        }
+        Ok(Response::new(settings))
+    }
+
+    async fn export_settings(&self, request: Request<bool>) -> ServiceResult<String> {
+        let include_account_token = request.into_inner();
+        log::debug!("export_settings({})", include_account_token);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetSettings(tx))?;
+        let mut settings = self.wait_for_result(rx).await?;
+        if !include_account_token {
+            settings.set_account_token(None);
+        }
+        serde_json::to_string_pretty(&settings)
+            .map(Response::new)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+
+    async fn import_settings(
+        &self,
+        request: Request<types::SettingsImport>,
+    ) -> ServiceResult<types::SettingsImportResult> {
+        let request = request.into_inner();
+        log::debug!("import_settings({})", request.include_account_token);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ImportSettings(
+            tx,
+            request.settings,
+            request.include_account_token,
+        ))?;
         self.wait_for_result(rx)
-            .await
-            .map(|settings| Response::new(types::Settings::from(&settings)))
+            .await?
+            .map(|changed_fields| Response::new(types::SettingsImportResult { changed_fields }))
+            .map_err(map_daemon_error)
     }
 
     async fn set_allow_lan(&self, request: Request<bool>) -> ServiceResult<()> {
@@ -320,6 +442,23 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_network_rules(&self, request: Request<types::NetworkRules>) -> ServiceResult<()> {
+        log::debug!("set_network_rules");
+        let network_rules =
+            NetworkRules::try_from(request.into_inner()).map_err(|error| match error {
+                types::FromProtobufTypeError::InvalidArgument(error) => {
+                    Status::invalid_argument(error)
+                }
+            })?;
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetNetworkRules(tx, network_rules))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_openvpn_mssfix(&self, request: Request<u32>) -> ServiceResult<()> {
         let mssfix = request.into_inner();
         let mssfix = if mssfix != 0 {
@@ -348,6 +487,20 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_quantum_resistant_tunnel(&self, request: Request<bool>) -> ServiceResult<()> {
+        let quantum_resistant = request.into_inner();
+        log::debug!("set_quantum_resistant_tunnel({})", quantum_resistant);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetQuantumResistantTunnel(
+            tx,
+            quantum_resistant,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_enable_ipv6(&self, request: Request<bool>) -> ServiceResult<()> {
         let enable_ipv6 = request.into_inner();
         log::debug!("set_enable_ipv6({})", enable_ipv6);
@@ -371,13 +524,68 @@ impl ManagementService for ManagementServiceImpl {
         self.wait_for_result(rx)
             .await?
             .map(Response::new)
-            .map_err(map_settings_error)
+            .map_err(map_daemon_error)
     }
     #[cfg(target_os = "android")]
     async fn set_dns_options(&self, _: Request<types::DnsOptions>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
 
+    async fn get_dns_blocklist_stats(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::DnsBlocklistStats> {
+        log::debug!("get_dns_blocklist_stats");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetDnsBlocklistStats(tx))?;
+        let stats = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::DnsBlocklistStats::from(stats)))
+    }
+
+    async fn update_dns_blocklists(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("update_dns_blocklists");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UpdateDnsBlocklists(tx))?;
+        self.wait_for_result(rx).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn create_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("create_profile({})", name);
+        if name.is_empty() {
+            return Err(Status::invalid_argument("profile name must not be empty"));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::CreateProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn activate_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("activate_profile({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ActivateProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn delete_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("delete_profile({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::DeleteProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     // Account management
     //
 
@@ -433,6 +641,22 @@ impl ManagementService for ManagementServiceImpl {
             })
     }
 
+    async fn get_connection_history(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ConnectionHistory> {
+        log::debug!("get_connection_history");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetConnectionHistory(tx))?;
+        let entries = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::ConnectionHistory {
+            entries: entries
+                .into_iter()
+                .map(types::ConnectionHistoryEntry::from)
+                .collect(),
+        }))
+    }
+
     async fn get_account_history(&self, _: Request<()>) -> ServiceResult<types::AccountHistory> {
         // TODO: this might be a stream
         log::debug!("get_account_history");
@@ -575,6 +799,16 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn export_wireguard_config(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("export_wireguard_config");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportWireguardConfig(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
     // Split tunneling
     //
 
@@ -672,7 +906,7 @@ impl ManagementServiceImpl {
 }
 
 pub struct ManagementInterfaceServer {
-    subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    subscriptions: Arc<RwLock<Vec<EventsSubscriber>>>,
     socket_path: String,
     server_abort_tx: triggered::Trigger,
     server_join_handle: Option<
@@ -682,7 +916,7 @@ pub struct ManagementInterfaceServer {
 
 impl ManagementInterfaceServer {
     pub async fn start(tunnel_tx: DaemonCommandSender) -> Result<Self, Error> {
-        let subscriptions = Arc::<RwLock<Vec<EventsListenerSender>>>::default();
+        let subscriptions = Arc::<RwLock<Vec<EventsSubscriber>>>::default();
 
         let socket_path = mullvad_paths::get_rpc_socket_path()
             .to_string_lossy()
@@ -693,6 +927,7 @@ impl ManagementInterfaceServer {
         let server = ManagementServiceImpl {
             daemon_tx: tunnel_tx,
             subscriptions: subscriptions.clone(),
+            traffic_stats_subscriptions: Arc::default(),
         };
         let server_join_handle = tokio::spawn(mullvad_management_interface::spawn_rpc_server(
             server,
@@ -741,7 +976,7 @@ impl ManagementInterfaceServer {
 /// A handle that allows broadcasting messages to all subscribers of the management interface.
 #[derive(Clone)]
 pub struct ManagementInterfaceEventBroadcaster {
-    subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    subscriptions: Arc<RwLock<Vec<EventsSubscriber>>>,
     close_handle: triggered::Trigger,
 }
 
@@ -758,10 +993,16 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
     /// Sends settings to all `settings` subscribers of the management interface.
     fn notify_settings(&self, settings: Settings) {
         log::debug!("Broadcasting new settings");
-        self.notify(types::DaemonEvent {
-            event: Some(daemon_event::Event::Settings(types::Settings::from(
-                &settings,
-            ))),
+        // Every client may listen for events, so the secrets are only sent to admins.
+        let settings = types::Settings::from(&settings);
+        let mut public_settings = settings.clone();
+        public_settings.clear_secrets();
+        self.notify_each(|include_secrets| types::DaemonEvent {
+            event: Some(daemon_event::Event::Settings(if include_secrets {
+                settings.clone()
+            } else {
+                public_settings.clone()
+            })),
         })
     }
 
@@ -799,13 +1040,33 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
             ))),
         })
     }
+
+    fn notify_network_rule_applied(&self, event: NetworkRuleEvent) {
+        log::debug!("Broadcasting network rule event");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::NetworkRule(
+                types::NetworkRuleEvent::from(event),
+            )),
+        })
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
     fn notify(&self, value: types::DaemonEvent) {
+        self.notify_each(|_| value.clone())
+    }
+
+    /// Sends the event returned by `event` to every subscriber. `event` is given whether the
+    /// subscriber may receive secrets.
+    fn notify_each(&self, event: impl Fn(bool) -> types::DaemonEvent) {
         let mut subscriptions = self.subscriptions.write();
         // TODO: using write-lock everywhere. use a mutex instead?
-        subscriptions.retain(|tx| tx.send(Ok(value.clone())).is_ok());
+        subscriptions.retain(|subscriber| {
+            subscriber
+                .tx
+                .send(Ok(event(subscriber.include_secrets)))
+                .is_ok()
+        });
     }
 }
 
@@ -815,6 +1076,79 @@ impl Drop for ManagementInterfaceEventBroadcaster {
     }
 }
 
+/// Samples the traffic statistics of the tunnel and sends them to all `TrafficStatsListen`
+/// clients, until none are left.
+async fn broadcast_traffic_stats(
+    daemon_tx: DaemonCommandSender,
+    subscriptions: Arc<RwLock<Vec<TrafficStatsSender>>>,
+) {
+    let mut previous = None;
+    loop {
+        let (tx, rx) = oneshot::channel();
+        if daemon_tx.send(DaemonCommand::GetTrafficStats(tx)).is_err() {
+            // Close the streams, so that the next client starts a new task
+            subscriptions.write().clear();
+            break;
+        }
+        let stats = rx.await.ok().flatten();
+        let now = Instant::now();
+        let message = convert_traffic_stats(stats, previous, now);
+        previous = stats.map(|stats| (stats, now));
+
+        {
+            let mut subscriptions = subscriptions.write();
+            subscriptions.retain(|tx| tx.send(Ok(message.clone())).is_ok());
+            if subscriptions.is_empty() {
+                break;
+            }
+        }
+        tokio::time::delay_for(TRAFFIC_STATS_INTERVAL).await;
+    }
+}
+
+/// Converts a sample of traffic statistics to a message. Rates are computed from the previous
+/// sample, if there is one.
+fn convert_traffic_stats(
+    stats: Option<TunnelStats>,
+    previous: Option<(TunnelStats, Instant)>,
+    now: Instant,
+) -> types::TrafficStats {
+    let stats = match stats {
+        Some(stats) => stats,
+        None => return types::TrafficStats::default(),
+    };
+
+    let (rx_rate, tx_rate) = match previous {
+        // The counters start over if the tunnel was replaced between the samples
+        Some((previous, time))
+            if stats.rx_bytes >= previous.rx_bytes && stats.tx_bytes >= previous.tx_bytes =>
+        {
+            let elapsed = now.duration_since(time).as_secs_f64();
+            if elapsed > 0.0 {
+                (
+                    ((stats.rx_bytes - previous.rx_bytes) as f64 / elapsed) as u64,
+                    ((stats.tx_bytes - previous.tx_bytes) as f64 / elapsed) as u64,
+                )
+            } else {
+                (0, 0)
+            }
+        }
+        _ => (0, 0),
+    };
+
+    types::TrafficStats {
+        connected: true,
+        rx_bytes: stats.rx_bytes,
+        tx_bytes: stats.tx_bytes,
+        rx_rate,
+        tx_rate,
+        handshake_age: stats
+            .last_handshake
+            .and_then(|handshake| SystemTime::now().duration_since(handshake).ok())
+            .map(types::Duration::from),
+    }
+}
+
 /// Converts [`mullvad_daemon::Error`] into a tonic status.
 fn map_daemon_error(error: crate::Error) -> Status {
     use crate::Error as DaemonError;
@@ -822,10 +1156,17 @@ fn map_daemon_error(error: crate::Error) -> Status {
     match error {
         DaemonError::RestError(error) => map_rest_error(error),
         DaemonError::SettingsError(error) => map_settings_error(error),
+        DaemonError::ParseImportedSettings(_)
+        | DaemonError::UnknownRelayLocations(_)
+        | DaemonError::InvalidDnsBlocklist(_) => Status::invalid_argument(error.display_chain()),
+        DaemonError::SplitDnsNotSupported(_) => Status::failed_precondition(error.to_string()),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
             Status::unauthenticated(error.to_string())
         }
+        DaemonError::NoWireguardTunnel | DaemonError::ExportWireguardConfig(_) => {
+            Status::failed_precondition(error.display_chain())
+        }
         error => Status::unknown(error.to_string()),
     }
 }
@@ -863,10 +1204,14 @@ fn map_rest_error(error: RestError) -> Status {
 /// Converts an instance of [`mullvad_daemon::settings::Error`] into a tonic status.
 fn map_settings_error(error: settings::Error) -> Status {
     match error {
-        settings::Error::DeleteError(..) | settings::Error::WriteError(..) => {
+        settings::Error::DeleteError(..)
+        | settings::Error::WriteError(..)
+        | settings::Error::SettingLocked(..) => {
             Status::new(Code::FailedPrecondition, error.to_string())
         }
         settings::Error::SerializeError(..) => Status::new(Code::Internal, error.to_string()),
+        settings::Error::ProfileNotFound(..) => Status::new(Code::NotFound, error.to_string()),
+        settings::Error::ProfileExists(..) => Status::new(Code::AlreadyExists, error.to_string()),
     }
 }
 
@@ -881,3 +1226,79 @@ fn map_account_history_error(error: account_history::Error) -> Status {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: TunnelStats = TunnelStats {
+        rx_bytes: 1000,
+        tx_bytes: 500,
+        last_handshake: None,
+    };
+
+    #[test]
+    fn test_traffic_stats_without_tunnel() {
+        let message = convert_traffic_stats(None, Some((SAMPLE, Instant::now())), Instant::now());
+        assert_eq!(message, types::TrafficStats::default());
+        assert!(!message.connected);
+    }
+
+    #[test]
+    fn test_traffic_stats_first_sample() {
+        let message = convert_traffic_stats(Some(SAMPLE), None, Instant::now());
+        assert!(message.connected);
+        assert_eq!((message.rx_bytes, message.tx_bytes), (1000, 500));
+        assert_eq!((message.rx_rate, message.tx_rate), (0, 0));
+        assert_eq!(message.handshake_age, None);
+    }
+
+    #[test]
+    fn test_traffic_stats_rates() {
+        let start = Instant::now();
+        let stats = TunnelStats {
+            rx_bytes: 5000,
+            tx_bytes: 1500,
+            ..SAMPLE
+        };
+        let message = convert_traffic_stats(
+            Some(stats),
+            Some((SAMPLE, start)),
+            start + Duration::from_secs(2),
+        );
+        assert_eq!((message.rx_bytes, message.tx_bytes), (5000, 1500));
+        assert_eq!((message.rx_rate, message.tx_rate), (2000, 500));
+
+        // No time has passed between the samples
+        let message = convert_traffic_stats(Some(stats), Some((SAMPLE, start)), start);
+        assert_eq!((message.rx_rate, message.tx_rate), (0, 0));
+    }
+
+    #[test]
+    fn test_traffic_stats_reset_counters() {
+        let start = Instant::now();
+        let stats = TunnelStats {
+            rx_bytes: 100,
+            ..SAMPLE
+        };
+        let message = convert_traffic_stats(
+            Some(stats),
+            Some((SAMPLE, start)),
+            start + Duration::from_secs(1),
+        );
+        assert_eq!((message.rx_bytes, message.tx_bytes), (100, 500));
+        assert_eq!((message.rx_rate, message.tx_rate), (0, 0));
+    }
+
+    #[test]
+    fn test_traffic_stats_handshake_age() {
+        let stats = TunnelStats {
+            last_handshake: Some(SystemTime::now() - Duration::from_secs(30)),
+            ..SAMPLE
+        };
+        let age = convert_traffic_stats(Some(stats), None, Instant::now())
+            .handshake_age
+            .unwrap();
+        assert!((30..=31).contains(&age.seconds));
+    }
+}
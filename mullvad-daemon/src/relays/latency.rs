@@ -0,0 +1,291 @@
+//! Measures and caches the round-trip time to relays. Used by the relay selector when the relay
+//! selection mode is set to `RelaySelectionMode::Fastest`.
+
+use super::Error;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use log::debug;
+use mullvad_types::relay_list::Relay;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use talpid_types::net::TransportProtocol;
+
+pub const LATENCY_CACHE_FILENAME: &str = "relay-latencies.json";
+/// How long a measured round-trip time is considered valid.
+const LATENCY_TTL: Duration = Duration::from_secs(30 * 60);
+/// How long to wait for a single probe before giving up on a relay.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of probes in flight at the same time.
+const MAX_CONCURRENT_PROBES: usize = 16;
+/// Port to probe on relays that do not list any OpenVPN TCP endpoint.
+const DEFAULT_PROBE_PORT: u16 = 443;
+/// Number of relays with the lowest round-trip time that the selector picks randomly among.
+/// Picking among a few relays instead of always the fastest one spreads out the load.
+pub const FASTEST_POOL_SIZE: usize = 5;
+
+
+/// Measures the round-trip time to a relay.
+pub trait LatencyProber: Send + Sync {
+    /// Returns the round-trip time to `addr`, or `None` if the relay could not be reached.
+    fn probe(&self, addr: SocketAddr) -> BoxFuture<'static, Option<Duration>>;
+}
+
+/// Measures the time it takes to establish a TCP connection with the relay. A connection that is
+/// refused counts as a sample as well, since the reset still makes a full round-trip.
+pub struct TcpLatencyProber;
+
+impl LatencyProber for TcpLatencyProber {
+    fn probe(&self, addr: SocketAddr) -> BoxFuture<'static, Option<Duration>> {
+        async move {
+            let start = Instant::now();
+            let result = tokio::time::timeout(PROBE_TIMEOUT, tokio::net::TcpStream::connect(addr))
+                .await
+                .ok()?;
+            match result {
+                Ok(_) => Some(start.elapsed()),
+                Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+                    Some(start.elapsed())
+                }
+                Err(_) => None,
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Returns the address that should be probed to measure the latency to `relay`.
+pub fn probe_address(relay: &Relay) -> SocketAddr {
+    let port = relay
+        .tunnels
+        .openvpn
+        .iter()
+        .find(|endpoint| endpoint.protocol == TransportProtocol::Tcp)
+        .map(|endpoint| endpoint.port)
+        .unwrap_or(DEFAULT_PROBE_PORT);
+    SocketAddr::new(relay.ipv4_addr_in.into(), port)
+}
+
+/// Probes all given relays, a bounded number at a time, and returns the round-trip times for
+/// the relays that responded.
+pub async fn probe_relays(
+    prober: &dyn LatencyProber,
+    targets: Vec<(String, SocketAddr)>,
+) -> Vec<(String, Duration)> {
+    futures::stream::iter(targets.into_iter().map(|(hostname, addr)| {
+        prober
+            .probe(addr)
+            .map(move |rtt| rtt.map(|rtt| (hostname, rtt)))
+    }))
+    .buffer_unordered(MAX_CONCURRENT_PROBES)
+    .filter_map(|result| async move { result })
+    .collect()
+    .await
+}
+
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+struct LatencyEntry {
+    rtt: Duration,
+    measured_at: SystemTime,
+}
+
+/// Round-trip times to relays, indexed by hostname.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct LatencyCache {
+    entries: HashMap<String, LatencyEntry>,
+}
+
+impl LatencyCache {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        debug!("Reading relay latencies from {}", path.as_ref().display());
+        let file = std::fs::File::open(path).map_err(Error::OpenLatencyCache)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Serialize)
+    }
+
+    /// Write the cache to disk. Expired entries are not written.
+    pub async fn write_to_file(&self, path: &Path) -> Result<(), Error> {
+        debug!("Writing relay latencies to {}", path.display());
+        let now = SystemTime::now();
+        let fresh = LatencyCache {
+            entries: self
+                .entries
+                .iter()
+                .filter(|(_, entry)| Self::is_fresh(entry, now))
+                .map(|(hostname, entry)| (hostname.clone(), *entry))
+                .collect(),
+        };
+        let bytes = serde_json::to_vec_pretty(&fresh).map_err(Error::Serialize)?;
+        tokio::fs::write(path, bytes)
+            .await
+            .map_err(Error::WriteLatencyCache)
+    }
+
+    /// Returns the round-trip time to the given relay, unless it has not been measured or the
+    /// measurement is too old.
+    pub fn get(&self, hostname: &str) -> Option<Duration> {
+        self.get_at(hostname, SystemTime::now())
+    }
+
+    fn get_at(&self, hostname: &str, now: SystemTime) -> Option<Duration> {
+        self.entries
+            .get(hostname)
+            .filter(|entry| Self::is_fresh(entry, now))
+            .map(|entry| entry.rtt)
+    }
+
+    pub fn insert(&mut self, hostname: String, rtt: Duration) {
+        self.insert_at(hostname, rtt, SystemTime::now());
+    }
+
+    fn insert_at(&mut self, hostname: String, rtt: Duration, measured_at: SystemTime) {
+        self.entries
+            .insert(hostname, LatencyEntry { rtt, measured_at });
+    }
+
+    /// Returns up to `FASTEST_POOL_SIZE` relays with the lowest round-trip time, ordered by
+    /// latency. Relays without a fresh measurement, and relays with zero weight, are left out.
+    pub fn fastest_relays<'a>(&self, relays: &'a [Relay]) -> Vec<&'a Relay> {
+        let now = SystemTime::now();
+        let mut measured: Vec<(Duration, &'a Relay)> = relays
+            .iter()
+            .filter(|relay| relay.weight > 0)
+            .filter_map(|relay| Some((self.get_at(&relay.hostname, now)?, relay)))
+            .collect();
+        measured.sort_by_key(|(rtt, _)| *rtt);
+        measured
+            .into_iter()
+            .take(FASTEST_POOL_SIZE)
+            .map(|(_, relay)| relay)
+            .collect()
+    }
+
+    fn is_fresh(entry: &LatencyEntry, now: SystemTime) -> bool {
+        now.duration_since(entry.measured_at)
+            .map(|age| age < LATENCY_TTL)
+            // A measurement from the future means the clock changed. Treat it as stale.
+            .unwrap_or(false)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future;
+    use mullvad_types::relay_list::{RelayBridges, RelayTunnels};
+    use std::net::Ipv4Addr;
+
+    /// Prober that answers from a fixed table instead of touching the network.
+    struct FakeProber(HashMap<SocketAddr, Duration>);
+
+    impl LatencyProber for FakeProber {
+        fn probe(&self, addr: SocketAddr) -> BoxFuture<'static, Option<Duration>> {
+            future::ready(self.0.get(&addr).copied()).boxed()
+        }
+    }
+
+    fn relay(hostname: &str, last_octet: u8) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: Ipv4Addr::new(10, 0, 0, last_octet),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: "provider".to_owned(),
+            weight: 1,
+            tunnels: RelayTunnels::default(),
+            bridges: RelayBridges::default(),
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_probe_relays() {
+        let relays = vec![relay("a", 1), relay("b", 2), relay("unreachable", 3)];
+        let prober = FakeProber(
+            vec![
+                (probe_address(&relays[0]), Duration::from_millis(40)),
+                (probe_address(&relays[1]), Duration::from_millis(10)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let targets = relays
+            .iter()
+            .map(|relay| (relay.hostname.clone(), probe_address(relay)))
+            .collect();
+
+        let mut cache = LatencyCache::default();
+        for (hostname, rtt) in futures::executor::block_on(probe_relays(&prober, targets)) {
+            cache.insert(hostname, rtt);
+        }
+
+        assert_eq!(cache.get("a"), Some(Duration::from_millis(40)));
+        assert_eq!(cache.get("b"), Some(Duration::from_millis(10)));
+        assert_eq!(cache.get("unreachable"), None);
+
+        let fastest: Vec<&str> = cache
+            .fastest_relays(&relays)
+            .into_iter()
+            .map(|relay| relay.hostname.as_str())
+            .collect();
+        assert_eq!(fastest, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_expired_latency() {
+        let now = SystemTime::now();
+        let mut cache = LatencyCache::default();
+        cache.insert_at(
+            "old".to_owned(),
+            Duration::from_millis(1),
+            now - LATENCY_TTL - Duration::from_secs(1),
+        );
+        cache.insert_at("new".to_owned(), Duration::from_millis(50), now);
+
+        assert_eq!(cache.get_at("old", now), None);
+        assert_eq!(cache.get_at("new", now), Some(Duration::from_millis(50)));
+
+        let relays = vec![relay("old", 1), relay("new", 2)];
+        let fastest = cache.fastest_relays(&relays);
+        assert_eq!(fastest.len(), 1);
+        assert_eq!(fastest[0].hostname, "new");
+    }
+
+    #[test]
+    fn test_fastest_pool_size() {
+        let mut cache = LatencyCache::default();
+        let relays: Vec<Relay> = (0..FASTEST_POOL_SIZE as u8 * 2)
+            .map(|i| relay(&format!("relay{}", i), i))
+            .collect();
+        for (i, relay) in relays.iter().enumerate() {
+            cache.insert(
+                relay.hostname.clone(),
+                Duration::from_millis(100 - i as u64),
+            );
+        }
+
+        let fastest = cache.fastest_relays(&relays);
+        assert_eq!(fastest.len(), FASTEST_POOL_SIZE);
+        assert_eq!(fastest[0].hostname, relays.last().unwrap().hostname);
+    }
+
+    #[test]
+    fn test_fastest_skips_zero_weight() {
+        let mut cache = LatencyCache::default();
+        let mut relays = vec![relay("disabled", 1), relay("enabled", 2)];
+        relays[0].weight = 0;
+        cache.insert("disabled".to_owned(), Duration::from_millis(10));
+        cache.insert("enabled".to_owned(), Duration::from_millis(50));
+
+        let fastest = cache.fastest_relays(&relays);
+        assert_eq!(fastest.len(), 1);
+        assert_eq!(fastest[0].hostname, "enabled");
+    }
+}
@@ -0,0 +1,1919 @@
+//! When changing relay selection, please verify if `docs/relay-selector.md` needs to be
+//! updated as well.
+
+use chrono::{DateTime, Local};
+use futures::{
+    channel::mpsc,
+    future::{Fuse, FusedFuture},
+    FutureExt, SinkExt, StreamExt,
+};
+use ipnetwork::IpNetwork;
+use log::{debug, error, info, warn};
+use mullvad_rpc::{rest::MullvadRestHandle, RelayListProxy};
+use mullvad_types::{
+    endpoint::MullvadEndpoint,
+    location::{GeoIpLocation, Location},
+    relay_constraints::{
+        BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint, Match,
+        OpenVpnConstraints, Ownership, Providers, RelayConstraints, RelaySelectionMode,
+        WireguardConstraints,
+    },
+    relay_list::{OpenVpnEndpointData, Relay, RelayList, RelayTunnels, WireguardEndpointData},
+};
+use parking_lot::Mutex;
+use rand::{self, rngs::ThreadRng, seq::SliceRandom, Rng};
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{self, Duration, Instant, SystemTime},
+};
+use talpid_core::future_retry::{retry_future_with_backoff, ExponentialBackoff, Jittered};
+use talpid_types::{
+    net::{
+        all_of_the_internet,
+        obfuscation::{ObfuscationType, ObfuscatorConfig, SHADOWSOCKS_UDP_CIPHERS},
+        openvpn::ProxySettings,
+        wireguard, IpVersion, TransportProtocol, TunnelType,
+    },
+    ErrorExt,
+};
+use tokio::fs::File;
+
+mod latency;
+
+use latency::{LatencyCache, LATENCY_CACHE_FILENAME};
+pub use latency::{LatencyProber, TcpLatencyProber};
+
+const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
+const RELAYS_FILENAME: &str = "relays.json";
+/// How often the updater should wake up to check the cache of the in-memory cache of relays.
+/// This check is very cheap. The only reason to not have it very often is because if downloading
+/// constantly fails it will try very often and fill the logs etc.
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 15);
+/// How old the cached relays need to be to trigger an update
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const EXPONENTIAL_BACKOFF_INITIAL: Duration = Duration::from_secs(16);
+const EXPONENTIAL_BACKOFF_FACTOR: u32 = 8;
+
+const DEFAULT_WIREGUARD_PORT: u16 = 51820;
+const WIREGUARD_EXIT_CONSTRAINTS: WireguardConstraints = WireguardConstraints {
+    port: Constraint::Only(DEFAULT_WIREGUARD_PORT),
+    ip_version: Constraint::Only(IpVersion::V4),
+    entry_location: None,
+    intermediate_locations: Vec::new(),
+    obfuscation: None,
+};
+/// Ports that the UDP-over-TCP servers on the WireGuard relays listen on.
+const UDP2TCP_PORTS: [u16; 2] = [80, 5001];
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to open relay cache file")]
+    OpenRelayCache(#[error(source)] io::Error),
+
+    #[error(display = "Failed to write relay cache file to disk")]
+    WriteRelayCache(#[error(source)] io::Error),
+
+    #[error(display = "No relays matching current constraints")]
+    NoRelay,
+
+    #[error(display = "Failure in serialization of the relay list")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "Downloader already shut down")]
+    DownloaderShutDown,
+
+    #[error(display = "Failed to open relay latency cache file")]
+    OpenLatencyCache(#[error(source)] io::Error),
+
+    #[error(display = "Failed to write relay latency cache file to disk")]
+    WriteLatencyCache(#[error(source)] io::Error),
+}
+
+struct ParsedRelays {
+    last_updated: SystemTime,
+    locations: RelayList,
+    relays: Vec<Relay>,
+}
+
+impl ParsedRelays {
+    pub fn empty() -> Self {
+        ParsedRelays {
+            last_updated: time::UNIX_EPOCH,
+            locations: RelayList::empty(),
+            relays: Vec::new(),
+        }
+    }
+
+    pub fn from_relay_list(relay_list: RelayList, last_updated: SystemTime) -> Self {
+        let mut relays = Vec::new();
+        for country in &relay_list.countries {
+            let country_name = country.name.clone();
+            let country_code = country.code.clone();
+            for city in &country.cities {
+                let city_name = city.name.clone();
+                let city_code = city.code.clone();
+                let latitude = city.latitude;
+                let longitude = city.longitude;
+                for relay in &city.relays {
+                    let mut relay_with_location = relay.clone();
+                    relay_with_location.location = Some(Location {
+                        country: country_name.clone(),
+                        country_code: country_code.clone(),
+                        city: city_name.clone(),
+                        city_code: city_code.clone(),
+                        latitude,
+                        longitude,
+                    });
+                    relays.push(relay_with_location);
+                }
+            }
+        }
+        ParsedRelays {
+            last_updated,
+            locations: relay_list,
+            relays,
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        debug!("Reading relays from {}", path.as_ref().display());
+        let (last_modified, file) =
+            Self::open_file(path.as_ref()).map_err(Error::OpenRelayCache)?;
+        let relay_list =
+            serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Serialize)?;
+
+        Ok(Self::from_relay_list(relay_list, last_modified))
+    }
+
+    fn open_file(path: &Path) -> io::Result<(SystemTime, std::fs::File)> {
+        let file = std::fs::File::open(path)?;
+        let last_modified = file.metadata()?.modified()?;
+        Ok((last_modified, file))
+    }
+
+    pub fn last_updated(&self) -> SystemTime {
+        self.last_updated
+    }
+
+    pub fn locations(&self) -> &RelayList {
+        &self.locations
+    }
+
+    pub fn relays(&self) -> &Vec<Relay> {
+        &self.relays
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.locations.etag.as_deref()
+    }
+}
+
+pub struct RelaySelector {
+    parsed_relays: Arc<Mutex<ParsedRelays>>,
+    rng: ThreadRng,
+    updater: RelayListUpdaterHandle,
+    selection_mode: RelaySelectionMode,
+    latencies: Arc<Mutex<LatencyCache>>,
+    latency_cache_path: PathBuf,
+    latency_prober: Arc<dyn LatencyProber>,
+    device_location: Option<GeoIpLocation>,
+}
+
+impl RelaySelector {
+    /// Returns a new `RelaySelector` backed by relays cached on disk. Use the `update` method
+    /// to refresh the relay list from the internet. `latency_prober` is used to measure the
+    /// round-trip times that the fastest relay selection is based on.
+    pub fn new(
+        rpc_handle: MullvadRestHandle,
+        on_update: impl Fn(&RelayList) + Send + 'static,
+        resource_dir: &Path,
+        cache_dir: &Path,
+        latency_prober: Arc<dyn LatencyProber>,
+    ) -> Self {
+        let cache_path = cache_dir.join(RELAYS_FILENAME);
+        let resource_path = resource_dir.join(RELAYS_FILENAME);
+        let unsynchronized_parsed_relays = Self::read_relays_from_disk(&cache_path, &resource_path)
+            .unwrap_or_else(|error| {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to load cached relays")
+                );
+                ParsedRelays::empty()
+            });
+        info!(
+            "Initialized with {} cached relays from {}",
+            unsynchronized_parsed_relays.relays().len(),
+            DateTime::<Local>::from(unsynchronized_parsed_relays.last_updated())
+                .format(DATE_TIME_FORMAT_STR)
+        );
+        let parsed_relays = Arc::new(Mutex::new(unsynchronized_parsed_relays));
+
+        let updater = RelayListUpdater::new(
+            rpc_handle,
+            cache_path,
+            parsed_relays.clone(),
+            Box::new(on_update),
+        );
+
+
+        let latency_cache_path = cache_dir.join(LATENCY_CACHE_FILENAME);
+        let latencies = LatencyCache::from_file(&latency_cache_path).unwrap_or_else(|error| {
+            debug!(
+                "{}",
+                error.display_chain_with_msg("Unable to load cached relay latencies")
+            );
+            LatencyCache::default()
+        });
+
+        RelaySelector {
+            parsed_relays,
+            rng: rand::thread_rng(),
+            updater,
+            selection_mode: RelaySelectionMode::default(),
+            latencies: Arc::new(Mutex::new(latencies)),
+            latency_cache_path,
+            latency_prober,
+            device_location: None,
+        }
+    }
+
+    /// Sets the strategy used to pick among the relays that match the constraints.
+    pub fn set_selection_mode(&mut self, selection_mode: RelaySelectionMode) {
+        self.selection_mode = selection_mode;
+    }
+
+    /// Sets the location of the device, as seen when not connected to any relay. Used to find the
+    /// nearest relays.
+    pub fn set_device_location(&mut self, location: GeoIpLocation) {
+        debug!(
+            "Device location set to {}, {}",
+            location.latitude, location.longitude
+        );
+        self.device_location = Some(location);
+    }
+
+    /// Measures the round-trip time to all active relays that may be selected with the given
+    /// constraints and that lack a recent measurement. The results are cached on disk.
+    pub fn update_latencies(&self, constraints: &RelayConstraints) -> impl Future<Output = ()> {
+        let hop_locations: Vec<Constraint<LocationConstraint>> = constraints
+            .wireguard_constraints
+            .hop_locations()
+            .into_iter()
+            .chain(constraints.openvpn_constraints.entry_location.clone())
+            .collect();
+        let candidates: Vec<(String, SocketAddr)> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| {
+                relay.active
+                    && constraints.providers.matches(*relay)
+                    && constraints.ownership.matches(*relay)
+                    && (constraints.location.matches(*relay)
+                        || hop_locations
+                            .iter()
+                            .any(|location| location.matches(*relay)))
+            })
+            .map(|relay| (relay.hostname.clone(), latency::probe_address(relay)))
+            .collect();
+        let targets: Vec<(String, SocketAddr)> = {
+            let latencies = self.latencies.lock();
+            candidates
+                .into_iter()
+                .filter(|(hostname, _)| latencies.get(hostname).is_none())
+                .collect()
+        };
+
+        let prober = self.latency_prober.clone();
+        let latencies = self.latencies.clone();
+        let cache_path = self.latency_cache_path.clone();
+        async move {
+            if targets.is_empty() {
+                return;
+            }
+            debug!("Measuring latency to {} relays", targets.len());
+            let results = latency::probe_relays(&*prober, targets).await;
+            debug!("Got latency measurements for {} relays", results.len());
+
+            let snapshot = {
+                let mut latencies = latencies.lock();
+                for (hostname, rtt) in results {
+                    latencies.insert(hostname, rtt);
+                }
+                latencies.clone()
+            };
+            if let Err(error) = snapshot.write_to_file(&cache_path).await {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write relay latency cache")
+                );
+            }
+        }
+    }
+
+    /// Download the newest relay list.
+    pub fn update(&mut self) -> impl Future<Output = ()> {
+        let mut updater = self.updater.clone();
+        async move {
+            updater
+                .update_relay_list()
+                .await
+                .expect("Relay list updated thread has stopped unexpectedly");
+        }
+    }
+
+    /// Returns all countries and cities. The cities in the object returned does not have any
+    /// relays in them.
+    pub fn get_locations(&mut self) -> RelayList {
+        self.parsed_relays.lock().locations().clone()
+    }
+
+    /// Returns a random relay and relay endpoint matching the given constraints and with
+    /// preferences applied.
+    pub fn get_tunnel_endpoint(
+        &mut self,
+        relay_constraints: &RelayConstraints,
+        bridge_state: BridgeState,
+        retry_attempt: u32,
+        wg_key_exists: bool,
+    ) -> Result<(Relay, MullvadEndpoint), Error> {
+        let mut relay_constraints = relay_constraints.clone();
+        if relay_constraints
+            .wireguard_constraints
+            .entry_location
+            .is_some()
+        {
+            relay_constraints.wireguard_constraints = WIREGUARD_EXIT_CONSTRAINTS;
+        }
+        // The OpenVPN entry relay is reached through one of its bridges, so the same preferences
+        // as for bridges apply.
+        let bridge_state = if relay_constraints
+            .openvpn_constraints
+            .entry_location
+            .is_some()
+            && relay_constraints.tunnel_protocol != Constraint::Only(TunnelType::Wireguard)
+        {
+            BridgeState::On
+        } else {
+            bridge_state
+        };
+        let preferred_constraints = self.preferred_constraints(
+            &relay_constraints,
+            bridge_state,
+            retry_attempt,
+            wg_key_exists,
+        );
+        if let Some((relay, endpoint)) = self.get_tunnel_endpoint_internal(&preferred_constraints) {
+            debug!(
+                "Relay matched on highest preference for retry attempt {}",
+                retry_attempt
+            );
+            Ok((relay, endpoint))
+        } else if let Some((relay, endpoint)) =
+            self.get_tunnel_endpoint_internal(&relay_constraints)
+        {
+            debug!(
+                "Relay matched on second preference for retry attempt {}",
+                retry_attempt
+            );
+            Ok((relay, endpoint))
+        } else {
+            warn!("No relays matching {}", &relay_constraints);
+            Err(Error::NoRelay)
+        }
+    }
+
+    fn preferred_constraints(
+        &self,
+        original_constraints: &RelayConstraints,
+        bridge_state: BridgeState,
+        retry_attempt: u32,
+        wg_key_exists: bool,
+    ) -> RelayConstraints {
+        let (preferred_port, preferred_protocol, preferred_tunnel) =
+            if bridge_state != BridgeState::On {
+                self.preferred_tunnel_constraints(
+                    retry_attempt,
+                    &original_constraints.location,
+                    &original_constraints.providers,
+                    &original_constraints.ownership,
+                    wg_key_exists,
+                )
+            } else {
+                (Constraint::Any, TransportProtocol::Tcp, TunnelType::OpenVpn)
+            };
+
+
+        let mut relay_constraints = original_constraints.clone();
+        relay_constraints.openvpn_constraints = Default::default();
+
+        // Highest priority preference. Where we prefer OpenVPN using UDP. But without changing
+        // any constraints that are explicitly specified.
+        match original_constraints.tunnel_protocol {
+            // If no tunnel protocol is selected, use preferred constraints
+            Constraint::Any => {
+                if original_constraints.openvpn_constraints.port.is_any()
+                    && original_constraints.openvpn_constraints.protocol.is_any()
+                    || bridge_state == BridgeState::On
+                {
+                    relay_constraints.openvpn_constraints = OpenVpnConstraints {
+                        port: preferred_port,
+                        protocol: Constraint::Only(preferred_protocol),
+                        entry_location: original_constraints
+                            .openvpn_constraints
+                            .entry_location
+                            .clone(),
+                    };
+                } else {
+                    relay_constraints.openvpn_constraints =
+                        original_constraints.openvpn_constraints.clone();
+                }
+
+                if relay_constraints.wireguard_constraints.port.is_any() {
+                    relay_constraints.wireguard_constraints.port = preferred_port;
+                }
+
+                relay_constraints.tunnel_protocol = Constraint::Only(preferred_tunnel);
+            }
+            Constraint::Only(TunnelType::OpenVpn) => {
+                let openvpn_constraints = &mut relay_constraints.openvpn_constraints;
+                *openvpn_constraints = original_constraints.openvpn_constraints.clone();
+                if bridge_state == BridgeState::On && openvpn_constraints.protocol.is_any() {
+                    // FIXME: This is temporary while talpid-core only supports TCP proxies
+                    openvpn_constraints.protocol = Constraint::Only(TransportProtocol::Tcp);
+                } else if openvpn_constraints.port.is_any() && openvpn_constraints.protocol.is_any()
+                {
+                    let (preferred_port, preferred_protocol) =
+                        Self::preferred_openvpn_constraints(retry_attempt);
+                    openvpn_constraints.port = preferred_port;
+                    openvpn_constraints.protocol = Constraint::Only(preferred_protocol);
+                }
+            }
+            Constraint::Only(TunnelType::Wireguard) => {
+                relay_constraints.wireguard_constraints =
+                    original_constraints.wireguard_constraints.clone();
+                // This ensures that if after the first 2 failed attempts the daemon does not
+                // connect, then afterwards 2 of each 4 successive attempts will try to connect on
+                // port 53.
+                if retry_attempt % 4 > 1 && relay_constraints.wireguard_constraints.port.is_any() {
+                    relay_constraints.wireguard_constraints.port = Constraint::Only(53);
+                }
+            }
+        }
+
+        relay_constraints
+    }
+
+    /// Returns the relays and peers that a WireGuard tunnel passes through before reaching
+    /// `exit_peer` on `exit_relay`, starting with the entry relay. One relay is selected for each
+    /// of the hop locations in the WireGuard constraints, and no relay is used twice. Each peer
+    /// only routes the endpoint of the next peer, so that the traffic goes through every relay
+    /// in the chain. Returns an empty chain if no entry location is set.
+    pub fn get_tunnel_entry_chain(
+        &mut self,
+        exit_relay: &Relay,
+        exit_peer: &wireguard::PeerConfig,
+        relay_constraints: &RelayConstraints,
+        retry_attempt: u32,
+    ) -> Option<Vec<(Relay, wireguard::PeerConfig)>> {
+        let hop_locations = relay_constraints.wireguard_constraints.hop_locations();
+        let mut used_hostnames = vec![exit_relay.hostname.clone()];
+        let mut chain = Vec::with_capacity(hop_locations.len());
+
+        for (index, location) in hop_locations.into_iter().enumerate() {
+            let hop_constraints = RelayConstraints {
+                location,
+                tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+                ..relay_constraints.clone()
+            };
+            // Only the entry relay is reached directly, so the preferences for getting through
+            // restrictive networks only apply to it.
+            let hop_constraints = if index == 0 {
+                self.preferred_constraints(&hop_constraints, BridgeState::Off, retry_attempt, true)
+            } else {
+                RelayConstraints {
+                    wireguard_constraints: WIREGUARD_EXIT_CONSTRAINTS,
+                    ..hop_constraints
+                }
+            };
+
+            let (relay, endpoint) = self.get_hop_endpoint(&hop_constraints, &used_hostnames)?;
+            let peer = match endpoint {
+                MullvadEndpoint::Wireguard { peer, .. } => peer,
+                _ => {
+                    log::error!("BUG: Endpoint must be WireGuard endpoint");
+                    return None;
+                }
+            };
+            used_hostnames.push(relay.hostname.clone());
+            chain.push((relay, peer));
+        }
+
+        let next_endpoints: Vec<IpAddr> = chain
+            .iter()
+            .skip(1)
+            .map(|(_, peer)| peer.endpoint.ip())
+            .chain(std::iter::once(exit_peer.endpoint.ip()))
+            .collect();
+        for ((_, peer), next_endpoint) in chain.iter_mut().zip(next_endpoints) {
+            peer.allowed_ips = vec![IpNetwork::from(next_endpoint)];
+        }
+
+        Some(chain)
+    }
+
+    /// Returns a relay matching `constraints` that is not in `excluded_hostnames`, along with an
+    /// endpoint on it.
+    fn get_hop_endpoint(
+        &mut self,
+        constraints: &RelayConstraints,
+        excluded_hostnames: &[String],
+    ) -> Option<(Relay, MullvadEndpoint)> {
+        let matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active && !excluded_hostnames.contains(&relay.hostname))
+            .filter_map(|relay| Self::matching_relay(relay, constraints))
+            .collect();
+
+        self.pick_relay(&matching_relays)
+            .and_then(|selected_relay| {
+                let endpoint = self.get_random_tunnel(selected_relay, constraints);
+                let addr_in = endpoint
+                    .as_ref()
+                    .map(|endpoint| endpoint.to_endpoint().address.ip())
+                    .unwrap_or(IpAddr::from(selected_relay.ipv4_addr_in));
+                info!(
+                    "Selected entry relay {} at {}",
+                    selected_relay.hostname, addr_in
+                );
+                endpoint.map(|endpoint| (selected_relay.clone(), endpoint))
+            })
+    }
+
+    /// Returns a bridge on a relay matching the OpenVPN entry location, for reaching
+    /// `exit_relay` through. Returns `None` if no entry location is set or no relay matches it.
+    pub fn get_openvpn_entry_proxy(
+        &mut self,
+        exit_relay: &Relay,
+        relay_constraints: &RelayConstraints,
+    ) -> Option<(ProxySettings, Relay)> {
+        let entry_location = relay_constraints
+            .openvpn_constraints
+            .entry_location
+            .clone()?;
+        let bridge_constraints = InternalBridgeConstraints {
+            location: entry_location,
+            providers: relay_constraints.providers.clone(),
+            ownership: relay_constraints.ownership,
+            // FIXME: This is temporary while talpid-core only supports TCP proxies
+            transport_protocol: Constraint::Only(TransportProtocol::Tcp),
+        };
+        let matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active && relay.hostname != exit_relay.hostname)
+            .filter_map(|relay| Self::matching_bridge_relay(relay, &bridge_constraints))
+            .collect();
+
+        let entry_relay = self.pick_relay(&matching_relays)?.clone();
+        self.pick_random_bridge(&entry_relay)
+            .map(|bridge| (bridge, entry_relay))
+    }
+
+    pub fn get_auto_proxy_settings(
+        &mut self,
+        bridge_constraints: &InternalBridgeConstraints,
+        location: &Location,
+        retry_attempt: u32,
+    ) -> Option<(ProxySettings, Relay)> {
+        if !self.should_use_bridge(retry_attempt) {
+            return None;
+        }
+
+        // For now, only TCP tunnels are supported.
+        if let Constraint::Only(TransportProtocol::Udp) = bridge_constraints.transport_protocol {
+            return None;
+        }
+
+        self.get_proxy_settings(bridge_constraints, location)
+    }
+
+    pub fn should_use_bridge(&self, retry_attempt: u32) -> bool {
+        // shouldn't use a bridge for the first 3 times
+        retry_attempt > 3 &&
+            // i.e. 4th and 5th with bridge, 6th & 7th without
+            // The test is to see whether the current _couple of connections_ is even or not.
+            // | retry_attempt                | 4 | 5 | 6 | 7 | 8 | 9 |
+            // | (retry_attempt % 4) < 2      | t | t | f | f | t | t |
+            (retry_attempt % 4) < 2
+    }
+
+    pub fn get_proxy_settings(
+        &mut self,
+        constraints: &InternalBridgeConstraints,
+        location: &Location,
+    ) -> Option<(ProxySettings, Relay)> {
+        let mut matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active)
+            .filter_map(|relay| Self::matching_bridge_relay(relay, constraints))
+            .collect();
+
+        if matching_relays.is_empty() {
+            return None;
+        }
+
+        matching_relays.sort_by_cached_key(|relay| {
+            (relay.location.as_ref().unwrap().distance_from(&location) * 1000.0) as i64
+        });
+        matching_relays.get(0).and_then(|relay| {
+            self.pick_random_bridge(&relay)
+                .map(|bridge| (bridge, relay.clone()))
+        })
+    }
+
+    /// Returns an obfuscator of the given type for the traffic to the WireGuard peer at
+    /// `peer_endpoint`, which belongs to `relay`. Obfuscation servers are subject to the
+    /// provider, ownership and excluded locations of `constraints`.
+    pub fn get_obfuscator(
+        &mut self,
+        obfuscation: ObfuscationType,
+        constraints: &RelayConstraints,
+        relay: &Relay,
+        peer_endpoint: SocketAddr,
+    ) -> Option<ObfuscatorConfig> {
+        match obfuscation {
+            ObfuscationType::Udp2Tcp => {
+                let port = *UDP2TCP_PORTS.choose(&mut self.rng)?;
+                Some(ObfuscatorConfig::Udp2Tcp {
+                    endpoint: SocketAddr::new(peer_endpoint.ip(), port),
+                })
+            }
+            ObfuscationType::Shadowsocks => self.get_shadowsocks_obfuscator(constraints, relay),
+        }
+    }
+
+    /// Returns the Shadowsocks server closest to `relay` that can relay WireGuard traffic. The
+    /// server may be in any location that is not excluded by `constraints`.
+    fn get_shadowsocks_obfuscator(
+        &mut self,
+        constraints: &RelayConstraints,
+        relay: &Relay,
+    ) -> Option<ObfuscatorConfig> {
+        let location = relay.location.as_ref()?;
+        let constraints = InternalBridgeConstraints {
+            location: constraints
+                .location
+                .as_ref()
+                .map(|location| LocationConstraint {
+                    include: vec![],
+                    exclude: location.exclude.clone(),
+                    exclude_providers: location.exclude_providers.clone(),
+                }),
+            providers: constraints.providers.clone(),
+            ownership: constraints.ownership,
+            transport_protocol: Constraint::Only(TransportProtocol::Udp),
+        };
+        let mut matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active)
+            .filter_map(|relay| Self::matching_bridge_relay(relay, &constraints))
+            .filter_map(|mut relay| {
+                relay
+                    .bridges
+                    .shadowsocks
+                    .retain(|bridge| SHADOWSOCKS_UDP_CIPHERS.contains(&bridge.cipher.as_str()));
+                if relay.bridges.shadowsocks.is_empty() {
+                    None
+                } else {
+                    Some(relay)
+                }
+            })
+            .collect();
+
+        matching_relays.sort_by_cached_key(|relay| {
+            (relay.location.as_ref().unwrap().distance_from(location) * 1000.0) as i64
+        });
+        let bridge_relay = matching_relays.get(0)?;
+        let bridge = bridge_relay.bridges.shadowsocks.choose(&mut self.rng)?;
+        info!(
+            "Selected Shadowsocks obfuscation server {} at {}:{}/{}",
+            bridge_relay.hostname, bridge_relay.ipv4_addr_in, bridge.port, bridge.protocol
+        );
+        Some(ObfuscatorConfig::Shadowsocks {
+            endpoint: SocketAddr::new(bridge_relay.ipv4_addr_in.into(), bridge.port),
+            password: bridge.password.clone(),
+            cipher: bridge.cipher.clone(),
+        })
+    }
+
+    /// Returns preferred constraints
+    #[allow(unused_variables)]
+    fn preferred_tunnel_constraints(
+        &self,
+        retry_attempt: u32,
+        location_constraint: &Constraint<LocationConstraint>,
+        providers_constraint: &Constraint<Providers>,
+        ownership_constraint: &Constraint<Ownership>,
+        wg_key_exists: bool,
+    ) -> (Constraint<u16>, TransportProtocol, TunnelType) {
+        #[cfg(not(target_os = "windows"))]
+        {
+            let location_supports_wireguard =
+                self.parsed_relays.lock().relays().iter().any(|relay| {
+                    relay.active
+                        && !relay.tunnels.wireguard.is_empty()
+                        && location_constraint.matches(relay)
+                        && providers_constraint.matches(relay)
+                        && ownership_constraint.matches(relay)
+                });
+            // If location does not support WireGuard, defer to preferred OpenVPN tunnel
+            // constraints
+            if !location_supports_wireguard || !wg_key_exists {
+                let (preferred_port, preferred_protocol) =
+                    Self::preferred_openvpn_constraints(retry_attempt);
+                return (preferred_port, preferred_protocol, TunnelType::OpenVpn);
+            }
+
+
+            // Try out WireGuard in the first two connection attempts, first with any port,
+            // afterwards on port 53. Afterwards, connect through OpenVPN alternating between UDP
+            // on any port twice and TCP on port 443 once.
+            match retry_attempt {
+                0 if location_supports_wireguard => (
+                    Constraint::Any,
+                    TransportProtocol::Udp,
+                    TunnelType::Wireguard,
+                ),
+                1 => (
+                    Constraint::Only(53),
+                    TransportProtocol::Udp,
+                    TunnelType::Wireguard,
+                ),
+                _ => {
+                    let (preferred_port, preferred_protocol) =
+                        Self::preferred_openvpn_constraints(retry_attempt - 2);
+                    (preferred_port, preferred_protocol, TunnelType::OpenVpn)
+                }
+            }
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            let (preferred_port, preferred_protocol) =
+                Self::preferred_openvpn_constraints(retry_attempt);
+
+
+            (preferred_port, preferred_protocol, TunnelType::OpenVpn)
+        }
+    }
+
+    fn preferred_openvpn_constraints(retry_attempt: u32) -> (Constraint<u16>, TransportProtocol) {
+        // Prefer UDP by default. But if that has failed a couple of times, then try TCP port
+        // 443, which works for many with UDP problems. After that, just alternate
+        // between protocols.
+        match retry_attempt {
+            0 | 1 => (Constraint::Any, TransportProtocol::Udp),
+            2 | 3 => (Constraint::Only(443), TransportProtocol::Tcp),
+            attempt if attempt % 2 == 0 => (Constraint::Any, TransportProtocol::Udp),
+            _ => (Constraint::Any, TransportProtocol::Tcp),
+        }
+    }
+
+
+    /// Returns a random relay endpoint if any is matching the given constraints.
+    fn get_tunnel_endpoint_internal(
+        &mut self,
+        constraints: &RelayConstraints,
+    ) -> Option<(Relay, MullvadEndpoint)> {
+        let matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active)
+            .filter_map(|relay| Self::matching_relay(relay, constraints))
+            .collect();
+
+        self.pick_relay(&matching_relays)
+            .and_then(|selected_relay| {
+                let endpoint = self.get_random_tunnel(&selected_relay, &constraints);
+                let addr_in = endpoint
+                    .as_ref()
+                    .map(|endpoint| endpoint.to_endpoint().address.ip())
+                    .unwrap_or(IpAddr::from(selected_relay.ipv4_addr_in));
+                info!("Selected relay {} at {}", selected_relay.hostname, addr_in);
+                endpoint.map(|endpoint| (selected_relay.clone(), endpoint))
+            })
+    }
+
+    /// Takes a `Relay` and a corresponding `RelayConstraints` and returns a new `Relay` if the
+    /// given relay matches the constraints.
+    fn matching_relay(relay: &Relay, constraints: &RelayConstraints) -> Option<Relay> {
+        if !constraints.location.matches(relay) {
+            return None;
+        }
+        if !constraints.providers.matches(&relay) {
+            return None;
+        }
+        if !constraints.ownership.matches(relay) {
+            return None;
+        }
+
+
+        let relay = match constraints.tunnel_protocol {
+            Constraint::Any => {
+                let mut relay = relay.clone();
+                relay.tunnels = RelayTunnels {
+                    wireguard: Self::matching_wireguard_tunnels(
+                        &relay.tunnels,
+                        &constraints.wireguard_constraints,
+                    ),
+                    openvpn: Self::matching_openvpn_tunnels(
+                        &relay.tunnels,
+                        &constraints.openvpn_constraints,
+                    ),
+                };
+                relay
+            }
+            Constraint::Only(TunnelType::Wireguard) => {
+                let mut relay = relay.clone();
+                relay.tunnels = RelayTunnels {
+                    wireguard: Self::matching_wireguard_tunnels(
+                        &relay.tunnels,
+                        &constraints.wireguard_constraints,
+                    ),
+                    openvpn: vec![],
+                };
+                relay
+            }
+
+            Constraint::Only(TunnelType::OpenVpn) => {
+                let mut relay = relay.clone();
+                relay.tunnels = RelayTunnels {
+                    openvpn: Self::matching_openvpn_tunnels(
+                        &relay.tunnels,
+                        &constraints.openvpn_constraints,
+                    ),
+                    wireguard: vec![],
+                };
+                relay
+            }
+        };
+
+
+        let relay_matches = match constraints.tunnel_protocol {
+            Constraint::Any => {
+                !relay.tunnels.openvpn.is_empty() || !relay.tunnels.wireguard.is_empty()
+            }
+            Constraint::Only(TunnelType::OpenVpn) => !relay.tunnels.openvpn.is_empty(),
+            Constraint::Only(TunnelType::Wireguard) => !relay.tunnels.wireguard.is_empty(),
+        };
+
+        if relay_matches {
+            Some(relay)
+        } else {
+            None
+        }
+    }
+
+    fn matching_bridge_relay(
+        relay: &Relay,
+        constraints: &InternalBridgeConstraints,
+    ) -> Option<Relay> {
+        if !constraints.location.matches(relay) {
+            return None;
+        }
+        if !constraints.providers.matches(relay) {
+            return None;
+        }
+        if !constraints.ownership.matches(relay) {
+            return None;
+        }
+
+        let mut filtered_relay = relay.clone();
+        filtered_relay
+            .bridges
+            .shadowsocks
+            .retain(|bridge| constraints.transport_protocol.matches_eq(&bridge.protocol));
+        if filtered_relay.bridges.shadowsocks.is_empty() {
+            return None;
+        }
+
+        Some(filtered_relay)
+    }
+
+    fn matching_openvpn_tunnels(
+        tunnels: &RelayTunnels,
+        constraints: &OpenVpnConstraints,
+    ) -> Vec<OpenVpnEndpointData> {
+        tunnels
+            .openvpn
+            .iter()
+            .filter(|endpoint| constraints.matches(*endpoint))
+            .cloned()
+            .collect()
+    }
+
+    fn matching_wireguard_tunnels(
+        tunnels: &RelayTunnels,
+        constraints: &WireguardConstraints,
+    ) -> Vec<WireguardEndpointData> {
+        tunnels
+            .wireguard
+            .iter()
+            .filter(|endpoint| constraints.matches(*endpoint))
+            .cloned()
+            .collect()
+    }
+
+    /// Pick a relay from the given slice using the current selection mode.
+    fn pick_relay<'a>(&mut self, relays: &'a [Relay]) -> Option<&'a Relay> {
+        match self.selection_mode {
+            RelaySelectionMode::Weighted => self.pick_random_relay(relays),
+            RelaySelectionMode::Fastest => self
+                .pick_fastest_relay(relays)
+                .or_else(|| self.pick_random_relay(relays)),
+            RelaySelectionMode::Nearest { max_distance } => self
+                .pick_nearest_relay(relays, max_distance)
+                .or_else(|| self.pick_random_relay(relays)),
+        }
+    }
+
+    /// Pick a random relay, by weight, among the relays closest to the device. Will return `None`
+    /// if the location of the device is unknown, or if no relay is within `max_distance`
+    /// kilometers.
+    fn pick_nearest_relay<'a>(
+        &mut self,
+        relays: &'a [Relay],
+        max_distance: Option<u32>,
+    ) -> Option<&'a Relay> {
+        let device_location = match self.device_location.as_ref() {
+            Some(location) => location,
+            None => {
+                debug!("Device location is unknown, unable to select nearest relay");
+                return None;
+            }
+        };
+        let distances: Vec<(f64, &'a Relay)> = relays
+            .iter()
+            .filter_map(|relay| {
+                let distance = device_location.distance_from(relay.location.as_ref()?);
+                Some((distance, relay))
+            })
+            .collect();
+        let min_distance = distances
+            .iter()
+            .map(|(distance, _)| *distance)
+            .fold(f64::INFINITY, f64::min);
+        if let Some(max_distance) = max_distance {
+            if min_distance > f64::from(max_distance) {
+                debug!(
+                    "Nearest relay is {:.0} km away, which is further than {} km",
+                    min_distance, max_distance
+                );
+                return None;
+            }
+        }
+
+        // All relays in a city share the same coordinates, so this selects among the relays in
+        // the nearest city.
+        let nearest: Vec<&'a Relay> = distances
+            .into_iter()
+            .filter(|(distance, _)| *distance <= min_distance)
+            .map(|(_, relay)| relay)
+            .collect();
+        debug!(
+            "Selecting among {} relays {:.0} km away",
+            nearest.len(),
+            min_distance
+        );
+        nearest
+            .choose_weighted(&mut self.rng, |relay| relay.weight)
+            .ok()
+            .copied()
+    }
+
+    /// Pick a random relay among the relays with the lowest measured round-trip time. Will
+    /// return `None` if no relay in the given slice has a recent latency measurement.
+    fn pick_fastest_relay<'a>(&mut self, relays: &'a [Relay]) -> Option<&'a Relay> {
+        let fastest = self.latencies.lock().fastest_relays(relays);
+        debug!(
+            "Selecting among {} relays with lowest latency out of {} relays",
+            fastest.len(),
+            relays.len()
+        );
+        fastest.choose(&mut self.rng).copied()
+    }
+
+    /// Pick a random relay from the given slice. Will return `None` if the given slice is empty
+    /// or all relays in it has zero weight.
+    fn pick_random_relay<'a>(&mut self, relays: &'a [Relay]) -> Option<&'a Relay> {
+        let total_weight: u64 = relays.iter().map(|relay| relay.weight).sum();
+        debug!(
+            "Selecting among {} relays with combined weight {}",
+            relays.len(),
+            total_weight
+        );
+        if total_weight == 0 {
+            None
+        } else {
+            // Pick a random number in the range 0 - total_weight. This choses the relay.
+            let mut i: u64 = self.rng.gen_range(0, total_weight + 1);
+            Some(
+                relays
+                    .iter()
+                    .find(|relay| {
+                        i = i.saturating_sub(relay.weight);
+                        i == 0
+                    })
+                    .unwrap(),
+            )
+        }
+    }
+
+    /// Picks a random bridge from a relay.
+    fn pick_random_bridge(&mut self, relay: &Relay) -> Option<ProxySettings> {
+        relay
+            .bridges
+            .shadowsocks
+            .choose(&mut self.rng)
+            .map(|shadowsocks_endpoint| {
+                info!(
+                    "Selected Shadowsocks bridge {} at {}:{}/{}",
+                    relay.hostname,
+                    relay.ipv4_addr_in,
+                    shadowsocks_endpoint.port,
+                    shadowsocks_endpoint.protocol
+                );
+                shadowsocks_endpoint
+                    .clone()
+                    .to_proxy_settings(relay.ipv4_addr_in.into())
+            })
+    }
+
+    fn get_random_tunnel(
+        &mut self,
+        relay: &Relay,
+        constraints: &RelayConstraints,
+    ) -> Option<MullvadEndpoint> {
+        #[cfg(not(target_os = "android"))]
+        let mut thread_rng = self.rng.clone();
+        #[cfg(not(target_os = "android"))]
+        let mut new_openvpn_endpoint = || {
+            relay
+                .tunnels
+                .openvpn
+                .choose(&mut thread_rng)
+                .cloned()
+                .map(|endpoint| endpoint.into_mullvad_endpoint(relay.ipv4_addr_in.into()))
+        };
+
+        let mut new_wg_endpoint = || {
+            relay
+                .tunnels
+                .wireguard
+                .choose(&mut self.rng)
+                .cloned()
+                .and_then(|wg_tunnel| {
+                    self.wg_data_to_endpoint(relay, wg_tunnel, &constraints.wireguard_constraints)
+                })
+        };
+
+        #[cfg(not(target_os = "android"))]
+        match constraints.tunnel_protocol {
+            Constraint::Only(TunnelType::OpenVpn) => new_openvpn_endpoint(),
+
+            #[cfg(target_os = "windows")]
+            Constraint::Any => new_openvpn_endpoint(),
+
+            #[cfg(not(target_os = "windows"))]
+            Constraint::Any => vec![new_openvpn_endpoint(), new_wg_endpoint()]
+                .into_iter()
+                .filter_map(|relay| relay)
+                .collect::<Vec<_>>()
+                .choose(&mut self.rng)
+                .cloned(),
+
+            Constraint::Only(TunnelType::Wireguard) => new_wg_endpoint(),
+        }
+        #[cfg(target_os = "android")]
+        new_wg_endpoint()
+    }
+
+    fn wg_data_to_endpoint(
+        &mut self,
+        relay: &Relay,
+        data: WireguardEndpointData,
+        constraints: &WireguardConstraints,
+    ) -> Option<MullvadEndpoint> {
+        let host = self.get_address_for_wireguard_relay(relay, constraints)?;
+        let port = self.get_port_for_wireguard_relay(&data, constraints)?;
+        let peer_config = wireguard::PeerConfig {
+            public_key: data.public_key,
+            endpoint: SocketAddr::new(host, port),
+            allowed_ips: all_of_the_internet(),
+            protocol: TransportProtocol::Udp,
+            psk: None,
+        };
+        Some(MullvadEndpoint::Wireguard {
+            peer: peer_config,
+            ipv4_gateway: data.ipv4_gateway,
+            ipv6_gateway: data.ipv6_gateway,
+        })
+    }
+
+    fn get_address_for_wireguard_relay(
+        &mut self,
+        relay: &Relay,
+        constraints: &WireguardConstraints,
+    ) -> Option<IpAddr> {
+        match constraints.ip_version {
+            Constraint::Any | Constraint::Only(IpVersion::V4) => Some(relay.ipv4_addr_in.into()),
+            Constraint::Only(IpVersion::V6) => relay.ipv6_addr_in.map(|addr| addr.into()),
+        }
+    }
+
+    fn get_port_for_wireguard_relay(
+        &mut self,
+        data: &WireguardEndpointData,
+        constraints: &WireguardConstraints,
+    ) -> Option<u16> {
+        match constraints.port {
+            Constraint::Any => {
+                let get_port_amount =
+                    |range: &(u16, u16)| -> u64 { (1 + range.1 - range.0) as u64 };
+                let port_amount: u64 = data.port_ranges.iter().map(get_port_amount).sum();
+
+                if port_amount < 1 {
+                    return None;
+                }
+
+                let mut port_index = self.rng.gen_range(0, port_amount);
+
+                for range in data.port_ranges.iter() {
+                    let ports_in_range = get_port_amount(range);
+                    if port_index < ports_in_range {
+                        return Some(port_index as u16 + range.0);
+                    }
+                    port_index -= ports_in_range;
+                }
+                panic!("Port selection algorithm is broken")
+            }
+            Constraint::Only(port) => {
+                if data
+                    .port_ranges
+                    .iter()
+                    .any(|range| (range.0 <= port && port <= range.1))
+                {
+                    Some(port)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Try to read the relays from disk, preferring the newer ones.
+    fn read_relays_from_disk(
+        cache_path: &Path,
+        resource_path: &Path,
+    ) -> Result<ParsedRelays, Error> {
+        // prefer the resource path's relay list if the cached one doesn't exist or was modified
+        // before the resource one was created.
+        let cached_relays = ParsedRelays::from_file(cache_path);
+        let bundled_relays = match ParsedRelays::from_file(resource_path) {
+            Ok(bundled_relays) => bundled_relays,
+            Err(e) => {
+                log::error!("Failed to load bundled relays - {}", e);
+                return cached_relays;
+            }
+        };
+
+        if cached_relays
+            .as_ref()
+            .map(|cached| cached.last_updated > bundled_relays.last_updated)
+            .unwrap_or(false)
+        {
+            cached_relays
+        } else {
+            Ok(bundled_relays)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RelayListUpdaterHandle {
+    tx: mpsc::Sender<()>,
+}
+
+impl RelayListUpdaterHandle {
+    async fn update_relay_list(&mut self) -> Result<(), Error> {
+        self.tx
+            .send(())
+            .await
+            .map_err(|_| Error::DownloaderShutDown)
+    }
+}
+
+struct RelayListUpdater {
+    rpc_client: RelayListProxy,
+    cache_path: PathBuf,
+    parsed_relays: Arc<Mutex<ParsedRelays>>,
+    on_update: Box<dyn Fn(&RelayList) + Send + 'static>,
+    earliest_next_try: Instant,
+}
+
+impl RelayListUpdater {
+    pub fn new(
+        rpc_handle: MullvadRestHandle,
+        cache_path: PathBuf,
+        parsed_relays: Arc<Mutex<ParsedRelays>>,
+        on_update: Box<dyn Fn(&RelayList) + Send + 'static>,
+    ) -> RelayListUpdaterHandle {
+        let (tx, cmd_rx) = mpsc::channel(1);
+        let service = rpc_handle.service();
+        let rpc_client = RelayListProxy::new(rpc_handle);
+        let updater = RelayListUpdater {
+            rpc_client,
+            cache_path,
+            parsed_relays,
+            on_update,
+            earliest_next_try: Instant::now() + UPDATE_INTERVAL,
+        };
+
+        service.spawn(updater.run(cmd_rx));
+
+        RelayListUpdaterHandle { tx }
+    }
+
+    async fn run(mut self, mut cmd_rx: mpsc::Receiver<()>) {
+        let mut check_interval = tokio::time::interval(UPDATE_CHECK_INTERVAL).fuse();
+        let mut download_future = Box::pin(Fuse::terminated());
+        loop {
+            futures::select! {
+                _check_update = check_interval.next() => {
+                    if download_future.is_terminated() && self.should_update() {
+                        let tag = self.parsed_relays.lock().tag().map(|tag| tag.to_string());
+                        download_future = Box::pin(Self::download_relay_list(self.rpc_client.clone(), tag).fuse());
+                        self.earliest_next_try = Instant::now() + UPDATE_INTERVAL;
+                    }
+                },
+
+                new_relay_list = download_future => {
+                    self.consume_new_relay_list(new_relay_list).await;
+
+                },
+
+                cmd = cmd_rx.next() => {
+                    match cmd {
+                        Some(_) => {
+                            let tag = self.parsed_relays.lock().tag().map(|tag| tag.to_string());
+                            self.consume_new_relay_list(self.rpc_client.relay_list(tag).await).await;
+                        },
+                        None => {
+                            log::error!("Relay list updater shutting down");
+                            return;
+                        }
+                    }
+                }
+
+            };
+        }
+    }
+
+    async fn consume_new_relay_list(
+        &mut self,
+        result: Result<Option<RelayList>, mullvad_rpc::rest::Error>,
+    ) {
+        match result {
+            Ok(Some(relay_list)) => {
+                if let Err(err) = self.update_cache(relay_list).await {
+                    log::error!("Failed to update relay list cache: {}", err);
+                }
+            }
+            Ok(None) => log::debug!("Relay list is up-to-date"),
+            Err(err) => {
+                log::error!(
+                    "Failed to fetch new relay list: {}. Will retry in {} minutes",
+                    err,
+                    self.earliest_next_try
+                        .saturating_duration_since(Instant::now())
+                        .as_secs()
+                        / 60
+                );
+            }
+        }
+    }
+
+    /// Returns true if the current parsed_relays is older than UPDATE_INTERVAL
+    fn should_update(&mut self) -> bool {
+        match SystemTime::now().duration_since(self.parsed_relays.lock().last_updated()) {
+            Ok(duration) => duration > UPDATE_INTERVAL && self.earliest_next_try <= Instant::now(),
+            // If the clock is skewed we have no idea by how much or when the last update
+            // actually was, better download again to get in sync and get a `last_updated`
+            // timestamp corresponding to the new time.
+            Err(_) => true,
+        }
+    }
+
+    fn download_relay_list(
+        rpc_handle: RelayListProxy,
+        tag: Option<String>,
+    ) -> impl Future<Output = Result<Option<RelayList>, mullvad_rpc::rest::Error>> + 'static {
+        let download_futures = move || rpc_handle.relay_list(tag.clone());
+
+        let exponential_backoff =
+            ExponentialBackoff::new(EXPONENTIAL_BACKOFF_INITIAL, EXPONENTIAL_BACKOFF_FACTOR)
+                .max_delay(UPDATE_INTERVAL * 2);
+
+        let download_future = retry_future_with_backoff(
+            download_futures,
+            |result| result.is_err(),
+            Jittered::jitter(exponential_backoff),
+        );
+        download_future
+    }
+
+    async fn update_cache(&mut self, new_relay_list: RelayList) -> Result<(), Error> {
+        if let Err(error) = Self::cache_relays(&self.cache_path, &new_relay_list).await {
+            error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update relay cache on disk")
+            );
+        }
+
+        let new_parsed_relays = ParsedRelays::from_relay_list(new_relay_list, SystemTime::now());
+        info!(
+            "Downloaded relay inventory has {} relays",
+            new_parsed_relays.relays().len()
+        );
+
+        let mut parsed_relays = self.parsed_relays.lock();
+        *parsed_relays = new_parsed_relays;
+        (self.on_update)(parsed_relays.locations());
+        Ok(())
+    }
+
+    /// Write a `RelayList` to the cache file.
+    async fn cache_relays(cache_path: &Path, relays: &RelayList) -> Result<(), Error> {
+        debug!("Writing relays cache to {}", cache_path.display());
+        let mut file = File::create(cache_path)
+            .await
+            .map_err(Error::OpenRelayCache)?;
+        let bytes = serde_json::to_vec_pretty(relays).map_err(Error::Serialize)?;
+        let mut slice: &[u8] = bytes.as_slice();
+        let _ = tokio::io::copy(&mut slice, &mut file)
+            .await
+            .map_err(Error::WriteRelayCache)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future::{self, BoxFuture, FutureExt};
+    use mullvad_types::{
+        relay_constraints::GeographicLocationConstraint,
+        relay_list::{RelayBridges, RelayListCity, RelayListCountry, ShadowsocksEndpointData},
+    };
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, Ipv6Addr},
+        time::Duration,
+    };
+
+    /// Prober that answers from a fixed table of round-trip times, indexed by the last octet of
+    /// the relay address, instead of touching the network.
+    #[derive(Default)]
+    struct MockProber(HashMap<u8, Duration>);
+
+    impl MockProber {
+        fn new(rtts: &[(u8, u64)]) -> Self {
+            MockProber(
+                rtts.iter()
+                    .map(|(last_octet, millis)| (*last_octet, Duration::from_millis(*millis)))
+                    .collect(),
+            )
+        }
+    }
+
+    impl LatencyProber for MockProber {
+        fn probe(&self, addr: SocketAddr) -> BoxFuture<'static, Option<Duration>> {
+            let rtt = match addr.ip() {
+                IpAddr::V4(ip) => self.0.get(&ip.octets()[3]).copied(),
+                IpAddr::V6(_) => None,
+            };
+            future::ready(rtt).boxed()
+        }
+    }
+
+    fn relay(hostname: &str, last_octet: u8) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: Ipv4Addr::new(10, 0, 0, last_octet),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: "provider".to_owned(),
+            weight: 1,
+            tunnels: RelayTunnels {
+                openvpn: vec![],
+                wireguard: vec![WireguardEndpointData {
+                    port_ranges: vec![(53, 53), (4000, 33433), (51820, 51820)],
+                    ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                    ipv6_gateway: Ipv6Addr::LOCALHOST,
+                    public_key: wireguard::PublicKey::from([last_octet; 32]),
+                }],
+            },
+            bridges: RelayBridges {
+                shadowsocks: vec![ShadowsocksEndpointData {
+                    port: 443,
+                    cipher: "aes-256-gcm".to_owned(),
+                    password: "mullvad".to_owned(),
+                    protocol: TransportProtocol::Tcp,
+                }],
+            },
+            location: None,
+        }
+    }
+
+    fn city(code: &str, latitude: f64, longitude: f64, relays: Vec<Relay>) -> RelayListCity {
+        RelayListCity {
+            name: code.to_owned(),
+            code: code.to_owned(),
+            latitude,
+            longitude,
+            relays,
+        }
+    }
+
+    fn country(code: &str, cities: Vec<RelayListCity>) -> RelayListCountry {
+        RelayListCountry {
+            name: code.to_owned(),
+            code: code.to_owned(),
+            cities,
+        }
+    }
+
+    /// Returns a relay selector for three relays in Sweden and one in Germany, which does not
+    /// read or update any relay list. None of the relays respond to latency probes.
+    fn new_selector() -> RelaySelector {
+        let relay_list = RelayList {
+            etag: None,
+            countries: vec![
+                country(
+                    "se",
+                    vec![
+                        city("got", 57.7, 11.97, vec![relay("se1", 1), relay("se2", 2)]),
+                        city("sto", 59.33, 18.07, vec![relay("se3", 3)]),
+                    ],
+                ),
+                country("de", vec![city("fra", 50.11, 8.68, vec![relay("de1", 4)])]),
+            ],
+        };
+        RelaySelector {
+            parsed_relays: Arc::new(Mutex::new(ParsedRelays::from_relay_list(
+                relay_list,
+                SystemTime::now(),
+            ))),
+            rng: rand::thread_rng(),
+            updater: RelayListUpdaterHandle {
+                tx: mpsc::channel(1).0,
+            },
+            selection_mode: RelaySelectionMode::default(),
+            latencies: Arc::new(Mutex::new(LatencyCache::default())),
+            latency_cache_path: PathBuf::new(),
+            latency_prober: Arc::new(MockProber::default()),
+            device_location: None,
+        }
+    }
+
+    fn device_location(latitude: f64, longitude: f64) -> GeoIpLocation {
+        GeoIpLocation {
+            ipv4: None,
+            ipv6: None,
+            country: String::new(),
+            city: None,
+            latitude,
+            longitude,
+            mullvad_exit_ip: false,
+            hostname: None,
+            bridge_hostname: None,
+            entry_hostname: None,
+            intermediate_hostnames: vec![],
+        }
+    }
+
+    fn hostnames(relays: &[Relay]) -> Vec<&str> {
+        relays.iter().map(|relay| relay.hostname.as_str()).collect()
+    }
+
+    fn country_constraint(code: &str) -> Constraint<LocationConstraint> {
+        Constraint::Only(GeographicLocationConstraint::Country(code.to_owned()).into())
+    }
+
+    fn hostname_constraint(country: &str, city: &str, hostname: &str) -> RelayConstraints {
+        RelayConstraints {
+            location: Constraint::Only(
+                GeographicLocationConstraint::Hostname(
+                    country.to_owned(),
+                    city.to_owned(),
+                    hostname.to_owned(),
+                )
+                .into(),
+            ),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        }
+    }
+
+    /// Selects the exit relay and peer for `constraints`.
+    fn select_exit(
+        selector: &mut RelaySelector,
+        constraints: &RelayConstraints,
+    ) -> (Relay, wireguard::PeerConfig) {
+        match selector
+            .get_tunnel_endpoint(constraints, BridgeState::Off, 0, true)
+            .expect("no exit relay")
+        {
+            (relay, MullvadEndpoint::Wireguard { peer, .. }) => (relay, peer),
+            (_, endpoint) => panic!("unexpected exit endpoint: {:?}", endpoint),
+        }
+    }
+
+    #[test]
+    fn test_entry_chain_without_entry_location() {
+        let mut selector = new_selector();
+        let constraints = hostname_constraint("se", "got", "se1");
+        let (exit_relay, exit_peer) = select_exit(&mut selector, &constraints);
+        assert_eq!(exit_relay.hostname, "se1");
+
+        let chain = selector
+            .get_tunnel_entry_chain(&exit_relay, &exit_peer, &constraints, 0)
+            .unwrap();
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_entry_chain_uses_distinct_relays() {
+        let mut selector = new_selector();
+        let mut constraints = hostname_constraint("se", "got", "se1");
+        constraints.wireguard_constraints.entry_location = Some(country_constraint("se"));
+        constraints.wireguard_constraints.intermediate_locations = vec![country_constraint("se")];
+        let (exit_relay, exit_peer) = select_exit(&mut selector, &constraints);
+        assert_eq!(exit_relay.hostname, "se1");
+
+        for retry_attempt in 0..4 {
+            let chain = selector
+                .get_tunnel_entry_chain(&exit_relay, &exit_peer, &constraints, retry_attempt)
+                .unwrap();
+            let mut hostnames: Vec<&str> = chain
+                .iter()
+                .map(|(relay, _)| relay.hostname.as_str())
+                .collect();
+            hostnames.sort_unstable();
+            assert_eq!(hostnames, vec!["se2", "se3"]);
+        }
+    }
+
+    #[test]
+    fn test_entry_chain_routes_to_next_hop() {
+        let mut selector = new_selector();
+        let mut constraints = hostname_constraint("de", "fra", "de1");
+        constraints.wireguard_constraints.entry_location = Some(country_constraint("se"));
+        constraints.wireguard_constraints.intermediate_locations =
+            vec![country_constraint("se"), Constraint::Any];
+        let (exit_relay, exit_peer) = select_exit(&mut selector, &constraints);
+
+        let chain = selector
+            .get_tunnel_entry_chain(&exit_relay, &exit_peer, &constraints, 0)
+            .unwrap();
+        assert_eq!(chain.len(), 3);
+        let next_endpoints = chain
+            .iter()
+            .skip(1)
+            .map(|(_, peer)| peer.endpoint)
+            .chain(std::iter::once(exit_peer.endpoint));
+        for ((relay, peer), next_endpoint) in chain.iter().zip(next_endpoints) {
+            assert_eq!(peer.endpoint.ip(), IpAddr::from(relay.ipv4_addr_in));
+            assert_eq!(peer.allowed_ips, vec![IpNetwork::from(next_endpoint.ip())]);
+        }
+        // Only the entry relay is reached directly, so only it may use another port.
+        for (_, peer) in chain.iter().skip(1) {
+            assert_eq!(peer.endpoint.port(), DEFAULT_WIREGUARD_PORT);
+        }
+    }
+
+    #[test]
+    fn test_entry_chain_without_matching_hop() {
+        let mut selector = new_selector();
+        let mut constraints = hostname_constraint("se", "got", "se1");
+        let (exit_relay, exit_peer) = select_exit(&mut selector, &constraints);
+
+        // The only German relay cannot be used twice.
+        constraints.wireguard_constraints.entry_location = Some(country_constraint("de"));
+        constraints.wireguard_constraints.intermediate_locations = vec![country_constraint("de")];
+        assert!(selector
+            .get_tunnel_entry_chain(&exit_relay, &exit_peer, &constraints, 0)
+            .is_none());
+
+        // The exit relay cannot also be the entry relay.
+        constraints.wireguard_constraints.entry_location = Some(Constraint::Only(
+            GeographicLocationConstraint::Hostname(
+                "se".to_owned(),
+                "got".to_owned(),
+                "se1".to_owned(),
+            )
+            .into(),
+        ));
+        constraints.wireguard_constraints.intermediate_locations = vec![];
+        assert!(selector
+            .get_tunnel_entry_chain(&exit_relay, &exit_peer, &constraints, 0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_openvpn_entry_proxy() {
+        let mut selector = new_selector();
+        let exit_relay = selector.parsed_relays.lock().relays()[0].clone();
+        assert_eq!(exit_relay.hostname, "se1");
+        let mut constraints = RelayConstraints::default();
+        assert!(selector
+            .get_openvpn_entry_proxy(&exit_relay, &constraints)
+            .is_none());
+
+        constraints.openvpn_constraints.entry_location = Some(country_constraint("de"));
+        let (proxy, entry_relay) = selector
+            .get_openvpn_entry_proxy(&exit_relay, &constraints)
+            .unwrap();
+        assert_eq!(entry_relay.hostname, "de1");
+        match proxy {
+            ProxySettings::Shadowsocks(settings) => assert_eq!(
+                settings.peer,
+                SocketAddr::new(entry_relay.ipv4_addr_in.into(), 443)
+            ),
+            proxy => panic!("unexpected proxy: {:?}", proxy),
+        }
+
+        // The exit relay cannot also be the entry relay.
+        constraints.openvpn_constraints.entry_location = Some(Constraint::Only(
+            GeographicLocationConstraint::City("se".to_owned(), "got".to_owned()).into(),
+        ));
+        for _ in 0..4 {
+            let (_, entry_relay) = selector
+                .get_openvpn_entry_proxy(&exit_relay, &constraints)
+                .unwrap();
+            assert_eq!(entry_relay.hostname, "se2");
+        }
+        constraints.openvpn_constraints.entry_location = Some(Constraint::Only(
+            GeographicLocationConstraint::Hostname(
+                "se".to_owned(),
+                "got".to_owned(),
+                "se1".to_owned(),
+            )
+            .into(),
+        ));
+        assert!(selector
+            .get_openvpn_entry_proxy(&exit_relay, &constraints)
+            .is_none());
+    }
+
+    #[test]
+    fn test_nearest_relay_without_device_location() {
+        let mut selector = new_selector();
+        let relays = selector.parsed_relays.lock().relays().clone();
+
+        assert!(selector.pick_nearest_relay(&relays, None).is_none());
+    }
+
+    #[test]
+    fn test_nearest_relay_picks_nearest_city() {
+        let mut selector = new_selector();
+        let relays = selector.parsed_relays.lock().relays().clone();
+
+        // Uppsala is closest to Stockholm, which has a single relay.
+        selector.set_device_location(device_location(59.86, 17.64));
+        for _ in 0..10 {
+            let relay = selector.pick_nearest_relay(&relays, None).unwrap();
+            assert_eq!(relay.hostname, "se3");
+        }
+
+        // Both relays in Gothenburg share the same distance, so either may be selected.
+        selector.set_device_location(device_location(57.0, 12.0));
+        for _ in 0..10 {
+            let relay = selector.pick_nearest_relay(&relays, None).unwrap();
+            assert!(hostnames(&relays[..2]).contains(&relay.hostname.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_nearest_relay_max_distance() {
+        let mut selector = new_selector();
+        let relays = selector.parsed_relays.lock().relays().clone();
+
+        // Uppsala is about 60 km from Stockholm.
+        selector.set_device_location(device_location(59.86, 17.64));
+        assert_eq!(
+            selector
+                .pick_nearest_relay(&relays, Some(100))
+                .unwrap()
+                .hostname,
+            "se3"
+        );
+        assert!(selector.pick_nearest_relay(&relays, Some(10)).is_none());
+    }
+
+    #[test]
+    fn test_nearest_relay_among_filtered_relays() {
+        let mut selector = new_selector();
+        let relays = selector.parsed_relays.lock().relays().clone();
+        let german_relays: Vec<Relay> = relays
+            .into_iter()
+            .filter(|relay| relay.hostname.starts_with("de"))
+            .collect();
+
+        selector.set_device_location(device_location(59.86, 17.64));
+        assert_eq!(
+            selector
+                .pick_nearest_relay(&german_relays, None)
+                .unwrap()
+                .hostname,
+            "de1"
+        );
+        assert!(selector.pick_nearest_relay(&[], None).is_none());
+    }
+
+    /// Returns a relay selector that uses the fastest relay selection, where the relays respond
+    /// to latency probes with the given round-trip times.
+    fn new_fastest_selector(rtts: &[(u8, u64)], cache_dir: &Path) -> RelaySelector {
+        let mut selector = RelaySelector {
+            latency_prober: Arc::new(MockProber::new(rtts)),
+            latency_cache_path: cache_dir.join(LATENCY_CACHE_FILENAME),
+            ..new_selector()
+        };
+        selector.set_selection_mode(RelaySelectionMode::Fastest);
+        selector
+    }
+
+    fn update_latencies(selector: &RelaySelector, constraints: &RelayConstraints) {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(selector.update_latencies(constraints));
+    }
+
+    #[test]
+    fn test_fastest_relay() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut selector = new_fastest_selector(&[(1, 30), (2, 10), (4, 20)], cache_dir.path());
+        let constraints = RelayConstraints::default();
+        update_latencies(&selector, &constraints);
+
+        let relays = selector.parsed_relays.lock().relays().clone();
+        for _ in 0..10 {
+            let relay = selector.pick_fastest_relay(&relays).unwrap();
+            assert!(["se1", "se2", "de1"].contains(&relay.hostname.as_str()));
+        }
+        let fastest: Vec<&str> = selector
+            .latencies
+            .lock()
+            .fastest_relays(&relays)
+            .into_iter()
+            .map(|relay| relay.hostname.as_str())
+            .collect();
+        assert_eq!(fastest, vec!["se2", "de1", "se1"]);
+
+        let cached =
+            LatencyCache::from_file(cache_dir.path().join(LATENCY_CACHE_FILENAME)).unwrap();
+        assert_eq!(cached.get("se2"), Some(Duration::from_millis(10)));
+        assert_eq!(cached.get("se3"), None);
+    }
+
+    #[test]
+    fn test_fastest_relay_matching_constraints() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut selector = new_fastest_selector(&[(1, 30), (3, 20), (4, 10)], cache_dir.path());
+        let constraints = RelayConstraints {
+            location: country_constraint("se"),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        update_latencies(&selector, &constraints);
+
+        // Only relays that may be selected with the constraints are probed.
+        assert_eq!(selector.latencies.lock().get("de1"), None);
+        for _ in 0..10 {
+            let (relay, _) = select_exit(&mut selector, &constraints);
+            assert!(["se1", "se3"].contains(&relay.hostname.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_fastest_relay_without_measurements() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut selector = new_fastest_selector(&[], cache_dir.path());
+        let constraints = hostname_constraint("se", "got", "se1");
+        update_latencies(&selector, &constraints);
+
+        let relays = selector.parsed_relays.lock().relays().clone();
+        assert!(selector.pick_fastest_relay(&relays).is_none());
+        // The selector falls back to a weighted random selection.
+        let (relay, _) = select_exit(&mut selector, &constraints);
+        assert_eq!(relay.hostname, "se1");
+    }
+
+    /// Returns the IP of the Shadowsocks obfuscation server selected for traffic to `se1`, when
+    /// every relay runs a Shadowsocks server for UDP and `se1` and `se2` are hosted by
+    /// `excluded-provider`.
+    fn select_shadowsocks_server(constraints: &RelayConstraints) -> Option<IpAddr> {
+        let mut selector = new_selector();
+        for relay in selector.parsed_relays.lock().relays.iter_mut() {
+            relay.bridges.shadowsocks[0].protocol = TransportProtocol::Udp;
+            if relay.hostname == "se1" || relay.hostname == "se2" {
+                relay.provider = "excluded-provider".to_owned();
+            }
+        }
+        let (relay, peer) = select_exit(&mut selector, &hostname_constraint("se", "got", "se1"));
+        match selector.get_obfuscator(
+            ObfuscationType::Shadowsocks,
+            constraints,
+            &relay,
+            peer.endpoint,
+        )? {
+            ObfuscatorConfig::Shadowsocks { endpoint, .. } => Some(endpoint.ip()),
+            config => panic!("unexpected obfuscator: {:?}", config),
+        }
+    }
+
+    #[test]
+    fn test_shadowsocks_obfuscator_is_closest_server() {
+        let constraints = RelayConstraints::default();
+        assert_eq!(
+            select_shadowsocks_server(&constraints),
+            Some(IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+    }
+
+    #[test]
+    fn test_shadowsocks_obfuscator_respects_constraints() {
+        let sto_relay = Some(IpAddr::from(Ipv4Addr::new(10, 0, 0, 3)));
+
+        let constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint {
+                exclude_providers: vec!["excluded-provider".to_owned()],
+                ..LocationConstraint::default()
+            }),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(select_shadowsocks_server(&constraints), sto_relay);
+
+        let constraints = RelayConstraints {
+            providers: Constraint::Only(
+                Providers::new(vec!["provider".to_owned()].into_iter())
+                    .unwrap_or_else(|_| panic!("no providers")),
+            ),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(select_shadowsocks_server(&constraints), sto_relay);
+
+        // Included locations only apply to the relays that the tunnel goes through.
+        let constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint {
+                include: vec![GeographicLocationConstraint::Country("de".to_owned())],
+                exclude: vec![GeographicLocationConstraint::City(
+                    "se".to_owned(),
+                    "got".to_owned(),
+                )],
+                ..LocationConstraint::default()
+            }),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(select_shadowsocks_server(&constraints), sto_relay);
+
+        let constraints = RelayConstraints {
+            ownership: Constraint::Only(Ownership::Rented),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(select_shadowsocks_server(&constraints), None);
+    }
+}
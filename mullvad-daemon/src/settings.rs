@@ -2,8 +2,8 @@
 use futures::TryFutureExt;
 use log::{debug, error, info};
 use mullvad_types::{
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
-    settings::{DnsOptions, Settings},
+    relay_constraints::{BridgeSettings, BridgeState, RelaySelectionMode, RelaySettingsUpdate},
+    settings::{DnsOptions, NetworkRules, Policy, Settings},
     wireguard::RotationInterval,
 };
 use std::{
@@ -11,10 +11,14 @@ use std::{
     path::{Path, PathBuf},
 };
 use talpid_types::ErrorExt;
-use tokio::{fs, io};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+};
 
 
 const SETTINGS_FILE: &str = "settings.json";
+const POLICY_FILE: &str = "policy.json";
 
 
 #[derive(err_derive::Error, Debug)]
@@ -28,6 +32,15 @@ pub enum Error {
 
     #[error(display = "Unable to write settings to {}", _0)]
     WriteError(String, #[error(source)] io::Error),
+
+    #[error(display = "There is no profile named \"{}\"", _0)]
+    ProfileNotFound(String),
+
+    #[error(display = "A profile named \"{}\" already exists", _0)]
+    ProfileExists(String),
+
+    #[error(display = "The setting \"{}\" is locked by the system policy", _0)]
+    SettingLocked(&'static str),
 }
 
 #[derive(err_derive::Error, Debug)]
@@ -54,11 +67,15 @@ pub struct SettingsPersister {
 }
 
 impl SettingsPersister {
-    /// Loads user settings from file. If no file is present it returns the defaults.
+    /// Loads user settings from file. If no file is present it returns the defaults. Settings
+    /// locked by the policy file in the same directory override the ones from the settings file.
     pub async fn load(settings_dir: &Path) -> Self {
         let path = settings_dir.join(SETTINGS_FILE);
         let (mut settings, mut should_save) = Self::load_settings(&path).await;
 
+        settings.policy = Self::load_policy(&settings_dir.join(POLICY_FILE)).await;
+        should_save |= settings.enforce_policy();
+
         // Force IPv6 to be enabled on Android
         if cfg!(target_os = "android") {
             should_save |=
@@ -133,20 +150,87 @@ impl SettingsPersister {
             .map_err(LoadSettingsError::ParseError)
     }
 
-    /// Serializes the settings and saves them to the file it was loaded from.
+    async fn load_policy(path: &Path) -> Policy {
+        let policy_bytes = match fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Policy::default(),
+            Err(error) => {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to read policy file. No settings are locked"
+                    )
+                );
+                return Policy::default();
+            }
+        };
+        match Policy::load_from_bytes(&policy_bytes) {
+            Ok(policy) => {
+                info!(
+                    "Loaded policy from {}. Locked settings: {}",
+                    path.display(),
+                    policy.locked_fields().join(", ")
+                );
+                policy
+            }
+            Err(error) => {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to parse policy file. No settings are locked"
+                    )
+                );
+                Policy::default()
+            }
+        }
+    }
+
+    /// Serializes the settings and saves them to the file it was loaded from. Fails without
+    /// saving anything if a setting that is locked by the policy was changed.
     async fn save(&mut self) -> Result<(), Error> {
+        if let Some(field) = self.settings.changed_locked_field() {
+            return Err(Error::SettingLocked(field));
+        }
         debug!("Writing settings to {}", self.path.display());
 
         let buffer = serde_json::to_string_pretty(&self.settings).map_err(Error::SerializeError)?;
-        fs::write(&self.path, &buffer)
+        // Write to a temporary file first, so that the settings are never left half-written
+        let temp_path = self.path.with_extension("json.tmp");
+        Self::write_file(&temp_path, buffer.as_bytes())
+            .await
+            .map_err(|e| Error::WriteError(temp_path.display().to_string(), e))?;
+        fs::rename(&temp_path, &self.path)
             .await
             .map_err(|e| Error::WriteError(self.path.display().to_string(), e))
     }
 
+    /// Writes `contents` to a new file at `path`. On Unix, the file is only accessible to its
+    /// owner from the start, since the settings may contain the private key of a custom
+    /// WireGuard relay.
+    async fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+        match fs::remove_file(path).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => (),
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = fs::OpenOptions::from(options).open(path).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await
+    }
+
     /// Resets default settings
     #[cfg(not(target_os = "android"))]
     pub async fn reset(&mut self) -> Result<(), Error> {
+        let policy = self.settings.policy.clone();
         self.settings = Settings::default();
+        self.settings.policy = policy;
+        self.settings.enforce_policy();
         let path = self.path.clone();
         self.save()
             .or_else(|e| async move {
@@ -172,98 +256,209 @@ impl SettingsPersister {
         &mut self,
         account_token: Option<String>,
     ) -> Result<bool, Error> {
-        let should_save = self.settings.set_account_token(account_token);
-        self.update(should_save).await
+        self.update(|settings| settings.set_account_token(account_token))
+            .await
     }
 
     pub async fn update_relay_settings(
         &mut self,
         update: RelaySettingsUpdate,
     ) -> Result<bool, Error> {
-        let should_save = self.settings.update_relay_settings(update);
-        self.update(should_save).await
+        self.update(|settings| settings.update_relay_settings(update))
+            .await
     }
 
     pub async fn set_allow_lan(&mut self, allow_lan: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(&mut self.settings.allow_lan, allow_lan);
-        self.update(should_save).await
+        self.ensure_unlocked("allow_lan")?;
+        self.update(|settings| Self::update_field(&mut settings.allow_lan, allow_lan))
+            .await
     }
 
     pub async fn set_block_when_disconnected(
         &mut self,
         block_when_disconnected: bool,
     ) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.block_when_disconnected,
-            block_when_disconnected,
-        );
-        self.update(should_save).await
+        self.ensure_unlocked("block_when_disconnected")?;
+        self.update(|settings| {
+            Self::update_field(
+                &mut settings.block_when_disconnected,
+                block_when_disconnected,
+            )
+        })
+        .await
     }
 
     pub async fn set_auto_connect(&mut self, auto_connect: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(&mut self.settings.auto_connect, auto_connect);
-        self.update(should_save).await
+        self.ensure_unlocked("auto_connect")?;
+        self.update(|settings| Self::update_field(&mut settings.auto_connect, auto_connect))
+            .await
+    }
+
+    pub async fn set_network_rules(&mut self, network_rules: NetworkRules) -> Result<bool, Error> {
+        self.update(|settings| Self::update_field(&mut settings.network_rules, network_rules))
+            .await
+    }
+
+    pub async fn set_reconnect_interval(
+        &mut self,
+        reconnect_interval: Option<RotationInterval>,
+    ) -> Result<bool, Error> {
+        self.update(|settings| {
+            Self::update_field(&mut settings.reconnect_interval, reconnect_interval)
+        })
+        .await
     }
 
     pub async fn set_openvpn_mssfix(&mut self, openvpn_mssfix: Option<u16>) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.tunnel_options.openvpn.mssfix,
-            openvpn_mssfix,
-        );
-        self.update(should_save).await
+        self.update(|settings| {
+            Self::update_field(&mut settings.tunnel_options.openvpn.mssfix, openvpn_mssfix)
+        })
+        .await
     }
 
     pub async fn set_enable_ipv6(&mut self, enable_ipv6: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.tunnel_options.generic.enable_ipv6,
-            enable_ipv6,
-        );
-        self.update(should_save).await
+        self.ensure_unlocked("enable_ipv6")?;
+        self.update(|settings| {
+            Self::update_field(
+                &mut settings.tunnel_options.generic.enable_ipv6,
+                enable_ipv6,
+            )
+        })
+        .await
     }
 
     pub async fn set_dns_options(&mut self, options: DnsOptions) -> Result<bool, Error> {
-        let should_save =
-            Self::update_field(&mut self.settings.tunnel_options.dns_options, options);
-        self.update(should_save).await
+        self.ensure_unlocked("dns_options")?;
+        self.update(|settings| {
+            Self::update_field(&mut settings.tunnel_options.dns_options, options)
+        })
+        .await
     }
 
     pub async fn set_wireguard_mtu(&mut self, mtu: Option<u16>) -> Result<bool, Error> {
-        let should_save =
-            Self::update_field(&mut self.settings.tunnel_options.wireguard.options.mtu, mtu);
-        self.update(should_save).await
+        self.update(|settings| {
+            Self::update_field(&mut settings.tunnel_options.wireguard.options.mtu, mtu)
+        })
+        .await
+    }
+
+    pub async fn set_quantum_resistant_tunnel(
+        &mut self,
+        quantum_resistant: bool,
+    ) -> Result<bool, Error> {
+        self.update(|settings| {
+            Self::update_field(
+                &mut settings.tunnel_options.wireguard.options.quantum_resistant,
+                quantum_resistant,
+            )
+        })
+        .await
     }
 
     pub async fn set_wireguard_rotation_interval(
         &mut self,
         interval: Option<RotationInterval>,
     ) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.tunnel_options.wireguard.rotation_interval,
-            interval,
-        );
-        self.update(should_save).await
+        self.update(|settings| {
+            Self::update_field(
+                &mut settings.tunnel_options.wireguard.rotation_interval,
+                interval,
+            )
+        })
+        .await
     }
 
     pub async fn set_show_beta_releases(
         &mut self,
         show_beta_releases: bool,
     ) -> Result<bool, Error> {
-        let should_save =
-            Self::update_field(&mut self.settings.show_beta_releases, show_beta_releases);
-        self.update(should_save).await
+        self.ensure_unlocked("show_beta_releases")?;
+        self.update(|settings| {
+            Self::update_field(&mut settings.show_beta_releases, show_beta_releases)
+        })
+        .await
     }
 
     pub async fn set_bridge_settings(
         &mut self,
         bridge_settings: BridgeSettings,
     ) -> Result<bool, Error> {
-        let should_save = Self::update_field(&mut self.settings.bridge_settings, bridge_settings);
-        self.update(should_save).await
+        self.update(|settings| Self::update_field(&mut settings.bridge_settings, bridge_settings))
+            .await
     }
 
     pub async fn set_bridge_state(&mut self, bridge_state: BridgeState) -> Result<bool, Error> {
-        let should_save = self.settings.set_bridge_state(bridge_state);
-        self.update(should_save).await
+        self.ensure_unlocked("bridge_state")?;
+        self.update(|settings| settings.set_bridge_state(bridge_state))
+            .await
+    }
+
+    pub async fn set_relay_selection_mode(
+        &mut self,
+        relay_selection_mode: RelaySelectionMode,
+    ) -> Result<bool, Error> {
+        self.update(|settings| {
+            Self::update_field(&mut settings.relay_selection_mode, relay_selection_mode)
+        })
+        .await
+    }
+
+    /// Stores the current connection settings as a new profile.
+    pub async fn create_profile(&mut self, name: String) -> Result<(), Error> {
+        if self.settings.profiles.contains_key(&name) {
+            return Err(Error::ProfileExists(name));
+        }
+        let profile = self.settings.current_profile();
+        self.update(|settings| {
+            settings.profiles.insert(name, profile);
+            true
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// Replaces the connection settings with the ones stored in a profile. If the new settings
+    /// cannot be saved, or change locked settings, the previous settings are restored.
+    pub async fn activate_profile(&mut self, name: &str) -> Result<bool, Error> {
+        let profile = self
+            .settings
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::ProfileNotFound(name.to_owned()))?;
+
+        self.update(|settings| settings.apply_profile(profile))
+            .await
+    }
+
+    /// Replaces all settings at once, e.g. with imported ones. Returns whether anything changed.
+    /// The previous settings are kept if the new ones cannot be saved, or change locked settings.
+    pub async fn replace(&mut self, mut settings: Settings) -> Result<bool, Error> {
+        settings.policy = self.settings.policy.clone();
+        if self.settings == settings {
+            return Ok(false);
+        }
+        self.update(|current_settings| {
+            *current_settings = settings;
+            true
+        })
+        .await
+    }
+
+    pub async fn delete_profile(&mut self, name: &str) -> Result<(), Error> {
+        if !self.settings.profiles.contains_key(name) {
+            return Err(Error::ProfileNotFound(name.to_owned()));
+        }
+        self.update(|settings| settings.profiles.remove(name).is_some())
+            .await
+            .map(|_| ())
+    }
+
+    fn ensure_unlocked(&self, field: &'static str) -> Result<(), Error> {
+        if self.settings.policy.is_locked(field) {
+            return Err(Error::SettingLocked(field));
+        }
+        Ok(())
     }
 
     fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
@@ -275,12 +470,18 @@ impl SettingsPersister {
         }
     }
 
-    async fn update(&mut self, should_save: bool) -> Result<bool, Error> {
-        if should_save {
-            self.save().await.map(|_| true)
-        } else {
-            Ok(false)
+    /// Applies `update` to the settings and saves them if it reports a change. The previous
+    /// settings are restored if the new ones cannot be saved, or change locked settings.
+    async fn update(&mut self, update: impl FnOnce(&mut Settings) -> bool) -> Result<bool, Error> {
+        let previous_settings = self.settings.clone();
+        if !update(&mut self.settings) {
+            return Ok(false);
+        }
+        if let Err(error) = self.save().await {
+            self.settings = previous_settings;
+            return Err(error);
         }
+        Ok(true)
     }
 }
 
@@ -487,3 +688,243 @@ mod windows {
         unsafe { IsWellKnownSid(sid as *const SID as *mut _, well_known_sid_type) == TRUE }
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_settings_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        std::fs::write(&path, b"").unwrap();
+        std::fs::set_permissions(&path, PermissionsExt::from_mode(0o644)).unwrap();
+
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = SettingsPersister::load(dir.path()).await;
+            persister.set_allow_lan(true).await.unwrap();
+        });
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!path.with_extension("json.tmp").exists());
+        let settings = Settings::load_from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        assert!(settings.allow_lan);
+    }
+
+    /// Loads settings from `dir` with a policy that only allows LAN access to be blocked.
+    async fn load_with_locked_lan_access(dir: &Path) -> SettingsPersister {
+        std::fs::write(dir.join(POLICY_FILE), br#"{"allow_lan": false}"#).unwrap();
+        SettingsPersister::load(dir).await
+    }
+
+    fn read_settings_file(dir: &Path) -> Settings {
+        Settings::load_from_bytes(&std::fs::read(dir.join(SETTINGS_FILE)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_load_enforces_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.allow_lan = true;
+        std::fs::write(
+            dir.path().join(SETTINGS_FILE),
+            serde_json::to_vec(&settings).unwrap(),
+        )
+        .unwrap();
+
+        let persister = Runtime::new()
+            .unwrap()
+            .block_on(load_with_locked_lan_access(dir.path()));
+
+        assert!(!persister.allow_lan);
+        assert!(!read_settings_file(dir.path()).allow_lan);
+    }
+
+    #[test]
+    fn test_set_locked_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = load_with_locked_lan_access(dir.path()).await;
+
+            assert!(matches!(
+                persister.set_allow_lan(true).await,
+                Err(Error::SettingLocked("allow_lan"))
+            ));
+            assert!(!persister.allow_lan);
+            assert!(persister.set_auto_connect(true).await.unwrap());
+        });
+        let settings = read_settings_file(dir.path());
+        assert!(!settings.allow_lan);
+        assert!(settings.auto_connect);
+    }
+
+    #[test]
+    fn test_replace_locked_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = load_with_locked_lan_access(dir.path()).await;
+
+            let mut settings = persister.to_settings();
+            settings.allow_lan = true;
+            settings.auto_connect = true;
+            assert!(matches!(
+                persister.replace(settings.clone()).await,
+                Err(Error::SettingLocked("allow_lan"))
+            ));
+            assert!(!persister.allow_lan);
+            assert!(!persister.auto_connect);
+
+            settings.allow_lan = false;
+            assert!(persister.replace(settings).await.unwrap());
+            assert!(persister.auto_connect);
+        });
+        let settings = read_settings_file(dir.path());
+        assert!(!settings.allow_lan);
+        assert!(settings.auto_connect);
+    }
+
+    #[test]
+    fn test_activate_profile_with_locked_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = load_with_locked_lan_access(dir.path()).await;
+            let previous_settings = persister.to_settings();
+
+            let mut profile = persister.current_profile();
+            profile.allow_lan = true;
+            profile.tunnel_options.generic.enable_ipv6 = true;
+            persister
+                .settings
+                .profiles
+                .insert("lan".to_owned(), profile);
+
+            assert!(matches!(
+                persister.activate_profile("lan").await,
+                Err(Error::SettingLocked("allow_lan"))
+            ));
+            assert_eq!(persister.allow_lan, previous_settings.allow_lan);
+            assert_eq!(persister.tunnel_options, previous_settings.tunnel_options);
+        });
+    }
+
+    #[test]
+    fn test_activate_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = SettingsPersister::load(dir.path()).await;
+            persister
+                .create_profile("default".to_owned())
+                .await
+                .unwrap();
+            assert!(matches!(
+                persister.create_profile("default".to_owned()).await,
+                Err(Error::ProfileExists(_))
+            ));
+
+            persister.set_allow_lan(true).await.unwrap();
+            persister.set_bridge_state(BridgeState::On).await.unwrap();
+            persister
+                .create_profile("bridged".to_owned())
+                .await
+                .unwrap();
+
+            assert!(persister.activate_profile("default").await.unwrap());
+            assert!(!persister.allow_lan);
+            assert_eq!(persister.get_bridge_state(), BridgeState::Auto);
+            assert!(!persister.activate_profile("default").await.unwrap());
+
+            assert!(persister.activate_profile("bridged").await.unwrap());
+            assert!(persister.allow_lan);
+            assert_eq!(persister.get_bridge_state(), BridgeState::On);
+        });
+        let settings = read_settings_file(dir.path());
+        assert!(settings.allow_lan);
+        assert_eq!(settings.get_bridge_state(), BridgeState::On);
+        assert_eq!(settings.profiles.len(), 2);
+    }
+
+    #[test]
+    fn test_activate_missing_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = SettingsPersister::load(dir.path()).await;
+            persister
+                .create_profile("default".to_owned())
+                .await
+                .unwrap();
+            persister.delete_profile("default").await.unwrap();
+            let previous_settings = persister.to_settings();
+
+            assert!(matches!(
+                persister.activate_profile("default").await,
+                Err(Error::ProfileNotFound(name)) if name == "default"
+            ));
+            assert!(matches!(
+                persister.delete_profile("default").await,
+                Err(Error::ProfileNotFound(_))
+            ));
+            assert_eq!(persister.to_settings(), previous_settings);
+        });
+        assert!(read_settings_file(dir.path()).profiles.is_empty());
+    }
+
+    #[test]
+    fn test_activate_profile_matching_locked_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = load_with_locked_lan_access(dir.path()).await;
+
+            // Profiles that keep the locked setting at its pinned value can still be activated.
+            let mut profile = persister.current_profile();
+            profile.tunnel_options.generic.enable_ipv6 = true;
+            persister
+                .settings
+                .profiles
+                .insert("ipv6".to_owned(), profile);
+
+            assert!(persister.activate_profile("ipv6").await.unwrap());
+            assert!(persister.tunnel_options.generic.enable_ipv6);
+            assert!(!persister.allow_lan);
+        });
+        let settings = read_settings_file(dir.path());
+        assert!(settings.tunnel_options.generic.enable_ipv6);
+        assert!(!settings.allow_lan);
+    }
+
+    #[test]
+    fn test_indirect_change_of_locked_setting() {
+        use mullvad_types::relay_constraints::{Constraint, RelayConstraintsUpdate};
+        use talpid_types::net::TunnelType;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(POLICY_FILE), br#"{"bridge_state": "on"}"#).unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let mut persister = SettingsPersister::load(dir.path()).await;
+            let previous_settings = persister.to_settings();
+
+            // Relay settings that cannot be used with bridges would turn bridges off.
+            let update = RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                tunnel_protocol: Some(Constraint::Only(TunnelType::Wireguard)),
+                ..RelayConstraintsUpdate::default()
+            });
+            assert!(matches!(
+                persister.update_relay_settings(update).await,
+                Err(Error::SettingLocked("bridge_state"))
+            ));
+            assert_eq!(persister.to_settings(), previous_settings);
+            assert_eq!(persister.get_bridge_state(), BridgeState::On);
+
+            // Unrelated settings can still be saved afterwards.
+            assert!(persister.set_auto_connect(true).await.unwrap());
+        });
+        let settings = read_settings_file(dir.path());
+        assert_eq!(settings.get_bridge_state(), BridgeState::On);
+        assert!(settings.auto_connect);
+    }
+}
@@ -7,7 +7,10 @@ use jnix::{
 };
 use mullvad_daemon::EventListener;
 use mullvad_types::{
-    relay_list::RelayList, settings::Settings, states::TunnelState, version::AppVersionInfo,
+    relay_list::RelayList,
+    settings::{NetworkRuleEvent, Settings},
+    states::TunnelState,
+    version::AppVersionInfo,
     wireguard::KeygenEvent,
 };
 use std::{sync::mpsc, thread};
@@ -63,6 +66,10 @@ impl EventListener for JniEventListener {
     fn notify_app_version(&self, app_version_info: AppVersionInfo) {
         let _ = self.0.send(Event::AppVersionInfo(app_version_info));
     }
+
+    fn notify_network_rule_applied(&self, _event: NetworkRuleEvent) {
+        // Network rules are not supported on Android
+    }
 }
 
 struct JniEventHandler<'env> {
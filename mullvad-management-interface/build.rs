@@ -1,5 +1,26 @@
+include!("json_fields.rs");
+
 fn main() {
     const PROTO_FILE: &str = "proto/management_interface.proto";
-    tonic_build::compile_protos(PROTO_FILE).unwrap();
+
+    let mut builder = tonic_build::configure().type_attribute(
+        ".",
+        "#[derive(serde::Serialize)] #[serde(rename_all = \"snake_case\")]",
+    );
+    for (field, serializer) in JSON_FIELD_SERIALIZERS {
+        builder = builder.field_attribute(
+            format!(".mullvad_daemon.management_interface.{}", field),
+            format!("#[serde(serialize_with = \"crate::json::{}\")]", serializer),
+        );
+    }
+    // Absolute paths also match everything nested in a field, such as the variants of a `oneof`.
+    // The relative path of a field is matched against the end of its path, so it only matches the
+    // field itself.
+    for field in JSON_FLATTENED_FIELDS {
+        builder = builder.field_attribute(field, "#[serde(flatten)]");
+    }
+    builder.compile(&[PROTO_FILE], &["proto"]).unwrap();
+
     println!("cargo:rerun-if-changed={}", PROTO_FILE);
+    println!("cargo:rerun-if-changed=json_fields.rs");
 }
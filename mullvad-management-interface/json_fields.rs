@@ -0,0 +1,80 @@
+// The fields that need custom serde attributes. This file is included both by the build script,
+// which adds the attributes, and by the tests in `src/json.rs`, which check that no field that
+// needs one is missing.
+
+/// Fields that are not serialized as they are stored in the generated types. Enumerations are
+/// stored as integers, and are serialized as the names of their values instead.
+const JSON_FIELD_SERIALIZERS: &[(&str, &str)] = &[
+    ("AccountData.expiry", "timestamp"),
+    ("VoucherSubmission.new_expiry", "timestamp"),
+    (
+        "ErrorState.FirewallPolicyError.type",
+        "firewall_policy_error_type",
+    ),
+    ("ErrorState.cause", "error_state_cause"),
+    ("ErrorState.parameter_error", "generation_error"),
+    (
+        "TunnelState.Disconnecting.after_disconnect",
+        "after_disconnect",
+    ),
+    ("ConnectionHistoryEntry.timestamp", "timestamp"),
+    ("TrafficStats.handshake_age", "duration"),
+    ("TunnelEndpoint.protocol", "transport_protocol"),
+    ("TunnelEndpoint.tunnel_type", "tunnel_type"),
+    ("Endpoint.protocol", "transport_protocol"),
+    ("ProxyEndpoint.protocol", "transport_protocol"),
+    ("ProxyEndpoint.proxy_type", "proxy_type"),
+    ("BridgeState.state", "bridge_state"),
+    ("RelaySelectionMode.mode", "relay_selection_mode"),
+    ("Settings.reconnect_interval", "duration"),
+    ("NetworkRule.action", "network_action"),
+    ("DefaultNetworkAction.action", "network_action"),
+    ("NetworkRuleEvent.action", "network_action"),
+    ("TunnelTypeConstraint.tunnel_type", "tunnel_type"),
+    ("OwnershipConstraint.ownership", "ownership"),
+    ("TransportProtocolConstraint.protocol", "transport_protocol"),
+    ("IpVersionConstraint.protocol", "ip_version"),
+    (
+        "ConnectionConfig.OpenvpnConfig.protocol",
+        "transport_protocol",
+    ),
+    (
+        "ConnectionConfig.WireguardConfig.TunnelConfig.private_key",
+        "bytes",
+    ),
+    (
+        "ConnectionConfig.WireguardConfig.PeerConfig.public_key",
+        "bytes",
+    ),
+    (
+        "ConnectionConfig.WireguardConfig.PeerConfig.protocol",
+        "transport_protocol",
+    ),
+    (
+        "TunnelOptions.WireguardOptions.rotation_interval",
+        "duration",
+    ),
+    ("EncryptedDnsUpstream.protocol", "encrypted_dns_protocol"),
+    ("DnsOptions.state", "dns_state"),
+    ("PublicKey.key", "bytes"),
+    ("PublicKey.created", "timestamp"),
+    ("KeygenEvent.event", "keygen_event"),
+    ("ShadowsocksEndpointData.protocol", "transport_protocol"),
+    ("OpenVpnEndpointData.protocol", "transport_protocol"),
+    ("WireguardEndpointData.public_key", "bytes"),
+    ("ObfuscationEndpoint.protocol", "transport_protocol"),
+    ("ObfuscationEndpoint.obfuscation_type", "obfuscation_type"),
+    ("ObfuscationConstraint.obfuscation_type", "obfuscation_type"),
+];
+
+/// `oneof` fields. The selected variant is serialized as a field of the containing message.
+const JSON_FLATTENED_FIELDS: &[&str] = &[
+    "RelaySettingsUpdate.type",
+    "TunnelState.state",
+    "BridgeSettings.type",
+    "NetworkRule.network",
+    "RelaySettings.endpoint",
+    "ConnectionConfig.config",
+    "DnsBlocklist.source",
+    "DaemonEvent.event",
+];
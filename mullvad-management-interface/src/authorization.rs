@@ -0,0 +1,343 @@
+//! Authorization of management interface calls based on the credentials of the peer process,
+//! as reported by `SO_PEERCRED`.
+
+use crate::{Error, ManagementService, ManagementServiceServer, StreamBox};
+use futures::future::{self, Either};
+use nix::{
+    sys::socket::{getsockopt, sockopt::PeerCredentials, UnixCredentials},
+    unistd::{Gid, Group, Uid, User},
+};
+use std::{
+    env, fs, io,
+    os::unix::{fs::PermissionsExt, io::AsRawFd},
+    path::Path,
+    task::{Context, Poll},
+};
+use tokio::net::{UnixListener, UnixStream};
+use tonic::{
+    body::BoxBody,
+    codegen::http,
+    transport::{NamedService, Server},
+    Code,
+};
+use tower::Service;
+
+/// Header that is added to the calls of peers that may not change the state of the daemon.
+pub const UNPRIVILEGED_PEER_HEADER: &str = "mullvad-unprivileged-peer";
+
+/// Calls that do not change the state of the daemon. These are allowed for every peer.
+const READ_ONLY_METHODS: &[&str] = &[
+    "GetTunnelState",
+    "TrafficStatsListen",
+    "GetConnectionHistory",
+    "EventsListen",
+    "GetCurrentVersion",
+    "GetVersionInfo",
+    "GetRelayLocations",
+    "GetCurrentLocation",
+    "GetSettings",
+    "GetAccountData",
+    "GetWireguardKey",
+    "VerifyWireguardKey",
+    "GetSplitTunnelProcesses",
+    "GetDnsBlocklistStats",
+];
+
+lazy_static::lazy_static! {
+    static ref MULLVAD_MANAGEMENT_ADMIN_GROUP: Option<String> =
+        env::var("MULLVAD_MANAGEMENT_ADMIN_GROUP").ok();
+}
+
+/// Returns the group whose members, along with root, may make calls that change the state of the
+/// daemon. All peers may make such calls if this is `None`.
+pub fn admin_gid() -> Result<Option<Gid>, Error> {
+    match &*MULLVAD_MANAGEMENT_ADMIN_GROUP {
+        Some(group_name) => {
+            let group = Group::from_name(group_name)
+                .map_err(Error::ObtainGidError)?
+                .ok_or(Error::NoGidError)?;
+            Ok(Some(group.gid))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Creates the management interface socket, accessible to everyone.
+pub fn bind(socket_path: &Path) -> Result<UnixListener, Error> {
+    let listener = UnixListener::bind(socket_path).map_err(Error::StartServerError)?;
+    fs::set_permissions(socket_path, PermissionsExt::from_mode(0o766))
+        .map_err(Error::PermissionsError)?;
+    Ok(listener)
+}
+
+/// Serves every connection on `listener` separately, so that calls can be authorized based on
+/// the credentials of the peer.
+pub async fn serve<T: ManagementService>(
+    service: ManagementServiceServer<T>,
+    mut listener: UnixListener,
+    admin_gid: Gid,
+    abort_rx: triggered::Listener,
+) -> Result<(), Error> {
+    loop {
+        let accept = Box::pin(listener.accept());
+        let stream = match future::select(accept, Box::pin(abort_rx.clone())).await {
+            Either::Left((Ok((stream, _)), _)) => stream,
+            Either::Left((Err(error), _)) => {
+                log::error!(
+                    "Failed to accept management interface connection: {}",
+                    error
+                );
+                continue;
+            }
+            Either::Right(_) => return Ok(()),
+        };
+
+        let peer = match Peer::new(&stream, admin_gid) {
+            Ok(peer) => peer,
+            Err(error) => {
+                log::error!(
+                    "Rejecting management interface connection. Failed to get peer \
+                     credentials: {}",
+                    error
+                );
+                continue;
+            }
+        };
+        let service = AuthorizedService {
+            inner: service.clone(),
+            peer,
+        };
+        let incoming = futures::stream::once(future::ok::<_, io::Error>(StreamBox(stream)));
+        let connection_abort_rx = abort_rx.clone();
+        tokio::spawn(async move {
+            if let Err(error) = Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, connection_abort_rx)
+                .await
+            {
+                log::error!("Management interface connection failed: {}", error);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Peer {
+    pid: i32,
+    uid: u32,
+    is_admin: bool,
+}
+
+impl Peer {
+    fn new(stream: &UnixStream, admin_gid: Gid) -> nix::Result<Self> {
+        let credentials = getsockopt(stream.as_raw_fd(), PeerCredentials)?;
+        Ok(Peer {
+            pid: credentials.pid(),
+            uid: credentials.uid(),
+            is_admin: Self::is_admin(&credentials, admin_gid),
+        })
+    }
+
+    /// Returns whether the peer runs as root or as a member of the admin group.
+    fn is_admin(credentials: &UnixCredentials, admin_gid: Gid) -> bool {
+        if credentials.uid() == 0 || Gid::from_raw(credentials.gid()) == admin_gid {
+            return true;
+        }
+        let user = User::from_uid(Uid::from_raw(credentials.uid()));
+        let group = Group::from_gid(admin_gid);
+        match (user, group) {
+            (Ok(Some(user)), Ok(Some(group))) => {
+                user.gid == admin_gid || group.mem.contains(&user.name)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Wraps the management service, and rejects calls that the peer is not allowed to make.
+#[derive(Clone)]
+struct AuthorizedService<S> {
+    inner: S,
+    peer: Peer,
+}
+
+impl<S, B> Service<http::Request<B>> for AuthorizedService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, future::Ready<Result<S::Response, S::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let method = method_name(request.uri().path()).to_owned();
+        let headers = request.headers_mut();
+        headers.remove(UNPRIVILEGED_PEER_HEADER);
+        if !self.peer.is_admin {
+            headers.insert(
+                UNPRIVILEGED_PEER_HEADER,
+                http::HeaderValue::from_static("1"),
+            );
+        }
+        if self.peer.is_admin || is_read_only(&method) {
+            return Either::Left(self.inner.call(request));
+        }
+        log::warn!(
+            "Denied call to {} from peer with uid {} and pid {}",
+            method,
+            self.peer.uid,
+            self.peer.pid
+        );
+        Either::Right(future::ok(permission_denied_response(&method)))
+    }
+}
+
+impl<S: NamedService> NamedService for AuthorizedService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+/// Returns the name of the method that is called through the request path `path`.
+fn method_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+fn is_read_only(method: &str) -> bool {
+    READ_ONLY_METHODS.contains(&method)
+}
+
+fn permission_denied_response(method: &str) -> http::Response<BoxBody> {
+    http::Response::builder()
+        .status(200)
+        .header("content-type", "application/grpc")
+        .header("grpc-status", (Code::PermissionDenied as i32).to_string())
+        .header(
+            "grpc-message",
+            format!("{} is only allowed for root and the admin group", method),
+        )
+        .body(BoxBody::empty())
+        .unwrap()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nix::libc;
+
+    const SERVICE_PATH: &str = "/mullvad_daemon.management_interface.ManagementService";
+
+    fn credentials(uid: u32, gid: u32) -> UnixCredentials {
+        UnixCredentials::from(libc::ucred { pid: 1, uid, gid })
+    }
+
+    fn is_read_only_path(method: &str) -> bool {
+        is_read_only(method_name(&format!("{}/{}", SERVICE_PATH, method)))
+    }
+
+    #[test]
+    fn test_is_admin() {
+        let admin_gid = Gid::from_raw(4_000_000);
+        assert!(Peer::is_admin(&credentials(0, 4_000_001), admin_gid));
+        assert!(Peer::is_admin(
+            &credentials(4_000_002, 4_000_000),
+            admin_gid
+        ));
+        // Neither the user nor the group exists, so the user cannot be a member of the group
+        assert!(!Peer::is_admin(
+            &credentials(4_000_002, 4_000_001),
+            admin_gid
+        ));
+    }
+
+    /// Calls `method` as a peer, and returns whether the call was marked as unprivileged, or
+    /// `None` if it was denied.
+    fn call_as_peer(is_admin: bool, method: &str, spoofed_header: bool) -> Option<bool> {
+        let inner = tower::service_fn(|request: http::Request<()>| {
+            let unprivileged = request.headers().contains_key(UNPRIVILEGED_PEER_HEADER);
+            future::ok::<_, std::convert::Infallible>(
+                http::Response::builder()
+                    .header("unprivileged", unprivileged.to_string())
+                    .body(BoxBody::empty())
+                    .unwrap(),
+            )
+        });
+        let mut service = AuthorizedService {
+            inner,
+            peer: Peer {
+                pid: 1,
+                uid: 1000,
+                is_admin,
+            },
+        };
+        let mut request = http::Request::builder().uri(format!("{}/{}", SERVICE_PATH, method));
+        if spoofed_header {
+            request = request.header(UNPRIVILEGED_PEER_HEADER, "1");
+        }
+        let response =
+            futures::executor::block_on(service.call(request.body(()).unwrap())).unwrap();
+        response
+            .headers()
+            .get("unprivileged")
+            .map(|value| value == "true")
+    }
+
+    #[test]
+    fn test_authorized_calls() {
+        assert_eq!(call_as_peer(true, "SetAllowLan", false), Some(false));
+        assert_eq!(call_as_peer(true, "GetSettings", true), Some(false));
+        assert_eq!(call_as_peer(false, "GetSettings", false), Some(true));
+        assert_eq!(call_as_peer(false, "SetAllowLan", false), None);
+    }
+
+    #[test]
+    fn test_account_token_calls() {
+        // The account history consists of account tokens, so only admins may read it.
+        assert_eq!(call_as_peer(true, "GetAccountHistory", false), Some(false));
+        assert_eq!(call_as_peer(false, "GetAccountHistory", false), None);
+        assert_eq!(call_as_peer(false, "GetAccountHistory", true), None);
+
+        // Unprivileged peers may read the settings, but without the account token.
+        assert_eq!(call_as_peer(false, "GetSettings", false), Some(true));
+        let mut settings = crate::types::Settings {
+            account_token: "1234123412341234".to_owned(),
+            ..crate::types::Settings::default()
+        };
+        settings.clear_secrets();
+        assert!(settings.account_token.is_empty());
+    }
+
+    #[test]
+    fn test_read_only_methods() {
+        for method in &[
+            "GetTunnelState",
+            "GetSettings",
+            "TrafficStatsListen",
+            "GetConnectionHistory",
+            "GetDnsBlocklistStats",
+        ] {
+            assert!(is_read_only_path(method), "{} is not read-only", method);
+        }
+    }
+
+    #[test]
+    fn test_state_changing_methods() {
+        for method in &[
+            "ConnectTunnel",
+            "SetDnsOptions",
+            "UpdateDnsBlocklists",
+            "ImportSettings",
+            "ExportSettings",
+            "ActivateProfile",
+            "GetAccountHistory",
+            "GetSettingsX",
+            "",
+        ] {
+            assert!(!is_read_only_path(method), "{} is read-only", method);
+        }
+        assert!(!is_read_only(method_name("")));
+    }
+}
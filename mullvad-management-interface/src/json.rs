@@ -0,0 +1,240 @@
+//! Serialization of the generated types to JSON, as printed by the CLI. Enumerations are written
+//! as the snake case names of their values, timestamps as RFC 3339 strings, durations as whole
+//! seconds and bytes as base64. Which fields use these functions is listed in `json_fields.rs`.
+
+use crate::types::{self, Duration, Timestamp};
+use serde::Serializer;
+use std::fmt::Debug;
+
+pub fn timestamp<S: Serializer>(
+    value: &Option<Timestamp>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(timestamp) => {
+            let ndt =
+                chrono::NaiveDateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32);
+            let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
+            serializer.serialize_str(&utc.to_rfc3339())
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn duration<S: Serializer>(value: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(duration) => serializer.serialize_i64(duration.seconds),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn bytes<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(value))
+}
+
+macro_rules! enum_serializers {
+    ($($name:ident => $enum:ty),* $(,)?) => {
+        $(
+            pub fn $name<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_enum(<$enum>::from_i32(*value), *value, serializer)
+            }
+        )*
+    };
+}
+
+enum_serializers! {
+    after_disconnect => types::AfterDisconnect,
+    bridge_state => types::bridge_state::State,
+    dns_state => types::dns_options::DnsState,
+    encrypted_dns_protocol => types::encrypted_dns_upstream::Protocol,
+    error_state_cause => types::error_state::Cause,
+    firewall_policy_error_type => types::error_state::firewall_policy_error::ErrorType,
+    generation_error => types::error_state::GenerationError,
+    ip_version => types::IpVersion,
+    keygen_event => types::keygen_event::KeygenEvent,
+    network_action => types::NetworkAction,
+    obfuscation_type => types::ObfuscationType,
+    ownership => types::Ownership,
+    proxy_type => types::ProxyType,
+    relay_selection_mode => types::relay_selection_mode::Mode,
+    transport_protocol => types::TransportProtocol,
+    tunnel_type => types::TunnelType,
+}
+
+/// Serializes the name of `variant` in snake case, or `value` if it is not a known value.
+fn serialize_enum<E: Debug, S: Serializer>(
+    variant: Option<E>,
+    value: i32,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match variant {
+        Some(variant) => serializer.serialize_str(&to_snake_case(&format!("{:?}", variant))),
+        None => serializer.serialize_i32(value),
+    }
+}
+
+/// Converts a type or variant name to snake case, such as `AuthFailed` to `auth_failed`.
+pub fn to_snake_case(name: &str) -> String {
+    let mut snake_case = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    snake_case
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    include!("../json_fields.rs");
+
+    const PROTO: &str = include_str!("../proto/management_interface.proto");
+
+    /// A field of a message in the proto file.
+    struct ProtoField {
+        /// Names of the enclosing messages.
+        scope: Vec<String>,
+        /// Name of the field.
+        name: String,
+        /// Name of the field type, as written in the proto file.
+        type_name: String,
+    }
+
+    impl ProtoField {
+        /// Returns the path of the field, such as `ErrorState.FirewallPolicyError.type`.
+        fn path(&self) -> String {
+            format!("{}.{}", self.scope.join("."), self.name)
+        }
+
+        /// Returns whether the field type is an enumeration. Like in protoc, the type name is
+        /// resolved in the innermost scope in which it is defined.
+        fn is_enum(&self, definitions: &HashMap<String, &str>) -> bool {
+            (0..=self.scope.len())
+                .rev()
+                .map(|depth| {
+                    let mut path = self.scope[..depth].to_vec();
+                    path.push(self.type_name.clone());
+                    path.join(".")
+                })
+                .find_map(|path| definitions.get(&path))
+                .map(|keyword| *keyword == "enum")
+                .unwrap_or(false)
+        }
+    }
+
+    struct Proto {
+        fields: Vec<ProtoField>,
+        /// The keywords of all defined messages and enumerations, by their full names.
+        definitions: HashMap<String, &'static str>,
+        /// Paths of all `oneof` fields.
+        oneofs: Vec<String>,
+    }
+
+    fn parse_proto() -> Proto {
+        let mut proto = Proto {
+            fields: Vec::new(),
+            definitions: HashMap::new(),
+            oneofs: Vec::new(),
+        };
+        // The enclosing blocks, as pairs of keyword and name.
+        let mut blocks: Vec<(&str, &str)> = Vec::new();
+
+        for line in PROTO.lines() {
+            let line = line.split("//").next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let scope = blocks
+                .iter()
+                .filter(|(keyword, _)| *keyword == "message")
+                .map(|(_, name)| name.to_string())
+                .collect::<Vec<_>>();
+            let path = |name: &str| [&scope[..], &[name.to_owned()]].concat().join(".");
+
+            match words.as_slice() {
+                ["}"] => {
+                    blocks.pop();
+                }
+                [keyword, name, "{"] => {
+                    match *keyword {
+                        "message" | "enum" => {
+                            proto.definitions.insert(path(name), keyword);
+                        }
+                        "oneof" => proto.oneofs.push(path(name)),
+                        _ => (),
+                    }
+                    blocks.push((keyword, name));
+                }
+                [.., type_name, name, "=", _number]
+                    if matches!(blocks.last(), Some(("message", _)) | Some(("oneof", _))) =>
+                {
+                    proto.fields.push(ProtoField {
+                        scope,
+                        name: name.to_string(),
+                        type_name: type_name.to_string(),
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        proto
+    }
+
+    #[test]
+    fn test_enum_fields_have_serializers() {
+        let proto = parse_proto();
+        let enum_fields: Vec<_> = proto
+            .fields
+            .iter()
+            .filter(|field| field.is_enum(&proto.definitions))
+            .collect();
+        assert!(enum_fields
+            .iter()
+            .any(|field| field.path() == "TunnelEndpoint.protocol"));
+        assert!(enum_fields
+            .iter()
+            .any(|field| field.path() == "KeygenEvent.event"));
+        assert!(!enum_fields
+            .iter()
+            .any(|field| field.path() == "DaemonEvent.key_event"));
+
+        for field in enum_fields {
+            assert!(
+                JSON_FIELD_SERIALIZERS
+                    .iter()
+                    .any(|(path, _)| *path == field.path()),
+                "enum field {} of type {} has no serializer in json_fields.rs",
+                field.path(),
+                field.type_name,
+            );
+        }
+    }
+
+    #[test]
+    fn test_oneof_fields_are_flattened() {
+        let oneofs = parse_proto().oneofs;
+        assert!(!oneofs.is_empty());
+
+        for oneof in oneofs {
+            assert!(
+                JSON_FLATTENED_FIELDS.contains(&oneof.as_str()),
+                "oneof field {} is not flattened in json_fields.rs",
+                oneof,
+            );
+        }
+    }
+
+    #[test]
+    fn test_enum_names() {
+        assert_eq!(to_snake_case("Udp"), "udp");
+        assert_eq!(to_snake_case("AuthFailed"), "auth_failed");
+        assert_eq!(to_snake_case("MullvadOwned"), "mullvad_owned");
+    }
+}
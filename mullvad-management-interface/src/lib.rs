@@ -1,8 +1,12 @@
+pub mod json;
 pub mod types;
 
+#[cfg(target_os = "linux")]
+mod authorization;
+
 use parity_tokio_ipc::Endpoint as IpcEndpoint;
 #[cfg(unix)]
-use std::{env, fs, os::unix::fs::PermissionsExt};
+use std::{env, fs, os::unix::fs::PermissionsExt, path::Path};
 use std::{
     io,
     pin::Pin,
@@ -76,6 +80,21 @@ pub async fn spawn_rpc_server<T: ManagementService>(
 
     let socket_path = mullvad_paths::get_rpc_socket_path();
 
+    #[cfg(target_os = "linux")]
+    if let Some(admin_gid) = authorization::admin_gid()? {
+        let listener = authorization::bind(&socket_path)?;
+        set_socket_group(&socket_path)?;
+        let _ = server_start_tx.send(());
+
+        return authorization::serve(
+            ManagementServiceServer::new(service),
+            listener,
+            admin_gid,
+            abort_rx,
+        )
+        .await;
+    }
+
     let mut endpoint = IpcEndpoint::new(socket_path.to_string_lossy().to_string());
     endpoint.set_security_attributes(
         SecurityAttributes::allow_everyone_create()
@@ -86,14 +105,7 @@ pub async fn spawn_rpc_server<T: ManagementService>(
     let incoming = endpoint.incoming().map_err(Error::StartServerError)?;
 
     #[cfg(unix)]
-    if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
-        let group = nix::unistd::Group::from_name(group_name)
-            .map_err(Error::ObtainGidError)?
-            .ok_or(Error::NoGidError)?;
-        nix::unistd::chown(&socket_path, None, Some(group.gid)).map_err(Error::SetGidError)?;
-        fs::set_permissions(&socket_path, PermissionsExt::from_mode(0o760))
-            .map_err(Error::PermissionsError)?;
-    }
+    set_socket_group(&socket_path)?;
 
     let _ = server_start_tx.send(());
 
@@ -104,6 +116,36 @@ pub async fn spawn_rpc_server<T: ManagementService>(
         .map_err(Error::GrpcTransportError)
 }
 
+/// Returns whether `request` was made by a peer that may change the state of the daemon. This is
+/// true for every peer unless an admin group is configured.
+pub fn is_admin_request<T>(request: &Request<T>) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        !request
+            .metadata()
+            .contains_key(authorization::UNPRIVILEGED_PEER_HEADER)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = request;
+        true
+    }
+}
+
+/// Restricts access to the socket to `MULLVAD_MANAGEMENT_SOCKET_GROUP`, if it is set.
+#[cfg(unix)]
+fn set_socket_group(socket_path: &Path) -> Result<(), Error> {
+    if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
+        let group = nix::unistd::Group::from_name(group_name)
+            .map_err(Error::ObtainGidError)?
+            .ok_or(Error::NoGidError)?;
+        nix::unistd::chown(socket_path, None, Some(group.gid)).map_err(Error::SetGidError)?;
+        fs::set_permissions(socket_path, PermissionsExt::from_mode(0o760))
+            .map_err(Error::PermissionsError)?;
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 struct StreamBox<T: AsyncRead + AsyncWrite>(pub T);
 impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {}
@@ -17,6 +17,8 @@ impl From<mullvad_types::location::GeoIpLocation> for GeoIpLocation {
             mullvad_exit_ip: geoip.mullvad_exit_ip,
             hostname: geoip.hostname.unwrap_or_default(),
             bridge_hostname: geoip.bridge_hostname.unwrap_or_default(),
+            entry_hostname: geoip.entry_hostname.unwrap_or_default(),
+            intermediate_hostnames: geoip.intermediate_hostnames,
         }
     }
 }
@@ -44,6 +46,23 @@ impl From<talpid_types::net::TunnelEndpoint> for TunnelEndpoint {
                 address: entry.address.to_string(),
                 protocol: i32::from(TransportProtocol::from(entry.protocol)),
             }),
+            intermediate_endpoints: endpoint
+                .intermediate_endpoints
+                .into_iter()
+                .map(|intermediate| Endpoint {
+                    address: intermediate.address.to_string(),
+                    protocol: i32::from(TransportProtocol::from(intermediate.protocol)),
+                })
+                .collect(),
+            obfuscation: endpoint
+                .obfuscation
+                .map(|obfuscation_ep| ObfuscationEndpoint {
+                    address: obfuscation_ep.endpoint.address.to_string(),
+                    protocol: i32::from(TransportProtocol::from(obfuscation_ep.endpoint.protocol)),
+                    obfuscation_type: i32::from(ObfuscationType::from(
+                        obfuscation_ep.obfuscation_type,
+                    )),
+                }),
         }
     }
 }
@@ -191,6 +210,18 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
     }
 }
 
+impl From<mullvad_types::connection_history::ConnectionHistoryEntry> for ConnectionHistoryEntry {
+    fn from(entry: mullvad_types::connection_history::ConnectionHistoryEntry) -> Self {
+        ConnectionHistoryEntry {
+            timestamp: Some(Timestamp {
+                seconds: entry.timestamp.timestamp(),
+                nanos: entry.timestamp.timestamp_subsec_nanos() as i32,
+            }),
+            state: Some(TunnelState::from(entry.state)),
+        }
+    }
+}
+
 impl From<mullvad_types::wireguard::KeygenEvent> for KeygenEvent {
     fn from(event: mullvad_types::wireguard::KeygenEvent) -> Self {
         use keygen_event::KeygenEvent as Event;
@@ -267,6 +298,11 @@ impl From<mullvad_types::ConnectionConfig> for ConnectionConfig {
                                 .collect(),
                             endpoint: config.peer.endpoint.to_string(),
                             protocol: i32::from(TransportProtocol::from(config.peer.protocol)),
+                            psk: config
+                                .peer
+                                .psk
+                                .map(|psk| psk.as_bytes().to_vec())
+                                .unwrap_or_default(),
                         }),
                         ipv4_gateway: config.ipv4_gateway.to_string(),
                         ipv6_gateway: config
@@ -315,6 +351,33 @@ impl From<IpVersion> for IpVersionConstraint {
     }
 }
 
+impl From<talpid_types::net::obfuscation::ObfuscationType> for ObfuscationType {
+    fn from(obfuscation: talpid_types::net::obfuscation::ObfuscationType) -> Self {
+        use talpid_types::net::obfuscation::ObfuscationType as TalpidObfuscationType;
+        match obfuscation {
+            TalpidObfuscationType::Udp2Tcp => ObfuscationType::Udp2tcp,
+            TalpidObfuscationType::Shadowsocks => ObfuscationType::Shadowsocks,
+        }
+    }
+}
+
+impl From<ObfuscationType> for talpid_types::net::obfuscation::ObfuscationType {
+    fn from(obfuscation: ObfuscationType) -> Self {
+        match obfuscation {
+            ObfuscationType::Udp2tcp => Self::Udp2Tcp,
+            ObfuscationType::Shadowsocks => Self::Shadowsocks,
+        }
+    }
+}
+
+impl From<ObfuscationType> for ObfuscationConstraint {
+    fn from(obfuscation: ObfuscationType) -> Self {
+        Self {
+            obfuscation_type: i32::from(obfuscation),
+        }
+    }
+}
+
 impl
     From<
         mullvad_types::relay_constraints::Constraint<
@@ -329,26 +392,27 @@ impl
     ) -> Self {
         location
             .option()
+            .and_then(|location| location.single_location().cloned())
             .map(RelayLocation::from)
             .unwrap_or_default()
     }
 }
 
-impl From<mullvad_types::relay_constraints::LocationConstraint> for RelayLocation {
-    fn from(location: mullvad_types::relay_constraints::LocationConstraint) -> Self {
-        use mullvad_types::relay_constraints::LocationConstraint;
+impl From<mullvad_types::relay_constraints::GeographicLocationConstraint> for RelayLocation {
+    fn from(location: mullvad_types::relay_constraints::GeographicLocationConstraint) -> Self {
+        use mullvad_types::relay_constraints::GeographicLocationConstraint;
 
         match location {
-            LocationConstraint::Country(country) => Self {
+            GeographicLocationConstraint::Country(country) => Self {
                 country,
                 ..Default::default()
             },
-            LocationConstraint::City(country, city) => Self {
+            GeographicLocationConstraint::City(country, city) => Self {
                 country,
                 city,
                 ..Default::default()
             },
-            LocationConstraint::Hostname(country, city, hostname) => Self {
+            GeographicLocationConstraint::Hostname(country, city, hostname) => Self {
                 country,
                 city,
                 hostname,
@@ -357,8 +421,46 @@ impl From<mullvad_types::relay_constraints::LocationConstraint> for RelayLocatio
     }
 }
 
+impl
+    From<
+        mullvad_types::relay_constraints::Constraint<
+            mullvad_types::relay_constraints::LocationConstraint,
+        >,
+    > for LocationConstraint
+{
+    fn from(
+        location: mullvad_types::relay_constraints::Constraint<
+            mullvad_types::relay_constraints::LocationConstraint,
+        >,
+    ) -> Self {
+        location
+            .option()
+            .map(LocationConstraint::from)
+            .unwrap_or_default()
+    }
+}
+
+impl From<mullvad_types::relay_constraints::LocationConstraint> for LocationConstraint {
+    fn from(location: mullvad_types::relay_constraints::LocationConstraint) -> Self {
+        Self {
+            include: location
+                .include
+                .into_iter()
+                .map(RelayLocation::from)
+                .collect(),
+            exclude: location
+                .exclude
+                .into_iter()
+                .map(RelayLocation::from)
+                .collect(),
+            exclude_providers: location.exclude_providers,
+        }
+    }
+}
+
 impl From<&mullvad_types::settings::Settings> for Settings {
     fn from(settings: &mullvad_types::settings::Settings) -> Self {
+        let current_profile = settings.current_profile();
         Self {
             account_token: settings.get_account_token().unwrap_or_default(),
             relay_settings: Some(RelaySettings::from(settings.get_relay_settings())),
@@ -369,10 +471,80 @@ impl From<&mullvad_types::settings::Settings> for Settings {
             auto_connect: settings.auto_connect,
             tunnel_options: Some(TunnelOptions::from(&settings.tunnel_options)),
             show_beta_releases: settings.show_beta_releases,
+            relay_selection_mode: Some(RelaySelectionMode::from(settings.relay_selection_mode)),
+            profiles: settings
+                .profiles
+                .iter()
+                .map(|(name, profile)| {
+                    convert_profile(name.clone(), profile, *profile == current_profile)
+                })
+                .collect(),
+            network_rules: Some(NetworkRules::from(&settings.network_rules)),
+            reconnect_interval: settings
+                .reconnect_interval
+                .map(|ivl| Duration::from(std::time::Duration::from(ivl))),
+            locked_fields: settings
+                .policy
+                .locked_fields()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+impl Settings {
+    /// Removes the account token and the secret keys of custom WireGuard relays, including
+    /// those of profiles.
+    pub fn clear_secrets(&mut self) {
+        self.account_token.clear();
+        let profile_relay_settings = self
+            .profiles
+            .iter_mut()
+            .filter_map(|profile| profile.relay_settings.as_mut());
+        for relay_settings in self.relay_settings.iter_mut().chain(profile_relay_settings) {
+            relay_settings.clear_secrets();
         }
     }
 }
 
+impl RelaySettings {
+    /// Removes the secret keys of a custom WireGuard relay.
+    pub fn clear_secrets(&mut self) {
+        let config = match &mut self.endpoint {
+            Some(relay_settings::Endpoint::Custom(CustomRelaySettings {
+                config: Some(config),
+                ..
+            })) => config,
+            _ => return,
+        };
+        if let Some(connection_config::Config::Wireguard(config)) = &mut config.config {
+            if let Some(tunnel) = &mut config.tunnel {
+                tunnel.private_key.clear();
+            }
+            if let Some(peer) = &mut config.peer {
+                peer.psk.clear();
+            }
+        }
+    }
+}
+
+fn convert_profile(
+    name: String,
+    profile: &mullvad_types::settings::Profile,
+    active: bool,
+) -> Profile {
+    Profile {
+        name,
+        relay_settings: Some(RelaySettings::from(profile.relay_settings.clone())),
+        bridge_settings: Some(BridgeSettings::from(profile.bridge_settings.clone())),
+        bridge_state: Some(BridgeState::from(profile.bridge_state)),
+        tunnel_options: Some(TunnelOptions::from(&profile.tunnel_options)),
+        allow_lan: profile.allow_lan,
+        active,
+    }
+}
+
 impl From<mullvad_types::relay_constraints::BridgeState> for BridgeState {
     fn from(state: mullvad_types::relay_constraints::BridgeState) -> Self {
         use mullvad_types::relay_constraints::BridgeState;
@@ -386,6 +558,23 @@ impl From<mullvad_types::relay_constraints::BridgeState> for BridgeState {
     }
 }
 
+impl From<mullvad_types::relay_constraints::RelaySelectionMode> for RelaySelectionMode {
+    fn from(mode: mullvad_types::relay_constraints::RelaySelectionMode) -> Self {
+        use mullvad_types::relay_constraints::RelaySelectionMode;
+        let (mode, max_distance) = match mode {
+            RelaySelectionMode::Weighted => (relay_selection_mode::Mode::Weighted, None),
+            RelaySelectionMode::Fastest => (relay_selection_mode::Mode::Fastest, None),
+            RelaySelectionMode::Nearest { max_distance } => {
+                (relay_selection_mode::Mode::Nearest, max_distance)
+            }
+        };
+        Self {
+            mode: i32::from(mode),
+            max_distance,
+        }
+    }
+}
+
 impl From<mullvad_types::relay_constraints::BridgeSettings> for BridgeSettings {
     fn from(settings: mullvad_types::relay_constraints::BridgeSettings) -> Self {
         use mullvad_types::relay_constraints::BridgeSettings as MullvadBridgeSettings;
@@ -396,10 +585,16 @@ impl From<mullvad_types::relay_constraints::BridgeSettings> for BridgeSettings {
                 bridge_settings::Type::Normal(bridge_settings::BridgeConstraints {
                     location: constraints
                         .location
-                        .clone()
+                        .as_ref()
                         .option()
+                        .and_then(|location| location.single_location().cloned())
                         .map(RelayLocation::from),
                     providers: convert_providers_constraint(&constraints.providers),
+                    ownership: convert_ownership_constraint(constraints.ownership),
+                    location_constraint: constraints
+                        .location
+                        .option()
+                        .map(LocationConstraint::from),
                 })
             }
             MullvadBridgeSettings::Custom(proxy_settings) => match proxy_settings {
@@ -450,8 +645,19 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
             }
             MullvadRelaySettings::Normal(constraints) => {
                 relay_settings::Endpoint::Normal(NormalRelaySettings {
-                    location: constraints.location.option().map(RelayLocation::from),
+                    location: constraints
+                        .location
+                        .as_ref()
+                        .option()
+                        .and_then(|location| location.single_location().cloned())
+                        .map(RelayLocation::from),
+                    location_constraint: constraints
+                        .location
+                        .clone()
+                        .option()
+                        .map(LocationConstraint::from),
                     providers: convert_providers_constraint(&constraints.providers),
+                    ownership: convert_ownership_constraint(constraints.ownership),
                     tunnel_type: match constraints.tunnel_protocol {
                         Constraint::Any => None,
                         Constraint::Only(talpid_net::TunnelType::Wireguard) => {
@@ -476,7 +682,23 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
                         entry_location: constraints
                             .wireguard_constraints
                             .entry_location
+                            .clone()
                             .map(RelayLocation::from),
+                        entry_location_constraint: constraints
+                            .wireguard_constraints
+                            .entry_location
+                            .map(LocationConstraint::from),
+                        obfuscation: constraints
+                            .wireguard_constraints
+                            .obfuscation
+                            .map(ObfuscationType::from)
+                            .map(ObfuscationConstraint::from),
+                        intermediate_locations: constraints
+                            .wireguard_constraints
+                            .intermediate_locations
+                            .into_iter()
+                            .map(LocationConstraint::from)
+                            .collect(),
                     }),
 
                     openvpn_constraints: Some(OpenvpnConstraints {
@@ -488,6 +710,15 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
                             .option()
                             .map(|protocol| TransportProtocol::from(*protocol))
                             .map(TransportProtocolConstraint::from),
+                        entry_location: constraints
+                            .openvpn_constraints
+                            .entry_location
+                            .clone()
+                            .map(RelayLocation::from),
+                        entry_location_constraint: constraints
+                            .openvpn_constraints
+                            .entry_location
+                            .map(LocationConstraint::from),
                     }),
                 })
             }
@@ -499,12 +730,71 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
     }
 }
 
+impl From<mullvad_types::settings::NetworkAction> for NetworkAction {
+    fn from(action: mullvad_types::settings::NetworkAction) -> Self {
+        use mullvad_types::settings::NetworkAction as MullvadNetworkAction;
+        match action {
+            MullvadNetworkAction::Connect => NetworkAction::Connect,
+            MullvadNetworkAction::Disconnect => NetworkAction::Disconnect,
+        }
+    }
+}
+
+impl From<&mullvad_types::settings::NetworkRule> for NetworkRule {
+    fn from(rule: &mullvad_types::settings::NetworkRule) -> Self {
+        use mullvad_types::settings::NetworkMatch;
+        NetworkRule {
+            network: Some(match &rule.network {
+                NetworkMatch::Ssid(ssid) => network_rule::Network::Ssid(ssid.clone()),
+                NetworkMatch::GatewayMac(mac) => network_rule::Network::GatewayMac(mac.to_string()),
+            }),
+            action: i32::from(NetworkAction::from(rule.action)),
+        }
+    }
+}
+
+impl From<&mullvad_types::settings::NetworkRules> for NetworkRules {
+    fn from(rules: &mullvad_types::settings::NetworkRules) -> Self {
+        NetworkRules {
+            rules: rules.rules.iter().map(NetworkRule::from).collect(),
+            default_action: rules.default_action.map(|action| DefaultNetworkAction {
+                action: i32::from(NetworkAction::from(action)),
+            }),
+        }
+    }
+}
+
+impl From<talpid_types::net::NetworkIdentity> for NetworkIdentity {
+    fn from(network: talpid_types::net::NetworkIdentity) -> Self {
+        NetworkIdentity {
+            ssid: network.ssid.unwrap_or_default(),
+            gateway_mac: network
+                .gateway_mac
+                .map(|mac| mac.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<mullvad_types::settings::NetworkRuleEvent> for NetworkRuleEvent {
+    fn from(event: mullvad_types::settings::NetworkRuleEvent) -> Self {
+        NetworkRuleEvent {
+            network: Some(NetworkIdentity::from(event.network)),
+            action: i32::from(NetworkAction::from(event.action)),
+            rule: event.rule.as_ref().map(NetworkRule::from),
+        }
+    }
+}
+
 impl From<&mullvad_types::settings::DnsOptions> for DnsOptions {
     fn from(options: &mullvad_types::settings::DnsOptions) -> Self {
         DnsOptions {
             state: match options.state {
                 mullvad_types::settings::DnsState::Default => dns_options::DnsState::Default as i32,
                 mullvad_types::settings::DnsState::Custom => dns_options::DnsState::Custom as i32,
+                mullvad_types::settings::DnsState::Encrypted => {
+                    dns_options::DnsState::Encrypted as i32
+                }
             },
             default_options: Some(DefaultDnsOptions {
                 block_ads: options.default_options.block_ads,
@@ -518,6 +808,79 @@ impl From<&mullvad_types::settings::DnsOptions> for DnsOptions {
                     .map(|addr| addr.to_string())
                     .collect(),
             }),
+            encrypted_options: Some(EncryptedDnsOptions {
+                upstreams: options
+                    .encrypted_options
+                    .upstreams
+                    .iter()
+                    .map(EncryptedDnsUpstream::from)
+                    .collect(),
+            }),
+            split_rules: options.split_rules.iter().map(SplitDnsRule::from).collect(),
+            blocklists: options.blocklists.iter().map(DnsBlocklist::from).collect(),
+        }
+    }
+}
+
+impl From<&mullvad_types::dns_blocklist::DnsBlocklist> for DnsBlocklist {
+    fn from(blocklist: &mullvad_types::dns_blocklist::DnsBlocklist) -> Self {
+        use mullvad_types::dns_blocklist::DnsBlocklistSource;
+
+        DnsBlocklist {
+            name: blocklist.name.clone(),
+            source: Some(match &blocklist.source {
+                DnsBlocklistSource::Url(url) => dns_blocklist::Source::Url(url.clone()),
+                DnsBlocklistSource::File(path) => {
+                    dns_blocklist::Source::Path(path.to_string_lossy().into_owned())
+                }
+            }),
+            enabled: blocklist.enabled,
+        }
+    }
+}
+
+impl From<mullvad_types::dns_blocklist::DnsBlocklistStats> for DnsBlocklistStats {
+    fn from(stats: mullvad_types::dns_blocklist::DnsBlocklistStats) -> Self {
+        DnsBlocklistStats {
+            queries: stats.queries,
+            blocked: stats.blocked,
+            lists: stats
+                .lists
+                .into_iter()
+                .map(|list| dns_blocklist_stats::List {
+                    name: list.name,
+                    domains: list.domains,
+                    blocked: list.blocked,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&talpid_types::net::SplitDnsRule> for SplitDnsRule {
+    fn from(rule: &talpid_types::net::SplitDnsRule) -> Self {
+        SplitDnsRule {
+            domain: rule.domain.clone(),
+            resolvers: rule
+                .resolvers
+                .iter()
+                .map(|resolver| resolver.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl From<&mullvad_types::settings::EncryptedDnsUpstream> for EncryptedDnsUpstream {
+    fn from(upstream: &mullvad_types::settings::EncryptedDnsUpstream) -> Self {
+        use mullvad_types::settings::EncryptedDnsProtocol;
+
+        EncryptedDnsUpstream {
+            protocol: match upstream.protocol {
+                EncryptedDnsProtocol::Https => encrypted_dns_upstream::Protocol::Https as i32,
+                EncryptedDnsProtocol::Tls => encrypted_dns_upstream::Protocol::Tls as i32,
+            },
+            address: upstream.address.to_string(),
+            hostname: upstream.hostname.clone(),
         }
     }
 }
@@ -534,6 +897,7 @@ impl From<&mullvad_types::settings::TunnelOptions> for TunnelOptions {
                     .wireguard
                     .rotation_interval
                     .map(|ivl| Duration::from(std::time::Duration::from(ivl))),
+                quantum_resistant: options.wireguard.options.quantum_resistant,
             }),
             generic: Some(tunnel_options::GenericOptions {
                 enable_ipv6: options.generic.enable_ipv6,
@@ -737,6 +1101,19 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
                         let buffer = &peer.public_key[..public_key.len()];
                         public_key.copy_from_slice(buffer);
 
+                        let psk = if !peer.psk.is_empty() {
+                            if peer.psk.len() != 32 {
+                                return Err(FromProtobufTypeError::InvalidArgument(
+                                    "invalid preshared key",
+                                ));
+                            }
+                            let mut psk = [0; 32];
+                            psk.copy_from_slice(&peer.psk);
+                            Some(wireguard::PresharedKey::from(psk))
+                        } else {
+                            None
+                        };
+
                         let ipv4_gateway = match config.ipv4_gateway.parse() {
                             Ok(address) => address,
                             Err(_) => {
@@ -798,8 +1175,9 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
                                         "invalid transport protocol",
                                     ))?
                                     .into(),
+                                psk,
                             },
-                            exit_peer: None,
+                            next_peers: vec![],
                             ipv4_gateway,
                             ipv6_gateway,
                         })
@@ -820,9 +1198,15 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
                 // If `location` isn't provided, no changes are made.
                 // If `location` is provided, but is an empty vector,
                 // then the constraint is set to `Constraint::Any`.
-                let location = settings
-                    .location
-                    .map(Constraint::<mullvad_types::relay_constraints::LocationConstraint>::from);
+                // `location_constraint` takes precedence over `location`.
+                let location = match settings.location_constraint {
+                    Some(location) => Some(Constraint::<
+                        mullvad_types::relay_constraints::LocationConstraint,
+                    >::try_from(location)?),
+                    None => settings.location.map(
+                        Constraint::<mullvad_types::relay_constraints::LocationConstraint>::from,
+                    ),
+                };
 
                 let tunnel_protocol = if let Some(update) = settings.tunnel_type {
                     match update.tunnel_type {
@@ -879,6 +1263,10 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
                 } else {
                     None
                 };
+                let ownership = match settings.ownership {
+                    Some(update) => Some(try_ownership_constraint_from_proto(update.ownership)?),
+                    None => None,
+                };
                 let ip_version = if let Some(ref constraints) = settings.wireguard_constraints {
                     match &constraints.ip_version {
                         Some(constraint) => match IpVersion::from_i32(constraint.protocol) {
@@ -900,32 +1288,81 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
                     mullvad_constraints::RelayConstraintsUpdate {
                         location,
                         providers,
+                        ownership,
                         tunnel_protocol,
-                        wireguard_constraints: settings.wireguard_constraints.map(|constraints| {
-                            mullvad_constraints::WireguardConstraints {
-                                port: if constraints.port != 0 {
-                                    Constraint::Only(constraints.port as u16)
-                                } else {
-                                    Constraint::Any
-                                },
-                                ip_version: Constraint::from(ip_version),
-                                entry_location: constraints.entry_location.map(
-                                    Constraint::<
+                        wireguard_constraints: settings
+                            .wireguard_constraints
+                            .map(|constraints| {
+                                let entry_location = match constraints.entry_location_constraint {
+                                    Some(location) => Some(Constraint::<
                                         mullvad_types::relay_constraints::LocationConstraint,
-                                    >::from,
-                                ),
-                            }
-                        }),
-                        openvpn_constraints: settings.openvpn_constraints.map(|constraints| {
-                            mullvad_constraints::OpenVpnConstraints {
-                                port: if constraints.port != 0 {
-                                    Constraint::Only(constraints.port as u16)
-                                } else {
-                                    Constraint::Any
-                                },
-                                protocol: Constraint::from(transport_protocol),
-                            }
-                        }),
+                                    >::try_from(
+                                        location
+                                    )?),
+                                    None => constraints.entry_location.map(
+                                        Constraint::<
+                                            mullvad_types::relay_constraints::LocationConstraint,
+                                        >::from,
+                                    ),
+                                };
+                                let obfuscation = match constraints.obfuscation {
+                                    Some(constraint) => Some(
+                                        ObfuscationType::from_i32(constraint.obfuscation_type)
+                                            .ok_or(FromProtobufTypeError::InvalidArgument(
+                                                "invalid obfuscation type",
+                                            ))?
+                                            .into(),
+                                    ),
+                                    None => None,
+                                };
+                                let intermediate_locations = constraints
+                                    .intermediate_locations
+                                    .into_iter()
+                                    .map(
+                                        Constraint::<
+                                            mullvad_types::relay_constraints::LocationConstraint,
+                                        >::try_from,
+                                    )
+                                    .collect::<Result<Vec<_>, _>>()?;
+                                Ok(mullvad_constraints::WireguardConstraints {
+                                    port: if constraints.port != 0 {
+                                        Constraint::Only(constraints.port as u16)
+                                    } else {
+                                        Constraint::Any
+                                    },
+                                    ip_version: Constraint::from(ip_version),
+                                    entry_location,
+                                    intermediate_locations,
+                                    obfuscation,
+                                })
+                            })
+                            .transpose()?,
+                        openvpn_constraints: settings
+                            .openvpn_constraints
+                            .map(|constraints| {
+                                let entry_location = match constraints.entry_location_constraint {
+                                    Some(location) => Some(Constraint::<
+                                        mullvad_types::relay_constraints::LocationConstraint,
+                                    >::try_from(
+                                        location
+                                    )?),
+                                    None => constraints.entry_location.map(
+                                        Constraint::<
+                                            mullvad_types::relay_constraints::LocationConstraint,
+                                        >::from,
+                                    ),
+                                };
+                                Ok(mullvad_constraints::OpenVpnConstraints {
+                                    port: if constraints.port != 0 {
+                                        Constraint::Only(constraints.port as u16)
+                                    } else {
+                                        Constraint::Any
+                                    },
+                                    protocol: Constraint::from(transport_protocol),
+                                    entry_location,
+                                })
+                            })
+                            .transpose()?,
                     },
                 ))
             }
@@ -933,26 +1370,74 @@ impl TryFrom<RelaySettingsUpdate> for mullvad_types::relay_constraints::RelaySet
     }
 }
 
-impl From<RelayLocation> for Constraint<mullvad_types::relay_constraints::LocationConstraint> {
+impl From<RelayLocation>
+    for Constraint<mullvad_types::relay_constraints::GeographicLocationConstraint>
+{
     fn from(location: RelayLocation) -> Self {
-        use mullvad_types::relay_constraints::LocationConstraint;
+        use mullvad_types::relay_constraints::GeographicLocationConstraint;
 
         if !location.hostname.is_empty() {
-            Constraint::Only(LocationConstraint::Hostname(
+            Constraint::Only(GeographicLocationConstraint::Hostname(
                 location.country,
                 location.city,
                 location.hostname,
             ))
         } else if !location.city.is_empty() {
-            Constraint::Only(LocationConstraint::City(location.country, location.city))
+            Constraint::Only(GeographicLocationConstraint::City(
+                location.country,
+                location.city,
+            ))
         } else if !location.country.is_empty() {
-            Constraint::Only(LocationConstraint::Country(location.country))
+            Constraint::Only(GeographicLocationConstraint::Country(location.country))
         } else {
             Constraint::Any
         }
     }
 }
 
+impl From<RelayLocation> for Constraint<mullvad_types::relay_constraints::LocationConstraint> {
+    fn from(location: RelayLocation) -> Self {
+        Constraint::<mullvad_types::relay_constraints::GeographicLocationConstraint>::from(location)
+            .map(mullvad_types::relay_constraints::LocationConstraint::from)
+    }
+}
+
+impl TryFrom<LocationConstraint>
+    for Constraint<mullvad_types::relay_constraints::LocationConstraint>
+{
+    type Error = FromProtobufTypeError;
+
+    fn try_from(location: LocationConstraint) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::{
+            GeographicLocationConstraint, LocationConstraint as MullvadLocationConstraint,
+        };
+
+        let convert_locations = |locations: Vec<RelayLocation>| {
+            locations
+                .into_iter()
+                .map(|location| {
+                    Constraint::<GeographicLocationConstraint>::from(location)
+                        .option()
+                        .ok_or(FromProtobufTypeError::InvalidArgument(
+                            "empty relay location",
+                        ))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let location = MullvadLocationConstraint {
+            include: convert_locations(location.include)?,
+            exclude: convert_locations(location.exclude)?,
+            exclude_providers: location.exclude_providers,
+        };
+
+        if location.is_unrestricted() {
+            Ok(Constraint::Any)
+        } else {
+            Ok(Constraint::Only(location))
+        }
+    }
+}
+
 impl TryFrom<BridgeSettings> for mullvad_types::relay_constraints::BridgeSettings {
     type Error = FromProtobufTypeError;
 
@@ -966,11 +1451,14 @@ impl TryFrom<BridgeSettings> for mullvad_types::relay_constraints::BridgeSetting
                 "no settings provided",
             ))? {
             bridge_settings::Type::Normal(constraints) => {
-                let location = match constraints.location {
-                    None => Constraint::Any,
-                    Some(location) => {
+                let location = match (constraints.location_constraint, constraints.location) {
+                    (Some(location), _) => {
+                        Constraint::<mullvad_constraints::LocationConstraint>::try_from(location)?
+                    }
+                    (None, Some(location)) => {
                         Constraint::<mullvad_constraints::LocationConstraint>::from(location)
                     }
+                    (None, None) => Constraint::Any,
                 };
                 let providers = if constraints.providers.is_empty() {
                     Constraint::Any
@@ -987,10 +1475,13 @@ impl TryFrom<BridgeSettings> for mullvad_types::relay_constraints::BridgeSetting
                     )
                 };
 
+                let ownership = try_ownership_constraint_from_proto(constraints.ownership)?;
+
                 Ok(mullvad_constraints::BridgeSettings::Normal(
                     mullvad_constraints::BridgeConstraints {
                         location,
                         providers,
+                        ownership,
                     },
                 ))
             }
@@ -1057,6 +1548,29 @@ impl TryFrom<BridgeState> for mullvad_types::relay_constraints::BridgeState {
     }
 }
 
+impl TryFrom<RelaySelectionMode> for mullvad_types::relay_constraints::RelaySelectionMode {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(mode: RelaySelectionMode) -> Result<Self, Self::Error> {
+        match relay_selection_mode::Mode::from_i32(mode.mode) {
+            Some(relay_selection_mode::Mode::Weighted) => {
+                Ok(mullvad_types::relay_constraints::RelaySelectionMode::Weighted)
+            }
+            Some(relay_selection_mode::Mode::Fastest) => {
+                Ok(mullvad_types::relay_constraints::RelaySelectionMode::Fastest)
+            }
+            Some(relay_selection_mode::Mode::Nearest) => Ok(
+                mullvad_types::relay_constraints::RelaySelectionMode::Nearest {
+                    max_distance: mode.max_distance,
+                },
+            ),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid relay selection mode",
+            )),
+        }
+    }
+}
+
 impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
     type Error = FromProtobufTypeError;
 
@@ -1064,12 +1578,13 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
         use mullvad_types::settings::{
             CustomDnsOptions as MullvadCustomDnsOptions,
             DefaultDnsOptions as MullvadDefaultDnsOptions, DnsOptions as MullvadDnsOptions,
-            DnsState as MullvadDnsState,
+            DnsState as MullvadDnsState, EncryptedDnsOptions as MullvadEncryptedDnsOptions,
         };
 
         let state = match dns_options::DnsState::from_i32(options.state) {
             Some(dns_options::DnsState::Default) => MullvadDnsState::Default,
             Some(dns_options::DnsState::Custom) => MullvadDnsState::Custom,
+            Some(dns_options::DnsState::Encrypted) => MullvadDnsState::Encrypted,
             None => {
                 return Err(FromProtobufTypeError::InvalidArgument(
                     "invalid DNS options state",
@@ -1090,6 +1605,22 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
                     "missing default DNS options",
                 ))?;
 
+        let blocklists = options
+            .blocklists
+            .into_iter()
+            .map(mullvad_types::dns_blocklist::DnsBlocklist::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (index, blocklist) in blocklists.iter().enumerate() {
+            if blocklists[..index]
+                .iter()
+                .any(|other| other.name == blocklist.name)
+            {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "duplicate DNS blocklist name",
+                ));
+            }
+        }
+
         Ok(MullvadDnsOptions {
             state,
             default_options: MullvadDefaultDnsOptions {
@@ -1107,10 +1638,186 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             },
+            encrypted_options: MullvadEncryptedDnsOptions {
+                upstreams: options
+                    .encrypted_options
+                    .map(|options| options.upstreams)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(mullvad_types::settings::EncryptedDnsUpstream::try_from)
+                    .collect::<Result<Vec<_>, _>>()?,
+            },
+            split_rules: options
+                .split_rules
+                .into_iter()
+                .map(talpid_types::net::SplitDnsRule::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            blocklists,
+        })
+    }
+}
+
+impl TryFrom<DnsBlocklist> for mullvad_types::dns_blocklist::DnsBlocklist {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(blocklist: DnsBlocklist) -> Result<Self, Self::Error> {
+        use mullvad_types::dns_blocklist::DnsBlocklistSource;
+
+        let name = blocklist.name.trim().to_owned();
+        if name.is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "missing DNS blocklist name",
+            ));
+        }
+        let source = match blocklist.source {
+            Some(dns_blocklist::Source::Url(url)) => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(FromProtobufTypeError::InvalidArgument(
+                        "DNS blocklist URL must use HTTP or HTTPS",
+                    ));
+                }
+                DnsBlocklistSource::Url(url)
+            }
+            Some(dns_blocklist::Source::Path(path)) => {
+                let path = std::path::PathBuf::from(path);
+                if !path.is_absolute() {
+                    return Err(FromProtobufTypeError::InvalidArgument(
+                        "DNS blocklist path must be absolute",
+                    ));
+                }
+                DnsBlocklistSource::File(path)
+            }
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "missing DNS blocklist source",
+                ))
+            }
+        };
+
+        Ok(mullvad_types::dns_blocklist::DnsBlocklist {
+            name,
+            source,
+            enabled: blocklist.enabled,
+        })
+    }
+}
+
+impl TryFrom<SplitDnsRule> for talpid_types::net::SplitDnsRule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rule: SplitDnsRule) -> Result<Self, Self::Error> {
+        let domain = rule.domain.trim_matches('.').to_lowercase();
+        if domain.is_empty() || domain.split('.').any(|label| label.is_empty()) {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "invalid split DNS domain",
+            ));
+        }
+        if rule.resolvers.is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "missing split DNS resolvers",
+            ));
+        }
+        let resolvers = rule
+            .resolvers
+            .into_iter()
+            .map(|resolver| {
+                resolver
+                    .parse()
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid IP address"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(talpid_types::net::SplitDnsRule { domain, resolvers })
+    }
+}
+
+impl TryFrom<EncryptedDnsUpstream> for mullvad_types::settings::EncryptedDnsUpstream {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(upstream: EncryptedDnsUpstream) -> Result<Self, Self::Error> {
+        use mullvad_types::settings::EncryptedDnsProtocol;
+
+        let protocol = match encrypted_dns_upstream::Protocol::from_i32(upstream.protocol) {
+            Some(encrypted_dns_upstream::Protocol::Https) => EncryptedDnsProtocol::Https,
+            Some(encrypted_dns_upstream::Protocol::Tls) => EncryptedDnsProtocol::Tls,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid encrypted DNS protocol",
+                ))
+            }
+        };
+        let address = upstream
+            .address
+            .parse()
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid socket address"))?;
+
+        Ok(mullvad_types::settings::EncryptedDnsUpstream {
+            protocol,
+            address,
+            hostname: upstream.hostname,
         })
     }
 }
 
+impl TryFrom<NetworkRule> for mullvad_types::settings::NetworkRule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rule: NetworkRule) -> Result<Self, Self::Error> {
+        use mullvad_types::settings::NetworkMatch;
+
+        let network = match rule.network {
+            Some(network_rule::Network::Ssid(ssid)) => NetworkMatch::Ssid(ssid),
+            Some(network_rule::Network::GatewayMac(mac)) => {
+                NetworkMatch::GatewayMac(mac.parse().map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("invalid gateway MAC address")
+                })?)
+            }
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "missing network in network rule",
+                ))
+            }
+        };
+
+        Ok(mullvad_types::settings::NetworkRule {
+            network,
+            action: try_network_action_from_proto(rule.action)?,
+        })
+    }
+}
+
+impl TryFrom<NetworkRules> for mullvad_types::settings::NetworkRules {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rules: NetworkRules) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::settings::NetworkRules {
+            rules: rules
+                .rules
+                .into_iter()
+                .map(mullvad_types::settings::NetworkRule::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            default_action: rules
+                .default_action
+                .map(|default| try_network_action_from_proto(default.action))
+                .transpose()?,
+        })
+    }
+}
+
+fn try_network_action_from_proto(
+    action: i32,
+) -> Result<mullvad_types::settings::NetworkAction, FromProtobufTypeError> {
+    use mullvad_types::settings::NetworkAction as MullvadNetworkAction;
+
+    match NetworkAction::from_i32(action) {
+        Some(NetworkAction::Connect) => Ok(MullvadNetworkAction::Connect),
+        Some(NetworkAction::Disconnect) => Ok(MullvadNetworkAction::Disconnect),
+        None => Err(FromProtobufTypeError::InvalidArgument(
+            "invalid network action",
+        )),
+    }
+}
+
 fn convert_providers_constraint(
     providers: &Constraint<mullvad_types::relay_constraints::Providers>,
 ) -> Vec<String> {
@@ -1119,3 +1826,31 @@ fn convert_providers_constraint(
         Constraint::Only(providers) => Vec::from(providers.clone()),
     }
 }
+
+fn convert_ownership_constraint(
+    ownership: Constraint<mullvad_types::relay_constraints::Ownership>,
+) -> Option<OwnershipConstraint> {
+    use mullvad_types::relay_constraints::Ownership as MullvadOwnership;
+
+    ownership.option().map(|ownership| OwnershipConstraint {
+        ownership: i32::from(match ownership {
+            MullvadOwnership::MullvadOwned => Ownership::MullvadOwned,
+            MullvadOwnership::Rented => Ownership::Rented,
+        }),
+    })
+}
+
+fn try_ownership_constraint_from_proto(
+    ownership: Option<OwnershipConstraint>,
+) -> Result<Constraint<mullvad_types::relay_constraints::Ownership>, FromProtobufTypeError> {
+    use mullvad_types::relay_constraints::Ownership as MullvadOwnership;
+
+    match ownership {
+        Some(constraint) => match Ownership::from_i32(constraint.ownership) {
+            Some(Ownership::MullvadOwned) => Ok(Constraint::Only(MullvadOwnership::MullvadOwned)),
+            Some(Ownership::Rented) => Ok(Constraint::Only(MullvadOwnership::Rented)),
+            None => Err(FromProtobufTypeError::InvalidArgument("invalid ownership")),
+        },
+        None => Ok(Constraint::Any),
+    }
+}
@@ -1,6 +1,7 @@
 #![deny(rust_2018_idioms)]
 
 use lazy_static::lazy_static;
+use mullvad_types::connection_history::CONNECTION_HISTORY_FILE;
 use regex::Regex;
 use std::{
     borrow::Cow,
@@ -23,7 +24,6 @@ const EXTRA_BYTES: usize = 32 * 1024;
 /// Fit five logs plus some system information in the report.
 const REPORT_MAX_SIZE: usize = (5 * LOG_MAX_READ_BYTES) + EXTRA_BYTES;
 
-
 /// Field delimeter in generated problem report
 const LOG_DELIMITER: &str = "====================";
 
@@ -86,6 +86,9 @@ pub enum LogError {
     #[error(display = "Unable to get log directory")]
     GetLogDir(#[error(source)] mullvad_paths::Error),
 
+    #[error(display = "Unable to get cache directory")]
+    GetCacheDir(#[error(source, no_from)] mullvad_paths::Error),
+
     #[error(display = "Failed to list the files in the log directory: {}", path)]
     ListLogDir {
         path: String,
@@ -109,6 +112,7 @@ pub fn collect_report(
     extra_logs: &[&Path],
     output_path: &Path,
     redact_custom_strings: Vec<String>,
+    include_connection_history: bool,
     #[cfg(target_os = "android")] android_log_dir: &Path,
 ) -> Result<(), Error> {
     let mut problem_report = ProblemReport::new(redact_custom_strings);
@@ -168,6 +172,13 @@ pub fn collect_report(
         Err(error) => problem_report.add_error("Failed to collect logcat", &error),
     }
 
+    if include_connection_history {
+        match mullvad_paths::get_cache_dir().map_err(LogError::GetCacheDir) {
+            Ok(cache_dir) => problem_report.add_log(&cache_dir.join(CONNECTION_HISTORY_FILE)),
+            Err(error) => problem_report.add_error("Failed to collect connection history", &error),
+        }
+    }
+
     problem_report.add_logs(extra_logs);
 
     write_problem_report(&output_path, &problem_report).map_err(|source| Error::WriteReportError {
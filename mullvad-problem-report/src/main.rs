@@ -54,6 +54,11 @@ fn run() -> Result<(), Error> {
                         .value_name("PHRASE")
                         .multiple(true)
                         .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("connection_history")
+                        .help("Include the journal of tunnel state transitions kept by the daemon")
+                        .long("include-connection-history"),
                 ),
         )
         .subcommand(
@@ -96,7 +101,13 @@ fn run() -> Result<(), Error> {
             .map(|os_values| os_values.map(Path::new).collect())
             .unwrap_or_else(Vec::new);
         let output_path = Path::new(collect_matches.value_of_os("output").unwrap());
-        collect_report(&extra_logs, output_path, redact_custom_strings)?;
+        let include_connection_history = collect_matches.is_present("connection_history");
+        collect_report(
+            &extra_logs,
+            output_path,
+            redact_custom_strings,
+            include_connection_history,
+        )?;
 
         let expanded_output_path = output_path
             .canonicalize()
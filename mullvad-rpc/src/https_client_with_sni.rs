@@ -78,6 +78,12 @@ impl HttpsConnectorWithSni {
         }
     }
 
+    /// Use `config` instead of the default TLS configuration, which only trusts the root
+    /// certificates of the Mullvad API.
+    pub fn set_tls_config(&mut self, config: Arc<rustls::ClientConfig>) {
+        self.tls = config;
+    }
+
     fn read_cert_store() -> rustls::RootCertStore {
         let mut cert_store = rustls::RootCertStore::empty();
 
@@ -16,6 +16,7 @@ use std::{
     sync::Arc,
 };
 use talpid_types::{net::wireguard, ErrorExt};
+use tokio_rustls::rustls;
 
 
 pub mod rest;
@@ -145,13 +146,20 @@ impl MullvadRpcRuntime {
     }
 
     /// Creates a new request service and returns a handle to it.
-    fn new_request_service(&mut self, sni_hostname: Option<String>) -> rest::RequestServiceHandle {
-        let https_connector = HttpsConnectorWithSni::new(
+    fn new_request_service(
+        &mut self,
+        sni_hostname: Option<String>,
+        tls_config: Option<Arc<rustls::ClientConfig>>,
+    ) -> rest::RequestServiceHandle {
+        let mut https_connector = HttpsConnectorWithSni::new(
             self.handle.clone(),
             sni_hostname,
             #[cfg(target_os = "android")]
             self.socket_bypass_tx.clone(),
         );
+        if let Some(tls_config) = tls_config {
+            https_connector.set_tls_config(tls_config);
+        }
 
         let service = rest::RequestService::new(
             https_connector,
@@ -165,7 +173,7 @@ impl MullvadRpcRuntime {
 
     /// Returns a request factory initialized to create requests for the master API
     pub fn mullvad_rest_handle(&mut self) -> rest::MullvadRestHandle {
-        let service = self.new_request_service(Some(API_HOST.to_owned()));
+        let service = self.new_request_service(Some(API_HOST.to_owned()), None);
         let factory = rest::RequestFactory::new(
             API_HOST.to_owned(),
             Box::new(self.address_cache.clone()),
@@ -177,7 +185,16 @@ impl MullvadRpcRuntime {
 
     /// Returns a new request service handle
     pub fn rest_handle(&mut self) -> rest::RequestServiceHandle {
-        self.new_request_service(None)
+        self.new_request_service(None, None)
+    }
+
+    /// Returns a new request service handle for requests to hosts other than the Mullvad API,
+    /// whose certificates are verified using `tls_config`.
+    pub fn rest_handle_with_tls_config(
+        &mut self,
+        tls_config: Arc<rustls::ClientConfig>,
+    ) -> rest::RequestServiceHandle {
+        self.new_request_service(None, Some(tls_config))
     }
 
     pub fn handle(&mut self) -> &mut tokio::runtime::Handle {
@@ -0,0 +1,15 @@
+use crate::states::TunnelState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Name of the connection history file in the daemon's cache directory.
+pub const CONNECTION_HISTORY_FILE: &str = "connection-history.jsonl";
+
+/// A tunnel state that was entered, and when it was entered. The states include the endpoint and
+/// relay location while connecting or connected, what was done after disconnecting, and the
+/// cause of any error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionHistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub state: TunnelState,
+}
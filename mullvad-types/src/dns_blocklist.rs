@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+
+/// A list of domains that the local resolver refuses to resolve. The list is either a hosts file,
+/// where every domain that maps to an address is blocked, or a list with one domain per line.
+/// Subdomains of the listed domains are blocked as well.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct DnsBlocklist {
+    /// Name that the list is referred to by.
+    pub name: String,
+    pub source: DnsBlocklistSource,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsBlocklistSource {
+    /// HTTPS URL that the list is downloaded from. The latest downloaded copy is used if the list
+    /// cannot be downloaded.
+    Url(String),
+    /// Path to a file in the `dns-blocklists` directory of the settings directory.
+    File(PathBuf),
+}
+
+impl fmt::Display for DnsBlocklistSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsBlocklistSource::Url(url) => f.write_str(url),
+            DnsBlocklistSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Statistics for the blocklists that are in use, counted since they were last loaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsBlocklistStats {
+    /// Number of queries handled by the local resolver.
+    pub queries: u64,
+    /// Number of queries that were blocked by any list.
+    pub blocked: u64,
+    pub lists: Vec<DnsBlocklistListStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsBlocklistListStats {
+    pub name: String,
+    /// Number of domains in the list.
+    pub domains: u64,
+    /// Number of queries that were blocked by this list.
+    pub blocked: u64,
+}
@@ -2,6 +2,8 @@
 
 pub mod account;
 pub mod auth_failed;
+pub mod connection_history;
+pub mod dns_blocklist;
 pub mod endpoint;
 pub mod location;
 pub mod relay_constraints;
@@ -9,6 +11,7 @@ pub mod relay_list;
 pub mod settings;
 pub mod states;
 pub mod version;
+pub mod wg_quick;
 pub mod wireguard;
 
 mod custom_tunnel;
@@ -31,8 +31,9 @@ impl Location {
     }
 }
 
-/// Takes input as latitude and longitude degrees.
-fn haversine_dist_deg(lat: f64, lon: f64, other_lat: f64, other_lon: f64) -> f64 {
+/// Returns the distance in kilometers between two points. Takes input as latitude and longitude
+/// degrees.
+pub fn haversine_dist_deg(lat: f64, lon: f64, other_lat: f64, other_lon: f64) -> f64 {
     haversine_dist_rad(
         lat.to_radians(),
         lon.to_radians(),
@@ -83,6 +84,27 @@ pub struct GeoIpLocation {
     pub hostname: Option<String>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub bridge_hostname: Option<String>,
+    /// Hostname of the relay that the tunnel enters through, if it differs from the exit relay.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub entry_hostname: Option<String>,
+    /// Hostnames of the relays between the entry relay and the exit relay, in the order that the
+    /// traffic passes through them.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub intermediate_hostnames: Vec<String>,
+}
+
+impl GeoIpLocation {
+    /// Returns the distance in kilometers to the given relay location.
+    pub fn distance_from(&self, other: &Location) -> f64 {
+        haversine_dist_deg(
+            self.latitude,
+            self.longitude,
+            other.latitude,
+            other.longitude,
+        )
+    }
 }
 
 impl From<AmIMullvad> for GeoIpLocation {
@@ -102,6 +124,8 @@ impl From<AmIMullvad> for GeoIpLocation {
             mullvad_exit_ip: location.mullvad_exit_ip,
             hostname: None,
             bridge_hostname: None,
+            entry_hostname: None,
+            intermediate_hostnames: vec![],
         }
     }
 }
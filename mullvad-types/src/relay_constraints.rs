@@ -7,10 +7,12 @@ use crate::{
     CustomTunnelEndpoint,
 };
 #[cfg(target_os = "android")]
-use jnix::{FromJava, IntoJava};
+use jnix::{jni::objects::JObject, FromJava, IntoJava, JnixEnv};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, fmt};
-use talpid_types::net::{openvpn::ProxySettings, IpVersion, TransportProtocol, TunnelType};
+use talpid_types::net::{
+    obfuscation::ObfuscationType, openvpn::ProxySettings, IpVersion, TransportProtocol, TunnelType,
+};
 
 
 pub trait Match<T> {
@@ -161,10 +163,8 @@ impl RelaySettings {
                 if constraints.openvpn_constraints.protocol
                     == Constraint::Only(TransportProtocol::Udp)
                 {
-                    constraints.openvpn_constraints = OpenVpnConstraints {
-                        protocol: Constraint::Any,
-                        port: Constraint::Any,
-                    }
+                    constraints.openvpn_constraints.protocol = Constraint::Any;
+                    constraints.openvpn_constraints.port = Constraint::Any;
                 }
             }
             RelaySettings::CustomTunnelEndpoint(config) => {
@@ -185,10 +185,16 @@ impl RelaySettings {
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub struct RelayConstraints {
+    #[cfg_attr(
+        target_os = "android",
+        jnix(map = "|location| single_location_constraint(location)")
+    )]
     pub location: Constraint<LocationConstraint>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub providers: Constraint<Providers>,
     #[cfg_attr(target_os = "android", jnix(skip))]
+    pub ownership: Constraint<Ownership>,
+    #[cfg_attr(target_os = "android", jnix(skip))]
     pub tunnel_protocol: Constraint<TunnelType>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub wireguard_constraints: WireguardConstraints,
@@ -203,6 +209,7 @@ impl Default for RelayConstraints {
             tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
             location: Constraint::default(),
             providers: Constraint::default(),
+            ownership: Constraint::default(),
             wireguard_constraints: WireguardConstraints::default(),
             openvpn_constraints: OpenVpnConstraints::default(),
         }
@@ -214,6 +221,7 @@ impl RelayConstraints {
         RelayConstraints {
             location: update.location.unwrap_or_else(|| self.location.clone()),
             providers: update.providers.unwrap_or_else(|| self.providers.clone()),
+            ownership: update.ownership.unwrap_or(self.ownership),
             tunnel_protocol: update
                 .tunnel_protocol
                 .unwrap_or_else(|| self.tunnel_protocol.clone()),
@@ -254,20 +262,135 @@ impl fmt::Display for RelayConstraints {
         }
         write!(f, " using ")?;
         match self.providers {
-            Constraint::Any => write!(f, "any provider"),
-            Constraint::Only(ref constraint) => constraint.fmt(f),
+            Constraint::Any => write!(f, "any provider")?,
+            Constraint::Only(ref constraint) => constraint.fmt(f)?,
         }
+        if let Constraint::Only(ownership) = self.ownership {
+            write!(f, " on {} servers", ownership)?;
+        }
+        Ok(())
     }
 }
 
 
 /// Limits the set of [`crate::relay_list::Relay`]s used by a `RelaySelector` based on
-/// location.
+/// location, hostname and provider.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LocationConstraint {
+    /// Relays must be in one of these locations. Any location matches if this is empty.
+    pub include: Vec<GeographicLocationConstraint>,
+    /// Relays in any of these locations are never selected.
+    pub exclude: Vec<GeographicLocationConstraint>,
+    /// Relays hosted by any of these providers are never selected.
+    pub exclude_providers: Vec<Provider>,
+}
+
+impl LocationConstraint {
+    /// Returns the included location if the constraint consists of exactly one location and no
+    /// exclusions.
+    pub fn single_location(&self) -> Option<&GeographicLocationConstraint> {
+        match self.include.as_slice() {
+            [location] if self.exclude.is_empty() && self.exclude_providers.is_empty() => {
+                Some(location)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the constraint does not exclude any relay.
+    pub fn is_unrestricted(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.exclude_providers.is_empty()
+    }
+}
+
+impl From<GeographicLocationConstraint> for LocationConstraint {
+    fn from(location: GeographicLocationConstraint) -> Self {
+        LocationConstraint {
+            include: vec![location],
+            ..Default::default()
+        }
+    }
+}
+
+impl Match<Relay> for LocationConstraint {
+    fn matches(&self, relay: &Relay) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|location| location.matches(relay)))
+            && !self.exclude.iter().any(|location| location.contains(relay))
+            && !self.exclude_providers.contains(&relay.provider)
+    }
+}
+
+impl fmt::Display for LocationConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.include.is_empty() {
+            write!(f, "any location")?;
+        } else {
+            write_list(f, &self.include, " or ")?;
+        }
+        if !self.exclude.is_empty() {
+            write!(f, " except ")?;
+            write_list(f, &self.exclude, ", ")?;
+        }
+        if !self.exclude_providers.is_empty() {
+            write!(f, " not hosted by ")?;
+            write_list(f, &self.exclude_providers, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    items: &[T],
+    separator: &str,
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "android")]
+impl<'env, 'sub_env> FromJava<'env, JObject<'sub_env>> for LocationConstraint
+where
+    'env: 'sub_env,
+{
+    const JNI_SIGNATURE: &'static str = "Lnet/mullvad/mullvadvpn/model/LocationConstraint;";
+
+    fn from_java(env: &JnixEnv<'env>, object: JObject<'sub_env>) -> Self {
+        GeographicLocationConstraint::from_java(env, object).into()
+    }
+}
+
+/// The Android app only supports a single location. Constraints that cannot be expressed as a
+/// single location are presented as any location.
+#[cfg(target_os = "android")]
+fn single_location_constraint(
+    location: Constraint<LocationConstraint>,
+) -> Constraint<GeographicLocationConstraint> {
+    match location {
+        Constraint::Only(location) => location
+            .single_location()
+            .cloned()
+            .map(Constraint::Only)
+            .unwrap_or(Constraint::Any),
+        Constraint::Any => Constraint::Any,
+    }
+}
+
+/// A single location that a [`crate::relay_list::Relay`] can be in.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[cfg_attr(target_os = "android", derive(FromJava, IntoJava))]
-#[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
-pub enum LocationConstraint {
+#[cfg_attr(
+    target_os = "android",
+    jnix(class_name = "net.mullvad.mullvadvpn.model.LocationConstraint")
+)]
+pub enum GeographicLocationConstraint {
     /// A country is represented by its two letter country code.
     Country(CountryCode),
     /// A city is composed of a country code and a city code.
@@ -276,22 +399,21 @@ pub enum LocationConstraint {
     Hostname(CountryCode, CityCode, Hostname),
 }
 
-impl Match<Relay> for LocationConstraint {
-    fn matches(&self, relay: &Relay) -> bool {
+impl GeographicLocationConstraint {
+    /// Returns `true` if the relay is located in this location. Unlike `matches`, this does not
+    /// take into account whether the relay should be selected when only its country is given.
+    fn contains(&self, relay: &Relay) -> bool {
         match self {
-            LocationConstraint::Country(ref country) => {
-                relay
-                    .location
-                    .as_ref()
-                    .map_or(false, |loc| loc.country_code == *country)
-                    && relay.include_in_country
-            }
-            LocationConstraint::City(ref country, ref city) => {
+            GeographicLocationConstraint::Country(ref country) => relay
+                .location
+                .as_ref()
+                .map_or(false, |loc| loc.country_code == *country),
+            GeographicLocationConstraint::City(ref country, ref city) => {
                 relay.location.as_ref().map_or(false, |loc| {
                     loc.country_code == *country && loc.city_code == *city
                 })
             }
-            LocationConstraint::Hostname(ref country, ref city, ref hostname) => {
+            GeographicLocationConstraint::Hostname(ref country, ref city, ref hostname) => {
                 relay.location.as_ref().map_or(false, |loc| {
                     loc.country_code == *country
                         && loc.city_code == *city
@@ -302,6 +424,17 @@ impl Match<Relay> for LocationConstraint {
     }
 }
 
+impl Match<Relay> for GeographicLocationConstraint {
+    fn matches(&self, relay: &Relay) -> bool {
+        match self {
+            GeographicLocationConstraint::Country(_) => {
+                self.contains(relay) && relay.include_in_country
+            }
+            _ => self.contains(relay),
+        }
+    }
+}
+
 /// Limits the set of [`crate::relay_list::Relay`]s used by a `RelaySelector` based on
 /// provider.
 pub type Provider = String;
@@ -338,6 +471,33 @@ impl From<Providers> for Vec<Provider> {
     }
 }
 
+/// Limits the set of [`crate::relay_list::Relay`]s used by a `RelaySelector` based on whether
+/// the servers are owned by Mullvad or rented.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ownership {
+    MullvadOwned,
+    Rented,
+}
+
+impl Match<Relay> for Ownership {
+    fn matches(&self, relay: &Relay) -> bool {
+        match self {
+            Ownership::MullvadOwned => relay.owned,
+            Ownership::Rented => !relay.owned,
+        }
+    }
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Ownership::MullvadOwned => write!(f, "Mullvad-owned"),
+            Ownership::Rented => write!(f, "rented"),
+        }
+    }
+}
+
 impl fmt::Display for Providers {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "provider(s) ")?;
@@ -352,12 +512,14 @@ impl fmt::Display for Providers {
     }
 }
 
-impl fmt::Display for LocationConstraint {
+impl fmt::Display for GeographicLocationConstraint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
-            LocationConstraint::Country(country) => write!(f, "country {}", country),
-            LocationConstraint::City(country, city) => write!(f, "city {}, {}", city, country),
-            LocationConstraint::Hostname(country, city, hostname) => {
+            GeographicLocationConstraint::Country(country) => write!(f, "country {}", country),
+            GeographicLocationConstraint::City(country, city) => {
+                write!(f, "city {}, {}", city, country)
+            }
+            GeographicLocationConstraint::Hostname(country, city, hostname) => {
                 write!(f, "city {}, {}, hostname {}", city, country, hostname)
             }
         }
@@ -407,10 +569,14 @@ impl Match<WireguardEndpointData> for TunnelConstraints {
 }
 
 /// [`Constraint`]s applicable to OpenVPN relay servers.
-#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct OpenVpnConstraints {
     pub port: Constraint<u16>,
     pub protocol: Constraint<TransportProtocol>,
+    /// Location of a relay to route the tunnel through. The tunnel reaches the entry relay
+    /// through one of its bridges.
+    #[serde(default)]
+    pub entry_location: Option<Constraint<LocationConstraint>>,
 }
 
 impl fmt::Display for OpenVpnConstraints {
@@ -421,9 +587,13 @@ impl fmt::Display for OpenVpnConstraints {
         }
         write!(f, " over ")?;
         match self.protocol {
-            Constraint::Any => write!(f, "any protocol"),
-            Constraint::Only(protocol) => write!(f, "{}", protocol),
+            Constraint::Any => write!(f, "any protocol")?,
+            Constraint::Only(protocol) => write!(f, "{}", protocol)?,
+        }
+        if let Some(Constraint::Only(ref entry)) = self.entry_location {
+            write!(f, " (via {})", entry)?;
         }
+        Ok(())
     }
 }
 
@@ -440,6 +610,11 @@ pub struct WireguardConstraints {
     pub port: Constraint<u16>,
     pub ip_version: Constraint<IpVersion>,
     pub entry_location: Option<Constraint<LocationConstraint>>,
+    /// Locations of additional relays to route the tunnel through after the entry relay, in the
+    /// order that the traffic passes through them. Ignored unless `entry_location` is set.
+    pub intermediate_locations: Vec<Constraint<LocationConstraint>>,
+    /// Obfuscation to wrap the traffic to the entry relay in. `None` sends plain WireGuard.
+    pub obfuscation: Option<ObfuscationType>,
 }
 
 impl fmt::Display for WireguardConstraints {
@@ -454,9 +629,32 @@ impl fmt::Display for WireguardConstraints {
             Constraint::Only(protocol) => write!(f, "{}", protocol)?,
         }
         if let Some(Constraint::Only(ref entry)) = self.entry_location {
-            write!(f, " (via {})", entry)
-        } else {
-            Ok(())
+            write!(f, " (via {}", entry)?;
+            for intermediate in &self.intermediate_locations {
+                match intermediate {
+                    Constraint::Any => write!(f, " and any location")?,
+                    Constraint::Only(location) => write!(f, " and {}", location)?,
+                }
+            }
+            write!(f, ")")?;
+        }
+        if let Some(obfuscation) = self.obfuscation {
+            write!(f, " obfuscated by {}", obfuscation)?;
+        }
+        Ok(())
+    }
+}
+
+impl WireguardConstraints {
+    /// Returns the location constraints of the relays that the tunnel passes through before the
+    /// exit relay, starting with the entry relay.
+    pub fn hop_locations(&self) -> Vec<Constraint<LocationConstraint>> {
+        match &self.entry_location {
+            Some(entry_location) => std::iter::once(entry_location)
+                .chain(self.intermediate_locations.iter())
+                .cloned()
+                .collect(),
+            None => vec![],
         }
     }
 }
@@ -492,14 +690,24 @@ pub enum BridgeSettings {
 pub struct BridgeConstraints {
     pub location: Constraint<LocationConstraint>,
     pub providers: Constraint<Providers>,
+    pub ownership: Constraint<Ownership>,
 }
 
 impl fmt::Display for BridgeConstraints {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self.location {
-            Constraint::Any => write!(f, "any location"),
-            Constraint::Only(ref location_constraint) => location_constraint.fmt(f),
+            Constraint::Any => write!(f, "any location")?,
+            Constraint::Only(ref location_constraint) => location_constraint.fmt(f)?,
         }
+        write!(f, " using ")?;
+        match self.providers {
+            Constraint::Any => write!(f, "any provider")?,
+            Constraint::Only(ref constraint) => constraint.fmt(f)?,
+        }
+        if let Constraint::Only(ownership) = self.ownership {
+            write!(f, " on {} servers", ownership)?;
+        }
+        Ok(())
     }
 }
 
@@ -526,10 +734,43 @@ impl fmt::Display for BridgeState {
     }
 }
 
+/// Strategy used by the relay selector to pick among the relays that match the constraints.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelaySelectionMode {
+    /// Pick a random relay, weighted by the relay weights from the relay list.
+    Weighted,
+    /// Prefer the relays with the lowest measured round-trip time.
+    Fastest,
+    /// Prefer the relays closest to the last known location of the device. If `max_distance` is
+    /// set and no relay is within that many kilometers, a weighted selection is used instead.
+    Nearest { max_distance: Option<u32> },
+}
+
+impl Default for RelaySelectionMode {
+    fn default() -> Self {
+        RelaySelectionMode::Weighted
+    }
+}
+
+impl fmt::Display for RelaySelectionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelaySelectionMode::Weighted => write!(f, "weighted"),
+            RelaySelectionMode::Fastest => write!(f, "fastest"),
+            RelaySelectionMode::Nearest { max_distance: None } => write!(f, "nearest"),
+            RelaySelectionMode::Nearest {
+                max_distance: Some(max_distance),
+            } => write!(f, "nearest within {} km", max_distance),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct InternalBridgeConstraints {
     pub location: Constraint<LocationConstraint>,
     pub providers: Constraint<Providers>,
+    pub ownership: Constraint<Ownership>,
     pub transport_protocol: Constraint<TransportProtocol>,
 }
 
@@ -579,9 +820,193 @@ pub struct RelayConstraintsUpdate {
     #[cfg_attr(target_os = "android", jnix(default))]
     pub providers: Option<Constraint<Providers>>,
     #[cfg_attr(target_os = "android", jnix(default))]
+    pub ownership: Option<Constraint<Ownership>>,
+    #[cfg_attr(target_os = "android", jnix(default))]
     pub tunnel_protocol: Option<Constraint<TunnelType>>,
     #[cfg_attr(target_os = "android", jnix(default))]
     pub wireguard_constraints: Option<WireguardConstraints>,
     #[cfg_attr(target_os = "android", jnix(default))]
     pub openvpn_constraints: Option<OpenVpnConstraints>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        location::Location,
+        relay_list::{RelayBridges, RelayTunnels},
+    };
+    use std::net::Ipv4Addr;
+
+    fn country(code: &str) -> Constraint<LocationConstraint> {
+        Constraint::Only(GeographicLocationConstraint::Country(code.to_owned()).into())
+    }
+
+    fn relay(hostname: &str, country: &str, city: &str, provider: &str) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: Ipv4Addr::LOCALHOST,
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: provider.to_owned(),
+            weight: 1,
+            tunnels: RelayTunnels::default(),
+            bridges: RelayBridges::default(),
+            location: Some(Location {
+                country: country.to_owned(),
+                country_code: country.to_owned(),
+                city: city.to_owned(),
+                city_code: city.to_owned(),
+                latitude: 0.0,
+                longitude: 0.0,
+            }),
+        }
+    }
+
+    fn city_location(country: &str, city: &str) -> GeographicLocationConstraint {
+        GeographicLocationConstraint::City(country.to_owned(), city.to_owned())
+    }
+
+    fn hostname_location(
+        country: &str,
+        city: &str,
+        hostname: &str,
+    ) -> GeographicLocationConstraint {
+        GeographicLocationConstraint::Hostname(
+            country.to_owned(),
+            city.to_owned(),
+            hostname.to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_hop_locations() {
+        let mut constraints = WireguardConstraints {
+            intermediate_locations: vec![country("de")],
+            ..WireguardConstraints::default()
+        };
+        // Intermediate locations are ignored without an entry location.
+        assert!(constraints.hop_locations().is_empty());
+
+        constraints.entry_location = Some(country("se"));
+        assert_eq!(
+            constraints.hop_locations(),
+            vec![country("se"), country("de")]
+        );
+
+        constraints.intermediate_locations = vec![];
+        assert_eq!(constraints.hop_locations(), vec![country("se")]);
+    }
+
+    #[test]
+    fn test_location_constraint_include() {
+        let got = relay("se1", "se", "got", "provider");
+        let sto = relay("se2", "se", "sto", "provider");
+        let fra = relay("de1", "de", "fra", "provider");
+
+        let any = LocationConstraint::default();
+        assert!(any.matches(&got) && any.matches(&sto) && any.matches(&fra));
+
+        let constraint = LocationConstraint {
+            include: vec![
+                city_location("se", "got"),
+                GeographicLocationConstraint::Country("de".to_owned()),
+            ],
+            ..LocationConstraint::default()
+        };
+        assert!(constraint.matches(&got));
+        assert!(!constraint.matches(&sto));
+        assert!(constraint.matches(&fra));
+    }
+
+    #[test]
+    fn test_location_constraint_exclude() {
+        let se1 = relay("se1", "se", "got", "provider");
+        let se2 = relay("se2", "se", "got", "provider");
+        let se3 = relay("se3", "se", "sto", "provider");
+
+        let constraint = LocationConstraint {
+            include: vec![GeographicLocationConstraint::Country("se".to_owned())],
+            exclude: vec![
+                hostname_location("se", "got", "se1"),
+                city_location("se", "sto"),
+            ],
+            ..LocationConstraint::default()
+        };
+        assert!(!constraint.matches(&se1));
+        assert!(constraint.matches(&se2));
+        assert!(!constraint.matches(&se3));
+
+        // Exclusions apply to relays that are not included in their country as well.
+        let mut se2_not_in_country = se2.clone();
+        se2_not_in_country.include_in_country = false;
+        let constraint = LocationConstraint {
+            include: vec![hostname_location("se", "got", "se2")],
+            exclude: vec![city_location("se", "got")],
+            ..LocationConstraint::default()
+        };
+        assert!(!constraint.matches(&se2_not_in_country));
+
+        // Exclusions take precedence over inclusions.
+        let constraint = LocationConstraint {
+            include: vec![hostname_location("se", "got", "se2")],
+            exclude: vec![GeographicLocationConstraint::Country("se".to_owned())],
+            ..LocationConstraint::default()
+        };
+        assert!(!constraint.matches(&se2));
+    }
+
+    #[test]
+    fn test_location_constraint_exclude_providers() {
+        let rented = relay("se1", "se", "got", "rented-provider");
+        let other = relay("se2", "se", "got", "other-provider");
+
+        let constraint = LocationConstraint {
+            exclude_providers: vec!["rented-provider".to_owned()],
+            ..LocationConstraint::default()
+        };
+        assert!(!constraint.matches(&rented));
+        assert!(constraint.matches(&other));
+
+        let providers = Providers::new(vec!["rented-provider".to_owned()].into_iter())
+            .unwrap_or_else(|_| panic!("no providers"));
+        assert!(providers.matches(&rented));
+        assert!(!providers.matches(&other));
+    }
+
+    #[test]
+    fn test_ownership() {
+        let owned = relay("se1", "se", "got", "provider");
+        let rented = Relay {
+            owned: false,
+            ..relay("se2", "se", "got", "provider")
+        };
+
+        assert!(Ownership::MullvadOwned.matches(&owned));
+        assert!(!Ownership::MullvadOwned.matches(&rented));
+        assert!(!Ownership::Rented.matches(&owned));
+        assert!(Ownership::Rented.matches(&rented));
+    }
+
+    #[test]
+    fn test_bridge_constraints_display() {
+        let mut constraints = BridgeConstraints::default();
+        assert_eq!(constraints.to_string(), "any location using any provider");
+
+        constraints.location = country("se");
+        constraints.providers = Constraint::Only(
+            Providers::new(vec!["provider".to_owned()].into_iter())
+                .unwrap_or_else(|_| panic!("no providers")),
+        );
+        constraints.ownership = Constraint::Only(Ownership::Rented);
+        assert_eq!(
+            constraints.to_string(),
+            format!(
+                "{} using provider(s) provider on rented servers",
+                GeographicLocationConstraint::Country("se".to_owned())
+            )
+        );
+    }
+}
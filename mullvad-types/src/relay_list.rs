@@ -1,6 +1,7 @@
 use crate::{
     endpoint::MullvadEndpoint,
     location::{CityCode, CountryCode, Location},
+    relay_constraints::GeographicLocationConstraint,
 };
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
@@ -33,6 +34,27 @@ impl RelayList {
             countries: Vec::new(),
         }
     }
+
+    /// Returns `true` if the country, city or relay referred to by `location` is in the list.
+    pub fn contains_location(&self, location: &GeographicLocationConstraint) -> bool {
+        let (country_code, city_code, hostname) = match location {
+            GeographicLocationConstraint::Country(country) => (country, None, None),
+            GeographicLocationConstraint::City(country, city) => (country, Some(city), None),
+            GeographicLocationConstraint::Hostname(country, city, hostname) => {
+                (country, Some(city), Some(hostname))
+            }
+        };
+        self.countries
+            .iter()
+            .filter(|country| country.code == *country_code)
+            .flat_map(|country| country.cities.iter())
+            .filter(|city| city_code.map_or(true, |code| city.code == *code))
+            .any(|city| {
+                hostname.map_or(true, |hostname| {
+                    city.relays.iter().any(|relay| relay.hostname == *hostname)
+                })
+            })
+    }
 }
 
 /// A list of [`RelayListCity`]s within a country. Used by [`RelayList`].
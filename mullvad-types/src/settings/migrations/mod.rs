@@ -3,6 +3,8 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 mod v1;
 mod v2;
 mod v3;
+mod v4;
+mod v5;
 
 
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
@@ -11,9 +13,11 @@ pub enum SettingsVersion {
     V2 = 2,
     V3 = 3,
     V4 = 4,
+    V5 = 5,
+    V6 = 6,
 }
 
-pub const CURRENT_SETTINGS_VERSION: SettingsVersion = SettingsVersion::V3;
+pub const CURRENT_SETTINGS_VERSION: SettingsVersion = SettingsVersion::V6;
 
 impl<'de> Deserialize<'de> for SettingsVersion {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
@@ -24,6 +28,8 @@ impl<'de> Deserialize<'de> for SettingsVersion {
             v if v == SettingsVersion::V2 as u32 => Ok(SettingsVersion::V2),
             v if v == SettingsVersion::V3 as u32 => Ok(SettingsVersion::V3),
             v if v == SettingsVersion::V4 as u32 => Ok(SettingsVersion::V4),
+            v if v == SettingsVersion::V5 as u32 => Ok(SettingsVersion::V5),
+            v if v == SettingsVersion::V6 as u32 => Ok(SettingsVersion::V6),
             v => Err(serde::de::Error::custom(format!(
                 "{} is not a valid SettingsVersion",
                 v
@@ -59,6 +65,8 @@ pub fn try_migrate_settings(mut settings_file: &[u8]) -> Result<crate::settings:
         Box::new(v1::Migration),
         Box::new(v2::Migration),
         Box::new(v3::Migration),
+        Box::new(v4::Migration),
+        Box::new(v5::Migration),
     ];
 
     for migration in &migrations {
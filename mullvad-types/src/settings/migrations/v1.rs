@@ -2,8 +2,8 @@ use super::{Error, Result};
 use crate::{
     custom_tunnel::CustomTunnelEndpoint,
     relay_constraints::{
-        Constraint, LocationConstraint, OpenVpnConstraints, RelaySettings as NewRelaySettings,
-        WireguardConstraints,
+        Constraint, GeographicLocationConstraint, LocationConstraint, OpenVpnConstraints,
+        RelaySettings as NewRelaySettings, WireguardConstraints,
     },
 };
 use serde::{Deserialize, Serialize};
@@ -40,7 +40,7 @@ fn migrate_relay_settings(relay_settings: RelaySettings) -> NewRelaySettings {
         }
         RelaySettings::Normal(old_constraints) => {
             let mut new_constraints = crate::relay_constraints::RelayConstraints {
-                location: old_constraints.location,
+                location: old_constraints.location.map(LocationConstraint::from),
                 ..Default::default()
             };
             match old_constraints.tunnel {
@@ -67,7 +67,7 @@ pub enum RelaySettings {
 
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct RelayConstraints {
-    pub location: Constraint<LocationConstraint>,
+    pub location: Constraint<GeographicLocationConstraint>,
     pub tunnel: Constraint<TunnelConstraints>,
 }
 
@@ -91,7 +91,11 @@ mod test {
     "normal": {
       "location": {
         "only": {
-          "country": "se"
+          "include": [
+            {
+              "country": "se"
+            }
+          ]
         }
       },
       "tunnel_protocol": "any",
@@ -128,7 +132,7 @@ mod test {
       "enable_ipv6": false
     }
   },
-  "settings_version": 4
+  "settings_version": 6
 }
 "#;
 
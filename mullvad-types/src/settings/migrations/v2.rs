@@ -119,7 +119,11 @@ mod test {
     "normal": {
       "location": {
         "only": {
-          "country": "se"
+          "include": [
+            {
+              "country": "se"
+            }
+          ]
         }
       },
       "tunnel_protocol": "any",
@@ -160,7 +164,7 @@ mod test {
       "enable_ipv6": false
     }
   },
-  "settings_version": 4
+  "settings_version": 6
 }
 "#;
 
@@ -39,6 +39,7 @@ impl super::SettingsMigration for Migration {
                 state: new_state,
                 default_options: DefaultDnsOptions::default(),
                 custom_options: CustomDnsOptions { addresses },
+                ..DnsOptions::default()
             });
         }
 
@@ -119,7 +120,11 @@ mod test {
     "normal": {
       "location": {
         "only": {
-          "country": "se"
+          "include": [
+            {
+              "country": "se"
+            }
+          ]
         }
       },
       "tunnel_protocol": "any",
@@ -173,7 +178,7 @@ mod test {
       }
     }
   },
-  "settings_version": 4
+  "settings_version": 6
 }
 "#;
 
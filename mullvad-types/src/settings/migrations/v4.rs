@@ -0,0 +1,216 @@
+use super::{Result, SettingsVersion};
+
+
+pub(super) struct Migration;
+
+impl super::SettingsMigration for Migration {
+    fn version_matches(&self, settings: &mut serde_json::Value) -> bool {
+        settings
+            .get("settings_version")
+            .map(|version| version == SettingsVersion::V4 as u64)
+            .unwrap_or(false)
+    }
+
+    fn migrate(&self, settings: &mut serde_json::Value) -> Result<()> {
+        log::info!("Migrating settings format to V5");
+
+        if let Some(constraints) = settings
+            .get_mut("relay_settings")
+            .and_then(|relay_settings| relay_settings.get_mut("normal"))
+        {
+            migrate_location(constraints.get_mut("location"));
+            if let Some(wireguard_constraints) = constraints.get_mut("wireguard_constraints") {
+                migrate_location(wireguard_constraints.get_mut("entry_location"));
+            }
+        }
+        if let Some(constraints) = settings
+            .get_mut("bridge_settings")
+            .and_then(|bridge_settings| bridge_settings.get_mut("normal"))
+        {
+            migrate_location(constraints.get_mut("location"));
+        }
+
+        settings["settings_version"] = serde_json::json!(SettingsVersion::V5);
+
+        Ok(())
+    }
+}
+
+/// Turns a single location, such as `{"only": {"country": "se"}}`, into a location constraint
+/// including only that location: `{"only": {"include": [{"country": "se"}]}}`.
+fn migrate_location(location: Option<&mut serde_json::Value>) {
+    let location = match location.and_then(|location| location.get_mut("only")) {
+        Some(location) => location,
+        None => return,
+    };
+    let is_single_location = ["country", "city", "hostname"]
+        .iter()
+        .any(|key| location.get(key).is_some());
+    if is_single_location {
+        *location = serde_json::json!({ "include": [location.take()] });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::try_migrate_settings;
+    use serde_json;
+
+    pub const V4_SETTINGS: &str = r#"
+{
+  "account_token": "1234",
+  "relay_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "city": ["se", "got"]
+        }
+      },
+      "tunnel_protocol": "any",
+      "wireguard_constraints": {
+        "port": "any",
+        "entry_location": {
+          "only": {
+            "hostname": ["se", "sto", "se-sto-wg-001"]
+          }
+        }
+      },
+      "openvpn_constraints": {
+        "port": {
+          "only": 53
+        },
+        "protocol": {
+          "only": "udp"
+        }
+      }
+    }
+  },
+  "bridge_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "country": "de"
+        }
+      }
+    }
+  },
+  "bridge_state": "auto",
+  "allow_lan": true,
+  "block_when_disconnected": false,
+  "auto_connect": false,
+  "tunnel_options": {
+    "openvpn": {
+      "mssfix": null
+    },
+    "wireguard": {
+      "mtu": null,
+      "rotation_interval": null
+    },
+    "generic": {
+      "enable_ipv6": false
+    },
+    "dns_options": {
+      "state": "default",
+      "default_options": {
+        "block_ads": true,
+        "block_trackers": false
+      },
+      "custom_options": {
+        "addresses": []
+      }
+    }
+  },
+  "settings_version": 4
+}
+"#;
+
+    pub const NEW_SETTINGS: &str = r#"
+{
+  "account_token": "1234",
+  "relay_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "include": [
+            {
+              "city": ["se", "got"]
+            }
+          ]
+        }
+      },
+      "tunnel_protocol": "any",
+      "wireguard_constraints": {
+        "port": "any",
+        "entry_location": {
+          "only": {
+            "include": [
+              {
+                "hostname": ["se", "sto", "se-sto-wg-001"]
+              }
+            ]
+          }
+        }
+      },
+      "openvpn_constraints": {
+        "port": {
+          "only": 53
+        },
+        "protocol": {
+          "only": "udp"
+        }
+      }
+    }
+  },
+  "bridge_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "include": [
+            {
+              "country": "de"
+            }
+          ]
+        }
+      }
+    }
+  },
+  "bridge_state": "auto",
+  "allow_lan": true,
+  "block_when_disconnected": false,
+  "auto_connect": false,
+  "tunnel_options": {
+    "openvpn": {
+      "mssfix": null
+    },
+    "wireguard": {
+      "mtu": null,
+      "rotation_interval": null
+    },
+    "generic": {
+      "enable_ipv6": false
+    },
+    "dns_options": {
+      "state": "default",
+      "default_options": {
+        "block_ads": true,
+        "block_trackers": false
+      },
+      "custom_options": {
+        "addresses": []
+      }
+    }
+  },
+  "settings_version": 6
+}
+"#;
+
+
+    #[test]
+    fn test_v4_migration() {
+        let migrated_settings =
+            try_migrate_settings(V4_SETTINGS.as_bytes()).expect("Migration failed");
+        let new_settings = serde_json::from_str(NEW_SETTINGS).unwrap();
+
+        assert_eq!(&migrated_settings, &new_settings);
+    }
+}
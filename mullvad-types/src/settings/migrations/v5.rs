@@ -0,0 +1,160 @@
+use super::{Result, SettingsVersion};
+
+
+pub(super) struct Migration;
+
+impl super::SettingsMigration for Migration {
+    fn version_matches(&self, settings: &mut serde_json::Value) -> bool {
+        settings
+            .get("settings_version")
+            .map(|version| version == SettingsVersion::V5 as u64)
+            .unwrap_or(false)
+    }
+
+    fn migrate(&self, settings: &mut serde_json::Value) -> Result<()> {
+        log::info!("Migrating settings format to V6");
+
+        if settings.get("profiles").is_none() {
+            settings["profiles"] = serde_json::json!({});
+        }
+
+        settings["settings_version"] = serde_json::json!(SettingsVersion::V6);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::try_migrate_settings;
+    use serde_json;
+
+    pub const V5_SETTINGS: &str = r#"
+{
+  "account_token": "1234",
+  "relay_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "include": [
+            {
+              "country": "se"
+            }
+          ]
+        }
+      },
+      "tunnel_protocol": "any",
+      "wireguard_constraints": {
+        "port": "any"
+      },
+      "openvpn_constraints": {
+        "port": "any",
+        "protocol": "any"
+      }
+    }
+  },
+  "bridge_settings": {
+    "normal": {
+      "location": "any"
+    }
+  },
+  "bridge_state": "auto",
+  "allow_lan": false,
+  "block_when_disconnected": false,
+  "auto_connect": false,
+  "tunnel_options": {
+    "openvpn": {
+      "mssfix": null
+    },
+    "wireguard": {
+      "mtu": null,
+      "rotation_interval": null
+    },
+    "generic": {
+      "enable_ipv6": false
+    },
+    "dns_options": {
+      "state": "default",
+      "default_options": {
+        "block_ads": false,
+        "block_trackers": false
+      },
+      "custom_options": {
+        "addresses": []
+      }
+    }
+  },
+  "settings_version": 5
+}
+"#;
+
+    pub const NEW_SETTINGS: &str = r#"
+{
+  "account_token": "1234",
+  "relay_settings": {
+    "normal": {
+      "location": {
+        "only": {
+          "include": [
+            {
+              "country": "se"
+            }
+          ]
+        }
+      },
+      "tunnel_protocol": "any",
+      "wireguard_constraints": {
+        "port": "any"
+      },
+      "openvpn_constraints": {
+        "port": "any",
+        "protocol": "any"
+      }
+    }
+  },
+  "bridge_settings": {
+    "normal": {
+      "location": "any"
+    }
+  },
+  "bridge_state": "auto",
+  "allow_lan": false,
+  "block_when_disconnected": false,
+  "auto_connect": false,
+  "tunnel_options": {
+    "openvpn": {
+      "mssfix": null
+    },
+    "wireguard": {
+      "mtu": null,
+      "rotation_interval": null
+    },
+    "generic": {
+      "enable_ipv6": false
+    },
+    "dns_options": {
+      "state": "default",
+      "default_options": {
+        "block_ads": false,
+        "block_trackers": false
+      },
+      "custom_options": {
+        "addresses": []
+      }
+    }
+  },
+  "profiles": {},
+  "settings_version": 6
+}
+"#;
+
+
+    #[test]
+    fn test_v5_migration() {
+        let migrated_settings =
+            try_migrate_settings(V5_SETTINGS.as_bytes()).expect("Migration failed");
+        let new_settings = serde_json::from_str(NEW_SETTINGS).unwrap();
+
+        assert_eq!(&migrated_settings, &new_settings);
+    }
+}
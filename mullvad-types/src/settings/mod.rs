@@ -1,17 +1,26 @@
 use crate::{
+    dns_blocklist::DnsBlocklist,
     relay_constraints::{
-        BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
-        RelayConstraints, RelaySettings, RelaySettingsUpdate,
+        BridgeConstraints, BridgeSettings, BridgeState, Constraint, GeographicLocationConstraint,
+        LocationConstraint, RelayConstraints, RelaySelectionMode, RelaySettings,
+        RelaySettingsUpdate,
     },
-    wireguard,
+    relay_list::RelayList,
+    wireguard::{self, RotationInterval},
 };
 #[cfg(target_os = "android")]
 use jnix::{jni::objects::JObject, FromJava, IntoJava, JnixEnv};
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::net::IpAddr;
-use talpid_types::net::{self, openvpn, GenericTunnelOptions};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+use talpid_types::net::{
+    self, openvpn, GenericTunnelOptions, MacAddress, NetworkIdentity, SplitDnsRule,
+};
 
 mod migrations;
 
@@ -43,6 +52,9 @@ pub struct Settings {
     pub bridge_settings: BridgeSettings,
     #[cfg_attr(target_os = "android", jnix(skip))]
     bridge_state: BridgeState,
+    /// How to pick a relay among the ones matching the relay constraints.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub relay_selection_mode: RelaySelectionMode,
     /// If the daemon should allow communication with private (LAN) networks.
     pub allow_lan: bool,
     /// Extra level of kill switch. When this setting is on, the disconnected state will block
@@ -51,11 +63,26 @@ pub struct Settings {
     pub block_when_disconnected: bool,
     /// If the daemon should connect the VPN tunnel directly on start or not.
     pub auto_connect: bool,
+    /// Rules for connecting or disconnecting automatically when joining particular networks.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub network_rules: NetworkRules,
+    /// How long to stay connected to a relay before reconnecting to a different one. Scheduled
+    /// reconnects are disabled if this is `None`.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub reconnect_interval: Option<RotationInterval>,
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
     /// might be located.
     pub tunnel_options: TunnelOptions,
     /// Whether to notify users of beta updates.
     pub show_beta_releases: bool,
+    /// Named sets of connection settings that can be activated as a whole.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Settings locked by the system-wide policy file. This is never written to the settings
+    /// file.
+    #[serde(skip)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub policy: Policy,
     /// Specifies settings schema version
     #[cfg_attr(target_os = "android", jnix(skip))]
     settings_version: migrations::SettingsVersion,
@@ -66,16 +93,23 @@ impl Default for Settings {
         Settings {
             account_token: None,
             relay_settings: RelaySettings::Normal(RelayConstraints {
-                location: Constraint::Only(LocationConstraint::Country("se".to_owned())),
+                location: Constraint::Only(LocationConstraint::from(
+                    GeographicLocationConstraint::Country("se".to_owned()),
+                )),
                 ..Default::default()
             }),
             bridge_settings: BridgeSettings::Normal(BridgeConstraints::default()),
             bridge_state: BridgeState::Auto,
+            relay_selection_mode: RelaySelectionMode::default(),
             allow_lan: false,
             block_when_disconnected: false,
             auto_connect: false,
+            network_rules: NetworkRules::default(),
+            reconnect_interval: None,
             tunnel_options: TunnelOptions::default(),
             show_beta_releases: false,
+            profiles: BTreeMap::new(),
+            policy: Policy::default(),
             settings_version: migrations::CURRENT_SETTINGS_VERSION,
         }
     }
@@ -158,6 +192,324 @@ impl Settings {
             false
         }
     }
+
+    /// Returns the part of the settings that is stored in a [`Profile`].
+    pub fn current_profile(&self) -> Profile {
+        Profile {
+            relay_settings: self.relay_settings.clone(),
+            bridge_settings: self.bridge_settings.clone(),
+            bridge_state: self.bridge_state,
+            tunnel_options: self.tunnel_options.clone(),
+            allow_lan: self.allow_lan,
+        }
+    }
+
+    /// Replaces all settings stored in a [`Profile`] at once. Returns whether anything changed.
+    pub fn apply_profile(&mut self, profile: Profile) -> bool {
+        if self.current_profile() == profile {
+            return false;
+        }
+        debug!(
+            "Changing relay settings:\n\tfrom: {}\n\tto: {}",
+            self.relay_settings, profile.relay_settings
+        );
+        self.relay_settings = profile.relay_settings;
+        self.bridge_settings = profile.bridge_settings;
+        self.bridge_state = profile.bridge_state;
+        self.tunnel_options = profile.tunnel_options;
+        self.allow_lan = profile.allow_lan;
+        true
+    }
+
+    /// Overrides the settings that are locked by the policy with the values it pins them to.
+    /// Returns whether anything changed.
+    pub fn enforce_policy(&mut self) -> bool {
+        fn enforce<T: Clone + PartialEq>(field: &mut T, value: &Option<T>) -> bool {
+            match value {
+                Some(value) if field != value => {
+                    *field = value.clone();
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        let policy = &self.policy;
+        let mut changed = enforce(&mut self.allow_lan, &policy.allow_lan);
+        changed |= enforce(
+            &mut self.block_when_disconnected,
+            &policy.block_when_disconnected,
+        );
+        changed |= enforce(&mut self.auto_connect, &policy.auto_connect);
+        changed |= enforce(
+            &mut self.tunnel_options.generic.enable_ipv6,
+            &policy.enable_ipv6,
+        );
+        changed |= enforce(&mut self.tunnel_options.dns_options, &policy.dns_options);
+        changed |= enforce(&mut self.show_beta_releases, &policy.show_beta_releases);
+        if let Some(bridge_state) = self.policy.bridge_state {
+            changed |= self.set_bridge_state(bridge_state);
+        }
+        changed
+    }
+
+    /// Returns the name of a setting that differs from the value the policy locks it to, if there
+    /// is one.
+    pub fn changed_locked_field(&self) -> Option<&'static str> {
+        fn differs<T: PartialEq>(field: &T, value: &Option<T>) -> bool {
+            matches!(value, Some(value) if field != value)
+        }
+
+        let policy = &self.policy;
+        let fields = [
+            ("allow_lan", differs(&self.allow_lan, &policy.allow_lan)),
+            (
+                "block_when_disconnected",
+                differs(
+                    &self.block_when_disconnected,
+                    &policy.block_when_disconnected,
+                ),
+            ),
+            (
+                "auto_connect",
+                differs(&self.auto_connect, &policy.auto_connect),
+            ),
+            (
+                "bridge_state",
+                differs(&self.bridge_state, &policy.bridge_state),
+            ),
+            (
+                "enable_ipv6",
+                differs(
+                    &self.tunnel_options.generic.enable_ipv6,
+                    &policy.enable_ipv6,
+                ),
+            ),
+            (
+                "dns_options",
+                differs(&self.tunnel_options.dns_options, &policy.dns_options),
+            ),
+            (
+                "show_beta_releases",
+                differs(&self.show_beta_releases, &policy.show_beta_releases),
+            ),
+        ];
+        fields
+            .iter()
+            .find(|(_, changed)| *changed)
+            .map(|(field, _)| *field)
+    }
+
+    /// Returns the locations in the relay and bridge constraints, including the entry and
+    /// intermediate locations, that are not in `relay_list`.
+    pub fn unknown_relay_locations(
+        &self,
+        relay_list: &RelayList,
+    ) -> Vec<&GeographicLocationConstraint> {
+        let mut location_constraints = vec![];
+        if let RelaySettings::Normal(constraints) = &self.relay_settings {
+            location_constraints.push(&constraints.location);
+            location_constraints.extend(&constraints.wireguard_constraints.entry_location);
+            location_constraints.extend(&constraints.wireguard_constraints.intermediate_locations);
+            location_constraints.extend(&constraints.openvpn_constraints.entry_location);
+        }
+        if let BridgeSettings::Normal(constraints) = &self.bridge_settings {
+            location_constraints.push(&constraints.location);
+        }
+
+        location_constraints
+            .into_iter()
+            .filter_map(|constraint| constraint.as_ref().option())
+            .flat_map(|constraint| constraint.include.iter().chain(constraint.exclude.iter()))
+            .filter(|location| !relay_list.contains_location(location))
+            .collect()
+    }
+
+    /// Returns the names of the top-level fields that differ between `self` and `other`.
+    pub fn changed_fields(&self, other: &Settings) -> Vec<String> {
+        let to_map = |settings: &Settings| match serde_json::to_value(settings) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        changed_keys(&to_map(self), &to_map(other))
+    }
+}
+
+/// Returns the keys whose values differ between `old` and `new`, including keys that are only in
+/// one of them, in sorted order.
+fn changed_keys(
+    old: &serde_json::Map<String, serde_json::Value>,
+    new: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<String> {
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| old.get(key.as_str()) != new.get(key.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Settings pinned by an administrator in a system-wide policy file. The settings that are set
+/// here override the user's settings and cannot be changed through the management interface.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub allow_lan: Option<bool>,
+    pub block_when_disconnected: Option<bool>,
+    pub auto_connect: Option<bool>,
+    pub bridge_state: Option<BridgeState>,
+    pub enable_ipv6: Option<bool>,
+    pub dns_options: Option<DnsOptions>,
+    pub show_beta_releases: Option<bool>,
+}
+
+impl Policy {
+    pub fn load_from_bytes(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(Error::ParseError)
+    }
+
+    /// Returns the names of the settings that are locked by the policy.
+    pub fn locked_fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("allow_lan", self.allow_lan.is_some()),
+            (
+                "block_when_disconnected",
+                self.block_when_disconnected.is_some(),
+            ),
+            ("auto_connect", self.auto_connect.is_some()),
+            ("bridge_state", self.bridge_state.is_some()),
+            ("enable_ipv6", self.enable_ipv6.is_some()),
+            ("dns_options", self.dns_options.is_some()),
+            ("show_beta_releases", self.show_beta_releases.is_some()),
+        ];
+        fields
+            .iter()
+            .filter(|(_, locked)| *locked)
+            .map(|(field, _)| *field)
+            .collect()
+    }
+
+    pub fn is_locked(&self, field: &str) -> bool {
+        self.locked_fields().contains(&field)
+    }
+}
+
+/// A named set of connection settings, stored in [`Settings`], that can be activated as a whole.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub relay_settings: RelaySettings,
+    pub bridge_settings: BridgeSettings,
+    pub bridge_state: BridgeState,
+    pub tunnel_options: TunnelOptions,
+    pub allow_lan: bool,
+}
+
+/// Rules deciding whether the tunnel should be connected or disconnected when the device joins
+/// a network. The rules are only evaluated when the current network changes, so the user can
+/// still connect or disconnect manually afterwards.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkRules {
+    /// Rules that are checked in order. The first one matching the current network is applied.
+    pub rules: Vec<NetworkRule>,
+    /// Action to take on networks that match none of the rules. If this is `None`, the target
+    /// state is left alone on such networks.
+    pub default_action: Option<NetworkAction>,
+}
+
+impl NetworkRules {
+    /// Returns the action to take on `network`, and the rule that selected it. `None` is
+    /// returned as the rule if the default action was selected.
+    pub fn evaluate(
+        &self,
+        network: &NetworkIdentity,
+    ) -> Option<(NetworkAction, Option<&NetworkRule>)> {
+        match self.rules.iter().find(|rule| rule.network.matches(network)) {
+            Some(rule) => Some((rule.action, Some(rule))),
+            None => self.default_action.map(|action| (action, None)),
+        }
+    }
+}
+
+/// Action to take when [`NetworkRule::network`] matches the current network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkRule {
+    pub network: NetworkMatch,
+    pub action: NetworkAction,
+}
+
+impl fmt::Display for NetworkRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {}", self.action, self.network)
+    }
+}
+
+/// Identifies a network by one of its properties.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkMatch {
+    /// The wireless network with this SSID.
+    Ssid(String),
+    /// Any network where the default gateway has this hardware address.
+    GatewayMac(MacAddress),
+}
+
+impl NetworkMatch {
+    pub fn matches(&self, network: &NetworkIdentity) -> bool {
+        match self {
+            NetworkMatch::Ssid(ssid) => network.ssid.as_ref() == Some(ssid),
+            NetworkMatch::GatewayMac(mac) => network.gateway_mac.as_ref() == Some(mac),
+        }
+    }
+}
+
+impl fmt::Display for NetworkMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkMatch::Ssid(ssid) => write!(f, "SSID \"{}\"", ssid),
+            NetworkMatch::GatewayMac(mac) => write!(f, "gateway {}", mac),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkAction {
+    Connect,
+    Disconnect,
+}
+
+impl fmt::Display for NetworkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkAction::Connect => "connect".fmt(f),
+            NetworkAction::Disconnect => "disconnect".fmt(f),
+        }
+    }
+}
+
+/// Emitted when the daemon has changed the target state because of [`NetworkRules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkRuleEvent {
+    /// The network that was joined.
+    pub network: NetworkIdentity,
+    pub action: NetworkAction,
+    /// The rule that matched the network, or `None` if the default action was used.
+    pub rule: Option<NetworkRule>,
+}
+
+impl fmt::Display for NetworkRuleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Some(rule) => write!(f, "Joined {}, applied rule \"{}\"", self.network, rule),
+            None => write!(
+                f,
+                "Joined {}, which matches no rule, applied default action \"{}\"",
+                self.network, self.action
+            ),
+        }
+    }
 }
 
 /// TunnelOptions holds configuration data that applies to all kinds of tunnels.
@@ -183,6 +535,7 @@ pub struct TunnelOptions {
 pub enum DnsState {
     Default,
     Custom,
+    Encrypted,
 }
 
 impl Default for DnsState {
@@ -202,6 +555,25 @@ pub struct DnsOptions {
     pub default_options: DefaultDnsOptions,
     #[cfg_attr(target_os = "android", jnix(map = "|opts| opts.addresses"))]
     pub custom_options: CustomDnsOptions,
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub encrypted_options: EncryptedDnsOptions,
+    /// Domains that are resolved using other resolvers than the ones selected by `state`.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub split_rules: Vec<SplitDnsRule>,
+    /// Lists of domains to block. Queries are filtered by a resolver on the loopback interface
+    /// run by the daemon if any of the lists are enabled.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub blocklists: Vec<DnsBlocklist>,
+}
+
+impl DnsOptions {
+    /// Returns whether any blocklist is enabled.
+    pub fn has_enabled_blocklists(&self) -> bool {
+        self.blocklists.iter().any(|blocklist| blocklist.enabled)
+    }
 }
 
 #[cfg(target_os = "android")]
@@ -229,6 +601,9 @@ impl From<AndroidDnsOptions> for DnsOptions {
             custom_options: CustomDnsOptions {
                 addresses: options.addresses,
             },
+            encrypted_options: EncryptedDnsOptions::default(),
+            split_rules: vec![],
+            blocklists: vec![],
         }
     }
 }
@@ -258,6 +633,59 @@ pub struct CustomDnsOptions {
     pub addresses: Vec<IpAddr>,
 }
 
+/// Encrypted DNS config. Queries are answered by a resolver on the loopback interface run by the
+/// daemon, which forwards them to the upstream servers over DNS-over-HTTPS or DNS-over-TLS.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct EncryptedDnsOptions {
+    /// Servers that are tried in order until one of them responds.
+    pub upstreams: Vec<EncryptedDnsUpstream>,
+}
+
+/// A DNS-over-HTTPS or DNS-over-TLS server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct EncryptedDnsUpstream {
+    pub protocol: EncryptedDnsProtocol,
+    /// Address of the server. It is given as an IP address, since the server is what resolves
+    /// host names in the first place.
+    pub address: SocketAddr,
+    /// Name that the certificate of the server must be valid for.
+    pub hostname: String,
+}
+
+impl fmt::Display for EncryptedDnsUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({})", self.protocol, self.hostname, self.address)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptedDnsProtocol {
+    /// DNS-over-HTTPS, as described in RFC 8484. Queries are posted to `/dns-query`.
+    Https,
+    /// DNS-over-TLS, as described in RFC 7858.
+    Tls,
+}
+
+impl EncryptedDnsProtocol {
+    /// Returns the port that servers listen on unless configured otherwise.
+    pub fn default_port(self) -> u16 {
+        match self {
+            EncryptedDnsProtocol::Https => 443,
+            EncryptedDnsProtocol::Tls => 853,
+        }
+    }
+}
+
+impl fmt::Display for EncryptedDnsProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptedDnsProtocol::Https => write!(f, "DNS-over-HTTPS"),
+            EncryptedDnsProtocol::Tls => write!(f, "DNS-over-TLS"),
+        }
+    }
+}
+
 impl Default for TunnelOptions {
     fn default() -> Self {
         TunnelOptions {
@@ -288,7 +716,17 @@ mod test {
                 "normal": {
                   "location": {
                     "only": {
-                      "country": "gb"
+                      "include": [
+                        {
+                          "country": "gb"
+                        }
+                      ],
+                      "exclude": [
+                        {
+                          "hostname": ["gb", "lon", "gb-lon-001"]
+                        }
+                      ],
+                      "exclude_providers": ["Provider"]
                     }
                   },
                   "tunnel_protocol": {
@@ -324,10 +762,188 @@ mod test {
                   "enable_ipv6": true
                 }
               },
-              "settings_version": 3,
+              "settings_version": 6,
               "show_beta_releases": false
         }"#;
 
         let _ = Settings::load_from_bytes(settings).unwrap();
     }
+    #[test]
+    fn test_network_rules() {
+        let home: MacAddress = "aa:bb:cc:dd:ee:ff".parse().unwrap();
+        let rules = NetworkRules {
+            rules: vec![
+                NetworkRule {
+                    network: NetworkMatch::Ssid("Home".to_owned()),
+                    action: NetworkAction::Disconnect,
+                },
+                NetworkRule {
+                    network: NetworkMatch::GatewayMac(home),
+                    action: NetworkAction::Disconnect,
+                },
+            ],
+            default_action: Some(NetworkAction::Connect),
+        };
+
+        let wireless = NetworkIdentity {
+            ssid: Some("Home".to_owned()),
+            gateway_mac: None,
+        };
+        assert_eq!(
+            rules.evaluate(&wireless),
+            Some((NetworkAction::Disconnect, Some(&rules.rules[0])))
+        );
+
+        let wired = NetworkIdentity {
+            ssid: None,
+            gateway_mac: Some(home),
+        };
+        assert_eq!(
+            rules.evaluate(&wired),
+            Some((NetworkAction::Disconnect, Some(&rules.rules[1])))
+        );
+
+        let unknown = NetworkIdentity {
+            ssid: Some("Cafe".to_owned()),
+            gateway_mac: Some("00:11:22:33:44:55".parse().unwrap()),
+        };
+        assert_eq!(
+            rules.evaluate(&unknown),
+            Some((NetworkAction::Connect, None))
+        );
+
+        let no_default = NetworkRules {
+            default_action: None,
+            ..rules
+        };
+        assert_eq!(no_default.evaluate(&unknown), None);
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::load_from_bytes(
+            br#"{
+                "allow_lan": false,
+                "block_when_disconnected": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.locked_fields(),
+            vec!["allow_lan", "block_when_disconnected"]
+        );
+        assert!(policy.is_locked("allow_lan"));
+        assert!(!policy.is_locked("auto_connect"));
+        assert!(Policy::load_from_bytes(br#"{"allow_lann": true}"#).is_err());
+
+        let mut settings = Settings::default();
+        settings.allow_lan = true;
+        settings.policy = policy;
+        assert!(settings.enforce_policy());
+        assert!(!settings.allow_lan);
+        assert!(settings.block_when_disconnected);
+        assert!(!settings.enforce_policy());
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let settings = Settings::default();
+        assert!(settings.changed_fields(&settings).is_empty());
+
+        let mut other = settings.clone();
+        other.allow_lan = !settings.allow_lan;
+        other.tunnel_options.wireguard.options.mtu = Some(1280);
+        assert_eq!(
+            settings.changed_fields(&other),
+            vec!["allow_lan".to_owned(), "tunnel_options".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_unknown_relay_locations() {
+        use crate::relay_list::{Relay, RelayListCity, RelayListCountry};
+
+        let relay_list = RelayList {
+            etag: None,
+            countries: vec![RelayListCountry {
+                name: "Sweden".to_owned(),
+                code: "se".to_owned(),
+                cities: vec![RelayListCity {
+                    name: "Gothenburg".to_owned(),
+                    code: "got".to_owned(),
+                    latitude: 57.7,
+                    longitude: 11.97,
+                    relays: vec![Relay {
+                        hostname: "se1-wireguard".to_owned(),
+                        ipv4_addr_in: "185.213.154.66".parse().unwrap(),
+                        ipv6_addr_in: None,
+                        include_in_country: true,
+                        active: true,
+                        owned: true,
+                        provider: "31173".to_owned(),
+                        weight: 1,
+                        tunnels: Default::default(),
+                        bridges: Default::default(),
+                        location: None,
+                    }],
+                }],
+            }],
+        };
+        let location = |location: GeographicLocationConstraint| {
+            Constraint::Only(LocationConstraint::from(location))
+        };
+        let country = |code: &str| GeographicLocationConstraint::Country(code.to_owned());
+        let hostname = |hostname: &str| {
+            GeographicLocationConstraint::Hostname(
+                "se".to_owned(),
+                "got".to_owned(),
+                hostname.to_owned(),
+            )
+        };
+
+        let mut settings = Settings::default();
+        assert!(settings.unknown_relay_locations(&relay_list).is_empty());
+
+        let mut constraints = RelayConstraints {
+            location: location(hostname("se1-wireguard")),
+            ..RelayConstraints::default()
+        };
+        constraints.wireguard_constraints.entry_location = Some(location(country("de")));
+        constraints.wireguard_constraints.intermediate_locations =
+            vec![location(hostname("se2-wireguard")), Constraint::Any];
+        constraints.openvpn_constraints.entry_location = Some(location(country("se")));
+        settings.relay_settings = RelaySettings::Normal(constraints);
+        settings.bridge_settings = BridgeSettings::Normal(BridgeConstraints {
+            location: Constraint::Only(LocationConstraint {
+                include: vec![country("se")],
+                exclude: vec![country("no")],
+                ..LocationConstraint::default()
+            }),
+            ..BridgeConstraints::default()
+        });
+
+        assert_eq!(
+            settings.unknown_relay_locations(&relay_list),
+            vec![&country("de"), &hostname("se2-wireguard"), &country("no")]
+        );
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let to_map = |value: serde_json::Value| match value {
+            serde_json::Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        let old = to_map(serde_json::json!({ "kept": 1, "changed": 1, "removed": 1 }));
+        let new = to_map(serde_json::json!({ "kept": 1, "changed": 2, "added": 1 }));
+        assert_eq!(
+            changed_keys(&old, &new),
+            vec!["added", "changed", "removed"]
+        );
+        assert_eq!(
+            changed_keys(&new, &old),
+            vec!["added", "changed", "removed"]
+        );
+        assert!(changed_keys(&old, &old).is_empty());
+    }
 }
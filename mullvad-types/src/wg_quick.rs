@@ -0,0 +1,468 @@
+//! Reading and writing of WireGuard configurations in the INI format used by `wg-quick`.
+
+use crate::{ConnectionConfig, CustomTunnelEndpoint};
+use ipnetwork::IpNetwork;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use talpid_types::net::{wireguard, TransportProtocol};
+
+#[derive(err_derive::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error(display = "Line {}: Expected a section header or 'Key = Value'", _0)]
+    InvalidLine(usize),
+
+    #[error(display = "Line {}: Unknown section [{}]", _0, _1)]
+    UnknownSection(usize, String),
+
+    #[error(display = "Line {}: {} is not in a section", _0, _1)]
+    KeyOutsideSection(usize, String),
+
+    #[error(display = "Line {}: Unknown key {}", _0, _1)]
+    UnknownKey(usize, String),
+
+    #[error(display = "Line {}: Invalid value for {}", _0, _1)]
+    InvalidValue(usize, &'static str),
+
+    #[error(display = "The [{}] section is missing {}", _0, _1)]
+    MissingKey(&'static str, &'static str),
+
+    #[error(display = "The configuration has no [Peer] section")]
+    NoPeer,
+
+    #[error(display = "Only configurations with a single peer are supported")]
+    MultiplePeers,
+
+    #[error(display = "Configurations with an exit peer cannot be represented")]
+    ExitPeer,
+}
+
+/// Keys that `wg` or `wg-quick` accept, but that have no equivalent in the tunnel configuration.
+/// They are skipped when reading a file.
+const IGNORED_INTERFACE_KEYS: &[&str] = &[
+    "ListenPort",
+    "FwMark",
+    "Table",
+    "PreUp",
+    "PostUp",
+    "PreDown",
+    "PostDown",
+    "SaveConfig",
+];
+const IGNORED_PEER_KEYS: &[&str] = &["PersistentKeepalive"];
+
+/// A WireGuard configuration with a single peer, as read by `wg-quick`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgQuickConfig {
+    pub private_key: wireguard::PrivateKey,
+    /// Addresses of the interface, along with their prefix length.
+    pub addresses: Vec<IpNetwork>,
+    /// DNS servers. Search domains are not kept.
+    pub dns: Vec<IpAddr>,
+    pub mtu: Option<u16>,
+    pub peer_public_key: wireguard::PublicKey,
+    pub peer_psk: Option<wireguard::PresharedKey>,
+    pub allowed_ips: Vec<IpNetwork>,
+    /// Host name or IP address of the peer.
+    pub endpoint_host: String,
+    pub endpoint_port: u16,
+    /// Keys that were skipped when the configuration was read, since they have no equivalent in
+    /// the tunnel configuration.
+    pub ignored_keys: Vec<String>,
+}
+
+impl WgQuickConfig {
+    /// Returns a custom tunnel endpoint that connects to the peer of this configuration.
+    pub fn to_custom_tunnel_endpoint(
+        &self,
+        protocol: TransportProtocol,
+        ipv4_gateway: Ipv4Addr,
+        ipv6_gateway: Option<Ipv6Addr>,
+    ) -> CustomTunnelEndpoint {
+        let connection = wireguard::ConnectionConfig {
+            tunnel: wireguard::TunnelConfig {
+                private_key: self.private_key.clone(),
+                addresses: self.addresses.iter().map(IpNetwork::ip).collect(),
+            },
+            peer: wireguard::PeerConfig {
+                public_key: self.peer_public_key.clone(),
+                allowed_ips: self.allowed_ips.clone(),
+                endpoint: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), self.endpoint_port),
+                protocol,
+                psk: self.peer_psk.clone(),
+            },
+            next_peers: vec![],
+            ipv4_gateway,
+            ipv6_gateway,
+        };
+        CustomTunnelEndpoint::new(
+            self.endpoint_host.clone(),
+            ConnectionConfig::Wireguard(connection),
+        )
+    }
+
+    /// Returns the configuration of the tunnel described by `parameters`, using the given DNS
+    /// servers. Fails if the tunnel goes through more than one peer, since `wg-quick` cannot set
+    /// up such tunnels.
+    pub fn from_tunnel_parameters(
+        parameters: &wireguard::TunnelParameters,
+        dns: Vec<IpAddr>,
+    ) -> Result<Self, Error> {
+        let connection = &parameters.connection;
+        if !connection.next_peers.is_empty() {
+            return Err(Error::ExitPeer);
+        }
+        Ok(WgQuickConfig {
+            private_key: connection.tunnel.private_key.clone(),
+            addresses: connection
+                .tunnel
+                .addresses
+                .iter()
+                .map(|address| IpNetwork::from(*address))
+                .collect(),
+            dns,
+            mtu: parameters.options.mtu,
+            peer_public_key: connection.peer.public_key.clone(),
+            peer_psk: connection.peer.psk.clone(),
+            allowed_ips: connection.peer.allowed_ips.clone(),
+            endpoint_host: connection.peer.endpoint.ip().to_string(),
+            endpoint_port: connection.peer.endpoint.port(),
+            ignored_keys: vec![],
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Interface,
+    Peer,
+}
+
+impl FromStr for WgQuickConfig {
+    type Err = Error;
+
+    fn from_str(config: &str) -> Result<Self, Error> {
+        let mut section = None;
+        let mut peers = 0;
+
+        let mut private_key = None;
+        let mut addresses = vec![];
+        let mut dns = vec![];
+        let mut mtu = None;
+        let mut peer_public_key = None;
+        let mut peer_psk = None;
+        let mut allowed_ips = vec![];
+        let mut endpoint = None;
+        let mut ignored_keys = vec![];
+
+        for (index, line) in config.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim();
+                section = if name.eq_ignore_ascii_case("Interface") {
+                    Some(Section::Interface)
+                } else if name.eq_ignore_ascii_case("Peer") {
+                    peers += 1;
+                    if peers > 1 {
+                        return Err(Error::MultiplePeers);
+                    }
+                    Some(Section::Peer)
+                } else {
+                    return Err(Error::UnknownSection(line_number, name.to_owned()));
+                };
+                continue;
+            }
+
+            let equals = line.find('=').ok_or(Error::InvalidLine(line_number))?;
+            let key = line[..equals].trim();
+            let value = line[equals + 1..].trim();
+            let section =
+                section.ok_or_else(|| Error::KeyOutsideSection(line_number, key.to_owned()))?;
+            let is_key = |name: &str| key.eq_ignore_ascii_case(name);
+
+            match section {
+                Section::Interface if is_key("PrivateKey") => {
+                    private_key = Some(wireguard::PrivateKey::from(
+                        parse_key(value).ok_or(Error::InvalidValue(line_number, "PrivateKey"))?,
+                    ));
+                }
+                Section::Interface if is_key("Address") => {
+                    addresses.extend(parse_list::<IpNetwork>(value, line_number, "Address")?);
+                }
+                Section::Interface if is_key("DNS") => {
+                    dns.extend(
+                        split_list(value).filter_map(|server| server.parse::<IpAddr>().ok()),
+                    );
+                }
+                Section::Interface if is_key("MTU") => {
+                    mtu = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Error::InvalidValue(line_number, "MTU"))?,
+                    );
+                }
+                Section::Interface
+                    if IGNORED_INTERFACE_KEYS.iter().any(|ignored| is_key(ignored)) =>
+                {
+                    ignored_keys.push(key.to_owned());
+                }
+                Section::Peer if is_key("PublicKey") => {
+                    peer_public_key = Some(wireguard::PublicKey::from(
+                        parse_key(value).ok_or(Error::InvalidValue(line_number, "PublicKey"))?,
+                    ));
+                }
+                Section::Peer if is_key("AllowedIPs") => {
+                    allowed_ips.extend(parse_list::<IpNetwork>(value, line_number, "AllowedIPs")?);
+                }
+                Section::Peer if is_key("Endpoint") => {
+                    endpoint = Some(
+                        parse_endpoint(value)
+                            .ok_or(Error::InvalidValue(line_number, "Endpoint"))?,
+                    );
+                }
+                Section::Peer if is_key("PresharedKey") => {
+                    peer_psk = Some(wireguard::PresharedKey::from(
+                        parse_key(value).ok_or(Error::InvalidValue(line_number, "PresharedKey"))?,
+                    ));
+                }
+                Section::Peer if IGNORED_PEER_KEYS.iter().any(|ignored| is_key(ignored)) => {
+                    ignored_keys.push(key.to_owned());
+                }
+                _ => return Err(Error::UnknownKey(line_number, key.to_owned())),
+            }
+        }
+
+        if peers == 0 {
+            return Err(Error::NoPeer);
+        }
+        let (endpoint_host, endpoint_port) =
+            endpoint.ok_or(Error::MissingKey("Peer", "Endpoint"))?;
+        Ok(WgQuickConfig {
+            private_key: private_key.ok_or(Error::MissingKey("Interface", "PrivateKey"))?,
+            addresses,
+            dns,
+            mtu,
+            peer_public_key: peer_public_key.ok_or(Error::MissingKey("Peer", "PublicKey"))?,
+            peer_psk,
+            allowed_ips,
+            endpoint_host,
+            endpoint_port,
+            ignored_keys,
+        })
+    }
+}
+
+impl fmt::Display for WgQuickConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Interface]")?;
+        writeln!(f, "PrivateKey = {}", self.private_key.to_base64())?;
+        if !self.addresses.is_empty() {
+            writeln!(f, "Address = {}", join(&self.addresses))?;
+        }
+        if !self.dns.is_empty() {
+            writeln!(f, "DNS = {}", join(&self.dns))?;
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(f, "MTU = {}", mtu)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "[Peer]")?;
+        writeln!(f, "PublicKey = {}", self.peer_public_key.to_base64())?;
+        if let Some(psk) = &self.peer_psk {
+            writeln!(f, "PresharedKey = {}", psk.to_base64())?;
+        }
+        if !self.allowed_ips.is_empty() {
+            writeln!(f, "AllowedIPs = {}", join(&self.allowed_ips))?;
+        }
+        if self.endpoint_host.contains(':') {
+            writeln!(
+                f,
+                "Endpoint = [{}]:{}",
+                self.endpoint_host, self.endpoint_port
+            )
+        } else {
+            writeln!(
+                f,
+                "Endpoint = {}:{}",
+                self.endpoint_host, self.endpoint_port
+            )
+        }
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_list<T: FromStr>(
+    value: &str,
+    line_number: usize,
+    key: &'static str,
+) -> Result<Vec<T>, Error> {
+    split_list(value)
+        .map(|item| {
+            item.parse()
+                .map_err(|_| Error::InvalidValue(line_number, key))
+        })
+        .collect()
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_key(value: &str) -> Option<[u8; 32]> {
+    let bytes = base64::decode(value).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Some(key)
+}
+
+/// Parses `host:port`, where the host is a host name, an IPv4 address or an IPv6 address in
+/// brackets.
+fn parse_endpoint(value: &str) -> Option<(String, u16)> {
+    let colon = value.rfind(':')?;
+    let port = value[colon + 1..].parse().ok()?;
+    let host = &value[..colon];
+    let host = if host.starts_with('[') && host.ends_with(']') {
+        host[1..host.len() - 1]
+            .parse::<Ipv6Addr>()
+            .ok()?
+            .to_string()
+    } else if host.is_empty() || host.contains(':') {
+        return None;
+    } else {
+        host.to_owned()
+    };
+    Some((host, port))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Example configuration
+[Interface]
+PrivateKey = kHnA1PFbwgCQdZ/lgYyC4wAFJGCUexvgvXtAsKqxgFk=
+Address = 10.66.4.18/32,fc00:bbbb:bbbb:bb01::3:411/128
+DNS = 10.64.0.1, example.com
+MTU = 1380
+PostUp = iptables -I OUTPUT ! -o %i -j REJECT
+
+[Peer]
+PublicKey = m4jnogFbACz7LByjo++8z5+1WV0BuR1T7E1OWA+n8h0=
+PresharedKey = LVOBNwHc6lIpwcj3qSgqg8gHfd+GNJnBqy/jmMe3D8o=
+AllowedIPs = 0.0.0.0/0
+AllowedIPs = ::0/0
+Endpoint = se-got-wg-001.example.net:51820 # Gothenburg
+PersistentKeepalive = 25
+";
+
+    #[test]
+    fn test_parse() {
+        let config: WgQuickConfig = CONFIG.parse().unwrap();
+        assert_eq!(
+            config.private_key.to_base64(),
+            "kHnA1PFbwgCQdZ/lgYyC4wAFJGCUexvgvXtAsKqxgFk="
+        );
+        assert_eq!(
+            config.addresses,
+            vec![
+                "10.66.4.18/32".parse::<IpNetwork>().unwrap(),
+                "fc00:bbbb:bbbb:bb01::3:411/128".parse().unwrap(),
+            ]
+        );
+        assert_eq!(config.dns, vec![IpAddr::from([10, 64, 0, 1])]);
+        assert_eq!(config.mtu, Some(1380));
+        assert_eq!(
+            config.peer_public_key.to_base64(),
+            "m4jnogFbACz7LByjo++8z5+1WV0BuR1T7E1OWA+n8h0="
+        );
+        assert_eq!(
+            config.peer_psk.map(|psk| psk.to_base64()),
+            Some("LVOBNwHc6lIpwcj3qSgqg8gHfd+GNJnBqy/jmMe3D8o=".to_owned())
+        );
+        assert_eq!(
+            config.allowed_ips,
+            vec![
+                "0.0.0.0/0".parse::<IpNetwork>().unwrap(),
+                "::0/0".parse().unwrap(),
+            ]
+        );
+        assert_eq!(config.endpoint_host, "se-got-wg-001.example.net");
+        assert_eq!(config.endpoint_port, 51820);
+        assert_eq!(config.ignored_keys, vec!["PostUp", "PersistentKeepalive"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config: WgQuickConfig = CONFIG.parse().unwrap();
+        let mut expected = config.clone();
+        expected.ignored_keys.clear();
+        assert_eq!(config.to_string().parse(), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("1.2.3.4:51820"),
+            Some(("1.2.3.4".to_owned(), 51820))
+        );
+        assert_eq!(
+            parse_endpoint("[2001:db8::1]:53"),
+            Some(("2001:db8::1".to_owned(), 53))
+        );
+        assert_eq!(parse_endpoint("2001:db8::1:53"), None);
+        assert_eq!(parse_endpoint("example.net"), None);
+        assert_eq!(parse_endpoint(":51820"), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let without_peer =
+            "[Interface]\nPrivateKey = kHnA1PFbwgCQdZ/lgYyC4wAFJGCUexvgvXtAsKqxgFk=\n";
+        assert_eq!(without_peer.parse::<WgQuickConfig>(), Err(Error::NoPeer));
+
+        let two_peers = format!("{}\n[Peer]\n", CONFIG);
+        assert_eq!(
+            two_peers.parse::<WgQuickConfig>(),
+            Err(Error::MultiplePeers)
+        );
+
+        let invalid_psk = format!("{}PresharedKey = abc\n", CONFIG);
+        assert_eq!(
+            invalid_psk.parse::<WgQuickConfig>(),
+            Err(Error::InvalidValue(16, "PresharedKey"))
+        );
+
+        let unknown = CONFIG.replace("MTU", "Mtu2");
+        assert_eq!(
+            unknown.parse::<WgQuickConfig>(),
+            Err(Error::UnknownKey(6, "Mtu2".to_owned()))
+        );
+
+        let invalid_address = CONFIG.replace("10.66.4.18/32", "10.66.4.18/33");
+        assert_eq!(
+            invalid_address.parse::<WgQuickConfig>(),
+            Err(Error::InvalidValue(4, "Address"))
+        );
+    }
+}
@@ -93,7 +93,12 @@ fn declare_libs_dir(base: &str) {
 }
 
 fn generate_grpc_code() {
-    const PROTO_FILE: &str = "../talpid-openvpn-plugin/proto/openvpn_plugin.proto";
-    tonic_build::compile_protos(PROTO_FILE).unwrap();
-    println!("cargo:rerun-if-changed={}", PROTO_FILE);
+    const PROTO_FILES: &[&str] = &[
+        "../talpid-openvpn-plugin/proto/openvpn_plugin.proto",
+        "proto/tunnel_config.proto",
+    ];
+    for proto_file in PROTO_FILES {
+        tonic_build::compile_protos(proto_file).unwrap();
+        println!("cargo:rerun-if-changed={}", proto_file);
+    }
 }
@@ -9,6 +9,7 @@ use self::{
     systemd_resolved::SystemdResolved,
 };
 use std::{env, fmt, net::IpAddr, path::Path};
+use talpid_types::net::SplitDnsRule;
 
 
 const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
@@ -37,6 +38,13 @@ pub enum Error {
     /// No suitable DNS monitor implementation detected
     #[error(display = "No suitable DNS monitor implementation detected")]
     NoDnsMonitor,
+
+    /// The DNS monitor implementation in use cannot resolve some domains using other resolvers
+    #[error(
+        display = "Split DNS rules require systemd-resolved, but DNS is managed via {}",
+        _0
+    )]
+    SplitDnsNotSupported(String),
 }
 
 pub struct DnsMonitor {
@@ -55,12 +63,7 @@ impl super::DnsMonitorT for DnsMonitor {
     }
 
     fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<()> {
-        self.reset()?;
-        // Creating a new DNS monitor for each set, in case the system changed how it manages DNS.
-        let mut inner = DnsMonitorHolder::new()?;
-        inner.set(&self.handle, interface, servers)?;
-        self.inner = Some(inner);
-        Ok(())
+        self.set_with_split_rules(interface, servers, &[])
     }
 
     fn reset(&mut self) -> Result<()> {
@@ -71,6 +74,22 @@ impl super::DnsMonitorT for DnsMonitor {
     }
 }
 
+impl DnsMonitor {
+    pub fn set_with_split_rules(
+        &mut self,
+        interface: &str,
+        servers: &[IpAddr],
+        split_rules: &[SplitDnsRule],
+    ) -> Result<()> {
+        super::DnsMonitorT::reset(self)?;
+        // Creating a new DNS monitor for each set, in case the system changed how it manages DNS.
+        let mut inner = DnsMonitorHolder::new()?;
+        inner.set(&self.handle, interface, servers, split_rules)?;
+        self.inner = Some(inner);
+        Ok(())
+    }
+}
+
 pub enum DnsMonitorHolder {
     SystemdResolved(SystemdResolved),
     NetworkManager(NetworkManager),
@@ -130,15 +149,21 @@ impl DnsMonitorHolder {
         handle: &tokio::runtime::Handle,
         interface: &str,
         servers: &[IpAddr],
+        split_rules: &[SplitDnsRule],
     ) -> Result<()> {
         use self::DnsMonitorHolder::*;
+        match self {
+            SystemdResolved(..) => (),
+            _ if split_rules.is_empty() => (),
+            _ => return Err(Error::SplitDnsNotSupported(self.to_string())),
+        }
         match self {
             Resolvconf(ref mut resolvconf) => resolvconf.set_dns(interface, servers)?,
             StaticResolvConf(ref mut static_resolv_conf) => {
                 static_resolv_conf.set_dns(servers.to_vec())?
             }
             SystemdResolved(ref mut systemd_resolved) => {
-                handle.block_on(systemd_resolved.set_dns(interface, &servers))?
+                handle.block_on(systemd_resolved.set_dns(interface, &servers, split_rules))?
             }
             NetworkManager(ref mut network_manager) => {
                 network_manager.set_dns(interface, servers)?
@@ -161,6 +186,35 @@ impl DnsMonitorHolder {
     }
 }
 
+/// Returns an error unless split DNS rules can be applied, which requires DNS to be managed via
+/// systemd-resolved. Unlike [`DnsMonitorHolder::new`], this does not touch the DNS config.
+pub fn check_split_dns_support() -> Result<()> {
+    let manager = match env::var_os("TALPID_DNS_MODULE")
+        .as_ref()
+        .and_then(|value| value.to_str())
+    {
+        Some("static-file") => "/etc/resolv.conf",
+        Some("resolvconf") => "resolvconf",
+        Some("network-manager") => "network manager",
+        Some("systemd") => {
+            SystemdResolved::new()?;
+            return Ok(());
+        }
+        Some(_) | None => {
+            if SystemdResolved::new().is_ok() {
+                return Ok(());
+            } else if NetworkManager::new().is_ok() {
+                "network manager"
+            } else if Resolvconf::new().is_ok() {
+                "resolvconf"
+            } else {
+                "/etc/resolv.conf"
+            }
+        }
+    };
+    Err(Error::SplitDnsNotSupported(manager.to_owned()))
+}
+
 /// Returns true if DnsMonitor will use NetworkManager to manage DNS.
 pub fn will_use_nm() -> bool {
     crate::dns::imp::SystemdResolved::new().is_err()
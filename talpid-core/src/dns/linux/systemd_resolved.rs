@@ -10,7 +10,7 @@ use std::{
     thread,
 };
 use talpid_dbus::systemd_resolved::{AsyncHandle, DnsState, SystemdResolved as DbusInterface};
-use talpid_types::ErrorExt;
+use talpid_types::{net::SplitDnsRule, ErrorExt};
 
 pub(crate) use talpid_dbus::systemd_resolved::Error as SystemdDbusError;
 
@@ -26,14 +26,24 @@ pub enum Error {
 
     #[error(display = "Failed to spawn DNS interface monitor")]
     SpawnInterfaceMonitor(#[error(source)] super::routing::Error),
+
+    #[error(
+        display = "Resolver {} in a split DNS rule is not reachable outside the tunnel",
+        _0
+    )]
+    SplitDnsResolverInTunnel(IpAddr),
 }
 
 use super::routing::{DnsConfig, DnsRouteMonitor};
 
+type Domains = Vec<(String, bool)>;
+
 pub struct SystemdResolved {
     pub dbus_interface: AsyncHandle,
     current_config: Arc<Mutex<BTreeMap<u32, DnsConfig>>>,
     initial_states: Arc<Mutex<BTreeMap<u32, DnsState>>>,
+    /// Domains of the interfaces that routing domains have been added to, before they were added.
+    initial_domains: Arc<Mutex<BTreeMap<u32, Domains>>>,
     tunnel_index: u32,
     route_monitor: Option<(DnsRouteMonitor, tokio::task::JoinHandle<()>)>,
     watcher: Option<(thread::JoinHandle<()>, Arc<AtomicBool>)>,
@@ -48,6 +58,7 @@ impl SystemdResolved {
             dbus_interface,
             current_config: Arc::new(Mutex::new(BTreeMap::new())),
             initial_states: Arc::new(Mutex::new(BTreeMap::new())),
+            initial_domains: Arc::new(Mutex::new(BTreeMap::new())),
             tunnel_index: 0,
             route_monitor: None,
             watcher: None,
@@ -56,11 +67,21 @@ impl SystemdResolved {
         Ok(systemd_resolved)
     }
 
-    pub async fn set_dns(&mut self, interface_name: &str, servers: &[IpAddr]) -> Result<()> {
+    pub async fn set_dns(
+        &mut self,
+        interface_name: &str,
+        servers: &[IpAddr],
+        split_rules: &[SplitDnsRule],
+    ) -> Result<()> {
+        let split_dns = Arc::new(SplitDns {
+            servers: servers.to_vec(),
+            rules: split_rules.to_vec(),
+        });
         let (update_tx, mut update_rx) = mpsc::unbounded();
-        let (monitor, initial_config) = super::routing::spawn_monitor(servers.to_vec(), update_tx)
-            .await
-            .map_err(Error::SpawnInterfaceMonitor)?;
+        let (monitor, initial_config) =
+            super::routing::spawn_monitor(split_dns.destinations(), update_tx)
+                .await
+                .map_err(Error::SpawnInterfaceMonitor)?;
 
         let tunnel_index = iface_index(interface_name)?;
         self.tunnel_index = tunnel_index;
@@ -89,23 +110,21 @@ impl SystemdResolved {
         }
 
         if last_result.is_ok() {
-            if has_only_tunnel_config(&initial_config, tunnel_index) {
-                if let Err(error) = self
-                    .dbus_interface
-                    .set_domains(tunnel_index, &[(".", true)])
-                    .await
-                {
-                    last_result = Err(Error::SystemdResolvedError(error));
-                }
-            } else {
-                if let Err(error) = self.dbus_interface.set_domains(tunnel_index, &[]).await {
-                    last_result = Err(Error::SystemdResolvedError(error));
-                }
-            }
+            last_result = split_dns.check_resolvers(&initial_config, tunnel_index);
+        }
+        if last_result.is_ok() {
+            last_result = split_dns
+                .apply_domains(
+                    &self.dbus_interface,
+                    &initial_config,
+                    tunnel_index,
+                    &self.initial_domains,
+                )
+                .await;
         }
 
         if let Err(error) = last_result {
-            let _ = self.reset();
+            let _ = self.reset().await;
             return Err(error);
         }
 
@@ -117,12 +136,14 @@ impl SystemdResolved {
 
         self.watcher = Some(self.spawn_watcher_thread(
             tunnel_index,
+            split_dns.clone(),
             self.current_config.clone(),
             ignore_config_changes.clone(),
         ));
 
         let dbus_interface = DbusInterface::new_connection()?.async_handle();
         let initial_states = self.initial_states.clone();
+        let initial_domains = self.initial_domains.clone();
         let current_config = self.current_config.clone();
         let join_handle = tokio::spawn(async move {
             while let Some(new_config) = update_rx.next().await {
@@ -181,18 +202,16 @@ impl SystemdResolved {
                     }
                 }
 
-                let tunnel_domains = if has_only_tunnel_config(&new_config, tunnel_index) {
-                    &[(".", true)][..]
-                } else {
-                    &[][..]
-                };
-                if let Err(error) = dbus_interface
-                    .set_domains(tunnel_index, tunnel_domains)
+                if let Err(error) = split_dns.check_resolvers(&new_config, tunnel_index) {
+                    log::error!("{}", error.display_chain());
+                }
+                if let Err(error) = split_dns
+                    .apply_domains(&dbus_interface, &new_config, tunnel_index, &initial_domains)
                     .await
                 {
                     log::error!(
-                        "Failed to set DNS domains on tunnel interface\n{}",
-                        error.display_chain()
+                        "{}",
+                        error.display_chain_with_msg("Failed to set DNS domains")
                     );
                 }
 
@@ -212,10 +231,12 @@ impl SystemdResolved {
     fn spawn_watcher_thread(
         &mut self,
         tunnel_index: u32,
+        split_dns: Arc<SplitDns>,
         current_config: Arc<Mutex<BTreeMap<u32, DnsConfig>>>,
         disable_watcher: Arc<AtomicBool>,
     ) -> (thread::JoinHandle<()>, Arc<AtomicBool>) {
         let dbus_interface = self.dbus_interface.handle().clone();
+        let initial_domains = self.initial_domains.clone();
         let should_shutdown = Arc::new(AtomicBool::new(false));
         let watch_shutdown = should_shutdown.clone();
         let callback_shutdown = should_shutdown.clone();
@@ -246,13 +267,17 @@ impl SystemdResolved {
                         }
                     }
                     if anything_changed {
-                        let result = if has_only_tunnel_config(&configs, tunnel_index) {
-                            dbus_interface.set_domains(tunnel_index, &[(".", true)])
-                        } else {
-                            dbus_interface.set_domains(tunnel_index, &[])
-                        };
-                        if let Err(err) = result {
-                            log::error!("Failed to re-apply DNS domains - {}", err);
+                        let link_domains = split_dns.link_domains(
+                            &configs,
+                            tunnel_index,
+                            &initial_domains.lock().unwrap(),
+                        );
+                        for (iface, domains) in &link_domains {
+                            if let Err(err) =
+                                dbus_interface.set_domains(*iface, &domain_refs(domains))
+                            {
+                                log::error!("Failed to re-apply DNS domains - {}", err);
+                            }
                         }
                     }
                 },
@@ -294,12 +319,255 @@ impl SystemdResolved {
         }
         initial_states.clear();
 
+        let initial_domains = std::mem::take(&mut *self.initial_domains.lock().unwrap());
+        for (iface, domains) in &initial_domains {
+            if let Err(err) = self
+                .dbus_interface
+                .set_domains(*iface, &domain_refs(domains))
+                .await
+            {
+                log::error!(
+                    "{}",
+                    err.display_chain_with_msg("Failed to revert interface domains")
+                );
+            }
+        }
+
         self.current_config.lock().unwrap().clear();
 
         Ok(())
     }
 }
 
-fn has_only_tunnel_config(configs: &BTreeMap<u32, DnsConfig>, tunnel_index: u32) -> bool {
-    configs.len() == 1 && configs.contains_key(&tunnel_index)
+/// The resolvers to use for all domains, and the rules for domains that use other resolvers.
+struct SplitDns {
+    servers: Vec<IpAddr>,
+    rules: Vec<SplitDnsRule>,
+}
+
+impl SplitDns {
+    /// Returns every resolver, without duplicates.
+    fn destinations(&self) -> Vec<IpAddr> {
+        let mut destinations = self.servers.clone();
+        for resolver in self.rules.iter().flat_map(|rule| &rule.resolvers) {
+            if !destinations.contains(resolver) {
+                destinations.push(*resolver);
+            }
+        }
+        destinations
+    }
+
+    /// Fails if a resolver in a split DNS rule is reached through the tunnel interface, since
+    /// systemd-resolved cannot route domains to some of the resolvers of an interface.
+    fn check_resolvers(&self, configs: &BTreeMap<u32, DnsConfig>, tunnel_index: u32) -> Result<()> {
+        let tunnel_resolvers = match configs.get(&tunnel_index) {
+            Some(config) => &config.resolvers,
+            None => return Ok(()),
+        };
+        match self
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.resolvers)
+            .find(|resolver| tunnel_resolvers.contains(resolver))
+        {
+            Some(resolver) => Err(Error::SplitDnsResolverInTunnel(*resolver)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the interfaces, other than the tunnel interface, that the resolvers of a split DNS
+    /// rule are reached through.
+    fn routed_interfaces(&self, configs: &BTreeMap<u32, DnsConfig>, tunnel_index: u32) -> Vec<u32> {
+        configs
+            .iter()
+            .filter(|(iface, config)| **iface != tunnel_index && !self.rules_for(config).is_empty())
+            .map(|(iface, _)| *iface)
+            .collect()
+    }
+
+    fn rules_for(&self, config: &DnsConfig) -> Vec<&SplitDnsRule> {
+        self.rules
+            .iter()
+            .filter(|rule| {
+                rule.resolvers
+                    .iter()
+                    .any(|resolver| config.resolvers.contains(resolver))
+            })
+            .collect()
+    }
+
+    /// Returns the domains to set on every interface that is managed. The tunnel interface is
+    /// the default route for DNS queries, unless some of the resolvers for all domains are
+    /// reached through another interface. The domains of split DNS rules are added as routing
+    /// domains to the interfaces that their resolvers are reached through, after the domains
+    /// those interfaces had initially.
+    fn link_domains(
+        &self,
+        configs: &BTreeMap<u32, DnsConfig>,
+        tunnel_index: u32,
+        initial_domains: &BTreeMap<u32, Domains>,
+    ) -> BTreeMap<u32, Domains> {
+        let mut link_domains = BTreeMap::new();
+
+        let servers_only_in_tunnel = configs.contains_key(&tunnel_index)
+            && configs
+                .iter()
+                .filter(|(iface, _)| **iface != tunnel_index)
+                .all(|(_, config)| {
+                    !config
+                        .resolvers
+                        .iter()
+                        .any(|resolver| self.servers.contains(resolver))
+                });
+        let tunnel_domains = if servers_only_in_tunnel {
+            vec![(".".to_owned(), true)]
+        } else {
+            vec![]
+        };
+        link_domains.insert(tunnel_index, tunnel_domains);
+
+        for iface in self.routed_interfaces(configs, tunnel_index) {
+            let mut domains = initial_domains.get(&iface).cloned().unwrap_or_default();
+            for rule in self.rules_for(&configs[&iface]) {
+                domains.push((rule.domain.clone(), true));
+            }
+            link_domains.insert(iface, domains);
+        }
+
+        link_domains
+    }
+
+    /// Sets the domains of all managed interfaces, and reverts the domains of interfaces that no
+    /// longer need routing domains.
+    async fn apply_domains(
+        &self,
+        dbus_interface: &AsyncHandle,
+        configs: &BTreeMap<u32, DnsConfig>,
+        tunnel_index: u32,
+        initial_domains: &Mutex<BTreeMap<u32, Domains>>,
+    ) -> Result<()> {
+        let routed_interfaces = self.routed_interfaces(configs, tunnel_index);
+        for iface in &routed_interfaces {
+            if !initial_domains.lock().unwrap().contains_key(iface) {
+                let domains = dbus_interface.get_domains(*iface).await?;
+                initial_domains.lock().unwrap().insert(*iface, domains);
+            }
+        }
+
+        let unused_domains: Vec<(u32, Domains)> = {
+            let mut initial_domains = initial_domains.lock().unwrap();
+            let unused_interfaces: Vec<u32> = initial_domains
+                .keys()
+                .filter(|iface| !routed_interfaces.contains(iface))
+                .cloned()
+                .collect();
+            unused_interfaces
+                .into_iter()
+                .filter_map(|iface| initial_domains.remove_entry(&iface))
+                .collect()
+        };
+        for (iface, domains) in &unused_domains {
+            log::debug!("Reverting DNS domains on interface {}", iface);
+            dbus_interface
+                .set_domains(*iface, &domain_refs(domains))
+                .await?;
+        }
+
+        let link_domains = {
+            let initial_domains = initial_domains.lock().unwrap();
+            self.link_domains(configs, tunnel_index, &initial_domains)
+        };
+        for (iface, domains) in &link_domains {
+            dbus_interface
+                .set_domains(*iface, &domain_refs(domains))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn domain_refs(domains: &[(String, bool)]) -> Vec<(&str, bool)> {
+    domains
+        .iter()
+        .map(|(domain, routing_only)| (domain.as_str(), *routing_only))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TUNNEL_INDEX: u32 = 10;
+    const LAN_INDEX: u32 = 2;
+
+    fn config(interface: u32, resolvers: &[&str]) -> (u32, DnsConfig) {
+        (
+            interface,
+            DnsConfig {
+                interface,
+                resolvers: resolvers.iter().map(|ip| ip.parse().unwrap()).collect(),
+            },
+        )
+    }
+
+    fn split_dns() -> SplitDns {
+        SplitDns {
+            servers: vec!["10.64.0.1".parse().unwrap()],
+            rules: vec![SplitDnsRule {
+                domain: "corp.example".to_owned(),
+                resolvers: vec!["192.168.1.53".parse().unwrap()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_split_dns_link_domains() {
+        let configs = vec![
+            config(TUNNEL_INDEX, &["10.64.0.1"]),
+            config(LAN_INDEX, &["192.168.1.53"]),
+        ]
+        .into_iter()
+        .collect();
+        let initial_domains = vec![(LAN_INDEX, vec![("home.lan".to_owned(), false)])]
+            .into_iter()
+            .collect();
+
+        let link_domains = split_dns().link_domains(&configs, TUNNEL_INDEX, &initial_domains);
+
+        assert_eq!(link_domains.len(), 2);
+        assert_eq!(link_domains[&TUNNEL_INDEX], vec![(".".to_owned(), true)]);
+        assert_eq!(
+            link_domains[&LAN_INDEX],
+            vec![
+                ("home.lan".to_owned(), false),
+                ("corp.example".to_owned(), true)
+            ]
+        );
+        assert!(split_dns().check_resolvers(&configs, TUNNEL_INDEX).is_ok());
+    }
+
+    #[test]
+    fn test_split_dns_resolver_in_tunnel() {
+        let configs = vec![config(TUNNEL_INDEX, &["10.64.0.1", "192.168.1.53"])]
+            .into_iter()
+            .collect();
+
+        assert!(split_dns().check_resolvers(&configs, TUNNEL_INDEX).is_err());
+    }
+
+    #[test]
+    fn test_local_servers_without_split_dns() {
+        let split_dns = SplitDns {
+            servers: vec!["192.168.1.1".parse().unwrap()],
+            rules: vec![],
+        };
+        let configs = vec![config(LAN_INDEX, &["192.168.1.1"])]
+            .into_iter()
+            .collect();
+
+        let link_domains = split_dns.link_domains(&configs, TUNNEL_INDEX, &BTreeMap::new());
+
+        assert_eq!(link_domains.len(), 1);
+        assert!(link_domains[&TUNNEL_INDEX].is_empty());
+    }
 }
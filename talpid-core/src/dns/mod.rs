@@ -1,4 +1,5 @@
 use std::{net::IpAddr, path::Path};
+use talpid_types::net::SplitDnsRule;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -9,7 +10,7 @@ mod imp;
 mod imp;
 
 #[cfg(target_os = "linux")]
-pub use imp::will_use_nm;
+pub use imp::{check_split_dns_support, will_use_nm};
 
 #[cfg(windows)]
 #[path = "windows/mod.rs"]
@@ -34,8 +35,16 @@ impl DnsMonitor {
         })
     }
 
-    /// Set DNS to the given servers. And start monitoring the system for changes.
-    pub fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Error> {
+    /// Set DNS to the given servers, except for the domains in `split_rules`, which are resolved
+    /// using the resolvers in the rules. And start monitoring the system for changes.
+    ///
+    /// Split DNS rules are only supported on Linux, and ignored on other platforms.
+    pub fn set(
+        &mut self,
+        interface: &str,
+        servers: &[IpAddr],
+        split_rules: &[SplitDnsRule],
+    ) -> Result<(), Error> {
         log::info!(
             "Setting DNS servers to {}",
             servers
@@ -44,7 +53,23 @@ impl DnsMonitor {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        self.inner.set(interface, servers)
+        for rule in split_rules {
+            log::info!("Resolving {}", rule);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.inner
+                .set_with_split_rules(interface, servers, split_rules)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            if !split_rules.is_empty() {
+                log::warn!(
+                    "Ignoring split DNS rules since they are not supported on this platform"
+                );
+            }
+            self.inner.set(interface, servers)
+        }
     }
 
     /// Reset system DNS settings to what it was before being set by this instance.
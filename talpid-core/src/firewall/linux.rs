@@ -598,10 +598,16 @@ impl<'a> PolicyBatch<'a> {
                 tunnel,
                 allow_lan,
                 dns_servers,
+                split_dns_resolvers,
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint);
                 self.add_allow_dns_rules(tunnel, &dns_servers, TransportProtocol::Udp)?;
                 self.add_allow_dns_rules(tunnel, &dns_servers, TransportProtocol::Tcp)?;
+                for resolver in split_dns_resolvers {
+                    for protocol in &[TransportProtocol::Udp, TransportProtocol::Tcp] {
+                        self.add_allow_local_dns_rule(&tunnel.interface, *protocol, *resolver)?;
+                    }
+                }
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
                 self.add_drop_dns_rule();
@@ -740,6 +746,7 @@ impl<'a> PolicyBatch<'a> {
         Ok(())
     }
 
+    /// Allows DNS requests to `host` outside the tunnel.
     fn add_allow_local_dns_rule(
         &mut self,
         tunnel_interface: &str,
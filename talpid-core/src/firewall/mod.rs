@@ -126,6 +126,10 @@ pub enum FirewallPolicy {
         /// Servers that are allowed to respond to DNS requests.
         #[cfg(not(target_os = "android"))]
         dns_servers: Vec<IpAddr>,
+        /// Resolvers of split DNS rules, which are allowed to respond to DNS requests outside the
+        /// tunnel.
+        #[cfg(target_os = "linux")]
+        split_dns_resolvers: Vec<IpAddr>,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -1,4 +1,4 @@
-use crate::tunnel_state_machine::TunnelCommand;
+use crate::{mpsc::Sender, tunnel_state_machine::TunnelCommand};
 use futures::{
     channel::{mpsc::UnboundedSender, oneshot},
     FutureExt, StreamExt, TryStream, TryStreamExt,
@@ -10,13 +10,21 @@ use netlink_packet_route::{
 use rtnetlink::{
     constants::{
         RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_IPV6_ROUTE, RTMGRP_LINK,
-        RTMGRP_NOTIFY,
+        RTMGRP_NEIGH, RTMGRP_NOTIFY,
     },
     sys::SocketAddr,
     Handle, IpVersion,
 };
-use std::{io, net::Ipv4Addr, sync::Weak};
-use talpid_types::ErrorExt;
+use std::{
+    fs, io,
+    net::{IpAddr, Ipv4Addr},
+    sync::Weak,
+};
+use talpid_dbus::network_manager::NetworkManager;
+use talpid_types::{
+    net::{MacAddress, NetworkIdentity},
+    ErrorExt,
+};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -66,6 +74,8 @@ pub struct MonitorHandle {
 // work.
 const PUBLIC_INTERNET_ADDRESS: Ipv4Addr = Ipv4Addr::new(193, 138, 218, 78);
 
+const ARP_TABLE_PATH: &str = "/proc/net/arp";
+
 impl MonitorHandle {
     pub async fn is_offline(&mut self) -> bool {
         match public_ip_unreachable(&self.handle).await {
@@ -81,7 +91,10 @@ impl MonitorHandle {
     }
 }
 
-pub async fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Result<MonitorHandle> {
+pub async fn spawn_monitor(
+    sender: Weak<UnboundedSender<TunnelCommand>>,
+    network_identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
+) -> Result<MonitorHandle> {
     let (mut connection, handle, mut messages) =
         rtnetlink::new_connection().map_err(Error::NetlinkConnectionError)?;
 
@@ -90,7 +103,10 @@ pub async fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Resu
         | RTMGRP_IPV6_IFADDR
         | RTMGRP_IPV6_ROUTE
         | RTMGRP_LINK
-        | RTMGRP_NOTIFY;
+        | RTMGRP_NOTIFY
+        // The hardware address of the gateway is usually resolved after the route through it
+        // has been added, so the network identity is also updated when a neighbour changes.
+        | RTMGRP_NEIGH;
     let addr = SocketAddr::new(0, mgroup_flags);
 
     connection
@@ -108,6 +124,8 @@ pub async fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Resu
         }
     });
     let mut is_offline = public_ip_unreachable(&handle).await?;
+    let mut network_identity = current_network_identity(&handle).await;
+    let _ = network_identity_listener.send(network_identity.clone());
 
     let monitor_handle = MonitorHandle {
         handle: handle.clone(),
@@ -116,20 +134,34 @@ pub async fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Resu
 
 
     tokio::spawn(async move {
-        while let Some(_new_message) = messages.next().await {
+        while let Some((new_message, _)) = messages.next().await {
             match sender.upgrade() {
                 Some(sender) => {
-                    let new_offline_state =
-                        public_ip_unreachable(&handle).await.unwrap_or_else(|err| {
-                            log::error!(
-                                "{}",
-                                err.display_chain_with_msg("Failed to infer offline state")
-                            );
-                            false
-                        });
-                    if new_offline_state != is_offline {
-                        is_offline = new_offline_state;
-                        let _ = sender.unbounded_send(TunnelCommand::IsOffline(is_offline));
+                    let new_network_identity = if is_neighbour_message(&new_message) {
+                        // Neighbours do not affect the offline state or the SSID
+                        NetworkIdentity {
+                            gateway_mac: current_gateway_mac(&handle).await,
+                            ..network_identity.clone()
+                        }
+                    } else {
+                        let new_offline_state =
+                            public_ip_unreachable(&handle).await.unwrap_or_else(|err| {
+                                log::error!(
+                                    "{}",
+                                    err.display_chain_with_msg("Failed to infer offline state")
+                                );
+                                false
+                            });
+                        if new_offline_state != is_offline {
+                            is_offline = new_offline_state;
+                            let _ = sender.unbounded_send(TunnelCommand::IsOffline(is_offline));
+                        }
+
+                        current_network_identity(&handle).await
+                    };
+                    if new_network_identity != network_identity {
+                        network_identity = new_network_identity;
+                        let _ = network_identity_listener.send(network_identity.clone());
                     }
                 }
                 None => return,
@@ -142,6 +174,21 @@ pub async fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Resu
 
 
 async fn public_ip_unreachable(handle: &Handle) -> Result<bool> {
+    let mut stream = execute_route_get_request(handle.clone(), public_route_request(handle));
+    match stream.try_next().await {
+        // Presance of any route implies connectivity, even if it's a loopback route
+        Ok(Some(_)) => Ok(false),
+        Ok(None) => Err(Error::NoRouteError),
+        // ENETUNREACH implies that there exists no route that'd reach our random API address,
+        // as such, the host is assumed to be offline
+        Err(rtnetlink::Error::NetlinkError(nl_err)) if nl_err.code == -libc::ENETUNREACH => {
+            Ok(true)
+        }
+        Err(err) => Err(Error::GetRouteError(failure::Fail::compat(err))),
+    }
+}
+
+fn public_route_request(handle: &Handle) -> RouteMessage {
     let mut request = handle.route().get(IpVersion::V4);
     let message = request.message_mut();
     message
@@ -152,17 +199,88 @@ async fn public_ip_unreachable(handle: &Handle) -> Result<bool> {
     ));
     message.header.destination_prefix_length = 32;
     message.header.flags = RouteFlags::RTM_F_LOOKUP_TABLE;
-    let mut stream = execute_route_get_request(handle.clone(), message.clone());
-    match stream.try_next().await {
-        // Presance of any route implies connectivity, even if it's a loopback route
-        Ok(Some(_)) => Ok(false),
-        Ok(None) => Err(Error::NoRouteError),
-        // ENETUNREACH implies that there exists no route that'd reach our random API address,
-        // as such, the host is assumed to be offline
-        Err(rtnetlink::Error::NetlinkError(nl_err)) if nl_err.code == -libc::ENETUNREACH => {
-            Ok(true)
+    message.clone()
+}
+
+/// Identifies the current network by the SSID reported by NetworkManager and the hardware
+/// address of the gateway that traffic outside the tunnel is routed through.
+async fn current_network_identity(handle: &Handle) -> NetworkIdentity {
+    let gateway_mac = current_gateway_mac(handle).await;
+    let ssid = tokio::task::spawn_blocking(primary_ssid)
+        .await
+        .unwrap_or(None);
+
+    NetworkIdentity { ssid, gateway_mac }
+}
+
+async fn current_gateway_mac(handle: &Handle) -> Option<MacAddress> {
+    match default_gateway(handle).await {
+        Some(IpAddr::V4(gateway)) => gateway_mac_address(gateway),
+        _ => None,
+    }
+}
+
+fn is_neighbour_message(message: &NetlinkMessage<RtnlMessage>) -> bool {
+    match &message.payload {
+        NetlinkPayload::InnerMessage(message) => {
+            message.is_new_neighbour() || message.is_del_neighbour()
+        }
+        _ => false,
+    }
+}
+
+async fn default_gateway(handle: &Handle) -> Option<IpAddr> {
+    let mut stream = execute_route_get_request(handle.clone(), public_route_request(handle));
+    let route = stream.try_next().await.ok()??;
+    route.nlas.iter().find_map(|nla| match nla {
+        RouteNla::Gateway(gateway) if gateway.len() == 4 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(gateway);
+            Some(IpAddr::from(octets))
+        }
+        _ => None,
+    })
+}
+
+/// Looks up the hardware address of `gateway` in the kernel's ARP table.
+fn gateway_mac_address(gateway: Ipv4Addr) -> Option<MacAddress> {
+    let arp_table = match fs::read_to_string(ARP_TABLE_PATH) {
+        Ok(arp_table) => arp_table,
+        Err(error) => {
+            log::debug!(
+                "{}",
+                error.display_chain_with_msg("Failed to read the ARP table")
+            );
+            return None;
+        }
+    };
+    // Columns: IP address, HW type, Flags, HW address, Mask, Device
+    arp_table
+        .lines()
+        .skip(1)
+        .find_map(|line| {
+            let mut columns = line.split_whitespace();
+            let address: Ipv4Addr = columns.next()?.parse().ok()?;
+            if address != gateway {
+                return None;
+            }
+            columns.nth(2)?.parse().ok()
+        })
+        .filter(|mac: &MacAddress| mac.0 != [0u8; 6])
+}
+
+fn primary_ssid() -> Option<String> {
+    let result =
+        NetworkManager::new().and_then(|network_manager| network_manager.get_primary_ssid());
+    match result {
+        Ok(ssid) => ssid,
+        Err(error) => {
+            log::trace!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain SSID from NetworkManager")
+            );
+            None
         }
-        Err(err) => Err(Error::GetRouteError(failure::Fail::compat(err))),
     }
 }
 
@@ -202,6 +320,21 @@ mod test {
         sys::SocketAddr,
     };
 
+    #[test]
+    fn test_neighbour_messages() {
+        use netlink_packet_route::NeighbourMessage;
+
+        let new_neighbour =
+            NetlinkMessage::from(RtnlMessage::NewNeighbour(NeighbourMessage::default()));
+        let del_neighbour =
+            NetlinkMessage::from(RtnlMessage::DelNeighbour(NeighbourMessage::default()));
+        let new_route = NetlinkMessage::from(RtnlMessage::NewRoute(RouteMessage::default()));
+
+        assert!(is_neighbour_message(&new_neighbour));
+        assert!(is_neighbour_message(&del_neighbour));
+        assert!(!is_neighbour_message(&new_route));
+    }
+
     #[test]
     fn test_route_table_query() {
         let mut runtime = tokio::runtime::Runtime::new().expect("failed to initialize runtime");
@@ -1,8 +1,9 @@
-use crate::tunnel_state_machine::TunnelCommand;
+use crate::{mpsc::Sender, tunnel_state_machine::TunnelCommand};
 use futures::channel::mpsc::UnboundedSender;
 use std::sync::Weak;
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+use talpid_types::net::NetworkIdentity;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -40,14 +41,23 @@ impl MonitorHandle {
     }
 }
 
+/// Spawns the offline monitor. Changes in connectivity are sent to `sender`. Where the platform
+/// supports it, the identity of the current network is reported to `network_identity_listener`
+/// whenever it changes.
 pub async fn spawn_monitor(
     sender: Weak<UnboundedSender<TunnelCommand>>,
+    network_identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
     #[cfg(target_os = "android")] android_context: AndroidContext,
 ) -> Result<MonitorHandle, Error> {
+    #[cfg(not(target_os = "linux"))]
+    let _ = network_identity_listener;
+
     let monitor = if !*FORCE_DISABLE_OFFLINE_MONITOR {
         Some(
             imp::spawn_monitor(
                 sender,
+                #[cfg(target_os = "linux")]
+                network_identity_listener,
                 #[cfg(target_os = "android")]
                 android_context,
             )
@@ -8,6 +8,7 @@ use shell_escape;
 use std::{
     ffi::{OsStr, OsString},
     fmt, io,
+    net::SocketAddr,
     path::{Path, PathBuf},
 };
 use talpid_types::net;
@@ -85,6 +86,7 @@ pub struct OpenVpnCommand {
     iproute_bin: Option<OsString>,
     plugin: Option<(PathBuf, Vec<String>)>,
     log: Option<PathBuf>,
+    management_client: Option<SocketAddr>,
     tunnel_options: net::openvpn::TunnelOptions,
     proxy_settings: Option<net::openvpn::ProxySettings>,
     #[cfg(windows)]
@@ -109,6 +111,7 @@ impl OpenVpnCommand {
             iproute_bin: None,
             plugin: None,
             log: None,
+            management_client: None,
             tunnel_options: net::openvpn::TunnelOptions::default(),
             proxy_settings: None,
             #[cfg(windows)]
@@ -175,6 +178,12 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets the address of a management interface client that OpenVPN connects to on startup.
+    pub fn management_client(&mut self, address: SocketAddr) -> &mut Self {
+        self.management_client = Some(address);
+        self
+    }
+
     /// Sets extra options
     pub fn tunnel_options(&mut self, tunnel_options: &net::openvpn::TunnelOptions) -> &mut Self {
         self.tunnel_options = tunnel_options.clone();
@@ -257,6 +266,13 @@ impl OpenVpnCommand {
             args.push(OsString::from(path))
         }
 
+        if let Some(address) = self.management_client {
+            args.push(OsString::from("--management"));
+            args.push(OsString::from(address.ip().to_string()));
+            args.push(OsString::from(address.port().to_string()));
+            args.push(OsString::from("--management-client"));
+        }
+
         if let Some(mssfix) = self.tunnel_options.mssfix {
             args.push(OsString::from("--mssfix"));
             args.push(OsString::from(mssfix.to_string()));
@@ -489,4 +505,23 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("123")));
         assert!(testee_args.contains(&OsString::from("cde")));
     }
+
+    #[test]
+    fn passes_management_client_address() {
+        let testee_args = OpenVpnCommand::new("")
+            .management_client("127.0.0.1:7505".parse().unwrap())
+            .get_arguments();
+        let position = testee_args
+            .iter()
+            .position(|arg| arg == "--management")
+            .expect("missing --management argument");
+        assert_eq!(
+            &testee_args[position + 1..position + 4],
+            &[
+                OsString::from("127.0.0.1"),
+                OsString::from("7505"),
+                OsString::from("--management-client")
+            ]
+        );
+    }
 }
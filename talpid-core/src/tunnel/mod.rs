@@ -6,6 +6,7 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::openvpn as openvpn_types;
@@ -94,6 +95,17 @@ pub struct TunnelMetadata {
     pub ipv6_gateway: Option<Ipv6Addr>,
 }
 
+/// Traffic statistics of a VPN tunnel, counted since the tunnel was started.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TunnelStats {
+    /// Bytes received from the VPN server.
+    pub rx_bytes: u64,
+    /// Bytes sent to the VPN server.
+    pub tx_bytes: u64,
+    /// Time of the latest completed handshake, if it is known.
+    pub last_handshake: Option<SystemTime>,
+}
+
 #[cfg(not(target_os = "android"))]
 impl TunnelEvent {
     /// Converts an `openvpn_plugin::EventType` to a `TunnelEvent`.
@@ -306,6 +318,11 @@ impl TunnelMonitor {
         self.monitor.close_handle()
     }
 
+    /// Creates a handle for reading the traffic statistics of the tunnel.
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.monitor.stats_handle()
+    }
+
     /// Consumes the monitor and blocks until the tunnel exits or there is an error.
     pub fn wait(self) -> Result<()> {
         self.monitor.wait().map_err(Error::from)
@@ -336,6 +353,27 @@ impl CloseHandle {
     }
 }
 
+/// A handle for reading the traffic statistics of a `TunnelMonitor`
+#[derive(Clone)]
+pub enum StatsHandle {
+    #[cfg(not(target_os = "android"))]
+    /// OpenVpn stats handle
+    OpenVpn(openvpn::OpenVpnStatsHandle),
+    /// Wireguard stats handle
+    Wireguard(wireguard::StatsHandle),
+}
+
+impl StatsHandle {
+    /// Returns the current traffic statistics of the tunnel, if they are available.
+    pub fn stats(&self) -> Option<TunnelStats> {
+        match self {
+            #[cfg(not(target_os = "android"))]
+            StatsHandle::OpenVpn(handle) => handle.stats(),
+            StatsHandle::Wireguard(handle) => handle.stats(),
+        }
+    }
+}
+
 enum InternalTunnelMonitor {
     #[cfg(not(target_os = "android"))]
     OpenVpn(openvpn::OpenVpnMonitor),
@@ -351,6 +389,14 @@ impl InternalTunnelMonitor {
         }
     }
 
+    fn stats_handle(&self) -> StatsHandle {
+        match self {
+            #[cfg(not(target_os = "android"))]
+            InternalTunnelMonitor::OpenVpn(tun) => StatsHandle::OpenVpn(tun.stats_handle()),
+            InternalTunnelMonitor::Wireguard(tun) => StatsHandle::Wireguard(tun.stats_handle()),
+        }
+    }
+
     fn wait(self) -> Result<()> {
         match self {
             #[cfg(not(target_os = "android"))]
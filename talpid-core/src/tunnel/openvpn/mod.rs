@@ -1,4 +1,4 @@
-use super::TunnelEvent;
+use super::{TunnelEvent, TunnelStats};
 #[cfg(target_os = "linux")]
 use crate::routing::RequiredRoute;
 use crate::{
@@ -18,6 +18,7 @@ use std::{
     collections::HashMap,
     fs,
     io::{self, Write},
+    net::SocketAddr,
     path::{Path, PathBuf},
     process::ExitStatus,
     sync::{
@@ -119,6 +120,10 @@ pub enum Error {
     #[error(display = "Unable to start the event dispatcher IPC server")]
     EventDispatcherError(#[error(source)] event_server::Error),
 
+    /// Unable to listen for the management interface connection from OpenVPN.
+    #[error(display = "Unable to listen for the OpenVPN management interface")]
+    ManagementListenerError(#[error(source)] io::Error),
+
     /// The OpenVPN event dispatcher exited unexpectedly
     #[error(display = "The OpenVPN event dispatcher exited unexpectedly")]
     EventDispatcherExited,
@@ -275,6 +280,8 @@ pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     _user_pass_file: mktemp::TempFile,
     /// Keep the 'TempFile' for the proxy user-pass file in the struct, so it's removed on drop.
     _proxy_auth_file: Option<mktemp::TempFile>,
+    /// The latest traffic statistics reported by OpenVPN.
+    stats: Arc<Mutex<Option<TunnelStats>>>,
 
     runtime: tokio::runtime::Runtime,
     event_server_abort_tx: triggered::Trigger,
@@ -620,8 +627,18 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         #[cfg(windows)]
         let wintun = Arc::new(wintun);
 
+        let management_listener = runtime
+            .block_on(management::bind())
+            .map_err(Error::ManagementListenerError)?;
+        let management_address = management_listener
+            .local_addr()
+            .map_err(Error::ManagementListenerError)?;
+        let stats = Arc::new(Mutex::new(None));
+        runtime.spawn(management::read_stats(management_listener, stats.clone()));
+
         cmd.plugin(plugin_path, vec![ipc_path])
-            .log(log_path.as_ref().map(|p| p.as_path()));
+            .log(log_path.as_ref().map(|p| p.as_path()))
+            .management_client(management_address);
         let (spawn_task, abort_spawn) = futures::future::abortable(Self::prepare_process(
             cmd,
             #[cfg(windows)]
@@ -637,6 +654,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
             closed: Arc::new(AtomicBool::new(false)),
             _user_pass_file: user_pass_file,
             _proxy_auth_file: proxy_auth_file,
+            stats,
 
             runtime,
             event_server_abort_tx,
@@ -669,6 +687,13 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         }
     }
 
+    /// Returns a handle for reading the traffic statistics of the tunnel.
+    pub fn stats_handle(&self) -> OpenVpnStatsHandle {
+        OpenVpnStatsHandle {
+            stats: self.stats.clone(),
+        }
+    }
+
     /// Consumes the monitor and waits for both proxy and tunnel, as applicable.
     pub fn wait(mut self) -> Result<()> {
         if let Some(mut proxy_monitor) = self.proxy_monitor.take() {
@@ -948,6 +973,20 @@ impl<H: ProcessHandle> OpenVpnCloseHandle<H> {
     }
 }
 
+/// A handle to an `OpenVpnMonitor` for reading traffic statistics.
+#[derive(Debug, Clone)]
+pub struct OpenVpnStatsHandle {
+    stats: Arc<Mutex<Option<TunnelStats>>>,
+}
+
+impl OpenVpnStatsHandle {
+    /// Returns the latest statistics reported by OpenVPN. Returns `None` if OpenVPN has not
+    /// reported any statistics yet.
+    pub fn stats(&self) -> Option<TunnelStats> {
+        *self.stats.lock().unwrap()
+    }
+}
+
 /// Internal enum to differentiate between if the child process or the event dispatcher died first.
 #[derive(Debug)]
 enum WaitResult {
@@ -967,6 +1006,9 @@ pub trait OpenVpnBuilder {
     /// Set the OpenVPN log file path to use.
     fn log(&mut self, log_path: Option<impl AsRef<Path>>) -> &mut Self;
 
+    /// Set the address of the management interface client that OpenVPN connects to.
+    fn management_client(&mut self, address: SocketAddr) -> &mut Self;
+
     /// Spawn the subprocess and return a handle.
     fn start(&self) -> io::Result<Self::ProcessHandle>;
 }
@@ -995,6 +1037,10 @@ impl OpenVpnBuilder for OpenVpnCommand {
         }
     }
 
+    fn management_client(&mut self, address: SocketAddr) -> &mut Self {
+        self.management_client(address)
+    }
+
     fn start(&self) -> io::Result<OpenVpnProcHandle> {
         OpenVpnProcHandle::new(self.build())
     }
@@ -1010,6 +1056,75 @@ impl ProcessHandle for OpenVpnProcHandle {
     }
 }
 
+/// Reads traffic statistics from the OpenVPN management interface. OpenVPN connects to a socket
+/// owned by talpid, so the interface is not exposed to other processes. Once connected, OpenVPN
+/// is told to send a `BYTECOUNT` notification with the transport byte counters every second.
+mod management {
+    use super::TunnelStats;
+    use std::{
+        io,
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+    };
+    use talpid_types::ErrorExt;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// How often OpenVPN sends the byte counters, in seconds.
+    const BYTECOUNT_INTERVAL_SECS: u32 = 1;
+
+    /// Binds the socket that OpenVPN connects to.
+    pub async fn bind() -> io::Result<TcpListener> {
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await
+    }
+
+    /// Accepts the connection from OpenVPN and stores the byte counters it reports in `stats`,
+    /// until OpenVPN disconnects.
+    pub async fn read_stats(listener: TcpListener, stats: Arc<Mutex<Option<TunnelStats>>>) {
+        if let Err(error) = read_stats_inner(listener, stats).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to read OpenVPN traffic statistics")
+            );
+        }
+    }
+
+    async fn read_stats_inner(
+        mut listener: TcpListener,
+        stats: Arc<Mutex<Option<TunnelStats>>>,
+    ) -> io::Result<()> {
+        let (stream, _) = listener.accept().await?;
+        // Only OpenVPN is allowed to connect
+        drop(listener);
+
+        let (reader, mut writer) = tokio::io::split(stream);
+        writer
+            .write_all(format!("bytecount {}\n", BYTECOUNT_INTERVAL_SECS).as_bytes())
+            .await?;
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Some(new_stats) = parse_bytecount(&line) {
+                *stats.lock().unwrap() = Some(new_stats);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a notification of the form `>BYTECOUNT:{bytes in},{bytes out}`.
+    pub fn parse_bytecount(line: &str) -> Option<TunnelStats> {
+        let mut counters = line.trim_end().strip_prefix(">BYTECOUNT:")?.splitn(2, ',');
+        let rx_bytes = counters.next()?.parse().ok()?;
+        let tx_bytes = counters.next()?.parse().ok()?;
+        Some(TunnelStats {
+            rx_bytes,
+            tx_bytes,
+            last_handshake: None,
+        })
+    }
+}
 
 mod event_server {
     use futures::stream::TryStreamExt;
@@ -1289,6 +1404,10 @@ mod tests {
             self
         }
 
+        fn management_client(&mut self, _address: SocketAddr) -> &mut Self {
+            self
+        }
+
         fn start(&self) -> io::Result<Self::ProcessHandle> {
             self.process_handle
                 .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to start"))
@@ -1434,4 +1553,51 @@ mod tests {
             _ => panic!("Wrong error"),
         }
     }
+
+    #[test]
+    fn parses_bytecount() {
+        let stats = management::parse_bytecount(">BYTECOUNT:6021,5430\r").unwrap();
+        assert_eq!(stats.rx_bytes, 6021);
+        assert_eq!(stats.tx_bytes, 5430);
+        assert_eq!(stats.last_handshake, None);
+
+        assert_eq!(
+            management::parse_bytecount(">INFO:OpenVPN Management Interface"),
+            None
+        );
+        assert_eq!(management::parse_bytecount(">BYTECOUNT:6021"), None);
+    }
+
+    #[test]
+    fn reads_bytecount_notifications() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let stats = Arc::new(std::sync::Mutex::new(None));
+        runtime.block_on(async {
+            let listener = management::bind().await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let reader = tokio::spawn(management::read_stats(listener, stats.clone()));
+
+            let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            let (read_half, mut write_half) = tokio::io::split(stream);
+            let mut command = String::new();
+            BufReader::new(read_half)
+                .read_line(&mut command)
+                .await
+                .unwrap();
+            assert_eq!(command, "bytecount 1\n");
+
+            write_half
+                .write_all(b">INFO:OpenVPN Management Interface\r\n>BYTECOUNT:100,20\r\n")
+                .await
+                .unwrap();
+            drop(write_half);
+            reader.await.unwrap();
+        });
+
+        let stats = stats.lock().unwrap().unwrap();
+        assert_eq!((stats.rx_bytes, stats.tx_bytes), (100, 20));
+    }
+
 }
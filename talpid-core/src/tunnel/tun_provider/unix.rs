@@ -14,6 +14,10 @@ pub enum Error {
     #[error(display = "Failed to set tunnel IP address: {}", _0)]
     SetIpAddr(IpAddr, #[cause] network_interface::Error),
 
+    /// Failure to set the MTU of the tunnel device.
+    #[error(display = "Failed to set the tunnel MTU")]
+    SetMtu(#[cause] network_interface::Error),
+
     /// Failure to set the tunnel device as up.
     #[error(display = "Failed to set the tunnel device as up")]
     SetUp(#[cause] network_interface::Error),
@@ -36,6 +40,7 @@ impl UnixTunProvider {
                 .map_err(|cause| Error::SetIpAddr(*ip, cause))?;
         }
 
+        tunnel_device.set_mtu(config.mtu).map_err(Error::SetMtu)?;
         tunnel_device.set_up(true).map_err(Error::SetUp)?;
 
         Ok(UnixTun(tunnel_device))
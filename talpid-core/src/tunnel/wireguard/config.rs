@@ -3,7 +3,7 @@ use std::{
     ffi::CString,
     net::{Ipv4Addr, Ipv6Addr},
 };
-use talpid_types::net::{wireguard, GenericTunnelOptions};
+use talpid_types::net::{obfuscation::ObfuscatorConfig, wireguard, GenericTunnelOptions};
 
 /// Config required to set up a single WireGuard tunnel
 pub struct Config {
@@ -23,6 +23,10 @@ pub struct Config {
     /// Enable IPv6 routing rules
     #[cfg(target_os = "linux")]
     pub enable_ipv6: bool,
+    /// Obfuscation to wrap the traffic to the first peer in
+    pub obfuscator: Option<ObfuscatorConfig>,
+    /// Negotiate a preshared key with the relay once the tunnel is up
+    pub quantum_resistant: bool,
 }
 
 const DEFAULT_MTU: u16 = 1380;
@@ -47,17 +51,16 @@ impl Config {
     /// Constructs a Config from parameters
     pub fn from_parameters(params: &wireguard::TunnelParameters) -> Result<Config, Error> {
         let tunnel = params.connection.tunnel.clone();
-        let mut peers = vec![params.connection.peer.clone()];
-        if let Some(exit_peer) = &params.connection.exit_peer {
-            peers.push(exit_peer.clone());
-        }
-        Self::new(
+        let peers = params.connection.peers().cloned().collect();
+        let mut config = Self::new(
             tunnel,
             peers,
             &params.connection,
             &params.options,
             &params.generic_options,
-        )
+        )?;
+        config.obfuscator = params.obfuscation.clone();
+        Ok(config)
     }
 
     /// Constructs a new Config struct
@@ -109,17 +112,30 @@ impl Config {
             fwmark: crate::linux::TUNNEL_FW_MARK,
             #[cfg(target_os = "linux")]
             enable_ipv6: generic_options.enable_ipv6,
+            obfuscator: None,
+            quantum_resistant: wg_options.quantum_resistant,
         })
     }
 
     /// Returns a CString with the appropriate config for WireGuard-go
     // TODO: Consider outputting both overriding and additive configs
     pub fn to_userspace_format(&self) -> CString {
+        self.userspace_format(true)
+    }
+
+    /// Returns a CString with the config to apply to a running WireGuard-go tunnel. The listen
+    /// port is left out, so that the sockets of the tunnel are not rebound.
+    pub fn to_userspace_update_format(&self) -> CString {
+        self.userspace_format(false)
+    }
+
+    fn userspace_format(&self, set_listen_port: bool) -> CString {
         // the order of insertion matters, public key entry denotes a new peer entry
         let mut wg_conf = WgConfigBuffer::new();
-        wg_conf
-            .add("private_key", self.tunnel.private_key.to_bytes().as_ref())
-            .add("listen_port", "0");
+        wg_conf.add("private_key", self.tunnel.private_key.to_bytes().as_ref());
+        if set_listen_port {
+            wg_conf.add("listen_port", "0");
+        }
 
         #[cfg(target_os = "linux")]
         wg_conf.add("fwmark", self.fwmark.to_string().as_str());
@@ -131,6 +147,9 @@ impl Config {
                 .add("public_key", peer.public_key.as_bytes().as_ref())
                 .add("endpoint", peer.endpoint.to_string().as_str())
                 .add("replace_allowed_ips", "true");
+            if let Some(psk) = &peer.psk {
+                wg_conf.add("preshared_key", psk.as_bytes().as_ref());
+            }
             for addr in &peer.allowed_ips {
                 wg_conf.add("allowed_ip", addr.to_string().as_str());
             }
@@ -2,11 +2,14 @@ use crate::{
     ping_monitor::{new_pinger, Pinger},
     tunnel::wireguard::stats::Stats,
 };
+use lazy_static::lazy_static;
 use std::{
+    env,
     net::Ipv4Addr,
     sync::{mpsc, Mutex, Weak},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
+use talpid_types::ErrorExt;
 
 use super::{Tunnel, TunnelError};
 
@@ -28,6 +31,19 @@ const TRAFFIC_TIMEOUT: Duration = Duration::from_secs(120);
 const PING_TIMEOUT: Duration = Duration::from_secs(15);
 /// Number of seconds to wait between sending ICMP packets
 const SECONDS_PER_PING: Duration = Duration::from_secs(3);
+/// Timeout for waiting on incoming traffic or a new handshake after outgoing traffic, when relying
+/// on WireGuard handshakes. A peer answers data with a keepalive after 10 seconds, and WireGuard
+/// initiates a new handshake if nothing has been received after 15 seconds, then retries every 5
+/// seconds.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    /// Makes the connectivity monitor rely on WireGuard handshakes instead of replies to pings.
+    static ref FORCE_HANDSHAKE_CONNECTIVITY_CHECK: bool =
+        env::var("TALPID_FORCE_HANDSHAKE_CONNECTIVITY_CHECK")
+            .map(|v| v != "0")
+            .unwrap_or(false);
+}
 
 /// Connectivity monitor errors
 #[derive(err_derive::Error, Debug)]
@@ -64,12 +80,16 @@ pub enum Error {
 ///
 /// Once a connection established, a connection is only considered broken once the connectivity
 /// monitor has started pinging and no traffic has been received for a duration of `PING_TIMEOUT`.
+///
+/// If no pinger can be created, or if `TALPID_FORCE_HANDSHAKE_CONNECTIVITY_CHECK` is set, the
+/// monitor uses `Strategy::Handshake` instead, which does not depend on replies to pings. This
+/// requires a tunnel that reports its handshakes.
 pub struct ConnectivityMonitor {
     tunnel_handle: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
-    conn_state: ConnState,
+    strategy: Strategy,
     initial_ping_timestamp: Option<Instant>,
     num_pings_sent: u32,
-    pinger: Box<dyn Pinger>,
+    pinger: Option<Box<dyn Pinger>>,
     close_receiver: mpsc::Receiver<()>,
 }
 
@@ -80,25 +100,43 @@ impl ConnectivityMonitor {
         interface: String,
         tunnel_handle: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
         close_receiver: mpsc::Receiver<()>,
-    ) -> Result<Self, Error> {
-        let pinger = new_pinger(addr, interface).map_err(Error::PingError)?;
+    ) -> Self {
+        let pinger = match new_pinger(addr, interface) {
+            Ok(pinger) => Some(pinger),
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to create pinger")
+                );
+                None
+            }
+        };
+        let reports_handshakes = tunnel_handle
+            .upgrade()
+            .and_then(|tunnel| Some(tunnel.lock().ok()?.as_ref()?.reports_handshakes()))
+            .unwrap_or(false);
 
-        let now = Instant::now();
+        let strategy = Strategy::new(
+            Instant::now(),
+            pinger.is_some(),
+            reports_handshakes,
+            *FORCE_HANDSHAKE_CONNECTIVITY_CHECK,
+        );
 
-        Ok(Self {
+        Self {
             tunnel_handle,
-            conn_state: ConnState::new(now, Default::default()),
+            strategy,
             initial_ping_timestamp: None,
             num_pings_sent: 0,
             pinger,
             close_receiver,
-        })
+        }
     }
 
     // checks if the tunnel has ever worked. Intended to check if a connection to a tunnel is
     // successfull at the start of a connection.
     pub(super) fn establish_connectivity(&mut self) -> Result<bool, Error> {
-        if self.conn_state.connected() {
+        if self.strategy.connected() {
             return Ok(true);
         }
 
@@ -120,6 +158,21 @@ impl ConnectivityMonitor {
         Ok(false)
     }
 
+    /// Forgets that the tunnel has worked, so that connectivity has to be established again. Used
+    /// when the keys of the tunnel have been replaced.
+    pub(super) fn reset(&mut self) {
+        let now = Instant::now();
+        self.strategy = match self.strategy {
+            Strategy::Ping(_) => Strategy::Ping(ConnState::new(now, Default::default())),
+            // The handshake that preceded the reset must not count as a new one
+            Strategy::Handshake(_) => {
+                let stats = self.get_stats().and_then(Result::ok).unwrap_or_default();
+                Strategy::Handshake(HandshakeState::new(now, stats))
+            }
+        };
+        self.reset_pinger();
+    }
+
     pub(super) fn run(&mut self) -> Result<(), Error> {
         self.wait_loop(REGULAR_LOOP_SLEEP)
     }
@@ -150,7 +203,7 @@ impl ConnectivityMonitor {
                 // Loop was suspended for too long, so it's safer to assume that the host still has
                 // connectivity.
                 self.reset_pinger();
-                self.conn_state.reset_after_suspension(current_iteration);
+                self.strategy.reset_after_suspension(current_iteration);
             }
             last_iteration = current_iteration;
         }
@@ -164,13 +217,13 @@ impl ConnectivityMonitor {
             Some(new_stats) => {
                 let new_stats = new_stats?;
 
-                if self.conn_state.update(now, new_stats) {
+                if self.strategy.update(now, new_stats) {
                     self.reset_pinger();
                     return Ok(true);
                 }
 
                 self.maybe_send_ping(now)?;
-                Ok(!self.ping_timed_out() && self.conn_state.connected())
+                Ok(!self.timed_out(now) && self.strategy.connected())
             }
         }
     }
@@ -190,7 +243,7 @@ impl ConnectivityMonitor {
         // Only send out a ping if we haven't received a byte in a while or no traffic has flowed
         // in the last 2 minutes, but if a ping already has been sent out, only send one out every
         // 3 seconds.
-        if (self.conn_state.rx_timed_out() || self.conn_state.traffic_timed_out())
+        if self.strategy.needs_ping(now)
             && self
                 .initial_ping_timestamp
                 .map(|initial_ping_timestamp| {
@@ -198,7 +251,16 @@ impl ConnectivityMonitor {
                 })
                 .unwrap_or(true)
         {
-            self.pinger.send_icmp().map_err(Error::PingError)?;
+            if let Some(pinger) = self.pinger.as_mut() {
+                match (pinger.send_icmp(), &self.strategy) {
+                    (Ok(()), _) => (),
+                    // Pings only give WireGuard a reason to contact the relay
+                    (Err(error), Strategy::Handshake(_)) => {
+                        log::trace!("{}", error.display_chain_with_msg("Failed to send ping"));
+                    }
+                    (Err(error), Strategy::Ping(_)) => return Err(Error::PingError(error)),
+                }
+            }
             if self.initial_ping_timestamp.is_none() {
                 self.initial_ping_timestamp = Some(now);
             }
@@ -207,6 +269,13 @@ impl ConnectivityMonitor {
         Ok(())
     }
 
+    fn timed_out(&self, now: Instant) -> bool {
+        match &self.strategy {
+            Strategy::Ping(_) => self.ping_timed_out(),
+            Strategy::Handshake(state) => state.timed_out(now),
+        }
+    }
+
     fn ping_timed_out(&self) -> bool {
         self.initial_ping_timestamp
             .map(|initial_ping_timestamp| initial_ping_timestamp.elapsed() > PING_TIMEOUT)
@@ -217,7 +286,72 @@ impl ConnectivityMonitor {
     fn reset_pinger(&mut self) {
         self.initial_ping_timestamp = None;
         self.num_pings_sent = 0;
-        self.pinger.reset();
+        if let Some(pinger) = self.pinger.as_mut() {
+            pinger.reset();
+        }
+    }
+}
+
+/// Method used to decide whether the tunnel works.
+enum Strategy {
+    /// Traffic has to be received in response to pings sent to the gateway of the relay.
+    Ping(ConnState),
+    /// The tunnel works as long as WireGuard keeps completing handshakes and receiving traffic.
+    /// Pings are still sent to give WireGuard a reason to contact the relay, but they don't have
+    /// to be answered, so this works without a pinger and when ICMP is filtered.
+    Handshake(HandshakeState),
+}
+
+impl Strategy {
+    /// Selects the handshake strategy if there is no pinger or if it is forced, provided that
+    /// the tunnel reports its handshakes.
+    fn new(
+        now: Instant,
+        has_pinger: bool,
+        reports_handshakes: bool,
+        force_handshake: bool,
+    ) -> Self {
+        if reports_handshakes && (!has_pinger || force_handshake) {
+            log::debug!("Relying on WireGuard handshakes to check connectivity");
+            Strategy::Handshake(HandshakeState::new(now, Default::default()))
+        } else {
+            if !has_pinger {
+                log::error!(
+                    "The tunnel does not report handshakes, so connectivity cannot be checked \
+                     without a pinger"
+                );
+            }
+            Strategy::Ping(ConnState::new(now, Default::default()))
+        }
+    }
+
+    /// Returns true if incoming traffic or a new connection was observed
+    fn update(&mut self, now: Instant, new_stats: Stats) -> bool {
+        match self {
+            Strategy::Ping(state) => state.update(now, new_stats),
+            Strategy::Handshake(state) => state.update(now, new_stats),
+        }
+    }
+
+    fn needs_ping(&self, now: Instant) -> bool {
+        match self {
+            Strategy::Ping(state) => state.rx_timed_out() || state.traffic_timed_out(),
+            Strategy::Handshake(state) => state.needs_traffic(now),
+        }
+    }
+
+    fn reset_after_suspension(&mut self, now: Instant) {
+        match self {
+            Strategy::Ping(state) => state.reset_after_suspension(now),
+            Strategy::Handshake(state) => state.reset_after_suspension(now),
+        }
+    }
+
+    fn connected(&self) -> bool {
+        match self {
+            Strategy::Ping(state) => state.connected(),
+            Strategy::Handshake(state) => state.connected,
+        }
     }
 }
 
@@ -346,16 +480,87 @@ impl ConnState {
     }
 }
 
+/// Connection state used by `Strategy::Handshake`. WireGuard answers any data with a keepalive,
+/// and initiates a new handshake when its own data goes unanswered, so a tunnel is only considered
+/// broken if outgoing traffic has been observed without any incoming traffic or handshake for
+/// `HANDSHAKE_TIMEOUT`.
+struct HandshakeState {
+    /// Latest handshake when the state was created. Connectivity is established by a later one.
+    initial_handshake: Option<SystemTime>,
+    stats: Stats,
+    connected: bool,
+    /// Last time traffic in either direction was observed
+    traffic_timestamp: Instant,
+    /// First time outgoing traffic was observed since traffic was last received
+    unanswered_timestamp: Option<Instant>,
+}
+
+impl HandshakeState {
+    pub fn new(start: Instant, stats: Stats) -> Self {
+        HandshakeState {
+            initial_handshake: stats.last_handshake,
+            stats,
+            connected: false,
+            traffic_timestamp: start,
+            unanswered_timestamp: None,
+        }
+    }
+
+    /// Returns true if a handshake or incoming traffic was observed
+    pub fn update(&mut self, now: Instant, new_stats: Stats) -> bool {
+        let rx_incremented = self.stats.rx_bytes < new_stats.rx_bytes;
+        let tx_incremented = self.stats.tx_bytes < new_stats.tx_bytes;
+        let handshake_completed = self.stats.last_handshake < new_stats.last_handshake;
+        self.stats = new_stats;
+
+        if rx_incremented || tx_incremented {
+            self.traffic_timestamp = now;
+        }
+
+        if !self.connected {
+            self.connected = self.initial_handshake < new_stats.last_handshake;
+            return self.connected;
+        }
+
+        if rx_incremented || handshake_completed {
+            self.unanswered_timestamp = None;
+            return true;
+        }
+        if tx_incremented && self.unanswered_timestamp.is_none() {
+            self.unanswered_timestamp = Some(now);
+        }
+        false
+    }
+
+    pub fn reset_after_suspension(&mut self, now: Instant) {
+        self.traffic_timestamp = now;
+        self.unanswered_timestamp = None;
+    }
+
+    /// Returns true if WireGuard has to be made to contact the relay, either because no handshake
+    /// has been completed yet or because no traffic has flowed in a while
+    pub fn needs_traffic(&self, now: Instant) -> bool {
+        !self.connected || now.saturating_duration_since(self.traffic_timestamp) >= TRAFFIC_TIMEOUT
+    }
+
+    // check if outgoing traffic has gone unanswered for too long
+    pub fn timed_out(&self, now: Instant) -> bool {
+        self.unanswered_timestamp
+            .map(|timestamp| now.saturating_duration_since(timestamp) >= HANDSHAKE_TIMEOUT)
+            .unwrap_or(false)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::tunnel::wireguard::{stats, TunnelError};
+    use crate::tunnel::wireguard::{stats, Config, TunnelError};
     use std::{
         sync::{
-            atomic::{AtomicBool, Ordering},
+            atomic::{AtomicBool, AtomicUsize, Ordering},
             Arc, Mutex,
         },
-        time::{Duration, Instant},
+        time::{Duration, Instant, SystemTime},
     };
 
     /// Test if a newly created ConnState won't have timed out or consider itself connected
@@ -392,6 +597,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -415,6 +621,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -437,6 +644,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -446,6 +654,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 1,
+                last_handshake: None,
             },
         );
 
@@ -454,6 +663,101 @@ mod test {
         assert!(!conn_state.traffic_timed_out());
     }
 
+    fn handshake_stats(rx_bytes: u64, tx_bytes: u64, last_handshake: SystemTime) -> Stats {
+        Stats {
+            rx_bytes,
+            tx_bytes,
+            last_handshake: Some(last_handshake),
+        }
+    }
+
+    /// Test if HandshakeState only considers itself connected once a handshake has completed
+    #[test]
+    fn test_handshake_state_connects_on_handshake() {
+        let now = Instant::now();
+        let mut state = HandshakeState::new(now, Default::default());
+
+        assert!(!state.update(
+            now,
+            Stats {
+                rx_bytes: 1,
+                tx_bytes: 1,
+                last_handshake: None,
+            },
+        ));
+        assert!(!state.connected);
+        assert!(state.needs_traffic(now));
+
+        assert!(state.update(now, handshake_stats(2, 2, SystemTime::now())));
+        assert!(state.connected);
+        assert!(!state.needs_traffic(now));
+        assert!(!state.timed_out(now));
+    }
+
+    /// Test if HandshakeState ignores the handshake that had completed before it was created
+    #[test]
+    fn test_handshake_state_requires_new_handshake() {
+        let now = Instant::now();
+        let handshake = SystemTime::now();
+        let mut state = HandshakeState::new(now, handshake_stats(1, 1, handshake));
+
+        assert!(!state.update(now, handshake_stats(2, 2, handshake)));
+        assert!(!state.connected);
+
+        let new_handshake = handshake + Duration::from_secs(1);
+        assert!(state.update(now, handshake_stats(3, 3, new_handshake)));
+        assert!(state.connected);
+    }
+
+    /// Test if HandshakeState times out after outgoing traffic has gone unanswered for
+    /// HANDSHAKE_TIMEOUT, and not before
+    #[test]
+    fn test_handshake_state_times_out_without_incoming_traffic() {
+        let start = Instant::now();
+        let handshake = SystemTime::now();
+        let mut state = HandshakeState::new(start, Default::default());
+        state.update(start, handshake_stats(1, 1, handshake));
+
+        let unanswered = start + Duration::from_secs(1);
+        assert!(!state.update(unanswered, handshake_stats(1, 2, handshake)));
+        assert!(!state.update(
+            unanswered + HANDSHAKE_TIMEOUT - Duration::from_secs(1),
+            handshake_stats(1, 3, handshake)
+        ));
+        assert!(!state.timed_out(unanswered + HANDSHAKE_TIMEOUT - Duration::from_secs(1)));
+        assert!(state.timed_out(unanswered + HANDSHAKE_TIMEOUT));
+    }
+
+    /// Test if incoming traffic or a new handshake stops HandshakeState from timing out
+    #[test]
+    fn test_handshake_state_answered_traffic() {
+        let start = Instant::now();
+        let handshake = SystemTime::now();
+        let mut state = HandshakeState::new(start, Default::default());
+        state.update(start, handshake_stats(1, 1, handshake));
+
+        state.update(start, handshake_stats(1, 2, handshake));
+        assert!(state.update(start, handshake_stats(2, 2, handshake)));
+        assert!(!state.timed_out(start + HANDSHAKE_TIMEOUT));
+
+        state.update(start, handshake_stats(2, 3, handshake));
+        let new_handshake = handshake + Duration::from_secs(1);
+        assert!(state.update(start, handshake_stats(2, 3, new_handshake)));
+        assert!(!state.timed_out(start + HANDSHAKE_TIMEOUT));
+    }
+
+    /// Test if HandshakeState asks for traffic once the tunnel has been idle for TRAFFIC_TIMEOUT
+    #[test]
+    fn test_handshake_state_needs_traffic_when_idle() {
+        let start = Instant::now();
+        let mut state = HandshakeState::new(start, Default::default());
+        state.update(start, handshake_stats(1, 1, SystemTime::now()));
+
+        assert!(!state.needs_traffic(start + TRAFFIC_TIMEOUT - Duration::from_secs(1)));
+        assert!(state.needs_traffic(start + TRAFFIC_TIMEOUT));
+        assert!(!state.timed_out(start + TRAFFIC_TIMEOUT));
+    }
+
     #[derive(Default)]
     struct MockPinger {
         on_send_ping: Option<Box<dyn FnMut() + Send>>,
@@ -483,6 +787,25 @@ mod test {
             let traffic = Mutex::new(stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake: None,
+            });
+            Self {
+                on_get_stats: Box::new(move || {
+                    let mut traffic = traffic.lock().unwrap();
+                    traffic.tx_bytes += 1;
+                    traffic.rx_bytes += 1;
+
+                    Ok(*traffic)
+                }),
+            }
+        }
+
+        /// Traffic flows in both directions, and a handshake completed when the tunnel was created
+        fn handshaken_always_incrementing() -> Self {
+            let traffic = Mutex::new(stats::Stats {
+                tx_bytes: 0,
+                rx_bytes: 0,
+                last_handshake: Some(SystemTime::now()),
             });
             Self {
                 on_get_stats: Box::new(move || {
@@ -501,6 +824,7 @@ mod test {
                     Ok(stats::Stats {
                         tx_bytes: 0,
                         rx_bytes: 0,
+                        last_handshake: None,
                     })
                 }),
             }
@@ -536,6 +860,24 @@ mod test {
         fn get_tunnel_stats(&self) -> Result<stats::Stats, TunnelError> {
             (self.on_get_stats)()
         }
+
+        fn set_config(&mut self, _config: &Config) -> Result<(), TunnelError> {
+            Ok(())
+        }
+    }
+
+    /// Test if the handshake strategy is only selected for tunnels that report handshakes
+    #[test]
+    fn test_strategy_selection() {
+        let now = Instant::now();
+        let is_handshake = |strategy| matches!(strategy, Strategy::Handshake(_));
+
+        assert!(!is_handshake(Strategy::new(now, true, true, false)));
+        assert!(is_handshake(Strategy::new(now, false, true, false)));
+        assert!(is_handshake(Strategy::new(now, true, true, true)));
+
+        assert!(!is_handshake(Strategy::new(now, false, false, false)));
+        assert!(!is_handshake(Strategy::new(now, true, false, true)));
     }
 
     fn mock_monitor(
@@ -545,7 +887,23 @@ mod test {
         close_receiver: mpsc::Receiver<()>,
     ) -> ConnectivityMonitor {
         ConnectivityMonitor {
-            conn_state: ConnState::new(now, Default::default()),
+            strategy: Strategy::Ping(ConnState::new(now, Default::default())),
+            initial_ping_timestamp: None,
+            num_pings_sent: 0,
+            pinger: Some(pinger),
+            close_receiver,
+            tunnel_handle,
+        }
+    }
+
+    fn mock_handshake_monitor(
+        now: Instant,
+        pinger: Option<Box<dyn Pinger>>,
+        tunnel_handle: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+        close_receiver: mpsc::Receiver<()>,
+    ) -> ConnectivityMonitor {
+        ConnectivityMonitor {
+            strategy: Strategy::Handshake(HandshakeState::new(now, Default::default())),
             initial_ping_timestamp: None,
             num_pings_sent: 0,
             pinger,
@@ -554,6 +912,18 @@ mod test {
         }
     }
 
+    /// Returns a pinger that counts the pings sent
+    fn counting_pinger() -> (MockPinger, Arc<AtomicUsize>) {
+        let pings_sent = Arc::new(AtomicUsize::new(0));
+        let pings_sent_inner = pings_sent.clone();
+        let pinger = MockPinger {
+            on_send_ping: Some(Box::new(move || {
+                pings_sent_inner.fetch_add(1, Ordering::SeqCst);
+            })),
+        };
+        (pinger, pings_sent)
+    }
+
     fn connected_state(timestamp: Instant) -> ConnState {
         ConnState::Connected {
             rx_timestamp: timestamp,
@@ -561,6 +931,7 @@ mod test {
             stats: stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake: None,
             },
         }
     }
@@ -578,7 +949,7 @@ mod test {
         let mut monitor = mock_monitor(start, Box::new(pinger), tunnel, rx);
 
         // Mock the state - connectivity has been established
-        monitor.conn_state = connected_state(start);
+        monitor.strategy = Strategy::Ping(connected_state(start));
         // A ping was sent to verify connectivity
         monitor.maybe_send_ping(start).unwrap();
         assert!(!monitor.check_connectivity(now).unwrap())
@@ -610,11 +981,30 @@ mod test {
         let mut monitor = mock_monitor(start, Box::new(pinger), tunnel, rx);
 
         // Mock the state - connectivity has been established
-        monitor.conn_state = connected_state(start);
+        monitor.strategy = Strategy::Ping(connected_state(start));
 
         assert!(monitor.check_connectivity(now).unwrap())
     }
 
+    #[test]
+    /// Verify that `check_connectivity()` returns `false` after the monitor has been reset, until
+    /// traffic is received again.
+    fn test_reset() {
+        let (_tunnel_anchor, tunnel) = MockTunnel::never_incrementing().into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let pinger = MockPinger::default();
+        let now = Instant::now();
+        let start = now - Duration::from_secs(1);
+        let mut monitor = mock_monitor(start, Box::new(pinger), tunnel, rx);
+
+        // Mock the state - connectivity has been established
+        monitor.strategy = Strategy::Ping(connected_state(start));
+        monitor.reset();
+
+        assert!(!monitor.strategy.connected());
+        assert!(!monitor.check_connectivity(now).unwrap())
+    }
+
     #[test]
     /// Verify that the connectivity monitor doesn't fail if the tunnel constantly sends traffic,
     /// and it shuts down properly.
@@ -652,6 +1042,7 @@ mod test {
         let tunnel_stats = Mutex::new(stats::Stats {
             rx_bytes: 0,
             tx_bytes: 0,
+            last_handshake: None,
         });
 
         let pinger = MockPinger::default();
@@ -687,4 +1078,68 @@ mod test {
             .unwrap()
             .is_ok());
     }
+
+    #[test]
+    /// Verify that the handshake strategy establishes connectivity without a pinger.
+    fn test_handshake_connection_works_without_pinger() {
+        let (_tunnel_anchor, tunnel) = MockTunnel::handshaken_always_incrementing().into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let mut monitor = mock_handshake_monitor(Instant::now(), None, tunnel, rx);
+
+        assert!(monitor.establish_connectivity().unwrap());
+        assert!(monitor.check_connectivity(Instant::now()).unwrap());
+    }
+
+    #[test]
+    /// Verify that the handshake strategy pings to trigger a handshake, and doesn't consider the
+    /// tunnel connected until one has completed.
+    fn test_handshake_no_connection_on_start() {
+        let (_tunnel_anchor, tunnel) = MockTunnel::never_incrementing().into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let (pinger, pings_sent) = counting_pinger();
+        let now = Instant::now();
+        let mut monitor = mock_handshake_monitor(now, Some(Box::new(pinger)), tunnel, rx);
+
+        assert!(!monitor.check_connectivity(now).unwrap());
+        assert_eq!(pings_sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    /// Verify that `check_connectivity()` returns `false` for the handshake strategy once outgoing
+    /// traffic has gone unanswered for `HANDSHAKE_TIMEOUT`, without waiting for any pings.
+    fn test_handshake_times_out() {
+        let handshake = SystemTime::now();
+        let tunnel_stats = Mutex::new(handshake_stats(1, 1, handshake));
+        let (_tunnel_anchor, tunnel) = MockTunnel::new(move || {
+            let mut tunnel_stats = tunnel_stats.lock().unwrap();
+            tunnel_stats.tx_bytes += 1;
+            Ok(*tunnel_stats)
+        })
+        .into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let (pinger, pings_sent) = counting_pinger();
+        let start = Instant::now();
+        let mut monitor = mock_handshake_monitor(start, Some(Box::new(pinger)), tunnel, rx);
+
+        assert!(monitor.check_connectivity(start).unwrap());
+        assert!(monitor.check_connectivity(start).unwrap());
+        assert!(!monitor
+            .check_connectivity(start + HANDSHAKE_TIMEOUT)
+            .unwrap());
+        assert_eq!(pings_sent.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    /// Verify that the handshake monitor requires a new handshake after it has been reset.
+    fn test_handshake_reset() {
+        let (_tunnel_anchor, tunnel) = MockTunnel::handshaken_always_incrementing().into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let now = Instant::now();
+        let mut monitor = mock_handshake_monitor(now, None, tunnel, rx);
+
+        assert!(monitor.check_connectivity(now).unwrap());
+        monitor.reset();
+        assert!(!monitor.strategy.connected());
+        assert!(!monitor.check_connectivity(now).unwrap());
+    }
 }
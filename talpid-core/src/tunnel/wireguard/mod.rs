@@ -1,7 +1,7 @@
 use self::config::Config;
 #[cfg(not(windows))]
 use super::tun_provider;
-use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata};
+use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata, TunnelStats};
 use crate::routing::{self, RequiredRoute};
 use futures::future::abortable;
 #[cfg(target_os = "linux")]
@@ -16,14 +16,23 @@ use std::{
     path::Path,
     sync::{mpsc, Arc, Mutex},
 };
-use talpid_types::{net::TransportProtocol, ErrorExt};
-use udp_over_tcp::{TcpOptions, Udp2Tcp};
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::wireguard::PrivateKey;
+use talpid_types::{
+    net::{obfuscation::ObfuscatorConfig, TransportProtocol},
+    ErrorExt,
+};
 
 /// WireGuard config data-types
 pub mod config;
 mod connectivity_check;
 mod logging;
+mod obfuscation;
+#[cfg(not(target_os = "android"))]
+mod psk_exchange;
 mod stats;
+#[cfg(target_os = "linux")]
+mod wireguard_boringtun;
 mod wireguard_go;
 #[cfg(target_os = "linux")]
 pub(crate) mod wireguard_kernel;
@@ -48,18 +57,24 @@ pub enum Error {
     #[error(display = "Tunnel failed")]
     TunnelError(#[error(source)] TunnelError),
 
-    /// Failed to set up Udp2Tcp
-    #[error(display = "Failed to start UDP-over-TCP proxy")]
-    Udp2TcpError(#[error(source)] udp_over_tcp::udp2tcp::ConnectError),
-
-    /// Failed to obtain the local UDP socket address
-    #[error(display = "Failed obtain local address for the UDP socket in Udp2Tcp")]
-    GetLocalUdpAddress(#[error(source)] std::io::Error),
+    /// Failed to set up the obfuscation layer
+    #[error(display = "Failed to start the obfuscator")]
+    ObfuscatorError(#[error(source)] obfuscation::Error),
 
     /// Failed to set up connectivity monitor
     #[error(display = "Connectivity monitor failed")]
     ConnectivityMonitorError(#[error(source)] connectivity_check::Error),
 
+    /// Failed to negotiate a preshared key with the relay
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to negotiate a post-quantum preshared key")]
+    PskNegotiationError(#[error(source)] psk_exchange::Error),
+
+    /// A preshared key cannot be negotiated for a multihop tunnel
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Post-quantum preshared keys are not supported for multihop tunnels")]
+    MultihopPskError,
+
     /// Failed to set up IP interfaces.
     #[cfg(windows)]
     #[error(display = "Failed while waiting on IP interfaces")]
@@ -83,7 +98,7 @@ pub struct WireguardMonitor {
     #[cfg(target_os = "windows")]
     stop_setup_tx: Option<futures::channel::oneshot::Sender<()>>,
     pinger_stop_sender: mpsc::Sender<()>,
-    _tcp_proxies: Vec<TcpProxy>,
+    _obfuscators: Vec<ObfuscatorProxy>,
 }
 
 #[cfg(target_os = "linux")]
@@ -96,39 +111,45 @@ lazy_static! {
     static ref FORCE_NM_WIREGUARD: bool = env::var("TALPID_FORCE_NM_WIREGUARD")
         .map(|v| v != "0")
         .unwrap_or(false);
+
+    /// Selects the WireGuard implementation that runs in the daemon process. There is no fallback
+    /// if it cannot be set up.
+    static ref FORCE_BORINGTUN_WIREGUARD: bool = env::var("TALPID_FORCE_BORINGTUN_WIREGUARD")
+        .map(|v| v != "0")
+        .unwrap_or(false);
 }
 
-struct TcpProxy {
+struct ObfuscatorProxy {
     local_addr: SocketAddr,
     abort_handle: futures::future::AbortHandle,
 }
 
-impl TcpProxy {
-    pub fn new(runtime: &tokio::runtime::Handle, endpoint: SocketAddr) -> Result<Self> {
-        let listen_addr = if endpoint.is_ipv4() {
-            SocketAddr::new("127.0.0.1".parse().unwrap(), 0)
-        } else {
-            SocketAddr::new("::1".parse().unwrap(), 0)
-        };
+impl ObfuscatorProxy {
+    pub fn new(
+        runtime: &tokio::runtime::Handle,
+        config: &ObfuscatorConfig,
+        peer_endpoint: SocketAddr,
+    ) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let fwmark = Some(crate::linux::TUNNEL_FW_MARK);
+        #[cfg(not(target_os = "linux"))]
+        let fwmark = None;
 
-        let udp2tcp = runtime
-            .block_on(Udp2Tcp::new(
-                listen_addr,
-                endpoint,
-                Some(&TcpOptions {
-                    #[cfg(target_os = "linux")]
-                    fwmark: Some(crate::linux::TUNNEL_FW_MARK),
-                    ..TcpOptions::default()
-                }),
+        let obfuscator = runtime
+            .block_on(obfuscation::create_obfuscator(
+                config,
+                peer_endpoint,
+                fwmark,
             ))
-            .map_err(Error::Udp2TcpError)?;
+            .map_err(Error::ObfuscatorError)?;
+        let local_addr = obfuscator.endpoint();
 
-        let local_addr = udp2tcp
-            .local_udp_addr()
-            .map_err(Error::GetLocalUdpAddress)?;
-
-        let (udp2tcp_future, abort_handle) = abortable(udp2tcp.run());
-        runtime.spawn(udp2tcp_future);
+        let (obfuscator_future, abort_handle) = abortable(async move {
+            if let Err(error) = obfuscator.run().await {
+                log::error!("{}", error.display_chain_with_msg("Obfuscator failed"));
+            }
+        });
+        runtime.spawn(obfuscator_future);
 
         Ok(Self {
             local_addr,
@@ -141,7 +162,7 @@ impl TcpProxy {
     }
 }
 
-impl Drop for TcpProxy {
+impl Drop for ObfuscatorProxy {
     fn drop(&mut self) {
         self.abort_handle.abort();
     }
@@ -157,17 +178,28 @@ impl WireguardMonitor {
         tun_provider: &mut TunProvider,
         route_manager: &mut routing::RouteManager,
     ) -> Result<WireguardMonitor> {
-        let mut tcp_proxies = vec![];
+        // The entry relay of a multihop tunnel would not know about the ephemeral private key.
+        #[cfg(not(target_os = "android"))]
+        if config.quantum_resistant && config.peers.len() > 1 {
+            return Err(Error::MultihopPskError);
+        }
+
+        let mut obfuscators = vec![];
 
-        for peer in &mut config.peers {
-            if peer.protocol == TransportProtocol::Tcp {
-                let udp2tcp = TcpProxy::new(&runtime, peer.endpoint.clone())?;
+        for (index, peer) in config.peers.iter_mut().enumerate() {
+            let obfuscator_config = match &config.obfuscator {
+                Some(obfuscator_config) if index == 0 => obfuscator_config.clone(),
+                _ if peer.protocol == TransportProtocol::Tcp => ObfuscatorConfig::Udp2Tcp {
+                    endpoint: peer.endpoint,
+                },
+                _ => continue,
+            };
+            let obfuscator = ObfuscatorProxy::new(&runtime, &obfuscator_config, peer.endpoint)?;
 
-                // Replace remote peer with proxy
-                peer.endpoint = udp2tcp.local_udp_addr();
+            // Replace remote peer with proxy
+            peer.endpoint = obfuscator.local_udp_addr();
 
-                tcp_proxies.push(udp2tcp);
-            }
+            obfuscators.push(obfuscator);
         }
 
         let tunnel = Self::open_tunnel(&config, log_path, tun_provider, route_manager)?;
@@ -195,18 +227,19 @@ impl WireguardMonitor {
             #[cfg(target_os = "windows")]
             stop_setup_tx: Some(stop_setup_tx),
             pinger_stop_sender: pinger_tx,
-            _tcp_proxies: tcp_proxies,
+            _obfuscators: obfuscators,
         };
 
         let gateway = config.ipv4_gateway;
         let close_sender = monitor.close_msg_sender.clone();
+        #[cfg(not(target_os = "android"))]
+        let tunnel_handle = Arc::downgrade(&monitor.tunnel);
         let mut connectivity_monitor = connectivity_check::ConnectivityMonitor::new(
             gateway,
             iface_name.clone(),
             Arc::downgrade(&monitor.tunnel),
             pinger_rx,
-        )
-        .map_err(Error::ConnectivityMonitorError)?;
+        );
 
         let route_handle = route_manager.handle().map_err(Error::SetupRoutingError)?;
         #[cfg(windows)]
@@ -242,7 +275,7 @@ impl WireguardMonitor {
                 }
             }
 
-            let setup_iface_routes = move || -> Result<()> {
+            let setup_iface_routes = || -> Result<()> {
                 #[cfg(target_os = "windows")]
                 if !crate::winnet::add_device_ip_addresses(&iface_name, &config.tunnel.addresses) {
                     return Err(Error::SetIpAddressesError);
@@ -265,6 +298,19 @@ impl WireguardMonitor {
 
             match connectivity_monitor.establish_connectivity() {
                 Ok(true) => {
+                    #[cfg(not(target_os = "android"))]
+                    if config.quantum_resistant {
+                        if let Err(error) = Self::negotiate_psk(
+                            &runtime,
+                            &mut config,
+                            &tunnel_handle,
+                            &mut connectivity_monitor,
+                        ) {
+                            let _ = close_sender.send(CloseMsg::SetupError(error));
+                            return;
+                        }
+                    }
+
                     (on_event)(TunnelEvent::Up(metadata));
 
                     if let Err(error) = connectivity_monitor.run() {
@@ -289,6 +335,56 @@ impl WireguardMonitor {
         Ok(monitor)
     }
 
+    /// Replaces the private key of the tunnel with an ephemeral one and adds a preshared key that
+    /// is negotiated with the relay, then waits until the tunnel works with the new keys.
+    #[cfg(not(target_os = "android"))]
+    fn negotiate_psk(
+        runtime: &tokio::runtime::Handle,
+        config: &mut Config,
+        tunnel_handle: &std::sync::Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+        connectivity_monitor: &mut connectivity_check::ConnectivityMonitor,
+    ) -> Result<()> {
+        log::debug!("Negotiating a post-quantum preshared key");
+
+        let ephemeral_private_key = PrivateKey::new_from_random();
+        let service_address = SocketAddr::new(
+            config.ipv4_gateway.into(),
+            psk_exchange::CONFIG_SERVICE_PORT,
+        );
+        let psk = runtime
+            .block_on(psk_exchange::negotiate_psk(
+                service_address,
+                config.tunnel.private_key.public_key(),
+                ephemeral_private_key.public_key(),
+            ))
+            .map_err(Error::PskNegotiationError)?;
+
+        config.tunnel.private_key = ephemeral_private_key;
+        config.peers[0].psk = Some(psk);
+
+        {
+            let tunnel = tunnel_handle
+                .upgrade()
+                .ok_or(Error::TunnelError(TunnelError::SetConfigError))?;
+            let mut tunnel = tunnel.lock().expect("Tunnel lock poisoned");
+            tunnel
+                .as_mut()
+                .ok_or(Error::TunnelError(TunnelError::SetConfigError))?
+                .set_config(config)
+                .map_err(Error::TunnelError)?;
+        }
+
+        connectivity_monitor.reset();
+        match connectivity_monitor.establish_connectivity() {
+            Ok(true) => {
+                log::debug!("Replaced the keys of the tunnel");
+                Ok(())
+            }
+            Ok(false) => Err(Error::TimeoutError),
+            Err(error) => Err(Error::ConnectivityMonitorError(error)),
+        }
+    }
+
     #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
     fn open_tunnel(
         config: &Config,
@@ -296,6 +392,18 @@ impl WireguardMonitor {
         tun_provider: &mut TunProvider,
         route_manager: &mut routing::RouteManager,
     ) -> Result<Box<dyn Tunnel>> {
+        #[cfg(target_os = "linux")]
+        if *FORCE_BORINGTUN_WIREGUARD {
+            let tunnel = wireguard_boringtun::BoringTunnel::start_tunnel(
+                config,
+                tun_provider,
+                Self::get_tunnel_routes(config),
+            )
+            .map_err(Error::TunnelError)?;
+            log::debug!("Using BoringTun WireGuard implementation");
+            return Ok(Box::new(tunnel));
+        }
+
         #[cfg(target_os = "linux")]
         if !*FORCE_USERSPACE_WIREGUARD {
             if crate::dns::will_use_nm() {
@@ -351,6 +459,13 @@ impl WireguardMonitor {
         }
     }
 
+    /// Returns a handle for reading the traffic statistics of the tunnel
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            tunnel: self.tunnel.clone(),
+        }
+    }
+
     /// Blocks the current thread until tunnel disconnects
     pub fn wait(mut self) -> Result<()> {
         let wait_result = match self.close_msg_receiver.recv() {
@@ -527,12 +642,46 @@ impl CloseHandle {
     }
 }
 
+/// Handle for reading the traffic statistics of a WireGuard tunnel.
+#[derive(Clone)]
+pub struct StatsHandle {
+    tunnel: Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+}
+
+impl StatsHandle {
+    /// Returns the current statistics, or `None` if the tunnel is down or the statistics could
+    /// not be read.
+    pub fn stats(&self) -> Option<TunnelStats> {
+        let tunnel = self.tunnel.lock().expect("Tunnel lock poisoned");
+        match tunnel.as_ref()?.get_tunnel_stats() {
+            Ok(stats) => Some(TunnelStats {
+                rx_bytes: stats.rx_bytes,
+                tx_bytes: stats.tx_bytes,
+                last_handshake: stats.last_handshake,
+            }),
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to obtain tunnel stats")
+                );
+                None
+            }
+        }
+    }
+}
+
 pub(crate) trait Tunnel: Send {
     fn get_interface_name(&self) -> String;
     #[cfg(target_os = "windows")]
     fn get_interface_luid(&self) -> u64;
     fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError>;
     fn get_tunnel_stats(&self) -> std::result::Result<stats::Stats, TunnelError>;
+    /// Replaces the keys and peers of the running tunnel.
+    fn set_config(&mut self, config: &Config) -> std::result::Result<(), TunnelError>;
+    /// Whether `get_tunnel_stats` reports the time of the latest handshake.
+    fn reports_handshakes(&self) -> bool {
+        true
+    }
     #[cfg(target_os = "linux")]
     fn slow_stats_refresh_rate(&self) {}
 }
@@ -571,6 +720,10 @@ pub enum TunnelError {
     #[error(display = "Failed to get config of WireGuard tunnel")]
     GetConfigError,
 
+    /// Failed to apply a new config to a running WireGuard tunnel
+    #[error(display = "Failed to set config of WireGuard tunnel")]
+    SetConfigError,
+
     /// Failed to duplicate tunnel file descriptor for wireguard-go
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "android"))]
     #[error(display = "Failed to duplicate tunnel file descriptor for wireguard-go")]
@@ -581,6 +734,11 @@ pub enum TunnelError {
     #[error(display = "Failed to create tunnel device")]
     SetupTunnelDeviceError(#[error(source)] tun_provider::Error),
 
+    /// Failed to open a socket for the BoringTun implementation.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to open a socket for the WireGuard peer")]
+    OpenSocketError(#[error(source)] std::io::Error),
+
     /// Failed to configure Wireguard sockets to bypass the tunnel.
     #[cfg(target_os = "android")]
     #[error(display = "Failed to configure Wireguard sockets to bypass the tunnel")]
@@ -0,0 +1,213 @@
+//! Obfuscation layers that WireGuard traffic can be wrapped in, for networks that block plain
+//! WireGuard. Each obfuscator listens on a local UDP socket that WireGuard uses as the endpoint of
+//! the peer, and forwards the traffic in an obfuscated form to a server that unwraps it.
+
+use std::{io, net::SocketAddr};
+use talpid_types::net::obfuscation::ObfuscatorConfig;
+
+mod shadowsocks;
+mod udp2tcp;
+
+use self::{shadowsocks::Shadowsocks, udp2tcp::Udp2Tcp};
+
+/// Obfuscation errors
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to set up the local UDP socket
+    #[error(display = "Failed to bind the local UDP socket")]
+    BindLocalSocket(#[error(source)] io::Error),
+
+    /// Failed to obtain the local UDP socket address
+    #[error(display = "Failed to obtain the address of the local UDP socket")]
+    GetLocalUdpAddress(#[error(source)] io::Error),
+
+    /// Failed to set up Udp2Tcp
+    #[error(display = "Failed to start UDP-over-TCP proxy")]
+    Udp2TcpError(#[error(source)] udp_over_tcp::udp2tcp::ConnectError),
+
+    /// Udp2Tcp stopped forwarding traffic
+    #[error(display = "UDP-over-TCP proxy failed: {}", _0)]
+    RunUdp2Tcp(String),
+
+    /// Failed to set up the socket to the Shadowsocks server
+    #[error(display = "Failed to open a socket to the Shadowsocks server")]
+    ShadowsocksSocket(#[error(source)] io::Error),
+
+    /// The cipher of the Shadowsocks server cannot be used for UDP
+    #[error(display = "Unsupported Shadowsocks cipher: {}", _0)]
+    UnsupportedCipher(String),
+
+    /// Failed to receive or send traffic
+    #[error(display = "Failed to forward traffic")]
+    Forward(#[error(source)] io::Error),
+}
+
+/// An obfuscation protocol that WireGuard traffic is forwarded through.
+#[async_trait::async_trait]
+pub trait Obfuscator: Send {
+    /// Returns the address of the local UDP socket that WireGuard should send its traffic to.
+    fn endpoint(&self) -> SocketAddr;
+
+    /// Forwards traffic between the local socket and the server until an error occurs.
+    async fn run(self: Box<Self>) -> Result<(), Error>;
+}
+
+/// Creates the obfuscator described by `config`, which forwards the traffic to the WireGuard
+/// peer at `peer_endpoint`. On Linux, `fwmark` is set on the sockets that connect to the server,
+/// so that the traffic bypasses the tunnel.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+pub async fn create_obfuscator(
+    config: &ObfuscatorConfig,
+    peer_endpoint: SocketAddr,
+    fwmark: Option<u32>,
+) -> Result<Box<dyn Obfuscator>, Error> {
+    Ok(match config {
+        ObfuscatorConfig::Udp2Tcp { endpoint } => Box::new(Udp2Tcp::new(*endpoint, fwmark).await?),
+        ObfuscatorConfig::Shadowsocks {
+            endpoint,
+            password,
+            cipher,
+        } => Box::new(Shadowsocks::new(*endpoint, password, cipher, peer_endpoint, fwmark).await?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shadowsocks::Cipher, *};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, UdpSocket},
+        thread,
+        time::Duration,
+    };
+    use tokio::runtime::Runtime;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Starts a UDP server on the loopback interface that sends every datagram back.
+    fn start_echo_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 2048];
+            while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+                let _ = socket.send_to(&buffer[..len], from);
+            }
+        });
+        address
+    }
+
+    /// Starts a UDP-over-TCP server on the loopback interface, which sends every datagram back
+    /// over the same connection instead of to a WireGuard relay.
+    fn start_udp2tcp_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                thread::spawn(move || {
+                    let mut header = [0u8; 2];
+                    let mut datagram = vec![0u8; usize::from(u16::MAX)];
+                    while stream.read_exact(&mut header).is_ok() {
+                        let len = usize::from(u16::from_be_bytes(header));
+                        if stream.read_exact(&mut datagram[..len]).is_err()
+                            || stream.write_all(&header).is_err()
+                            || stream.write_all(&datagram[..len]).is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    /// Starts a Shadowsocks server on the loopback interface, which relays the datagrams to
+    /// their target and the replies back to the client.
+    fn start_shadowsocks_server(cipher: &str, password: &str) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let cipher = Cipher::new(cipher, password).unwrap();
+        thread::spawn(move || {
+            let upstream = UdpSocket::bind("127.0.0.1:0").unwrap();
+            upstream.set_read_timeout(Some(TIMEOUT)).unwrap();
+            let mut buffer = [0u8; 2048];
+            while let Ok((len, client)) = socket.recv_from(&mut buffer) {
+                let (target, payload) = match cipher.decrypt(&mut buffer[..len]) {
+                    Some((target, payload)) => (target, payload.to_vec()),
+                    None => continue,
+                };
+                upstream.send_to(&payload, target).unwrap();
+                let (len, source) = upstream.recv_from(&mut buffer).unwrap();
+                let reply = cipher.encrypt(source, &buffer[..len]);
+                socket.send_to(&reply, client).unwrap();
+            }
+        });
+        address
+    }
+
+    /// Sends datagrams through the obfuscator described by `config` and checks that they are
+    /// echoed back by the peer at `peer_endpoint`.
+    fn assert_echo_through(config: ObfuscatorConfig, peer_endpoint: SocketAddr) {
+        let mut runtime = Runtime::new().expect("Failed to initialize runtime");
+        let obfuscator = runtime
+            .block_on(create_obfuscator(&config, peer_endpoint, None))
+            .expect("Failed to create obfuscator");
+        let endpoint = obfuscator.endpoint();
+        runtime.spawn(async move {
+            let _ = obfuscator.run().await;
+        });
+
+        let wireguard = UdpSocket::bind("127.0.0.1:0").unwrap();
+        wireguard.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut buffer = [0u8; 2048];
+        for datagram in &[&b"handshake initiation"[..], &[0u8; 1420], &[0xff]] {
+            wireguard.send_to(datagram, endpoint).unwrap();
+            let (len, source) = wireguard.recv_from(&mut buffer).unwrap();
+            assert_eq!(source, endpoint);
+            assert_eq!(&buffer[..len], *datagram);
+        }
+    }
+
+    #[test]
+    fn test_udp2tcp() {
+        let server = start_udp2tcp_server();
+        assert_echo_through(
+            ObfuscatorConfig::Udp2Tcp { endpoint: server },
+            "127.0.0.1:51820".parse().unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_shadowsocks() {
+        for cipher in talpid_types::net::obfuscation::SHADOWSOCKS_UDP_CIPHERS {
+            let peer = start_echo_server();
+            let server = start_shadowsocks_server(cipher, "mullvad");
+            assert_echo_through(
+                ObfuscatorConfig::Shadowsocks {
+                    endpoint: server,
+                    password: "mullvad".to_owned(),
+                    cipher: cipher.to_string(),
+                },
+                peer,
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported_cipher() {
+        let mut runtime = Runtime::new().expect("Failed to initialize runtime");
+        let config = ObfuscatorConfig::Shadowsocks {
+            endpoint: "127.0.0.1:443".parse().unwrap(),
+            password: "mullvad".to_owned(),
+            cipher: "aes-256-cfb".to_owned(),
+        };
+        let result = runtime.block_on(create_obfuscator(
+            &config,
+            "127.0.0.1:51820".parse().unwrap(),
+            None,
+        ));
+        assert!(matches!(result, Err(Error::UnsupportedCipher(_))));
+    }
+}
@@ -0,0 +1,310 @@
+//! Client for the UDP relay of a Shadowsocks server, using the AEAD construction. Every datagram
+//! is sent to the server as `salt || seal(address of the peer || datagram)`, where the key is
+//! derived from the password and a random salt. Replies use the same format, with the address of
+//! the peer as the source.
+
+use super::{Error, Obfuscator};
+use parking_lot::Mutex;
+use rand::RngCore;
+use ring::{aead, hkdf};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+/// Info used to derive the key of every datagram from the master key.
+const SUBKEY_INFO: &[u8] = b"ss-subkey";
+
+/// Address types, as used by SOCKS5.
+const ADDRESS_TYPE_IPV4: u8 = 0x01;
+const ADDRESS_TYPE_IPV6: u8 = 0x04;
+
+/// Largest datagram that can be received.
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+/// Relays the datagrams through a Shadowsocks server.
+pub struct Shadowsocks {
+    local_socket: UdpSocket,
+    server_socket: UdpSocket,
+    local_addr: SocketAddr,
+    peer_endpoint: SocketAddr,
+    cipher: Cipher,
+}
+
+impl Shadowsocks {
+    pub async fn new(
+        endpoint: SocketAddr,
+        password: &str,
+        cipher: &str,
+        peer_endpoint: SocketAddr,
+        fwmark: Option<u32>,
+    ) -> Result<Self, Error> {
+        let cipher = Cipher::new(cipher, password)?;
+
+        let local_socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .await
+            .map_err(Error::BindLocalSocket)?;
+        let local_addr = local_socket
+            .local_addr()
+            .map_err(Error::GetLocalUdpAddress)?;
+        let server_socket = Self::open_server_socket(endpoint, fwmark)
+            .and_then(UdpSocket::from_std)
+            .map_err(Error::ShadowsocksSocket)?;
+
+        Ok(Self {
+            local_socket,
+            server_socket,
+            local_addr,
+            peer_endpoint,
+            cipher,
+        })
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn open_server_socket(
+        endpoint: SocketAddr,
+        fwmark: Option<u32>,
+    ) -> std::io::Result<std::net::UdpSocket> {
+        let domain = if endpoint.is_ipv4() {
+            Domain::ipv4()
+        } else {
+            Domain::ipv6()
+        };
+        let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))?;
+        #[cfg(target_os = "linux")]
+        if let Some(fwmark) = fwmark {
+            socket.set_mark(fwmark)?;
+        }
+        socket.connect(&endpoint.into())?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into_udp_socket())
+    }
+}
+
+#[async_trait::async_trait]
+impl Obfuscator for Shadowsocks {
+    fn endpoint(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(self: Box<Self>) -> Result<(), Error> {
+        let Self {
+            local_socket,
+            server_socket,
+            peer_endpoint,
+            cipher,
+            ..
+        } = *self;
+        let (mut local_rx, mut local_tx) = local_socket.split();
+        let (mut server_rx, mut server_tx) = server_socket.split();
+        // Replies are sent to the address that WireGuard last sent from.
+        let wireguard_addr = Mutex::new(None);
+
+        let upstream = async {
+            let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+            loop {
+                let (len, source) = local_rx
+                    .recv_from(&mut buffer)
+                    .await
+                    .map_err(Error::Forward)?;
+                *wireguard_addr.lock() = Some(source);
+                let datagram = cipher.encrypt(peer_endpoint, &buffer[..len]);
+                server_tx.send(&datagram).await.map_err(Error::Forward)?;
+            }
+        };
+
+        let downstream = async {
+            let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+            loop {
+                let len = server_rx.recv(&mut buffer).await.map_err(Error::Forward)?;
+                let payload = match cipher.decrypt(&mut buffer[..len]) {
+                    Some((_source, payload)) => payload,
+                    None => {
+                        log::debug!("Dropping invalid datagram from the Shadowsocks server");
+                        continue;
+                    }
+                };
+                let destination = *wireguard_addr.lock();
+                if let Some(destination) = destination {
+                    local_tx
+                        .send_to(payload, &destination)
+                        .await
+                        .map_err(Error::Forward)?;
+                }
+            }
+        };
+
+        futures::future::try_join(upstream, downstream)
+            .await
+            .map(|((), ())| ())
+    }
+}
+
+/// AEAD cipher of a Shadowsocks server.
+pub(super) struct Cipher {
+    algorithm: &'static aead::Algorithm,
+    master_key: Vec<u8>,
+}
+
+impl Cipher {
+    pub fn new(name: &str, password: &str) -> Result<Self, Error> {
+        let algorithm = match name {
+            "aes-128-gcm" => &aead::AES_128_GCM,
+            "aes-256-gcm" => &aead::AES_256_GCM,
+            "chacha20-ietf-poly1305" => &aead::CHACHA20_POLY1305,
+            _ => return Err(Error::UnsupportedCipher(name.to_owned())),
+        };
+        Ok(Cipher {
+            algorithm,
+            master_key: bytes_to_key(password.as_bytes(), algorithm.key_len()),
+        })
+    }
+
+    /// Returns `payload`, addressed to `address` and encrypted with a new random salt.
+    pub fn encrypt(&self, address: SocketAddr, payload: &[u8]) -> Vec<u8> {
+        let mut salt = vec![0u8; self.algorithm.key_len()];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+
+        let mut sealed =
+            Vec::with_capacity(address_len(&address) + payload.len() + self.algorithm.tag_len());
+        write_address(&mut sealed, address);
+        sealed.extend_from_slice(payload);
+        self.key(&salt)
+            .seal_in_place_append_tag(zero_nonce(), aead::Aad::empty(), &mut sealed)
+            .expect("Datagram is too large to encrypt");
+
+        salt.append(&mut sealed);
+        salt
+    }
+
+    /// Decrypts `datagram` in place and returns the address and payload in it. Returns `None` if
+    /// the datagram is invalid.
+    pub fn decrypt<'a>(&self, datagram: &'a mut [u8]) -> Option<(SocketAddr, &'a [u8])> {
+        if datagram.len() < self.algorithm.key_len() {
+            return None;
+        }
+        let (salt, sealed) = datagram.split_at_mut(self.algorithm.key_len());
+        let plaintext = self
+            .key(salt)
+            .open_in_place(zero_nonce(), aead::Aad::empty(), sealed)
+            .ok()?;
+        let (address, len) = read_address(plaintext)?;
+        Some((address, &plaintext[len..]))
+    }
+
+    /// Returns the key of the datagram with the given salt.
+    fn key(&self, salt: &[u8]) -> aead::LessSafeKey {
+        let prk =
+            hkdf::Salt::new(hkdf::HKDF_SHA1_FOR_LEGACY_USE_ONLY, salt).extract(&self.master_key);
+        let okm = prk
+            .expand(&[SUBKEY_INFO], self.algorithm)
+            .expect("Key length is valid for HKDF");
+        aead::LessSafeKey::new(aead::UnboundKey::from(okm))
+    }
+}
+
+/// Every datagram has its own key, so the nonce is always zero.
+fn zero_nonce() -> aead::Nonce {
+    aead::Nonce::assume_unique_for_key([0u8; aead::NONCE_LEN])
+}
+
+/// Derives the master key from the password the same way as OpenSSL's `EVP_BytesToKey`.
+fn bytes_to_key(password: &[u8], key_len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(key_len);
+    let mut digest: Vec<u8> = Vec::new();
+    while key.len() < key_len {
+        digest.extend_from_slice(password);
+        digest = md5::compute(&digest).0.to_vec();
+        key.extend_from_slice(&digest);
+    }
+    key.truncate(key_len);
+    key
+}
+
+fn address_len(address: &SocketAddr) -> usize {
+    match address {
+        SocketAddr::V4(_) => 1 + 4 + 2,
+        SocketAddr::V6(_) => 1 + 16 + 2,
+    }
+}
+
+fn write_address(buffer: &mut Vec<u8>, address: SocketAddr) {
+    match address.ip() {
+        IpAddr::V4(ip) => {
+            buffer.push(ADDRESS_TYPE_IPV4);
+            buffer.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buffer.push(ADDRESS_TYPE_IPV6);
+            buffer.extend_from_slice(&ip.octets());
+        }
+    }
+    buffer.extend_from_slice(&address.port().to_be_bytes());
+}
+
+/// Returns the address at the start of `buffer` and its length in bytes.
+fn read_address(buffer: &[u8]) -> Option<(SocketAddr, usize)> {
+    let ip = match *buffer.first()? {
+        ADDRESS_TYPE_IPV4 if buffer.len() >= 1 + 4 + 2 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&buffer[1..5]);
+            IpAddr::from(octets)
+        }
+        ADDRESS_TYPE_IPV6 if buffer.len() >= 1 + 16 + 2 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&buffer[1..17]);
+            IpAddr::from(octets)
+        }
+        _ => return None,
+    };
+    let address = SocketAddr::new(ip, 0);
+    let len = address_len(&address);
+    let port = u16::from_be_bytes([buffer[len - 2], buffer[len - 1]]);
+    Some((SocketAddr::new(ip, port), len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bytes_to_key() {
+        let key = "b07d30a4e225b94a6134650e1bce2b35ee8122fab5a6e6e7d47b7babdfce0f98";
+        assert_eq!(hex::encode(bytes_to_key(b"mullvad", 32)), key);
+        assert_eq!(hex::encode(bytes_to_key(b"mullvad", 16)), &key[..32]);
+    }
+
+    #[test]
+    fn test_address() {
+        for address in &["10.64.0.1:51820", "[fc00:bbbb:bbbb:bb01::1]:53"] {
+            let address: SocketAddr = address.parse().unwrap();
+            let mut buffer = Vec::new();
+            write_address(&mut buffer, address);
+            buffer.extend_from_slice(b"payload");
+            assert_eq!(
+                read_address(&buffer),
+                Some((address, address_len(&address)))
+            );
+        }
+        assert_eq!(read_address(&[ADDRESS_TYPE_IPV4, 10, 64, 0, 1]), None);
+        assert_eq!(
+            read_address(&[0x03, 4, b'a', b'b', b'c', b'd', 0, 53]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let cipher = Cipher::new("aes-256-gcm", "mullvad").unwrap();
+        let address = "10.64.0.1:51820".parse().unwrap();
+        let mut datagram = cipher.encrypt(address, b"payload");
+        assert_eq!(
+            cipher.decrypt(&mut datagram.clone()),
+            Some((address, &b"payload"[..]))
+        );
+
+        let last = datagram.len() - 1;
+        datagram[last] ^= 1;
+        assert_eq!(cipher.decrypt(&mut datagram), None);
+        assert_eq!(cipher.decrypt(&mut [0u8; 8]), None);
+    }
+}
@@ -0,0 +1,55 @@
+use super::{Error, Obfuscator};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use udp_over_tcp::TcpOptions;
+
+/// Sends the datagrams over a TCP stream to a UDP-over-TCP server.
+pub struct Udp2Tcp {
+    udp2tcp: udp_over_tcp::Udp2Tcp,
+    local_addr: SocketAddr,
+}
+
+impl Udp2Tcp {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub async fn new(endpoint: SocketAddr, fwmark: Option<u32>) -> Result<Self, Error> {
+        let listen_addr = if endpoint.is_ipv4() {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)
+        } else {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)
+        };
+
+        let udp2tcp = udp_over_tcp::Udp2Tcp::new(
+            listen_addr,
+            endpoint,
+            Some(&TcpOptions {
+                #[cfg(target_os = "linux")]
+                fwmark,
+                ..TcpOptions::default()
+            }),
+        )
+        .await
+        .map_err(Error::Udp2TcpError)?;
+
+        let local_addr = udp2tcp
+            .local_udp_addr()
+            .map_err(Error::GetLocalUdpAddress)?;
+
+        Ok(Self {
+            udp2tcp,
+            local_addr,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Obfuscator for Udp2Tcp {
+    fn endpoint(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(self: Box<Self>) -> Result<(), Error> {
+        self.udp2tcp
+            .run()
+            .await
+            .map_err(|error| Error::RunUdp2Tcp(error.to_string()))
+    }
+}
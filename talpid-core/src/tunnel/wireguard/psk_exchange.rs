@@ -0,0 +1,213 @@
+//! Negotiation of a preshared key with the relay, using a post-quantum key encapsulation mechanism
+//! (KEM). The relay runs a gRPC service that is only reachable inside the tunnel. The client sends
+//! the public key of an ephemeral KEM keypair, and the relay replies with a ciphertext that
+//! encapsulates a random secret, which becomes the preshared key. The relay assigns the key to the
+//! peer with a new WireGuard public key that the client also sends, so the client can replace its
+//! keys once the negotiation is done.
+
+use std::{net::SocketAddr, time::Duration};
+use talpid_types::net::wireguard::{PresharedKey, PublicKey};
+use tonic::transport::{Channel, Endpoint};
+
+mod proto {
+    tonic::include_proto!("tunnel_config");
+}
+use proto::{post_quantum_secure_client::PostQuantumSecureClient, KemPubkey, PskRequest};
+
+/// Port of the service on the gateway of the relay.
+pub const CONFIG_SERVICE_PORT: u16 = 1337;
+
+/// Name of the KEM, as expected by the service.
+const ALGORITHM_NAME: &str = "Kyber1024";
+
+/// Time to wait for the whole negotiation to complete.
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Preshared key negotiation errors
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to connect to the service on the relay
+    #[error(display = "Failed to connect to the config service")]
+    Connect(#[error(source)] tonic::transport::Error),
+
+    /// The service on the relay failed to handle the request
+    #[error(display = "The config service rejected the key exchange: {}", _0)]
+    Request(tonic::Status),
+
+    /// The negotiation did not complete in time
+    #[error(display = "Timed out while negotiating a preshared key")]
+    Timeout,
+
+    /// Failed to generate the ephemeral KEM keypair
+    #[error(display = "Failed to generate a KEM keypair")]
+    GenerateKeypair,
+
+    /// The ciphertext of the relay could not be decapsulated
+    #[error(display = "Failed to decapsulate the preshared key")]
+    Decapsulate,
+}
+
+/// Negotiates a preshared key with the service at `service_address`. The tunnel currently uses
+/// `current_pubkey`, and the relay will only accept the returned key along with `new_pubkey`.
+pub async fn negotiate_psk(
+    service_address: SocketAddr,
+    current_pubkey: PublicKey,
+    new_pubkey: PublicKey,
+) -> Result<PresharedKey, Error> {
+    tokio::time::timeout(
+        NEGOTIATION_TIMEOUT,
+        negotiate_psk_inner(service_address, current_pubkey, new_pubkey),
+    )
+    .await
+    .map_err(|_| Error::Timeout)?
+}
+
+async fn negotiate_psk_inner(
+    service_address: SocketAddr,
+    current_pubkey: PublicKey,
+    new_pubkey: PublicKey,
+) -> Result<PresharedKey, Error> {
+    let kem_keypair =
+        pqc_kyber::keypair(&mut rand_core::OsRng).map_err(|_| Error::GenerateKeypair)?;
+
+    let mut client = new_client(service_address).await?;
+    let response = client
+        .psk_exchange(PskRequest {
+            wg_pubkey: current_pubkey.as_bytes().to_vec(),
+            wg_psk_pubkey: new_pubkey.as_bytes().to_vec(),
+            kem_pubkey: Some(KemPubkey {
+                algorithm_name: ALGORITHM_NAME.to_owned(),
+                key_data: kem_keypair.public.to_vec(),
+            }),
+        })
+        .await
+        .map_err(Error::Request)?;
+
+    let ciphertext = response.into_inner().ciphertext;
+    let shared_secret =
+        pqc_kyber::decapsulate(&ciphertext, &kem_keypair.secret).map_err(|_| Error::Decapsulate)?;
+    Ok(PresharedKey::from(shared_secret))
+}
+
+async fn new_client(
+    service_address: SocketAddr,
+) -> Result<PostQuantumSecureClient<Channel>, Error> {
+    let channel = Endpoint::from_shared(format!("http://{}", service_address))
+        .expect("Service address is a valid URI")
+        .connect()
+        .await
+        .map_err(Error::Connect)?;
+    Ok(PostQuantumSecureClient::new(channel))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        proto::{
+            post_quantum_secure_server::{PostQuantumSecure, PostQuantumSecureServer},
+            PskResponse,
+        },
+        *,
+    };
+    use std::{
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+    };
+    use talpid_types::net::wireguard::PrivateKey;
+    use tokio::{net::TcpListener, runtime::Runtime};
+    use tonic::{transport::Server, Request, Response, Status};
+
+    /// The current public key, the ephemeral public key and the resulting preshared key.
+    type Exchange = (Vec<u8>, Vec<u8>, PresharedKey);
+
+    /// Stands in for the service on the relay. It records the keys of every exchange.
+    #[derive(Default, Clone)]
+    struct MockService {
+        exchanges: Arc<Mutex<Vec<Exchange>>>,
+    }
+
+    #[tonic::async_trait]
+    impl PostQuantumSecure for MockService {
+        async fn psk_exchange(
+            &self,
+            request: Request<PskRequest>,
+        ) -> Result<Response<PskResponse>, Status> {
+            let request = request.into_inner();
+            let kem_pubkey = request
+                .kem_pubkey
+                .ok_or_else(|| Status::invalid_argument("missing KEM public key"))?;
+            if kem_pubkey.algorithm_name != ALGORITHM_NAME {
+                return Err(Status::unimplemented("unsupported KEM"));
+            }
+            let (ciphertext, shared_secret) =
+                pqc_kyber::encapsulate(&kem_pubkey.key_data, &mut rand_core::OsRng)
+                    .map_err(|_| Status::invalid_argument("invalid KEM public key"))?;
+
+            self.exchanges.lock().unwrap().push((
+                request.wg_pubkey,
+                request.wg_psk_pubkey,
+                PresharedKey::from(shared_secret),
+            ));
+            Ok(Response::new(PskResponse {
+                ciphertext: ciphertext.to_vec(),
+            }))
+        }
+    }
+
+    /// Starts the mock service on the loopback interface and returns its address.
+    fn start_mock_service(runtime: &mut Runtime, service: MockService) -> SocketAddr {
+        let mut listener = runtime
+            .block_on(TcpListener::bind((Ipv4Addr::LOCALHOST, 0)))
+            .expect("Failed to bind listener");
+        let address = listener.local_addr().unwrap();
+        runtime.spawn(async move {
+            let _ = Server::builder()
+                .add_service(PostQuantumSecureServer::new(service))
+                .serve_with_incoming(listener.incoming())
+                .await;
+        });
+        address
+    }
+
+    #[test]
+    fn test_negotiate_psk() {
+        let mut runtime = Runtime::new().expect("Failed to initialize runtime");
+        let service = MockService::default();
+        let address = start_mock_service(&mut runtime, service.clone());
+
+        let current_pubkey = PrivateKey::new_from_random().public_key();
+        let new_pubkey = PrivateKey::new_from_random().public_key();
+        let psk = runtime
+            .block_on(negotiate_psk(
+                address,
+                current_pubkey.clone(),
+                new_pubkey.clone(),
+            ))
+            .expect("Failed to negotiate a preshared key");
+
+        let exchanges = service.exchanges.lock().unwrap();
+        assert_eq!(
+            *exchanges,
+            vec![(
+                current_pubkey.as_bytes().to_vec(),
+                new_pubkey.as_bytes().to_vec(),
+                psk
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_service() {
+        let mut runtime = Runtime::new().expect("Failed to initialize runtime");
+        // Nothing listens on the address once the listener has been dropped.
+        let address = runtime
+            .block_on(TcpListener::bind((Ipv4Addr::LOCALHOST, 0)))
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let key = PrivateKey::new_from_random().public_key();
+
+        let result = runtime.block_on(negotiate_psk(address, key.clone(), key));
+        assert!(matches!(result, Err(Error::Connect(_))));
+    }
+}
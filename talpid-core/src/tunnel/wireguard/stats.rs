@@ -1,5 +1,6 @@
 #[cfg(target_os = "linux")]
 use super::wireguard_kernel::wg_message::{DeviceMessage, DeviceNla, PeerNla};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 
 #[derive(err_derive::Error, Debug, PartialEq)]
@@ -19,12 +20,16 @@ pub enum Error {
 pub struct Stats {
     pub tx_bytes: u64,
     pub rx_bytes: u64,
+    /// Time of the latest handshake with any peer, if there has been one
+    pub last_handshake: Option<SystemTime>,
 }
 
 impl Stats {
     pub fn parse_config_str(config: &str) -> Result<Self, Error> {
         let mut tx_bytes = None;
         let mut rx_bytes = None;
+        let mut last_handshake = None;
+        let mut handshake_secs = 0;
 
         // parts iterates over keys and values
         let parts = config.split('\n').filter_map(|line| {
@@ -52,22 +57,51 @@ impl Stats {
                             .map_err(|err| Error::IntParseError(value.to_string(), err))?,
                     );
                 }
+                // The seconds of a peer's handshake time always precede the nanoseconds
+                "last_handshake_time_sec" => {
+                    handshake_secs = value
+                        .trim()
+                        .parse()
+                        .map_err(|err| Error::IntParseError(value.to_string(), err))?;
+                }
+                "last_handshake_time_nsec" => {
+                    let nanos = value
+                        .trim()
+                        .parse()
+                        .map_err(|err| Error::IntParseError(value.to_string(), err))?;
+                    last_handshake = Self::latest_handshake(last_handshake, handshake_secs, nanos);
+                }
 
                 _ => continue,
             }
         }
 
         match (tx_bytes, rx_bytes) {
-            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self { tx_bytes, rx_bytes }),
+            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self {
+                tx_bytes,
+                rx_bytes,
+                last_handshake,
+            }),
             _ => Err(Error::KeyNotFoundError),
         }
     }
 
+    /// Returns the later of `latest` and the handshake time given by `secs` and `nanos`, which
+    /// are zero if there has not been a handshake.
+    fn latest_handshake(latest: Option<SystemTime>, secs: u64, nanos: u32) -> Option<SystemTime> {
+        if secs == 0 && nanos == 0 {
+            return latest;
+        }
+        let handshake = UNIX_EPOCH + Duration::new(secs, nanos);
+        Some(latest.map_or(handshake, |latest| latest.max(handshake)))
+    }
+
     #[cfg(target_os = "linux")]
     pub fn parse_device_message(message: &DeviceMessage) -> Self {
         // iterate over device attributes
         let mut tx_bytes = 0;
         let mut rx_bytes = 0;
+        let mut last_handshake = None;
         for nla in &message.nlas {
             if let DeviceNla::Peers(peers) = nla {
                 // iterate over all peer attributes
@@ -77,13 +111,24 @@ impl Stats {
                     match peer_nla {
                         PeerNla::TxBytes(bytes) => tx_bytes += *bytes,
                         PeerNla::RxBytes(bytes) => rx_bytes += *bytes,
+                        PeerNla::LastHandshakeTime(time) => {
+                            last_handshake = Self::latest_handshake(
+                                last_handshake,
+                                time.tv_sec() as u64,
+                                time.tv_nsec() as u32,
+                            );
+                        }
                         _ => continue,
                     };
                 }
             }
         }
 
-        Self { tx_bytes, rx_bytes }
+        Self {
+            tx_bytes,
+            rx_bytes,
+            last_handshake,
+        }
     }
 }
 
@@ -91,6 +136,7 @@ impl Stats {
 #[cfg(test)]
 mod test {
     use super::{Error, Stats};
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_parsing() {
@@ -99,6 +145,10 @@ mod test {
         let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
         assert_eq!(stats.rx_bytes, 2396);
         assert_eq!(stats.tx_bytes, 2740);
+        assert_eq!(
+            stats.last_handshake,
+            Some(UNIX_EPOCH + Duration::new(1578420649, 369416131))
+        );
     }
 
     #[test]
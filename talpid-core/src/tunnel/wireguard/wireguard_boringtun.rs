@@ -0,0 +1,635 @@
+//! WireGuard implementation that runs in the daemon process, on top of the BoringTun protocol
+//! implementation. Unlike the other implementations on Linux, it requires neither the kernel
+//! module, NetworkManager nor wireguard-go.
+
+use super::{stats::Stats, wireguard_go::WgGoTunnel, Config, Tunnel, TunnelError};
+use crate::tunnel::tun_provider::{Tun, TunProvider};
+use boringtun::{
+    crypto::x25519::{X25519PublicKey, X25519SecretKey},
+    noise::{Tunn, TunnResult},
+};
+use ipnetwork::IpNetwork;
+use nix::poll::{poll, PollFd, PollFlags};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    os::unix::io::{AsRawFd, FromRawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+type Result<T> = std::result::Result<T, TunnelError>;
+
+/// Largest packet that can be read from the tunnel device or a socket.
+const MAX_PACKET_SIZE: usize = u16::MAX as usize;
+/// Space needed on top of a packet for the WireGuard header and authentication tag.
+const WIREGUARD_OVERHEAD: usize = 32;
+/// Length of the packet information header that precedes every packet on the tunnel device.
+const PACKET_INFO_LEN: usize = 4;
+/// Interval at which the timers of the peers are updated, as recommended by BoringTun.
+const TIMER_INTERVAL: Duration = Duration::from_millis(250);
+/// BoringTun derives the time of the latest handshake from the current time whenever it is
+/// asked, so the same handshake is reported at slightly different times. Handshakes are at least
+/// two minutes apart, so a difference below this is not a new handshake.
+const HANDSHAKE_TIME_TOLERANCE: Duration = Duration::from_secs(1);
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+pub struct BoringTunnel {
+    interface_name: String,
+    peers: Arc<Vec<Peer>>,
+    stop_flag: Arc<AtomicBool>,
+    worker: Option<thread::JoinHandle<()>>,
+    // holding on to the tunnel device ensures that the interface exists until the worker has
+    // stopped
+    tunnel_device: Tun,
+}
+
+struct Peer {
+    tunn: Box<Tunn>,
+    /// Socket connected to the endpoint of the peer.
+    socket: UdpSocket,
+    allowed_ips: Vec<IpNetwork>,
+    /// Time of the latest handshake, as first reported by BoringTun.
+    last_handshake: Mutex<Option<SystemTime>>,
+}
+
+impl BoringTunnel {
+    pub fn start_tunnel(
+        config: &Config,
+        tun_provider: &mut TunProvider,
+        routes: impl Iterator<Item = IpNetwork>,
+    ) -> Result<Self> {
+        let tunnel_device = tun_provider
+            .get_tun(WgGoTunnel::create_tunnel_config(config, routes))
+            .map_err(TunnelError::SetupTunnelDeviceError)?;
+        let interface_name = tunnel_device.interface_name().to_string();
+
+        let mut tunnel = BoringTunnel {
+            interface_name,
+            peers: Arc::new(vec![]),
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            worker: None,
+            tunnel_device,
+        };
+        tunnel.start_worker(config)?;
+        Ok(tunnel)
+    }
+
+    /// Creates the peers of `config` and starts a worker that moves packets between them and the
+    /// tunnel device.
+    fn start_worker(&mut self, config: &Config) -> Result<()> {
+        let tunnel_fd = nix::unistd::dup(self.tunnel_device.as_raw_fd())
+            .map_err(TunnelError::FdDuplicationError)?;
+        // The duplicated descriptor shares the non-blocking mode of the device.
+        let tunnel_file = unsafe { File::from_raw_fd(tunnel_fd) };
+
+        let private_key = Arc::new(
+            hex::encode(config.tunnel.private_key.to_bytes())
+                .parse::<X25519SecretKey>()
+                .map_err(|error| {
+                    log::error!("Invalid private key: {}", error);
+                    TunnelError::FatalStartWireguardError
+                })?,
+        );
+        let peers = config
+            .peers
+            .iter()
+            .enumerate()
+            .map(|(index, peer)| {
+                let tunn = Tunn::new(
+                    private_key.clone(),
+                    Arc::new(X25519PublicKey::from(&peer.public_key.as_bytes()[..])),
+                    peer.psk.as_ref().map(|psk| *psk.as_bytes()),
+                    None,
+                    index as u32,
+                    None,
+                )
+                .map_err(|error| {
+                    log::error!("Failed to create WireGuard peer: {}", error);
+                    TunnelError::FatalStartWireguardError
+                })?;
+                let socket = Self::open_socket(peer.endpoint, config.fwmark)
+                    .map_err(TunnelError::OpenSocketError)?;
+                Ok(Peer {
+                    tunn,
+                    socket,
+                    allowed_ips: peer.allowed_ips.clone(),
+                    last_handshake: Mutex::new(None),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.peers = Arc::new(peers);
+        self.stop_flag = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let peers = self.peers.clone();
+            let stop_flag = self.stop_flag.clone();
+            thread::spawn(move || Worker::new(tunnel_file, peers, stop_flag).run())
+        };
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    /// Opens a non-blocking UDP socket that is connected to `endpoint` and whose traffic bypasses
+    /// the tunnel.
+    fn open_socket(endpoint: SocketAddr, fwmark: u32) -> io::Result<UdpSocket> {
+        let (domain, bind_addr) = match endpoint {
+            SocketAddr::V4(_) => (Domain::ipv4(), SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))),
+            SocketAddr::V6(_) => (Domain::ipv6(), SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))),
+        };
+        let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))?;
+        socket.set_mark(fwmark)?;
+        socket.bind(&bind_addr.into())?;
+        socket.connect(&endpoint.into())?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into_udp_socket())
+    }
+
+    fn stop_worker(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.stop_flag.store(true, Ordering::SeqCst);
+            if worker.join().is_err() {
+                log::error!("The userspace WireGuard worker panicked");
+            }
+        }
+    }
+}
+
+impl Drop for BoringTunnel {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+impl Tunnel for BoringTunnel {
+    fn get_interface_name(&self) -> String {
+        self.interface_name.clone()
+    }
+
+    fn get_tunnel_stats(&self) -> Result<Stats> {
+        let mut stats = Stats::default();
+        for peer in self.peers.iter() {
+            let (_, tx_bytes, rx_bytes, ..) = peer.tunn.stats();
+            stats.tx_bytes += tx_bytes as u64;
+            stats.rx_bytes += rx_bytes as u64;
+
+            // Despite its name, this is the time of the handshake since the Unix epoch
+            let reported = peer
+                .tunn
+                .time_since_last_handshake()
+                .map(|since_epoch| UNIX_EPOCH + since_epoch);
+            let mut last_handshake = peer.last_handshake.lock().expect("Peer lock poisoned");
+            *last_handshake = stable_handshake_time(*last_handshake, reported);
+            stats.last_handshake = stats.last_handshake.max(*last_handshake);
+        }
+        Ok(stats)
+    }
+
+    fn set_config(&mut self, config: &Config) -> Result<()> {
+        // The sessions are keyed on the private key and preshared keys, so the peers are
+        // replaced. Packets on the tunnel device are left there until the new worker reads them.
+        self.stop_worker();
+        self.start_worker(config)
+    }
+
+    fn stop(mut self: Box<Self>) -> Result<()> {
+        self.stop_worker();
+        Ok(())
+    }
+}
+
+/// Moves packets between the tunnel device and the peers until it is told to stop.
+struct Worker {
+    tunnel_file: File,
+    peers: Arc<Vec<Peer>>,
+    stop_flag: Arc<AtomicBool>,
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
+}
+
+impl Worker {
+    fn new(tunnel_file: File, peers: Arc<Vec<Peer>>, stop_flag: Arc<AtomicBool>) -> Self {
+        Worker {
+            tunnel_file,
+            peers,
+            stop_flag,
+            read_buffer: vec![0u8; MAX_PACKET_SIZE],
+            write_buffer: vec![0u8; PACKET_INFO_LEN + MAX_PACKET_SIZE + WIREGUARD_OVERHEAD],
+        }
+    }
+
+    fn run(mut self) {
+        let mut poll_fds: Vec<PollFd> = std::iter::once(self.tunnel_file.as_raw_fd())
+            .chain(self.peers.iter().map(|peer| peer.socket.as_raw_fd()))
+            .map(|fd| PollFd::new(fd, PollFlags::POLLIN))
+            .collect();
+        let mut next_timer_update = Instant::now();
+
+        while !self.stop_flag.load(Ordering::SeqCst) {
+            let timeout = next_timer_update.saturating_duration_since(Instant::now());
+            match poll(&mut poll_fds, timeout.as_millis() as libc::c_int) {
+                Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => (),
+                Err(error) => {
+                    log::error!("Failed to poll the tunnel device and sockets: {}", error);
+                    return;
+                }
+            }
+
+            let readable = |poll_fd: &PollFd| {
+                poll_fd
+                    .revents()
+                    .map(|events| events.intersects(PollFlags::POLLIN | PollFlags::POLLERR))
+                    .unwrap_or(false)
+            };
+            if readable(&poll_fds[0]) {
+                self.read_tunnel_device();
+            }
+            for (index, poll_fd) in poll_fds[1..].iter().enumerate() {
+                if readable(poll_fd) {
+                    self.read_socket(index);
+                }
+            }
+
+            if Instant::now() >= next_timer_update {
+                for index in 0..self.peers.len() {
+                    self.update_timers(index);
+                }
+                next_timer_update = Instant::now() + TIMER_INTERVAL;
+            }
+        }
+    }
+
+    /// Encrypts all pending packets on the tunnel device and sends them to their peers.
+    fn read_tunnel_device(&mut self) {
+        loop {
+            let len = match self.tunnel_file.read(&mut self.read_buffer) {
+                Ok(len) => len,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    log::error!("Failed to read from the tunnel device: {}", error);
+                    return;
+                }
+            };
+            if len <= PACKET_INFO_LEN {
+                continue;
+            }
+            let packet = &self.read_buffer[PACKET_INFO_LEN..len];
+            let peer = match destination_address(packet)
+                .and_then(|destination| route_to_peer(&self.peers, destination))
+            {
+                Some(peer) => &self.peers[peer],
+                None => continue,
+            };
+            match peer.tunn.encapsulate(packet, &mut self.write_buffer) {
+                TunnResult::WriteToNetwork(datagram) => send(&peer.socket, datagram),
+                TunnResult::Err(error) => log::debug!("Failed to encapsulate packet: {:?}", error),
+                _ => (),
+            }
+        }
+    }
+
+    /// Decrypts all pending datagrams from the peer at `index` and writes the packets to the
+    /// tunnel device.
+    fn read_socket(&mut self, index: usize) {
+        let peers = self.peers.clone();
+        let peer = &peers[index];
+        loop {
+            let len = match peer.socket.recv(&mut self.read_buffer) {
+                Ok(len) => len,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    log::debug!("Failed to receive from WireGuard peer: {}", error);
+                    return;
+                }
+            };
+            let source = peer.socket.peer_addr().ok().map(|addr| addr.ip());
+
+            let (len, address, ethertype) = match peer.tunn.decapsulate(
+                source,
+                &self.read_buffer[..len],
+                &mut self.write_buffer[PACKET_INFO_LEN..],
+            ) {
+                TunnResult::WriteToNetwork(datagram) => {
+                    send(&peer.socket, datagram);
+                    // Packets that were queued while waiting for the handshake are sent now.
+                    while let TunnResult::WriteToNetwork(datagram) =
+                        peer.tunn
+                            .decapsulate(None, &[], &mut self.write_buffer[PACKET_INFO_LEN..])
+                    {
+                        send(&peer.socket, datagram);
+                    }
+                    continue;
+                }
+                TunnResult::WriteToTunnelV4(packet, address) => {
+                    (packet.len(), IpAddr::V4(address), ETHERTYPE_IPV4)
+                }
+                TunnResult::WriteToTunnelV6(packet, address) => {
+                    (packet.len(), IpAddr::V6(address), ETHERTYPE_IPV6)
+                }
+                TunnResult::Err(error) => {
+                    log::debug!("Failed to decapsulate datagram: {:?}", error);
+                    continue;
+                }
+                TunnResult::Done => continue,
+            };
+
+            if !peer
+                .allowed_ips
+                .iter()
+                .any(|network| network.contains(address))
+            {
+                log::trace!(
+                    "Dropping packet from {} outside of the allowed IPs",
+                    address
+                );
+                continue;
+            }
+
+            self.write_buffer[..2].copy_from_slice(&[0, 0]);
+            self.write_buffer[2..PACKET_INFO_LEN].copy_from_slice(&ethertype.to_be_bytes());
+            if let Err(error) = self
+                .tunnel_file
+                .write(&self.write_buffer[..PACKET_INFO_LEN + len])
+            {
+                log::debug!("Failed to write to the tunnel device: {}", error);
+            }
+        }
+    }
+
+    /// Sends the handshakes and keepalives that are due for the peer at `index`.
+    fn update_timers(&mut self, index: usize) {
+        let peer = &self.peers[index];
+        match peer.tunn.update_timers(&mut self.write_buffer) {
+            TunnResult::WriteToNetwork(datagram) => send(&peer.socket, datagram),
+            TunnResult::Err(error) => log::trace!("WireGuard timer error: {:?}", error),
+            _ => (),
+        }
+    }
+}
+
+fn send(socket: &UdpSocket, datagram: &[u8]) {
+    if let Err(error) = socket.send(datagram) {
+        log::debug!("Failed to send to WireGuard peer: {}", error);
+    }
+}
+
+/// Returns the destination address of an IPv4 or IPv6 packet.
+fn destination_address(packet: &[u8]) -> Option<IpAddr> {
+    match packet.first()? >> 4 {
+        4 if packet.len() >= 20 => {
+            let mut address = [0u8; 4];
+            address.copy_from_slice(&packet[16..20]);
+            Some(IpAddr::from(address))
+        }
+        6 if packet.len() >= 40 => {
+            let mut address = [0u8; 16];
+            address.copy_from_slice(&packet[24..40]);
+            Some(IpAddr::from(address))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the index of the peer whose allowed IPs most specifically match `destination`.
+fn route_to_peer(peers: &[Peer], destination: IpAddr) -> Option<usize> {
+    longest_prefix_match(peers.iter().map(|peer| &peer.allowed_ips[..]), destination)
+}
+
+fn longest_prefix_match<'a>(
+    allowed_ips: impl Iterator<Item = &'a [IpNetwork]>,
+    destination: IpAddr,
+) -> Option<usize> {
+    allowed_ips
+        .enumerate()
+        .flat_map(|(index, networks)| networks.iter().map(move |network| (index, network)))
+        .filter(|(_, network)| network.contains(destination))
+        .max_by_key(|(_, network)| network.prefix())
+        .map(|(index, _)| index)
+}
+
+/// Returns the time of the latest handshake, given the time that was previously returned and the
+/// time that BoringTun reports now. The previous time is kept unless the reported time differs
+/// from it by at least `HANDSHAKE_TIME_TOLERANCE`.
+fn stable_handshake_time(
+    previous: Option<SystemTime>,
+    reported: Option<SystemTime>,
+) -> Option<SystemTime> {
+    match (previous, reported) {
+        (Some(previous), Some(reported)) => {
+            let difference = reported
+                .duration_since(previous)
+                .unwrap_or_else(|error| error.duration());
+            if difference < HANDSHAKE_TIME_TOLERANCE {
+                Some(previous)
+            } else {
+                Some(reported)
+            }
+        }
+        // The session may have expired, but the handshake still happened
+        (previous, None) => previous,
+        (None, reported) => reported,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::{io::IntoRawFd, net::UnixDatagram};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Returns an IPv4 packet with the given addresses and payload.
+    fn ipv4_packet(source: [u8; 4], destination: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+        packet[8] = 64;
+        packet[9] = 17;
+        packet[12..16].copy_from_slice(&source);
+        packet[16..20].copy_from_slice(&destination);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn with_packet_info(packet: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0, 0];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(packet);
+        frame
+    }
+
+    /// Runs a worker between a fake tunnel device and a peer on the loopback interface, with a
+    /// BoringTun session on the other end standing in for the relay.
+    #[test]
+    fn test_worker_data_path() {
+        let (device, worker_device) = UnixDatagram::pair().unwrap();
+        worker_device.set_nonblocking(true).unwrap();
+        device.set_read_timeout(Some(TEST_TIMEOUT)).unwrap();
+        let tunnel_file = unsafe { File::from_raw_fd(worker_device.into_raw_fd()) };
+
+        let remote_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let local_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        local_socket
+            .connect(remote_socket.local_addr().unwrap())
+            .unwrap();
+        local_socket.set_nonblocking(true).unwrap();
+        remote_socket
+            .connect(local_socket.local_addr().unwrap())
+            .unwrap();
+        remote_socket.set_read_timeout(Some(TEST_TIMEOUT)).unwrap();
+
+        let local_key = Arc::new(X25519SecretKey::new());
+        let remote_key = Arc::new(X25519SecretKey::new());
+        let remote = Tunn::new(
+            remote_key.clone(),
+            Arc::new(local_key.public_key()),
+            None,
+            None,
+            1,
+            None,
+        )
+        .unwrap();
+        let peers = Arc::new(vec![Peer {
+            tunn: Tunn::new(
+                local_key.clone(),
+                Arc::new(remote_key.public_key()),
+                None,
+                None,
+                0,
+                None,
+            )
+            .unwrap(),
+            socket: local_socket,
+            allowed_ips: vec!["10.0.0.0/8".parse().unwrap()],
+            last_handshake: Mutex::new(None),
+        }]);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let peers = peers.clone();
+            let stop_flag = stop_flag.clone();
+            thread::spawn(move || Worker::new(tunnel_file, peers, stop_flag).run())
+        };
+
+        let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+        let mut out = vec![0u8; MAX_PACKET_SIZE];
+
+        // The first packet is queued until the handshake is done, and then encapsulated.
+        let outgoing = ipv4_packet([10, 64, 0, 2], [10, 0, 0, 1], b"ping");
+        device.send(&with_packet_info(&outgoing)).unwrap();
+        let received = loop {
+            let len = remote_socket.recv(&mut buffer).unwrap();
+            match remote.decapsulate(None, &buffer[..len], &mut out) {
+                TunnResult::WriteToNetwork(datagram) => {
+                    remote_socket.send(datagram).unwrap();
+                }
+                TunnResult::WriteToTunnelV4(packet, _) => break packet.to_vec(),
+                TunnResult::Done => (),
+                result => panic!("Unexpected result from the relay: {:?}", result),
+            }
+        };
+        assert_eq!(received, outgoing);
+        assert!(peers[0].tunn.time_since_last_handshake().is_some());
+
+        // Packets from outside of the allowed IPs are dropped, and the others are written to the
+        // tunnel device.
+        let dropped = ipv4_packet([192, 168, 1, 1], [10, 64, 0, 2], b"dropped");
+        let incoming = ipv4_packet([10, 0, 0, 1], [10, 64, 0, 2], b"pong");
+        for packet in &[dropped, incoming.clone()] {
+            match remote.encapsulate(packet, &mut out) {
+                TunnResult::WriteToNetwork(datagram) => {
+                    remote_socket.send(datagram).unwrap();
+                }
+                result => panic!("Failed to encapsulate packet: {:?}", result),
+            }
+        }
+        let len = device.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], &with_packet_info(&incoming)[..]);
+
+        stop_flag.store(true, Ordering::SeqCst);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_destination_address() {
+        let mut ipv4_packet = [0u8; 20];
+        ipv4_packet[0] = 0x45;
+        ipv4_packet[16..20].copy_from_slice(&[10, 64, 0, 1]);
+        assert_eq!(
+            destination_address(&ipv4_packet),
+            Some("10.64.0.1".parse().unwrap())
+        );
+
+        let mut ipv6_packet = [0u8; 40];
+        ipv6_packet[0] = 0x60;
+        ipv6_packet[24..40].copy_from_slice(
+            &"fc00:bbbb:bbbb:bb01::1"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        assert_eq!(
+            destination_address(&ipv6_packet),
+            Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap())
+        );
+
+        assert_eq!(destination_address(&ipv4_packet[..19]), None);
+        assert_eq!(destination_address(&[]), None);
+        assert_eq!(destination_address(&[0u8; 40]), None);
+    }
+
+    #[test]
+    fn test_stable_handshake_time() {
+        let handshake = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let jitter = Duration::from_millis(10);
+
+        assert_eq!(stable_handshake_time(None, None), None);
+        assert_eq!(
+            stable_handshake_time(None, Some(handshake)),
+            Some(handshake)
+        );
+        assert_eq!(
+            stable_handshake_time(Some(handshake), Some(handshake + jitter)),
+            Some(handshake)
+        );
+        assert_eq!(
+            stable_handshake_time(Some(handshake), Some(handshake - jitter)),
+            Some(handshake)
+        );
+        assert_eq!(
+            stable_handshake_time(Some(handshake), None),
+            Some(handshake)
+        );
+
+        let new_handshake = handshake + Duration::from_secs(120);
+        assert_eq!(
+            stable_handshake_time(Some(handshake), Some(new_handshake)),
+            Some(new_handshake)
+        );
+    }
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let entry: Vec<IpNetwork> = vec!["185.65.135.0/24".parse().unwrap()];
+        let exit: Vec<IpNetwork> = vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()];
+        let peers = [&entry[..], &exit[..]];
+
+        let route = |destination: &str| {
+            longest_prefix_match(peers.iter().cloned(), destination.parse().unwrap())
+        };
+        assert_eq!(route("185.65.135.10"), Some(0));
+        assert_eq!(route("1.1.1.1"), Some(1));
+        assert_eq!(route("2001:db8::1"), Some(1));
+        assert_eq!(
+            longest_prefix_match(std::iter::once(&entry[..]), "1.1.1.1".parse().unwrap()),
+            None
+        );
+    }
+}
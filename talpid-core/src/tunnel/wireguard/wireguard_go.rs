@@ -210,7 +210,10 @@ impl WgGoTunnel {
     }
 
     #[cfg(not(target_os = "windows"))]
-    fn create_tunnel_config(config: &Config, routes: impl Iterator<Item = IpNetwork>) -> TunConfig {
+    pub(super) fn create_tunnel_config(
+        config: &Config,
+        routes: impl Iterator<Item = IpNetwork>,
+    ) -> TunConfig {
         let mut dns_servers = vec![IpAddr::V4(config.ipv4_gateway)];
         dns_servers.extend(config.ipv6_gateway.map(IpAddr::V6));
 
@@ -335,6 +338,16 @@ impl Tunnel for WgGoTunnel {
         result
     }
 
+    fn set_config(&mut self, config: &Config) -> Result<()> {
+        let handle = self.handle.ok_or(TunnelError::SetConfigError)?;
+        let wg_config_str = config.to_userspace_update_format();
+        let status = unsafe { wgSetConfig(handle, wg_config_str.as_ptr() as *const i8) };
+        if status < 0 {
+            return Err(TunnelError::SetConfigError);
+        }
+        Ok(())
+    }
+
     fn stop(mut self: Box<Self>) -> Result<()> {
         self.stop_tunnel()
     }
@@ -392,6 +405,9 @@ extern "C" {
     // Returns the file descriptor of the tunnel IPv4 socket.
     fn wgGetConfig(handle: i32) -> *mut std::os::raw::c_char;
 
+    // Applies a new config to a running tunnel. Returns a negative value on failure.
+    fn wgSetConfig(handle: i32, settings: *const i8) -> i32;
+
     // Frees a pointer allocated by the go runtime - useful to free return value of wgGetConfig
     fn wgFreePtr(ptr: *mut c_void);
 
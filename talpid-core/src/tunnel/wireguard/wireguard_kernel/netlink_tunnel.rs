@@ -97,6 +97,17 @@ impl Tunnel for NetlinkTunnel {
         })
     }
 
+    fn set_config(&mut self, config: &Config) -> std::result::Result<(), TunnelError> {
+        let mut wg = self.netlink_connections.wg_handle.clone();
+        let interface_index = self.interface_index;
+        self.tokio_handle.block_on(async move {
+            wg.set_config(interface_index, config).await.map_err(|err| {
+                log::error!("Failed to apply WireGuard config: {}", err);
+                TunnelError::SetConfigError
+            })
+        })
+    }
+
     fn get_tunnel_stats(&self) -> std::result::Result<Stats, TunnelError> {
         let mut wg = self.netlink_connections.wg_handle.clone();
         let interface_index = self.interface_index;
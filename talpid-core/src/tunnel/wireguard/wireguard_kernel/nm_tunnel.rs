@@ -79,6 +79,19 @@ impl Tunnel for NetworkManagerTunnel {
         }
     }
 
+    fn set_config(&mut self, config: &Config) -> std::result::Result<(), TunnelError> {
+        let tunnel = self.tunnel.as_ref().ok_or(TunnelError::SetConfigError)?;
+        self.network_manager
+            .set_wireguard_config(tunnel, convert_wireguard_config_to_dbus(config))
+            .map_err(|err| {
+                log::error!(
+                    "{}",
+                    err.display_chain_with_msg("Failed to reapply WireGuard config via NM")
+                );
+                TunnelError::SetConfigError
+            })
+    }
+
     fn get_tunnel_stats(&self) -> std::result::Result<Stats, TunnelError> {
         let tunnel = self
             .tunnel
@@ -89,7 +102,16 @@ impl Tunnel for NetworkManagerTunnel {
             .get_tunnel_stats(tunnel)
             .map_err(|_| TunnelError::StatsError(StatsError::KeyNotFoundError))?;
 
-        Ok(Stats { tx_bytes, rx_bytes })
+        Ok(Stats {
+            tx_bytes,
+            rx_bytes,
+            last_handshake: None,
+        })
+    }
+
+    // NetworkManager does not expose the handshakes of the peers
+    fn reports_handshakes(&self) -> bool {
+        false
     }
 
     fn slow_stats_refresh_rate(&self) {
@@ -110,41 +132,9 @@ impl Tunnel for NetworkManagerTunnel {
 fn convert_config_to_dbus(config: &Config) -> DeviceConfig {
     let mut ipv6_config: VariantMap = HashMap::new();
     let mut ipv4_config: VariantMap = HashMap::new();
-    let mut wireguard_config: VariantMap = HashMap::new();
     let mut connection_config: VariantMap = HashMap::new();
-    let mut peer_configs = vec![];
-
-    wireguard_config.insert("mtu".into(), Variant(Box::new(config.mtu as u32)));
-    wireguard_config.insert("fwmark".into(), Variant(Box::new(config.fwmark as u32)));
-    wireguard_config.insert("peer-routes".into(), Variant(Box::new(false)));
-    wireguard_config.insert(
-        "private-key".into(),
-        Variant(Box::new(config.tunnel.private_key.to_base64())),
-    );
-    wireguard_config.insert("private-key-flags".into(), Variant(Box::new(0x0u32)));
-
-    for peer in config.peers.iter() {
-        let mut peer_config: VariantMap = HashMap::new();
-        let allowed_ips = peer
-            .allowed_ips
-            .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>();
-
 
-        peer_config.insert("allowed-ips".into(), Variant(Box::new(allowed_ips)));
-        peer_config.insert(
-            "endpoint".into(),
-            Variant(Box::new(peer.endpoint.to_string())),
-        );
-        peer_config.insert(
-            "public-key".into(),
-            Variant(Box::new(peer.public_key.to_base64())),
-        );
-
-        peer_configs.push(peer_config);
-    }
-    wireguard_config.insert("peers".into(), Variant(Box::new(peer_configs)));
+    let wireguard_config = convert_wireguard_config_to_dbus(config);
 
     connection_config.insert("type".into(), Variant(Box::new("wireguard".to_string())));
     connection_config.insert(
@@ -200,3 +190,46 @@ fn convert_config_to_dbus(config: &Config) -> DeviceConfig {
 
     settings
 }
+
+fn convert_wireguard_config_to_dbus(config: &Config) -> VariantMap {
+    let mut wireguard_config: VariantMap = HashMap::new();
+    let mut peer_configs = vec![];
+
+    wireguard_config.insert("mtu".into(), Variant(Box::new(config.mtu as u32)));
+    wireguard_config.insert("fwmark".into(), Variant(Box::new(config.fwmark as u32)));
+    wireguard_config.insert("peer-routes".into(), Variant(Box::new(false)));
+    wireguard_config.insert(
+        "private-key".into(),
+        Variant(Box::new(config.tunnel.private_key.to_base64())),
+    );
+    wireguard_config.insert("private-key-flags".into(), Variant(Box::new(0x0u32)));
+
+    for peer in config.peers.iter() {
+        let mut peer_config: VariantMap = HashMap::new();
+        let allowed_ips = peer
+            .allowed_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+
+        peer_config.insert("allowed-ips".into(), Variant(Box::new(allowed_ips)));
+        peer_config.insert(
+            "endpoint".into(),
+            Variant(Box::new(peer.endpoint.to_string())),
+        );
+        peer_config.insert(
+            "public-key".into(),
+            Variant(Box::new(peer.public_key.to_base64())),
+        );
+        if let Some(psk) = &peer.psk {
+            peer_config.insert("preshared-key".into(), Variant(Box::new(psk.to_base64())));
+            peer_config.insert("preshared-key-flags".into(), Variant(Box::new(0x0u32)));
+        }
+
+        peer_configs.push(peer_config);
+    }
+    wireguard_config.insert("peers".into(), Variant(Box::new(peer_configs)));
+
+    wireguard_config
+}
@@ -5,7 +5,7 @@ use super::{
 };
 use crate::{
     firewall::FirewallPolicy,
-    tunnel::{CloseHandle, TunnelEvent, TunnelMetadata},
+    tunnel::{CloseHandle, StatsHandle, TunnelEvent, TunnelMetadata},
 };
 use cfg_if::cfg_if;
 use futures::{channel::mpsc, stream::Fuse, StreamExt};
@@ -30,6 +30,7 @@ pub struct ConnectedStateBootstrap {
     pub tunnel_parameters: TunnelParameters,
     pub tunnel_close_event: TunnelCloseEvent,
     pub close_handle: Option<CloseHandle>,
+    pub stats_handle: StatsHandle,
 }
 
 /// The tunnel is up and working.
@@ -39,6 +40,7 @@ pub struct ConnectedState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: TunnelCloseEvent,
     close_handle: Option<CloseHandle>,
+    stats_handle: StatsHandle,
 }
 
 impl ConnectedState {
@@ -49,6 +51,7 @@ impl ConnectedState {
             tunnel_parameters: bootstrap.tunnel_parameters,
             tunnel_close_event: bootstrap.tunnel_close_event,
             close_handle: bootstrap.close_handle,
+            stats_handle: bootstrap.stats_handle,
         }
     }
 
@@ -101,13 +104,43 @@ impl ConnectedState {
         }
     }
 
+    /// Returns the DNS servers along with the servers that a local resolver forwards queries to.
+    #[cfg(not(target_os = "android"))]
+    fn get_allowed_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+        let mut dns_ips = self.get_dns_servers(shared_values);
+        for upstream in &shared_values.dns_upstreams {
+            if !dns_ips.contains(upstream) {
+                dns_ips.push(*upstream);
+            }
+        }
+        dns_ips
+    }
+
+    /// Returns the resolvers of the split DNS rules, which are reached outside the tunnel.
+    #[cfg(target_os = "linux")]
+    fn get_split_dns_resolvers(shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+        let mut resolvers = vec![];
+        for resolver in shared_values
+            .split_dns_rules
+            .iter()
+            .flat_map(|rule| &rule.resolvers)
+        {
+            if !resolvers.contains(resolver) {
+                resolvers.push(*resolver);
+            }
+        }
+        resolvers
+    }
+
     fn get_firewall_policy(&self, shared_values: &SharedTunnelStateValues) -> FirewallPolicy {
         FirewallPolicy::Connected {
             peer_endpoint: self.tunnel_parameters.get_next_hop_endpoint(),
             tunnel: self.metadata.clone(),
             allow_lan: shared_values.allow_lan,
             #[cfg(not(target_os = "android"))]
-            dns_servers: self.get_dns_servers(shared_values),
+            dns_servers: self.get_allowed_dns_servers(shared_values),
+            #[cfg(target_os = "linux")]
+            split_dns_resolvers: Self::get_split_dns_resolvers(shared_values),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(
                 &shared_values.resource_dir,
@@ -120,7 +153,11 @@ impl ConnectedState {
         let dns_ips = self.get_dns_servers(shared_values);
         shared_values
             .dns_monitor
-            .set(&self.metadata.interface, &dns_ips)
+            .set(
+                &self.metadata.interface,
+                &dns_ips,
+                &shared_values.split_dns_rules,
+            )
             .map_err(BoxedError::new)?;
 
         Ok(())
@@ -197,34 +234,58 @@ impl ConnectedState {
                 }
                 SameState(self.into())
             }
-            Some(TunnelCommand::Dns(servers)) => match shared_values.set_dns_servers(servers) {
-                Ok(true) => {
-                    if let Err(error) = self.set_firewall_policy(shared_values) {
-                        return self.disconnect(
-                            shared_values,
-                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
-                        );
-                    }
-
-                    match self.set_dns(shared_values) {
-                        #[cfg(target_os = "android")]
-                        Ok(()) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
-                        #[cfg(not(target_os = "android"))]
-                        Ok(()) => SameState(self.into()),
-                        Err(error) => {
-                            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
-                            self.disconnect(
+            Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
+                let split_dns_rules_changed = shared_values.set_split_dns_rules(split_dns_rules);
+                match shared_values.set_dns_servers(servers) {
+                    Ok(servers_changed) if servers_changed || split_dns_rules_changed => {
+                        if let Err(error) = self.set_firewall_policy(shared_values) {
+                            return self.disconnect(
                                 shared_values,
-                                AfterDisconnect::Block(ErrorStateCause::SetDnsError),
-                            )
+                                AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(
+                                    error,
+                                )),
+                            );
+                        }
+
+                        match self.set_dns(shared_values) {
+                            #[cfg(target_os = "android")]
+                            Ok(()) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
+                            #[cfg(not(target_os = "android"))]
+                            Ok(()) => SameState(self.into()),
+                            Err(error) => {
+                                log::error!(
+                                    "{}",
+                                    error.display_chain_with_msg("Failed to set DNS")
+                                );
+                                self.disconnect(
+                                    shared_values,
+                                    AfterDisconnect::Block(ErrorStateCause::SetDnsError),
+                                )
+                            }
                         }
                     }
+                    Ok(_) => SameState(self.into()),
+                    Err(error_cause) => {
+                        self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
+                    }
                 }
-                Ok(false) => SameState(self.into()),
-                Err(error_cause) => {
-                    self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
+            }
+            Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                if !shared_values.set_dns_upstreams(dns_upstreams) {
+                    return SameState(self.into());
                 }
-            },
+                match self.set_firewall_policy(shared_values) {
+                    Ok(()) => SameState(self.into()),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                let _ = stats_tx.send(self.stats_handle.stats());
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
@@ -323,6 +384,13 @@ impl TunnelState for ConnectedState {
                 ),
             )
         } else {
+            if shared_values
+                .tunnel_metadata_listener
+                .send(connected_state.metadata.clone())
+                .is_err()
+            {
+                log::warn!("The tunnel metadata listener was dropped");
+            }
             (
                 TunnelStateWrapper::from(connected_state),
                 TunnelStateTransition::Connected(tunnel_endpoint),
@@ -7,7 +7,8 @@ use crate::{
     firewall::FirewallPolicy,
     routing::RouteManager,
     tunnel::{
-        self, tun_provider::TunProvider, CloseHandle, TunnelEvent, TunnelMetadata, TunnelMonitor,
+        self, tun_provider::TunProvider, CloseHandle, StatsHandle, TunnelEvent, TunnelMetadata,
+        TunnelMonitor,
     },
 };
 use cfg_if::cfg_if;
@@ -49,6 +50,7 @@ pub struct ConnectingState {
     tunnel_metadata: Option<TunnelMetadata>,
     tunnel_close_event: TunnelCloseEvent,
     close_handle: Option<CloseHandle>,
+    stats_handle: StatsHandle,
     retry_attempt: u32,
 }
 
@@ -113,6 +115,7 @@ impl ConnectingState {
             route_manager,
         )?;
         let close_handle = Some(monitor.close_handle());
+        let stats_handle = monitor.stats_handle();
         let tunnel_close_event = Self::spawn_tunnel_monitor_wait_thread(Some(monitor));
 
         Ok(ConnectingState {
@@ -121,6 +124,7 @@ impl ConnectingState {
             tunnel_metadata: None,
             tunnel_close_event,
             close_handle,
+            stats_handle,
             retry_attempt,
         })
     }
@@ -192,6 +196,7 @@ impl ConnectingState {
             tunnel_parameters: self.tunnel_parameters,
             tunnel_close_event: self.tunnel_close_event,
             close_handle: self.close_handle,
+            stats_handle: self.stats_handle,
         }
     }
 
@@ -274,12 +279,23 @@ impl ConnectingState {
                 }
                 SameState(self.into())
             }
-            Some(TunnelCommand::Dns(servers)) => match shared_values.set_dns_servers(servers) {
-                #[cfg(target_os = "android")]
-                Ok(true) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
-                Ok(_) => SameState(self.into()),
-                Err(cause) => self.disconnect(shared_values, AfterDisconnect::Block(cause)),
-            },
+            Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
+                let _ = shared_values.set_split_dns_rules(split_dns_rules);
+                match shared_values.set_dns_servers(servers) {
+                    #[cfg(target_os = "android")]
+                    Ok(true) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
+                    Ok(_) => SameState(self.into()),
+                    Err(cause) => self.disconnect(shared_values, AfterDisconnect::Block(cause)),
+                }
+            }
+            Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                let _ = stats_tx.send(None);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
@@ -87,14 +87,23 @@ impl TunnelState for DisconnectedState {
                 }
                 SameState(self.into())
             }
-            Some(TunnelCommand::Dns(servers)) => {
+            Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
                 // Same situation as allow LAN above.
                 shared_values
                     .set_dns_servers(servers)
                     .expect("Failed to reconnect after changing custom DNS servers");
+                let _ = shared_values.set_split_dns_rules(split_dns_rules);
 
                 SameState(self.into())
             }
+            Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                let _ = stats_tx.send(None);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 if shared_values.block_when_disconnected != block_when_disconnected {
                     shared_values.block_when_disconnected = block_when_disconnected;
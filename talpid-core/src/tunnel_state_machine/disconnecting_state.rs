@@ -39,8 +39,17 @@ impl DisconnectingState {
                     }
                     AfterDisconnect::Nothing
                 }
-                Some(TunnelCommand::Dns(servers)) => {
+                Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
                     let _ = shared_values.set_dns_servers(servers);
+                    let _ = shared_values.set_split_dns_rules(split_dns_rules);
+                    AfterDisconnect::Nothing
+                }
+                Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                    let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                    AfterDisconnect::Nothing
+                }
+                Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                    let _ = stats_tx.send(None);
                     AfterDisconnect::Nothing
                 }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
@@ -72,8 +81,17 @@ impl DisconnectingState {
                     }
                     AfterDisconnect::Block(reason)
                 }
-                Some(TunnelCommand::Dns(servers)) => {
+                Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
                     let _ = shared_values.set_dns_servers(servers);
+                    let _ = shared_values.set_split_dns_rules(split_dns_rules);
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                    let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                    let _ = stats_tx.send(None);
                     AfterDisconnect::Block(reason)
                 }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
@@ -110,8 +128,17 @@ impl DisconnectingState {
                     }
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
-                Some(TunnelCommand::Dns(servers)) => {
+                Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
                     let _ = shared_values.set_dns_servers(servers);
+                    let _ = shared_values.set_split_dns_rules(split_dns_rules);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                    let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                    let _ = stats_tx.send(None);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
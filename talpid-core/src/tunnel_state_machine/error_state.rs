@@ -123,13 +123,22 @@ impl TunnelState for ErrorState {
                 }
                 SameState(self.into())
             }
-            Some(TunnelCommand::Dns(servers)) => {
+            Some(TunnelCommand::Dns(servers, split_dns_rules)) => {
+                let _ = shared_values.set_split_dns_rules(split_dns_rules);
                 if let Err(error_state_cause) = shared_values.set_dns_servers(servers) {
                     NewState(Self::enter(shared_values, error_state_cause))
                 } else {
                     SameState(self.into())
                 }
             }
+            Some(TunnelCommand::DnsUpstreams(dns_upstreams)) => {
+                let _ = shared_values.set_dns_upstreams(dns_upstreams);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetTrafficStats(stats_tx)) => {
+                let _ = stats_tx.send(None);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
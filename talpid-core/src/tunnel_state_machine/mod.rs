@@ -17,7 +17,7 @@ use crate::{
     mpsc::Sender,
     offline,
     routing::RouteManager,
-    tunnel::{tun_provider::TunProvider, TunnelEvent},
+    tunnel::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata, TunnelStats},
 };
 use futures::{
     channel::{mpsc, oneshot},
@@ -35,7 +35,7 @@ use std::{
 #[cfg(target_os = "android")]
 use talpid_types::{android::AndroidContext, ErrorExt};
 use talpid_types::{
-    net::{Endpoint, TunnelParameters},
+    net::{Endpoint, NetworkIdentity, SplitDnsRule, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
 };
 
@@ -77,12 +77,16 @@ pub async fn spawn(
     allow_lan: bool,
     block_when_disconnected: bool,
     dns_servers: Option<Vec<IpAddr>>,
+    split_dns_rules: Vec<SplitDnsRule>,
+    dns_upstreams: Vec<IpAddr>,
     allowed_endpoint: Endpoint,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
     cache_dir: impl AsRef<Path> + Send + 'static,
     state_change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
+    network_identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
+    tunnel_metadata_listener: impl Sender<TunnelMetadata> + Send + 'static,
     shutdown_tx: oneshot::Sender<()>,
     reset_firewall: bool,
     #[cfg(target_os = "android")] android_context: AndroidContext,
@@ -91,6 +95,7 @@ pub async fn spawn(
     let command_tx = Arc::new(command_tx);
     let mut offline_monitor = offline::spawn_monitor(
         Arc::downgrade(&command_tx),
+        network_identity_listener,
         #[cfg(target_os = "android")]
         android_context.clone(),
     )
@@ -119,6 +124,8 @@ pub async fn spawn(
             block_when_disconnected,
             is_offline,
             dns_servers,
+            split_dns_rules,
+            dns_upstreams,
             allowed_endpoint,
             tunnel_parameters_generator,
             tun_provider,
@@ -126,6 +133,7 @@ pub async fn spawn(
             resource_dir,
             cache_dir,
             command_rx,
+            Box::new(tunnel_metadata_listener),
             reset_firewall,
         );
         let state_machine = match state_machine {
@@ -161,8 +169,13 @@ pub enum TunnelCommand {
     /// Endpoint that should never be blocked.
     /// If an error occurs, the sender is dropped.
     AllowEndpoint(Endpoint, oneshot::Sender<()>),
-    /// Set DNS servers to use.
-    Dns(Option<Vec<IpAddr>>),
+    /// Set DNS servers to use, and domains to resolve using other resolvers than those servers.
+    Dns(Option<Vec<IpAddr>>, Vec<SplitDnsRule>),
+    /// Set the servers that a local resolver among the DNS servers forwards queries to. These are
+    /// allowed through the firewall, but are not used as DNS servers.
+    DnsUpstreams(Vec<IpAddr>),
+    /// Get the traffic statistics of the tunnel. `None` is sent unless the tunnel is connected.
+    GetTrafficStats(oneshot::Sender<Option<TunnelStats>>),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
     /// Notify the state machine of the connectivity of the device.
@@ -205,6 +218,8 @@ impl TunnelStateMachine {
         block_when_disconnected: bool,
         is_offline: bool,
         dns_servers: Option<Vec<IpAddr>>,
+        split_dns_rules: Vec<SplitDnsRule>,
+        dns_upstreams: Vec<IpAddr>,
         allowed_endpoint: Endpoint,
         tunnel_parameters_generator: impl TunnelParametersGenerator,
         tun_provider: TunProvider,
@@ -212,6 +227,7 @@ impl TunnelStateMachine {
         resource_dir: PathBuf,
         cache_dir: impl AsRef<Path>,
         commands: mpsc::UnboundedReceiver<TunnelCommand>,
+        tunnel_metadata_listener: Box<dyn Sender<TunnelMetadata> + Send>,
         reset_firewall: bool,
     ) -> Result<Self, Error> {
         let args = FirewallArguments {
@@ -234,11 +250,14 @@ impl TunnelStateMachine {
             block_when_disconnected,
             is_offline,
             dns_servers,
+            split_dns_rules,
+            dns_upstreams,
             allowed_endpoint,
             tunnel_parameters_generator: Box::new(tunnel_parameters_generator),
             tun_provider,
             log_dir,
             resource_dir,
+            tunnel_metadata_listener,
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
         };
@@ -307,6 +326,10 @@ struct SharedTunnelStateValues {
     is_offline: bool,
     /// DNS servers to use (overriding default).
     dns_servers: Option<Vec<IpAddr>>,
+    /// Domains to resolve using other resolvers than `dns_servers`.
+    split_dns_rules: Vec<SplitDnsRule>,
+    /// Servers that a local resolver among `dns_servers` forwards queries to.
+    dns_upstreams: Vec<IpAddr>,
     /// Endpoint that should not be blocked by the firewall.
     allowed_endpoint: Endpoint,
     /// The generator of new `TunnelParameter`s
@@ -317,6 +340,8 @@ struct SharedTunnelStateValues {
     log_dir: Option<PathBuf>,
     /// Resource directory path.
     resource_dir: PathBuf,
+    /// Receives the metadata of every tunnel that comes up.
+    tunnel_metadata_listener: Box<dyn Sender<TunnelMetadata> + Send>,
 
     /// NetworkManager's connecitivity check state.
     #[cfg(target_os = "linux")]
@@ -386,6 +411,24 @@ impl SharedTunnelStateValues {
         }
     }
 
+    pub fn set_split_dns_rules(&mut self, split_dns_rules: Vec<SplitDnsRule>) -> bool {
+        if self.split_dns_rules != split_dns_rules {
+            self.split_dns_rules = split_dns_rules;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_dns_upstreams(&mut self, dns_upstreams: Vec<IpAddr>) -> bool {
+        if self.dns_upstreams != dns_upstreams {
+            self.dns_upstreams = dns_upstreams;
+            true
+        } else {
+            false
+        }
+    }
+
     /// NetworkManager's connectivity check can get hung when DNS requests fail, thus the TSM
     /// should always disable it before applying firewall rules. The connectivity check should be
     /// reset whenever the firewall is cleared.
@@ -44,6 +44,8 @@ const NM_SETTINGS_INTERFACE: &str = "org.freedesktop.NetworkManager.Settings";
 const NM_SETTINGS_CONNECTION_INTERFACE: &str = "org.freedesktop.NetworkManager.Settings.Connection";
 const NM_SETTINGS_PATH: &str = "/org/freedesktop/NetworkManager/Settings";
 const NM_CONNECTION_ACTIVE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const NM_ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const NM_WIRELESS_CONNECTION_TYPE: &str = "802-11-wireless";
 
 const NM_ADD_CONNECTION_VOLATILE: u32 = 0x2;
 
@@ -397,6 +399,32 @@ impl NetworkManager {
         }
     }
 
+    /// Returns the SSID of the network used by NetworkManager's primary connection, or `None` if
+    /// the primary connection is not a wireless one.
+    pub fn get_primary_ssid(&self) -> Result<Option<String>> {
+        let primary_connection: dbus::Path<'static> =
+            self.as_manager().get(NM_MANAGER, "PrimaryConnection")?;
+        if &*primary_connection == "/" {
+            return Ok(None);
+        }
+
+        let connection = self.as_path(&primary_connection);
+        let connection_type: String = connection.get(NM_CONNECTION_ACTIVE, "Type")?;
+        if connection_type != NM_WIRELESS_CONNECTION_TYPE {
+            return Ok(None);
+        }
+
+        // For wireless connections, the specific object is the access point in use.
+        let access_point: dbus::Path<'static> =
+            connection.get(NM_CONNECTION_ACTIVE, "SpecificObject")?;
+        if &*access_point == "/" {
+            return Ok(None);
+        }
+        let ssid: Vec<u8> = self.as_path(&access_point).get(NM_ACCESS_POINT, "Ssid")?;
+
+        Ok(Some(String::from_utf8_lossy(&ssid).into_owned()))
+    }
+
     fn nm_manager(&self) -> Proxy<'_, &SyncConnection> {
         Proxy::new(NM_BUS, NM_MANAGER_PATH, RPC_TIMEOUT, &*self.connection)
     }
@@ -468,12 +496,73 @@ impl NetworkManager {
         self.wait_until_device_is_ready(&device_path)?;
 
 
-        let device = self.as_path(&device_path);
+        let (mut settings, version_id) = self.get_applied_connection(&device_path)?;
+
+        let mut settings_backup =
+            HashMap::<String, HashMap<String, Variant<Box<dyn RefArg>>>>::new();
+        for (top_key, map) in settings.iter() {
+            let mut inner_dict = HashMap::<String, Variant<Box<dyn RefArg>>>::new();
+            for (key, variant) in map.iter() {
+                inner_dict.insert(key.to_string(), Variant(variant.0.box_clone()));
+            }
+            settings_backup.insert(top_key.to_string(), inner_dict);
+        }
+
+        // Update the DNS config
+        let v4_dns: Vec<u32> = servers
+            .iter()
+            .filter_map(|server| {
+                match server {
+                    // Network-byte order
+                    IpAddr::V4(server) => Some(u32::to_be(server.clone().into())),
+                    IpAddr::V6(_) => None,
+                }
+            })
+            .collect();
+        if !v4_dns.is_empty() {
+            Self::update_dns_config(&mut settings, "ipv4", v4_dns);
+        }
+
+        let v6_dns: Vec<Vec<u8>> = servers
+            .iter()
+            .filter_map(|server| match server {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(server) => Some(server.octets().to_vec()),
+            })
+            .collect();
+        if !v6_dns.is_empty() {
+            Self::update_dns_config(&mut settings, "ipv6", v6_dns);
+        }
+
+        if let Some(wg_config) = settings.get_mut("wireguard") {
+            if !wg_config.contains_key("fwmark") {
+                log::error!("WireGuard config doesn't contain the firewall mark");
+            }
+        }
+
+        self.reapply_settings(&device_path, settings, version_id)?;
+        Ok(settings_backup)
+    }
+
+    /// Replaces the WireGuard settings of an active tunnel, keeping the rest of its applied
+    /// connection as is.
+    pub fn set_wireguard_config(
+        &self,
+        tunnel: &WireguardTunnel,
+        wireguard_config: VariantMap,
+    ) -> Result<()> {
+        let (mut settings, version_id) = self.get_applied_connection(&tunnel.device_path)?;
+        settings.insert("wireguard".to_string(), wireguard_config);
+        self.reapply_settings(&tunnel.device_path, settings, version_id)
+    }
+
+    /// Returns the last applied connection of a device, along with the routes and addresses
+    /// that were modified outside of NM.
+    fn get_applied_connection(&self, device_path: &dbus::Path<'_>) -> Result<(DeviceConfig, u64)> {
+        let device = self.as_path(device_path);
         // Get the last applied connection
-        let (mut settings, version_id): (
-            HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>>,
-            u64,
-        ) = device.method_call(NM_DEVICE, "GetAppliedConnection", (0u32,))?;
+        let (mut settings, version_id): (DeviceConfig, u64) =
+            device.method_call(NM_DEVICE, "GetAppliedConnection", (0u32,))?;
 
         // Keep changed routes.
         // These routes were modified outside NM, likely by RouteManager.
@@ -541,50 +630,7 @@ impl NetworkManager {
             }
         }
 
-        let mut settings_backup =
-            HashMap::<String, HashMap<String, Variant<Box<dyn RefArg>>>>::new();
-        for (top_key, map) in settings.iter() {
-            let mut inner_dict = HashMap::<String, Variant<Box<dyn RefArg>>>::new();
-            for (key, variant) in map.iter() {
-                inner_dict.insert(key.to_string(), Variant(variant.0.box_clone()));
-            }
-            settings_backup.insert(top_key.to_string(), inner_dict);
-        }
-
-        // Update the DNS config
-        let v4_dns: Vec<u32> = servers
-            .iter()
-            .filter_map(|server| {
-                match server {
-                    // Network-byte order
-                    IpAddr::V4(server) => Some(u32::to_be(server.clone().into())),
-                    IpAddr::V6(_) => None,
-                }
-            })
-            .collect();
-        if !v4_dns.is_empty() {
-            Self::update_dns_config(&mut settings, "ipv4", v4_dns);
-        }
-
-        let v6_dns: Vec<Vec<u8>> = servers
-            .iter()
-            .filter_map(|server| match server {
-                IpAddr::V4(_) => None,
-                IpAddr::V6(server) => Some(server.octets().to_vec()),
-            })
-            .collect();
-        if !v6_dns.is_empty() {
-            Self::update_dns_config(&mut settings, "ipv6", v6_dns);
-        }
-
-        if let Some(wg_config) = settings.get_mut("wireguard") {
-            if !wg_config.contains_key("fwmark") {
-                log::error!("WireGuard config doesn't contain the firewall mark");
-            }
-        }
-
-        self.reapply_settings(&device_path, settings, version_id)?;
-        Ok(settings_backup)
+        Ok((settings, version_id))
     }
 
     pub fn reapply_settings<Settings: arg::Append>(
@@ -534,18 +534,30 @@ impl AsyncHandle {
             .map_err(Error::AsyncTaskError)?
     }
 
-    pub async fn set_domains(
-        &self,
-        interface_index: u32,
-        domains: &[(&'static str, bool)],
-    ) -> Result<()> {
+    pub async fn get_domains(&self, interface_index: u32) -> Result<Vec<(String, bool)>> {
         let interface = self.dbus_interface.clone();
-        let domains = domains.to_vec();
-        tokio::task::spawn_blocking(move || interface.set_domains(interface_index, &domains))
+        tokio::task::spawn_blocking(move || interface.get_domains(interface_index))
             .await
             .map_err(Error::AsyncTaskError)?
     }
 
+    pub async fn set_domains(&self, interface_index: u32, domains: &[(&str, bool)]) -> Result<()> {
+        let interface = self.dbus_interface.clone();
+        let domains: Vec<(String, bool)> = domains
+            .iter()
+            .map(|(domain, routing_only)| (domain.to_string(), *routing_only))
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            let domains: Vec<(&str, bool)> = domains
+                .iter()
+                .map(|(domain, routing_only)| (domain.as_str(), *routing_only))
+                .collect();
+            interface.set_domains(interface_index, &domains)
+        })
+        .await
+        .map_err(Error::AsyncTaskError)?
+    }
+
     pub async fn revert_link(&self, state: DnsState) -> Result<()> {
         let mut interface = self.dbus_interface.clone();
         tokio::task::spawn_blocking(move || interface.revert_link(&state))
@@ -7,6 +7,7 @@ use std::{
     str::FromStr,
 };
 
+pub mod obfuscation;
 pub mod openvpn;
 pub mod proxy;
 pub mod wireguard;
@@ -29,6 +30,8 @@ impl TunnelParameters {
                 endpoint: params.config.endpoint,
                 proxy: params.proxy.as_ref().map(|proxy| proxy.get_endpoint()),
                 entry_endpoint: None,
+                intermediate_endpoints: vec![],
+                obfuscation: None,
             },
             TunnelParameters::Wireguard(params) => TunnelEndpoint {
                 tunnel_type: TunnelType::Wireguard,
@@ -41,6 +44,11 @@ impl TunnelParameters {
                     .connection
                     .get_exit_endpoint()
                     .map(|_| params.connection.get_endpoint()),
+                intermediate_endpoints: params.connection.get_intermediate_endpoints(),
+                obfuscation: params
+                    .obfuscation
+                    .as_ref()
+                    .map(|obfuscation| obfuscation.get_obfuscation_endpoint()),
             },
         }
     }
@@ -53,7 +61,11 @@ impl TunnelParameters {
                 .as_ref()
                 .map(|proxy| proxy.get_endpoint().endpoint)
                 .unwrap_or(params.config.endpoint),
-            TunnelParameters::Wireguard(params) => params.connection.get_endpoint(),
+            TunnelParameters::Wireguard(params) => params
+                .obfuscation
+                .as_ref()
+                .map(|obfuscation| obfuscation.get_endpoint())
+                .unwrap_or(params.connection.get_endpoint()),
         }
     }
 
@@ -107,7 +119,7 @@ impl fmt::Display for TunnelType {
 
 /// A tunnel endpoint is broadcast during the connecting and connected states of the tunnel state
 /// machine.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.talpid.net"))]
 pub struct TunnelEndpoint {
@@ -119,6 +131,14 @@ pub struct TunnelEndpoint {
     pub proxy: Option<proxy::ProxyEndpoint>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub entry_endpoint: Option<Endpoint>,
+    /// Relays between the entry relay and `endpoint`, in the order that the traffic passes
+    /// through them.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub intermediate_endpoints: Vec<Endpoint>,
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub obfuscation: Option<obfuscation::ObfuscationEndpoint>,
 }
 
 impl fmt::Display for TunnelEndpoint {
@@ -138,6 +158,12 @@ impl fmt::Display for TunnelEndpoint {
                 if let Some(ref entry_endpoint) = self.entry_endpoint {
                     write!(f, " via {}", entry_endpoint)?;
                 }
+                for intermediate_endpoint in &self.intermediate_endpoints {
+                    write!(f, " and {}", intermediate_endpoint)?;
+                }
+                if let Some(ref obfuscation) = self.obfuscation {
+                    write!(f, " obfuscated by {}", obfuscation)?;
+                }
             }
         }
         Ok(())
@@ -244,6 +270,106 @@ impl fmt::Display for TransportProtocolParseError {
     }
 }
 
+/// Hardware address of a network interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl FromStr for MacAddress {
+    type Err = MacAddressParseError;
+
+    fn from_str(s: &str) -> std::result::Result<MacAddress, Self::Err> {
+        let mut address = [0u8; 6];
+        let mut octets = s.split(|c| c == ':' || c == '-');
+        for octet in address.iter_mut() {
+            let part = octets.next().ok_or(MacAddressParseError)?;
+            if part.len() != 2 {
+                return Err(MacAddressParseError);
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| MacAddressParseError)?;
+        }
+        if octets.next().is_some() {
+            return Err(MacAddressParseError);
+        }
+        Ok(MacAddress(address))
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+impl Serialize for MacAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MacAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Returned when `MacAddress::from_str` fails to convert a string into a [`MacAddress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacAddressParseError;
+
+impl fmt::Display for MacAddressParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Not a valid MAC address")
+    }
+}
+
+/// Identifies the network that the device is currently connected to, as far as it can be
+/// determined.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NetworkIdentity {
+    /// SSID of the wireless network, if the primary connection is wireless.
+    pub ssid: Option<String>,
+    /// Hardware address of the default gateway.
+    pub gateway_mac: Option<MacAddress>,
+}
+
+impl fmt::Display for NetworkIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.ssid, &self.gateway_mac) {
+            (Some(ssid), Some(mac)) => write!(f, "SSID \"{}\" with gateway {}", ssid, mac),
+            (Some(ssid), None) => write!(f, "SSID \"{}\"", ssid),
+            (None, Some(mac)) => write!(f, "gateway {}", mac),
+            (None, None) => f.write_str("unknown network"),
+        }
+    }
+}
+
+/// Resolvers to use for a domain and all of its subdomains, instead of the resolvers that are
+/// used for everything else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SplitDnsRule {
+    /// Domain name, without a leading or trailing dot, such as `corp.example`.
+    pub domain: String,
+    /// Resolvers for the domain. These must be reachable outside the tunnel, such as a DNS
+    /// server on the local network.
+    pub resolvers: Vec<IpAddr>,
+}
+
+impl fmt::Display for SplitDnsRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} via", self.domain)?;
+        for resolver in &self.resolvers {
+            write!(f, " {}", resolver)?;
+        }
+        Ok(())
+    }
+}
+
 /// Holds optional settings that can apply to different kinds of tunnels
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct GenericTunnelOptions {
@@ -0,0 +1,109 @@
+use crate::net::{Endpoint, TransportProtocol};
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+/// Protocols that WireGuard traffic can be obfuscated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObfuscationType {
+    /// Sends the WireGuard datagrams over a TCP stream.
+    Udp2Tcp,
+    /// Relays the WireGuard datagrams through a Shadowsocks server.
+    Shadowsocks,
+}
+
+impl fmt::Display for ObfuscationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let obfuscation = match self {
+            ObfuscationType::Udp2Tcp => "UDP-over-TCP",
+            ObfuscationType::Shadowsocks => "Shadowsocks",
+        };
+        write!(f, "{}", obfuscation)
+    }
+}
+
+impl FromStr for ObfuscationType {
+    type Err = ObfuscationTypeParseError;
+
+    fn from_str(s: &str) -> Result<ObfuscationType, Self::Err> {
+        match s {
+            "udp2tcp" => Ok(ObfuscationType::Udp2Tcp),
+            "shadowsocks" => Ok(ObfuscationType::Shadowsocks),
+            _ => Err(ObfuscationTypeParseError),
+        }
+    }
+}
+
+/// Returned when `ObfuscationType::from_str` fails to convert a string into a
+/// [`ObfuscationType`] object.
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+#[error(display = "Not a valid obfuscation type")]
+pub struct ObfuscationTypeParseError;
+
+/// Obfuscation layer to wrap the traffic to the first WireGuard peer in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObfuscatorConfig {
+    /// Forward the datagrams over TCP to a UDP-over-TCP server, which sends them on to the peer.
+    Udp2Tcp { endpoint: SocketAddr },
+    /// Forward the datagrams to a Shadowsocks server, which sends them on to the peer.
+    Shadowsocks {
+        endpoint: SocketAddr,
+        /// Password on the server.
+        password: String,
+        /// One of [`SHADOWSOCKS_UDP_CIPHERS`].
+        cipher: String,
+    },
+}
+
+impl ObfuscatorConfig {
+    pub fn obfuscation_type(&self) -> ObfuscationType {
+        match self {
+            ObfuscatorConfig::Udp2Tcp { .. } => ObfuscationType::Udp2Tcp,
+            ObfuscatorConfig::Shadowsocks { .. } => ObfuscationType::Shadowsocks,
+        }
+    }
+
+    /// Returns the endpoint of the server that the obfuscated traffic is sent to.
+    pub fn get_endpoint(&self) -> Endpoint {
+        match self {
+            ObfuscatorConfig::Udp2Tcp { endpoint } => Endpoint {
+                address: *endpoint,
+                protocol: TransportProtocol::Tcp,
+            },
+            ObfuscatorConfig::Shadowsocks { endpoint, .. } => Endpoint {
+                address: *endpoint,
+                protocol: TransportProtocol::Udp,
+            },
+        }
+    }
+
+    pub fn get_obfuscation_endpoint(&self) -> ObfuscationEndpoint {
+        ObfuscationEndpoint {
+            endpoint: self.get_endpoint(),
+            obfuscation_type: self.obfuscation_type(),
+        }
+    }
+}
+
+/// Ciphers that can be used to relay WireGuard traffic through a Shadowsocks server.
+pub static SHADOWSOCKS_UDP_CIPHERS: &[&str] =
+    &["aes-128-gcm", "aes-256-gcm", "chacha20-ietf-poly1305"];
+
+/// Obfuscation server endpoint, broadcast as part of a [`crate::net::TunnelEndpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ObfuscationEndpoint {
+    #[serde(flatten)]
+    pub endpoint: Endpoint,
+    pub obfuscation_type: ObfuscationType,
+}
+
+impl fmt::Display for ObfuscationEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{} {} over {}",
+            self.obfuscation_type, self.endpoint.address, self.endpoint.protocol
+        )
+    }
+}
@@ -1,4 +1,6 @@
-use crate::net::{Endpoint, GenericTunnelOptions, TransportProtocol};
+use crate::net::{
+    obfuscation::ObfuscatorConfig, Endpoint, GenericTunnelOptions, TransportProtocol,
+};
 use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
@@ -18,6 +20,9 @@ pub struct TunnelParameters {
     pub connection: ConnectionConfig,
     pub options: TunnelOptions,
     pub generic_options: GenericTunnelOptions,
+    /// Obfuscation to wrap the traffic to the first peer in.
+    #[serde(default)]
+    pub obfuscation: Option<ObfuscatorConfig>,
 }
 
 /// Connection-specific configuration in [`TunnelParameters`].
@@ -25,7 +30,9 @@ pub struct TunnelParameters {
 pub struct ConnectionConfig {
     pub tunnel: TunnelConfig,
     pub peer: PeerConfig,
-    pub exit_peer: Option<PeerConfig>,
+    /// Peers that the traffic passes through after `peer`, in order. The last one is the exit.
+    #[serde(default)]
+    pub next_peers: Vec<PeerConfig>,
     /// Gateway used by the tunnel (a private address).
     pub ipv4_gateway: Ipv4Addr,
     pub ipv6_gateway: Option<Ipv6Addr>,
@@ -39,12 +46,32 @@ impl ConnectionConfig {
         }
     }
 
+    /// Returns the endpoints of the peers between the entry peer and the exit peer.
+    pub fn get_intermediate_endpoints(&self) -> Vec<Endpoint> {
+        let intermediate_peers = match self.next_peers.split_last() {
+            Some((_, intermediate_peers)) => intermediate_peers,
+            None => &[],
+        };
+        intermediate_peers
+            .iter()
+            .map(|peer| Endpoint {
+                address: peer.endpoint,
+                protocol: peer.protocol,
+            })
+            .collect()
+    }
+
     pub fn get_exit_endpoint(&self) -> Option<Endpoint> {
-        self.exit_peer.as_ref().map(|peer| Endpoint {
+        self.next_peers.last().map(|peer| Endpoint {
             address: peer.endpoint,
             protocol: peer.protocol,
         })
     }
+
+    /// Returns all peers, in the order that the traffic passes through them.
+    pub fn peers(&self) -> impl Iterator<Item = &PeerConfig> {
+        std::iter::once(&self.peer).chain(self.next_peers.iter())
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug, Hash)]
@@ -56,9 +83,13 @@ pub struct PeerConfig {
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
     /// Transport protocol. WireGuard only supports UDP directly.
-    /// If this is set to TCP, then traffic is proxied using [`udp_to_tcp::Udp2Tcp`].
+    /// If this is set to TCP, then traffic is proxied using [`udp_to_tcp::Udp2Tcp`], unless
+    /// [`TunnelParameters::obfuscation`] is set.
     #[serde(default = "default_peer_transport")]
     pub protocol: TransportProtocol,
+    /// Preshared key that is mixed into the handshake with the peer.
+    #[serde(default)]
+    pub psk: Option<PresharedKey>,
 }
 
 fn default_peer_transport() -> TransportProtocol {
@@ -86,6 +117,10 @@ pub struct TunnelOptions {
         jnix(map = "|maybe_mtu| maybe_mtu.map(|mtu| mtu as i32)")
     )]
     pub mtu: Option<u16>,
+    /// Negotiate a preshared key with the relay using a post-quantum KEM once the tunnel is up
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub quantum_resistant: bool,
 }
 
 /// Wireguard x25519 private key
@@ -228,6 +263,51 @@ impl fmt::Display for PublicKey {
     }
 }
 
+/// Wireguard preshared key, used as an additional layer of symmetric encryption
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PresharedKey(Box<[u8; 32]>);
+
+impl PresharedKey {
+    /// Get the preshared key as bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.as_bytes())
+    }
+}
+
+impl From<[u8; 32]> for PresharedKey {
+    fn from(key: [u8; 32]) -> PresharedKey {
+        PresharedKey(Box::new(key))
+    }
+}
+
+impl Serialize for PresharedKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_key(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PresharedKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_key(deserializer)
+    }
+}
+
+impl fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.to_base64())
+    }
+}
+
 fn serialize_key<S>(key: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -256,3 +336,58 @@ where
             Ok(From::from(key))
         })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peer(last_octet: u8) -> PeerConfig {
+        PeerConfig {
+            public_key: PublicKey::from([last_octet; 32]),
+            allowed_ips: vec![],
+            endpoint: SocketAddr::new(Ipv4Addr::new(10, 0, 0, last_octet).into(), 51820),
+            protocol: TransportProtocol::Udp,
+            psk: None,
+        }
+    }
+
+    fn connection_config(peers: &[u8]) -> ConnectionConfig {
+        ConnectionConfig {
+            tunnel: TunnelConfig {
+                private_key: PrivateKey::from([0; 32]),
+                addresses: vec![],
+            },
+            peer: peer(peers[0]),
+            next_peers: peers[1..].iter().map(|octet| peer(*octet)).collect(),
+            ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+            ipv6_gateway: None,
+        }
+    }
+
+    fn endpoints(peers: &[u8]) -> Vec<Endpoint> {
+        peers
+            .iter()
+            .map(|octet| Endpoint::new(peer(*octet).endpoint.ip(), 51820, TransportProtocol::Udp))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_hop_endpoints() {
+        let connection = connection_config(&[1]);
+        assert_eq!(connection.get_endpoint(), endpoints(&[1])[0]);
+        assert!(connection.get_intermediate_endpoints().is_empty());
+        assert_eq!(connection.get_exit_endpoint(), None);
+    }
+
+    #[test]
+    fn test_multihop_endpoints() {
+        let connection = connection_config(&[1, 2]);
+        assert!(connection.get_intermediate_endpoints().is_empty());
+        assert_eq!(connection.get_exit_endpoint(), Some(endpoints(&[2])[0]));
+
+        let connection = connection_config(&[1, 2, 3, 4]);
+        assert_eq!(connection.get_endpoint(), endpoints(&[1])[0]);
+        assert_eq!(connection.get_intermediate_endpoints(), endpoints(&[2, 3]));
+        assert_eq!(connection.get_exit_endpoint(), Some(endpoints(&[4])[0]));
+    }
+}